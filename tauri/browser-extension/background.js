@@ -132,6 +132,17 @@ function sendToNativeApp(eventType, data) {
   });
 }
 
+// Where the app's extension bridge listens, kept in storage as
+// `cortexServerUrl`. Set it to https://127.0.0.1:8080 once the app serves
+// HTTPS; the browser must trust the app's self-signed certificate first,
+// since extensions can't pin a certificate themselves (see README).
+const DEFAULT_SERVER_URL = 'http://127.0.0.1:8080';
+
+async function cortexServerUrl() {
+  const { cortexServerUrl: saved } = await chrome.storage.local.get(['cortexServerUrl']);
+  return (saved || DEFAULT_SERVER_URL).replace(/\/+$/, '');
+}
+
 // Send data to Tauri app via HTTP
 async function sendToTauriApp(eventType, data) {
   try {
@@ -144,11 +155,15 @@ async function sendToTauriApp(eventType, data) {
       timestamp: Date.now()
     };
     
-    // Send to Tauri HTTP server
-    const response = await fetch('http://127.0.0.1:8080/extension-data', {
+    // Send to Tauri HTTP server. The app rejects posts without its API key
+    // (shown in the app via get_api_key), which is kept in storage as
+    // `cortexApiKey`.
+    const { cortexApiKey } = await chrome.storage.local.get(['cortexApiKey']);
+    const response = await fetch(`${await cortexServerUrl()}/extension-data`, {
       method: 'POST',
       headers: {
         'Content-Type': 'application/json',
+        'X-Cortex-Key': cortexApiKey || '',
       },
       body: JSON.stringify({
         event_type: eventType,
@@ -206,7 +221,7 @@ async function sendToTauriApp(eventType, data) {
 // Check Tauri app connection
 async function checkTauriConnection() {
   try {
-    const response = await fetch('http://127.0.0.1:8080/health', {
+    const response = await fetch(`${await cortexServerUrl()}/health`, {
       method: 'GET',
       timeout: 3000
     });
@@ -250,6 +265,43 @@ chrome.runtime.onInstalled.addListener(() => {
 // Periodic health check (every 30 seconds)
 setInterval(checkTauriConnection, 30000);
 
+// Carry out close_tab and redirect commands queued by the app's rules
+async function pollPendingCommands() {
+  try {
+    const { cortexApiKey } = await chrome.storage.local.get(['cortexApiKey']);
+    const response = await fetch(`${await cortexServerUrl()}/pending-commands`, {
+      headers: { 'X-Cortex-Key': cortexApiKey || '' }
+    });
+    if (!response.ok) {
+      return;
+    }
+
+    const commands = await response.json();
+    for (const command of commands) {
+      // One failed command (e.g. its tab already closed) mustn't drop the rest
+      try {
+        // Compared exactly: chrome.tabs.query treats `url` as a match
+        // pattern, which most page URLs aren't
+        const tabs = (await chrome.tabs.query({})).filter((tab) => tab.url === command.tab_url);
+        for (const tab of tabs) {
+          if (command.command === 'close_tab') {
+            await chrome.tabs.remove(tab.id);
+          } else if (command.command === 'redirect') {
+            await chrome.tabs.update(tab.id, { url: command.url });
+          }
+        }
+      } catch (error) {
+        console.error('Failed to run command:', command, error);
+      }
+    }
+  } catch (error) {
+    console.error('Failed to fetch pending commands:', error);
+  }
+}
+
+// Poll for commands (every 2 seconds)
+setInterval(pollPendingCommands, 2000);
+
 // Handle extension startup
 chrome.runtime.onStartup.addListener(() => {
   console.log('Cortex Accountability Extension started');
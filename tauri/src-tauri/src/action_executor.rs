@@ -0,0 +1,494 @@
+use crate::database::{ActivityRecord, Database, NewActionLog, Rule};
+use crate::rule_definition::{NotificationAction, RuleAction, RuleDefinition, TabAction};
+use crate::websocket_server::{self, ExtensionCommand};
+use crate::{AppState, ExtensionLog};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tauri::{Emitter, Manager};
+use tauri_plugin_notification::NotificationExt;
+
+pub const MAX_POPUP_MESSAGE_LENGTH: usize = 200;
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActionValidationError {
+    pub action_index: usize,
+    pub field: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActionPreview {
+    pub action_type: String,
+    pub rendered_output: String,
+}
+
+// Payload of the "rule-action" event; the frontend carries the action out
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActionDispatch {
+    pub rule_id: i64,
+    pub action_type: String,
+    pub rendered_output: String,
+}
+
+pub struct ActionExecutor {
+    app_handle: tauri::AppHandle,
+    http: reqwest::Client,
+}
+
+impl ActionExecutor {
+    pub fn new(app_handle: tauri::AppHandle) -> Self {
+        ActionExecutor {
+            app_handle,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    // Dispatches every action of a matched rule and records each attempt in
+    // the action log, whether or not it went through. Notifications and
+    // webhooks are sent from here, tab actions are queued for the browser
+    // extension and other actions are handed to the frontend.
+    pub async fn execute(
+        &self,
+        db: &Database,
+        rule: &Rule,
+        log: &ExtensionLog,
+    ) -> Result<(), String> {
+        let definition = RuleDefinition::from_json(&rule.rule_json)?;
+        for action in &definition.actions {
+            let dispatch = ActionDispatch {
+                rule_id: rule.id,
+                action_type: action.action_type.clone(),
+                rendered_output: render_action(action, log),
+            };
+            let started = Instant::now();
+            let result = match action.action_type.as_str() {
+                "notification" => self.show_notification(rule.id, action, log).await,
+                "close_tab" | "redirect" => self.queue_extension_command(action, log).await,
+                "webhook" | "send_webhook" => {
+                    self.send_webhook(action, log, &dispatch.rendered_output).await
+                }
+                _ => self
+                    .app_handle
+                    .emit("rule-action", &dispatch)
+                    .map(|_| true)
+                    .map_err(|e| format!("Failed to emit rule-action: {}", e)),
+            };
+            // A webhook's latency is its request; other actions count from
+            // the triggering event
+            let is_webhook = matches!(dispatch.action_type.as_str(), "webhook" | "send_webhook");
+            let latency_ms = if is_webhook {
+                Some(started.elapsed().as_secs_f64() * 1000.0)
+            } else {
+                Some(chrono::Utc::now().timestamp_millis() as f64 - log.timestamp)
+                    .filter(|latency| *latency >= 0.0)
+            };
+            let error = match result {
+                Ok(true) => None,
+                // Debounced, so there is no attempt to record
+                Ok(false) => continue,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    Some(e)
+                }
+            };
+
+            db.log_action(NewActionLog {
+                rule_id: rule.id,
+                action_type: dispatch.action_type,
+                error,
+                domain: log.domain.clone(),
+                latency_ms,
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
+    // Returns false without showing anything if the rule already notified
+    // within the configured debounce window
+    async fn show_notification(
+        &self,
+        rule_id: i64,
+        action: &RuleAction,
+        log: &ExtensionLog,
+    ) -> Result<bool, String> {
+        let notification = notification_action(action, log)?;
+        let state = self.app_handle.state::<AppState>();
+        let debounce_seconds = state.user_config.lock().await.notification_debounce_seconds;
+        let mut last_shown = state.notification_debounce.lock().await;
+        let window = Duration::from_secs(debounce_seconds as u64);
+        if !should_notify(&mut last_shown, rule_id, Instant::now(), window) {
+            println!("Skipped notification for rule {}: debounced", rule_id);
+            return Ok(false);
+        }
+
+        let mut builder = self
+            .app_handle
+            .notification()
+            .builder()
+            .title(&notification.title)
+            .body(&notification.body);
+        if let Some(icon) = &notification.icon {
+            builder = builder.icon(icon);
+        }
+        builder.show().map_err(|e| format!("Failed to show notification: {}", e))?;
+        Ok(true)
+    }
+
+    // Posts the rendered body as JSON to the action's url
+    async fn send_webhook(
+        &self,
+        action: &RuleAction,
+        log: &ExtensionLog,
+        body: &str,
+    ) -> Result<bool, String> {
+        let url = action
+            .parameters
+            .get("url")
+            .and_then(|url| url.as_str())
+            .ok_or_else(|| "Webhook url is required".to_string())?;
+        self.http
+            .post(render_template(url, log))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .timeout(WEBHOOK_TIMEOUT)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("Webhook request failed: {}", e))?;
+        Ok(true)
+    }
+
+    // The extension picks the command up on its next poll
+    async fn queue_extension_command(
+        &self,
+        action: &RuleAction,
+        log: &ExtensionLog,
+    ) -> Result<bool, String> {
+        let command = extension_command(action, log)?;
+        let state = self.app_handle.state::<AppState>();
+        websocket_server::queue_command(&mut *state.pending_commands.lock().await, command);
+        Ok(true)
+    }
+}
+
+// Records the notification as shown unless the rule already showed one less
+// than `window` ago
+pub fn should_notify(
+    last_shown: &mut HashMap<i64, Instant>,
+    rule_id: i64,
+    now: Instant,
+    window: Duration,
+) -> bool {
+    if last_shown
+        .get(&rule_id)
+        .is_some_and(|shown| now.duration_since(*shown) < window)
+    {
+        return false;
+    }
+    last_shown.insert(rule_id, now);
+    true
+}
+
+// The notification's parameters with placeholders filled in from the log
+pub fn notification_action(
+    action: &RuleAction,
+    log: &ExtensionLog,
+) -> Result<NotificationAction, String> {
+    serde_json::from_value(render_json(&action.parameters, log))
+        .map_err(|e| format!("Invalid notification action: {}", e))
+}
+
+// The command for a "close_tab" or "redirect" action, aimed at the tab that
+// produced the log
+pub fn extension_command(
+    action: &RuleAction,
+    log: &ExtensionLog,
+) -> Result<ExtensionCommand, String> {
+    let action = match action.action_type.as_str() {
+        "close_tab" => TabAction::CloseTab,
+        "redirect" => {
+            let target = action
+                .parameters
+                .get("target_url")
+                .and_then(|target| target.as_str())
+                .ok_or_else(|| "Redirect target_url is required".to_string())?;
+            TabAction::Redirect {
+                url: render_template(target, log),
+            }
+        }
+        other => return Err(format!("{} is not a tab action", other)),
+    };
+    Ok(ExtensionCommand {
+        action,
+        tab_url: log.url.clone(),
+    })
+}
+
+// Activities carry no page, so templates referring to the url or title
+// render empty
+pub fn activity_context(activity: &ActivityRecord) -> ExtensionLog {
+    ExtensionLog {
+        timestamp: activity.timestamp,
+        domain: activity.domain.clone().unwrap_or_default(),
+        activity: activity.activity.clone(),
+        url: String::new(),
+        title: String::new(),
+        elements: None,
+        title_sentiment: None,
+        url_category: None,
+    }
+}
+
+// Substitutes `{{field}}` placeholders with values from the triggering log
+pub fn render_template(template: &str, log: &ExtensionLog) -> String {
+    template
+        .replace("{{domain}}", &log.domain)
+        .replace("{{activity}}", &log.activity)
+        .replace("{{url}}", &log.url)
+        .replace("{{title}}", &log.title)
+        .replace("{{timestamp}}", &log.timestamp.to_string())
+}
+
+// Renders every string inside a JSON value, leaving its structure intact so
+// substituted text can never break the surrounding JSON
+pub fn render_json(value: &serde_json::Value, log: &ExtensionLog) -> serde_json::Value {
+    match value {
+        serde_json::Value::String(text) => serde_json::Value::String(render_template(text, log)),
+        serde_json::Value::Array(items) => {
+            serde_json::Value::Array(items.iter().map(|item| render_json(item, log)).collect())
+        }
+        serde_json::Value::Object(map) => serde_json::Value::Object(
+            map.iter()
+                .map(|(key, item)| (key.clone(), render_json(item, log)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+pub fn render_action(action: &RuleAction, log: &ExtensionLog) -> String {
+    match action.action_type.as_str() {
+        "popup" => {
+            let message = action
+                .parameters
+                .get("message")
+                .and_then(|message| message.as_str())
+                .unwrap_or_default();
+            render_template(message, log)
+        }
+        "webhook" | "send_webhook" => {
+            let body = action.parameters.get("body").unwrap_or(&action.parameters);
+            render_json(body, log).to_string()
+        }
+        _ => render_json(&action.parameters, log).to_string(),
+    }
+}
+
+pub fn validate_action_config(actions: &[RuleAction]) -> Vec<ActionValidationError> {
+    let mut errors = Vec::new();
+    for (action_index, action) in actions.iter().enumerate() {
+        let mut fail = |field: &str, message: String| {
+            errors.push(ActionValidationError {
+                action_index,
+                field: field.to_string(),
+                message,
+            })
+        };
+        let text = |field: &str| action.parameters.get(field).and_then(|value| value.as_str());
+
+        match action.action_type.as_str() {
+            "popup" => match text("message").map(str::trim) {
+                None | Some("") => fail("message", "Popup message must not be empty".to_string()),
+                Some(message) if message.chars().count() > MAX_POPUP_MESSAGE_LENGTH => fail(
+                    "message",
+                    format!("Popup message exceeds {} characters", MAX_POPUP_MESSAGE_LENGTH),
+                ),
+                Some(_) => {}
+            },
+            "notification" => {
+                for field in ["title", "body"] {
+                    if text(field).is_none_or(|value| value.trim().is_empty()) {
+                        fail(field, format!("Notification {} must not be empty", field));
+                    }
+                }
+            }
+            "webhook" | "send_webhook" => {
+                let url = text("url").and_then(|url| url::Url::parse(url).ok());
+                if url.is_none_or(|url| url.scheme() != "https") {
+                    fail("url", "Webhook url must be a valid HTTPS URL".to_string());
+                }
+            }
+            "redirect" => {
+                let Some(target) = text("target_url") else {
+                    fail("target_url", "Redirect target_url is required".to_string());
+                    continue;
+                };
+                // Redirecting to the page being left sends the browser straight back
+                if target.contains("{{url}}") || target.contains("{{domain}}") {
+                    fail(
+                        "target_url",
+                        "Redirect target must not point back to the matched page".to_string(),
+                    );
+                    continue;
+                }
+                let url = url::Url::parse(target).ok();
+                if url.is_none_or(|url| !matches!(url.scheme(), "http" | "https")) {
+                    fail(
+                        "target_url",
+                        "Redirect target_url must be a valid http(s) URL".to_string(),
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+    errors
+}
+
+pub fn preview_actions(definition: &RuleDefinition, log: &ExtensionLog) -> Vec<ActionPreview> {
+    definition
+        .actions
+        .iter()
+        .map(|action| ActionPreview {
+            action_type: action.action_type.clone(),
+            rendered_output: render_action(action, log),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_log() -> ExtensionLog {
+        ExtensionLog {
+            timestamp: 1_700_000_000_000.0,
+            domain: "youtube.com".to_string(),
+            activity: "watching_videos".to_string(),
+            url: "https://youtube.com/watch?v=xyz".to_string(),
+            title: "A \"quoted\" title".to_string(),
+            elements: None,
+            title_sentiment: None,
+            url_category: None,
+        }
+    }
+
+    #[test]
+    fn popup_preview_renders_message() {
+        let action = RuleAction {
+            parameters: serde_json::json!({ "message": "Leave {{domain}} ({{activity}})" }),
+            action_type: "popup".to_string(),
+        };
+        assert_eq!(
+            render_action(&action, &sample_log()),
+            "Leave youtube.com (watching_videos)"
+        );
+    }
+
+    #[test]
+    fn webhook_preview_stays_valid_json() {
+        let action = RuleAction {
+            parameters: serde_json::json!({
+                "url": "https://hooks.example.com",
+                "body": { "text": "{{title}} on {{url}}", "count": 1 }
+            }),
+            action_type: "webhook".to_string(),
+        };
+        let rendered: serde_json::Value =
+            serde_json::from_str(&render_action(&action, &sample_log())).unwrap();
+        assert_eq!(
+            rendered,
+            serde_json::json!({
+                "text": "A \"quoted\" title on https://youtube.com/watch?v=xyz",
+                "count": 1
+            })
+        );
+    }
+
+    #[test]
+    fn action_config_validation_reports_each_bad_field() {
+        let action = |action_type: &str, parameters: serde_json::Value| RuleAction {
+            parameters,
+            action_type: action_type.to_string(),
+        };
+        let actions = vec![
+            action("popup", serde_json::json!({ "message": "Back to work" })),
+            action("popup", serde_json::json!({ "message": "x".repeat(201) })),
+            action("send_webhook", serde_json::json!({ "url": "http://hooks.example.com" })),
+            action("redirect", serde_json::json!({ "target_url": "https://{{domain}}/" })),
+            action("redirect", serde_json::json!({ "target_url": "https://todoist.com" })),
+        ];
+
+        let errors = validate_action_config(&actions);
+
+        let failed: Vec<(usize, &str)> =
+            errors.iter().map(|error| (error.action_index, error.field.as_str())).collect();
+        assert_eq!(failed, vec![(1, "message"), (2, "url"), (3, "target_url")]);
+    }
+
+    #[test]
+    fn notifications_are_debounced_per_rule() {
+        let window = Duration::from_secs(30);
+        let start = Instant::now();
+        let mut last_shown = HashMap::new();
+
+        assert!(should_notify(&mut last_shown, 1, start, window));
+        assert!(!should_notify(&mut last_shown, 1, start + Duration::from_secs(29), window));
+        assert!(should_notify(&mut last_shown, 2, start + Duration::from_secs(29), window));
+        assert!(should_notify(&mut last_shown, 1, start + Duration::from_secs(30), window));
+        assert!(!should_notify(&mut last_shown, 1, start + Duration::from_secs(45), window));
+
+        let action = RuleAction {
+            parameters: serde_json::json!({ "title": "Cortex", "body": "Leaving {{domain}}?" }),
+            action_type: "notification".to_string(),
+        };
+        assert_eq!(
+            notification_action(&action, &sample_log()).unwrap(),
+            NotificationAction {
+                title: "Cortex".to_string(),
+                body: "Leaving youtube.com?".to_string(),
+                icon: None,
+            }
+        );
+        assert!(validate_action_config(&[action]).is_empty());
+        let empty = RuleAction {
+            parameters: serde_json::json!({ "title": "Cortex" }),
+            action_type: "notification".to_string(),
+        };
+        let errors = validate_action_config(&[empty]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "body");
+    }
+
+    #[test]
+    fn tab_actions_target_the_matching_tab() {
+        let action = |action_type: &str, parameters: serde_json::Value| RuleAction {
+            parameters,
+            action_type: action_type.to_string(),
+        };
+
+        let close = extension_command(&action("close_tab", serde_json::json!({})), &sample_log());
+        assert_eq!(
+            close.unwrap(),
+            ExtensionCommand {
+                action: TabAction::CloseTab,
+                tab_url: "https://youtube.com/watch?v=xyz".to_string(),
+            }
+        );
+        let redirect = action(
+            "redirect",
+            serde_json::json!({ "target_url": "https://todoist.com/?from={{activity}}" }),
+        );
+        assert_eq!(
+            extension_command(&redirect, &sample_log()).unwrap().action,
+            TabAction::Redirect {
+                url: "https://todoist.com/?from=watching_videos".to_string(),
+            }
+        );
+        assert!(extension_command(&action("redirect", serde_json::json!({})), &sample_log())
+            .is_err());
+    }
+}
@@ -0,0 +1,2030 @@
+use crate::config::DomainCategoryMap;
+use crate::database::{ActionLog, ActivityRecord, Rule, RuleMatchRecord};
+use crate::rule_engine::{rule_covers_domain, schedule_allows};
+use crate::ExtensionLog;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DomainProductivityDay {
+    pub date: String,
+    pub record_count: u32,
+    pub productive_count: u32,
+    pub productive_fraction: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UrlVisitSummary {
+    pub url: String,
+    pub domain: String,
+    pub visit_count: u32,
+    pub title: String,
+    pub last_visited: f64,
+}
+
+// A run of consecutive productive records, found after the fact; unrelated to
+// the focus sessions users start in focus_mode
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProductiveRun {
+    pub start_ms: f64,
+    pub end_ms: f64,
+    pub minutes: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GoalProgress {
+    pub goal_minutes: u32,
+    pub achieved_minutes: u32,
+    pub fraction_complete: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DailyFocusReport {
+    pub date: String,
+    pub focus_sessions: Vec<ProductiveRun>,
+    pub total_focus_minutes: u32,
+    pub rules_triggered: u32,
+    pub top_distracting_domain: Option<String>,
+    pub productivity_score: f32,
+    pub goal_progress: Option<GoalProgress>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleRecommendation {
+    pub suggested_natural_language: String,
+    pub affected_domain: String,
+    pub trigger_frequency_estimate: u32,
+    pub rationale: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityTypeBreakdown {
+    pub activity: String,
+    pub count: u32,
+    pub fraction: f32,
+    pub top_domain: String,
+    pub example_title: String,
+}
+
+// matrix[domain_idx][rule_idx] is true when the rule refers to the domain or
+// has triggered on it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DomainRuleMatrix {
+    pub domains: Vec<String>,
+    pub rules: Vec<(i64, String)>,
+    pub matrix: Vec<Vec<bool>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityCorrelation {
+    pub activity_a: String,
+    pub activity_b: String,
+    pub co_occurrence_count: u32,
+    pub correlation_score: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InactiveRule {
+    #[serde(flatten)]
+    pub rule: Rule,
+    pub days_since_creation: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CooldownRecommendation {
+    pub rule_id: i64,
+    pub rule_name: String,
+    pub current_cooldown_seconds: Option<u64>,
+    pub recommended_cooldown_seconds: u64,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DomainTimeRanking {
+    pub domain: String,
+    pub estimated_time_ms: f64,
+    pub visit_count: u32,
+    pub productive_fraction: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DomainTimeStat {
+    pub domain: String,
+    pub total_seconds: f64,
+    pub visit_count: u32,
+    pub last_visit_ms: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppTimeStat {
+    pub app: String,
+    pub total_seconds: f64,
+}
+
+// Productive records count as productive and non-productive records on a
+// distraction domain as unproductive; everything else is neutral
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProductivitySummary {
+    pub productive_seconds: f64,
+    pub unproductive_seconds: f64,
+    pub neutral_seconds: f64,
+    pub productivity_score: f64,
+    pub top_productive_apps: Vec<AppTimeStat>,
+    pub top_unproductive_domains: Vec<DomainTimeStat>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TriggerForecast {
+    pub predicted_next_trigger: Option<i64>,
+    pub confidence: f32,
+    pub based_on_records: u32,
+    pub typical_triggers_per_day: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActionSuccessRate {
+    pub total_fires: u32,
+    pub succeeded: u32,
+    pub failed: u32,
+    pub success_rate: f32,
+    pub most_recent_error: Option<String>,
+    // Only webhook actions; None when there are none with a recorded latency
+    pub average_latency_ms: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoricalContext {
+    pub active_rules_at_time: Vec<Rule>,
+    pub logs_before_timestamp: Vec<ExtensionLog>,
+    pub productivity_score_at_time: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HourlyTriggerCount {
+    pub hour: u8,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AnomalyType {
+    UnusualSpike,
+    LateNightActivity,
+    LongSessionOutlier,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityAnomaly {
+    pub domain: String,
+    pub anomaly_type: AnomalyType,
+    pub severity: f32,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SentimentBucket {
+    pub hour_start_ms: f64,
+    pub average_sentiment: f32,
+    pub scored_logs: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActiveMinuteBucket {
+    pub minute_start: f64,
+    pub event_count: u32,
+    pub dominant_domain: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DayOfWeekSummary {
+    pub day: u8,
+    pub label: String,
+    pub total_records: u32,
+    pub productive_records: u32,
+    pub avg_records_per_week: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ElementsKeyFrequency {
+    pub key: String,
+    pub occurrence_count: u32,
+    pub domain_count: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FocusRecommendation {
+    pub recommended: bool,
+    pub suggested_duration_minutes: u32,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DomainNode {
+    pub domain: String,
+    pub visit_count: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DomainEdge {
+    pub from_domain: String,
+    pub to_domain: String,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DomainGraph {
+    pub nodes: Vec<DomainNode>,
+    pub edges: Vec<DomainEdge>,
+}
+
+// Domains visited more often than this per day are worth a rule
+const RECOMMENDATION_DAILY_VISIT_THRESHOLD: f64 = 10.0;
+
+// Thresholds for recommending focus mode
+const FOCUS_MAX_DISTRACTING_DOMAINS: usize = 3;
+const FOCUS_MIN_PRODUCTIVITY: f32 = 0.4;
+const FOCUS_MAX_RECENT_TRIGGERS: usize = 5;
+const FOCUS_BASE_MINUTES: u32 = 25;
+const FOCUS_EXTRA_MINUTES_PER_REASON: u32 = 15;
+
+// Rules re-triggering faster than this (median) get a cooldown recommendation
+const COOLDOWN_MEDIAN_THRESHOLD_SECONDS: i64 = 60;
+const COOLDOWN_MEDIAN_MULTIPLIER: u64 = 5;
+
+// Time credited to each extension log event for domains without recorded
+// activity durations
+const ESTIMATED_MS_PER_LOG_EVENT: f64 = 30_000.0;
+
+// Forecasts use this many of a rule's most recent matches; confidence stays
+// below 0.3 until there are at least FORECAST_MIN_CONFIDENT_MATCHES
+const FORECAST_MAX_MATCHES: usize = 30;
+const FORECAST_MIN_CONFIDENT_MATCHES: usize = 5;
+
+// Hours and sessions this many standard deviations above the mean are
+// anomalous; severity reaches 1.0 at ANOMALY_MAX_Z
+const ANOMALY_Z_THRESHOLD: f64 = 2.0;
+const ANOMALY_MAX_Z: f64 = 4.0;
+const MAX_ANOMALIES: usize = 20;
+
+// Local hours counted as night, and the fewest night events worth flagging
+const NIGHT_END_HOUR: u32 = 5;
+const LATE_NIGHT_MIN_EVENTS: u32 = 3;
+
+// Enabled rules older than this that have never triggered are reported
+const INACTIVE_RULE_MIN_AGE_DAYS: i64 = 7;
+
+// Activity pairs sharing fewer windows than this are not reported
+const MIN_CO_OCCURRENCES: u32 = 3;
+
+// Productive records further apart than this start a new focus session
+const FOCUS_SESSION_GAP_MS: f64 = 5.0 * 60_000.0;
+
+// Historical contexts include this many logs and score productivity over the
+// activities recorded in the preceding window
+const HISTORICAL_CONTEXT_LOGS: usize = 20;
+const HISTORICAL_PRODUCTIVITY_WINDOW_MS: f64 = 3_600_000.0;
+
+// Gaps between activity records longer than this are idle time, and the
+// summary lists this many apps and domains
+const PRODUCTIVITY_IDLE_GAP_MS: f64 = 5.0 * 60_000.0;
+const PRODUCTIVITY_TOP_LIMIT: usize = 5;
+
+// Rules created within this many seconds get a relevance bonus
+const RECENT_RULE_WINDOW_SECONDS: i64 = 7 * 86_400;
+const RECENT_RULE_BONUS: u32 = 1;
+
+// Timestamps are milliseconds since the Unix epoch
+pub fn utc_date(timestamp_ms: f64) -> Option<chrono::NaiveDate> {
+    chrono::DateTime::from_timestamp_millis(timestamp_ms as i64).map(|time| time.date_naive())
+}
+
+// Groups a domain's activity records by UTC calendar day. Days without records
+// are omitted, so the series is sparse and sorted oldest first.
+pub fn domain_productivity_history(
+    activities: &[ActivityRecord],
+    domain: &str,
+) -> Vec<DomainProductivityDay> {
+    let mut days: BTreeMap<chrono::NaiveDate, (u32, u32)> = BTreeMap::new();
+
+    for activity in activities {
+        let matches_domain = activity
+            .domain
+            .as_deref()
+            .is_some_and(|activity_domain| activity_domain.eq_ignore_ascii_case(domain));
+        if !matches_domain {
+            continue;
+        }
+
+        if let Some(date) = utc_date(activity.timestamp) {
+            let (total, productive) = days.entry(date).or_insert((0, 0));
+            *total += 1;
+            if activity.productive {
+                *productive += 1;
+            }
+        }
+    }
+
+    days.into_iter()
+        .map(|(date, (record_count, productive_count))| DomainProductivityDay {
+            date: date.format("%Y-%m-%d").to_string(),
+            record_count,
+            productive_count,
+            productive_fraction: productive_count as f32 / record_count as f32,
+        })
+        .collect()
+}
+
+// URLs are compared exactly, so differing query strings count as separate pages
+pub fn most_visited_urls(logs: &[ExtensionLog], since_ms: f64, limit: usize) -> Vec<UrlVisitSummary> {
+    let mut visits: HashMap<&str, UrlVisitSummary> = HashMap::new();
+
+    for log in logs.iter().filter(|log| log.timestamp >= since_ms) {
+        let summary = visits.entry(log.url.as_str()).or_insert_with(|| UrlVisitSummary {
+            url: log.url.clone(),
+            domain: log.domain.clone(),
+            visit_count: 0,
+            title: log.title.clone(),
+            last_visited: log.timestamp,
+        });
+        summary.visit_count += 1;
+        if log.timestamp >= summary.last_visited {
+            summary.last_visited = log.timestamp;
+            summary.title = log.title.clone();
+        }
+    }
+
+    let mut summaries: Vec<UrlVisitSummary> = visits.into_values().collect();
+    summaries.sort_by(|a, b| {
+        b.visit_count
+            .cmp(&a.visit_count)
+            .then(b.last_visited.total_cmp(&a.last_visited))
+    });
+    summaries.truncate(limit);
+    summaries
+}
+
+// Treats each run of consecutive productive records as a focus session
+pub fn focus_sessions(activities: &[ActivityRecord]) -> Vec<ProductiveRun> {
+    let mut sorted: Vec<&ActivityRecord> = activities.iter().collect();
+    sorted.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+
+    let mut sessions = Vec::new();
+    let mut current: Option<(f64, f64)> = None;
+
+    for activity in sorted {
+        current = match (current, activity.productive) {
+            (Some((start, end)), true) if activity.timestamp - end <= FOCUS_SESSION_GAP_MS => {
+                Some((start, activity.timestamp))
+            }
+            (previous, productive) => {
+                if let Some((start, end)) = previous {
+                    sessions.push((start, end));
+                }
+                productive.then_some((activity.timestamp, activity.timestamp))
+            }
+        };
+    }
+    if let Some(session) = current {
+        sessions.push(session);
+    }
+
+    sessions
+        .into_iter()
+        .map(|(start_ms, end_ms)| ProductiveRun {
+            start_ms,
+            end_ms,
+            minutes: ((end_ms - start_ms) / 60_000.0).round() as u32,
+        })
+        .collect()
+}
+
+pub fn daily_focus_report(
+    date: chrono::NaiveDate,
+    activities: &[ActivityRecord],
+    rules_triggered: u32,
+    goal_minutes: Option<u32>,
+) -> DailyFocusReport {
+    let day_activities: Vec<ActivityRecord> = activities
+        .iter()
+        .filter(|activity| utc_date(activity.timestamp) == Some(date))
+        .cloned()
+        .collect();
+
+    let focus_sessions = focus_sessions(&day_activities);
+    let total_focus_minutes = focus_sessions.iter().map(|session| session.minutes).sum();
+
+    let mut distractions: HashMap<&str, u32> = HashMap::new();
+    for activity in day_activities.iter().filter(|activity| !activity.productive) {
+        if let Some(domain) = activity.domain.as_deref() {
+            *distractions.entry(domain).or_insert(0) += 1;
+        }
+    }
+    let top_distracting_domain = distractions
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(a.0)))
+        .map(|(domain, _)| domain.to_string());
+
+    let productive = day_activities.iter().filter(|activity| activity.productive).count();
+    let productivity_score = if day_activities.is_empty() {
+        0.0
+    } else {
+        productive as f32 / day_activities.len() as f32
+    };
+
+    let goal_progress = goal_minutes.filter(|goal| *goal > 0).map(|goal_minutes| GoalProgress {
+        goal_minutes,
+        achieved_minutes: total_focus_minutes,
+        fraction_complete: (total_focus_minutes as f32 / goal_minutes as f32).min(1.0),
+    });
+
+    DailyFocusReport {
+        date: date.format("%Y-%m-%d").to_string(),
+        focus_sessions,
+        total_focus_minutes,
+        rules_triggered,
+        top_distracting_domain,
+        productivity_score,
+        goal_progress,
+    }
+}
+
+// Suggests a time limit for every frequently visited domain that no rule covers
+pub fn rule_recommendations(logs: &[ExtensionLog], rules: &[Rule]) -> Vec<RuleRecommendation> {
+    let mut visits_by_domain: HashMap<&str, HashMap<chrono::NaiveDate, u32>> = HashMap::new();
+    for log in logs.iter().filter(|log| !log.domain.is_empty()) {
+        if let Some(date) = utc_date(log.timestamp) {
+            *visits_by_domain.entry(&log.domain).or_default().entry(date).or_insert(0) += 1;
+        }
+    }
+
+    let mut recommendations: Vec<RuleRecommendation> = visits_by_domain
+        .into_iter()
+        .filter_map(|(domain, days)| {
+            let total: u32 = days.values().sum();
+            let daily_average = total as f64 / days.len() as f64;
+            if daily_average <= RECOMMENDATION_DAILY_VISIT_THRESHOLD
+                || rules.iter().any(|rule| rule_covers_domain(rule, domain))
+            {
+                return None;
+            }
+
+            Some(RuleRecommendation {
+                suggested_natural_language: format!("Limit time on {}", domain),
+                affected_domain: domain.to_string(),
+                trigger_frequency_estimate: daily_average.round() as u32,
+                rationale: format!(
+                    "Visited about {:.0} times per day across {} day(s) with no rule covering it",
+                    daily_average,
+                    days.len()
+                ),
+            })
+        })
+        .collect();
+
+    recommendations.sort_by(|a, b| {
+        b.trigger_frequency_estimate
+            .cmp(&a.trigger_frequency_estimate)
+            .then_with(|| a.affected_domain.cmp(&b.affected_domain))
+    });
+    recommendations
+}
+
+// Orders rules by `triggers * 3 + covered top-3 domains * 2 + recency bonus`.
+// `matches` and `activities` should already be limited to the scoring window.
+pub fn rules_by_relevance(
+    rules: Vec<Rule>,
+    matches: &[RuleMatchRecord],
+    activities: &[ActivityRecord],
+    now_seconds: i64,
+) -> Vec<Rule> {
+    let mut trigger_counts: HashMap<i64, u32> = HashMap::new();
+    for record in matches {
+        *trigger_counts.entry(record.rule_id).or_insert(0) += 1;
+    }
+
+    let mut domain_counts: HashMap<&str, u32> = HashMap::new();
+    for domain in activities.iter().filter_map(|activity| activity.domain.as_deref()) {
+        *domain_counts.entry(domain).or_insert(0) += 1;
+    }
+    let mut top_domains: Vec<(&str, u32)> = domain_counts.into_iter().collect();
+    top_domains.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    top_domains.truncate(3);
+
+    let mut scored: Vec<(u32, Rule)> = rules
+        .into_iter()
+        .map(|rule| {
+            let triggers = trigger_counts.get(&rule.id).copied().unwrap_or(0);
+            let covered = top_domains
+                .iter()
+                .filter(|(domain, _)| rule_covers_domain(&rule, domain))
+                .count() as u32;
+            let recent_bonus = if now_seconds - rule.created_at <= RECENT_RULE_WINDOW_SECONDS {
+                RECENT_RULE_BONUS
+            } else {
+                0
+            };
+            (triggers * 3 + covered * 2 + recent_bonus, rule)
+        })
+        .collect();
+
+    scored.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| b.1.created_at.cmp(&a.1.created_at)));
+    scored.into_iter().map(|(_, rule)| rule).collect()
+}
+
+pub fn domain_rule_matrix(
+    logs: &[ExtensionLog],
+    rules: &[Rule],
+    matches: &[RuleMatchRecord],
+) -> DomainRuleMatrix {
+    let mut domains: Vec<String> = logs
+        .iter()
+        .filter(|log| !log.domain.is_empty())
+        .map(|log| log.domain.clone())
+        .collect();
+    domains.sort();
+    domains.dedup();
+
+    let matrix = domains
+        .iter()
+        .map(|domain| {
+            rules
+                .iter()
+                .map(|rule| {
+                    rule_covers_domain(rule, domain)
+                        || matches.iter().any(|record| {
+                            record.rule_id == rule.id && record.domain.eq_ignore_ascii_case(domain)
+                        })
+                })
+                .collect()
+        })
+        .collect();
+
+    DomainRuleMatrix {
+        domains,
+        rules: rules.iter().map(|rule| (rule.id, rule.name.clone())).collect(),
+        matrix,
+    }
+}
+
+// Counts how often two activities appear in the same fixed time window. The
+// score is the fraction of windows containing either activity that contain
+// both.
+pub fn activity_correlation(
+    activities: &[ActivityRecord],
+    window_minutes: u32,
+) -> Vec<ActivityCorrelation> {
+    let window_ms = window_minutes.max(1) as f64 * 60_000.0;
+    let mut windows: HashMap<i64, BTreeSet<&str>> = HashMap::new();
+    for activity in activities {
+        let window = (activity.timestamp / window_ms).floor() as i64;
+        windows.entry(window).or_default().insert(&activity.activity);
+    }
+
+    let mut window_counts: HashMap<&str, u32> = HashMap::new();
+    let mut pair_counts: HashMap<(&str, &str), u32> = HashMap::new();
+    for names in windows.values() {
+        let names: Vec<&str> = names.iter().copied().collect();
+        for (i, a) in names.iter().enumerate() {
+            *window_counts.entry(a).or_insert(0) += 1;
+            for b in &names[i + 1..] {
+                *pair_counts.entry((a, b)).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let mut correlations: Vec<ActivityCorrelation> = pair_counts
+        .into_iter()
+        .filter(|(_, count)| *count >= MIN_CO_OCCURRENCES)
+        .map(|((a, b), count)| {
+            let either = window_counts[a] + window_counts[b] - count;
+            ActivityCorrelation {
+                activity_a: a.to_string(),
+                activity_b: b.to_string(),
+                co_occurrence_count: count,
+                correlation_score: count as f32 / either as f32,
+            }
+        })
+        .collect();
+
+    correlations.sort_by(|a, b| {
+        b.correlation_score
+            .total_cmp(&a.correlation_score)
+            .then_with(|| b.co_occurrence_count.cmp(&a.co_occurrence_count))
+            .then_with(|| a.activity_a.cmp(&b.activity_a))
+            .then_with(|| a.activity_b.cmp(&b.activity_b))
+    });
+    correlations
+}
+
+pub fn inactive_rules(rules: Vec<Rule>, now_seconds: i64) -> Vec<InactiveRule> {
+    rules
+        .into_iter()
+        .filter(|rule| rule.is_active && rule.last_triggered_at.is_none())
+        .filter(|rule| now_seconds - rule.created_at > INACTIVE_RULE_MIN_AGE_DAYS * 86_400)
+        .map(|rule| InactiveRule {
+            days_since_creation: ((now_seconds - rule.created_at) / 86_400) as u32,
+            rule,
+        })
+        .collect()
+}
+
+pub fn cooldown_recommendations(
+    rules: &[Rule],
+    matches: &[RuleMatchRecord],
+) -> Vec<CooldownRecommendation> {
+    let mut match_times: HashMap<i64, Vec<i64>> = HashMap::new();
+    for record in matches {
+        match_times.entry(record.rule_id).or_default().push(record.matched_at);
+    }
+
+    rules
+        .iter()
+        .filter_map(|rule| {
+            let times = match_times.get_mut(&rule.id)?;
+            times.sort_unstable();
+            let mut intervals: Vec<i64> = times.windows(2).map(|pair| pair[1] - pair[0]).collect();
+            if intervals.is_empty() {
+                return None;
+            }
+            intervals.sort_unstable();
+            let median = intervals[intervals.len() / 2];
+            if median >= COOLDOWN_MEDIAN_THRESHOLD_SECONDS {
+                return None;
+            }
+
+            Some(CooldownRecommendation {
+                rule_id: rule.id,
+                rule_name: rule.name.clone(),
+                // Rules have no cooldown setting yet
+                current_cooldown_seconds: None,
+                recommended_cooldown_seconds: median.max(1) as u64 * COOLDOWN_MEDIAN_MULTIPLIER,
+                reason: format!(
+                    "Triggered {} times with a median of {}s between triggers",
+                    times.len(),
+                    median
+                ),
+            })
+        })
+        .collect()
+}
+
+// Ranks domains by time spent. Domains with activity durations are measured
+// from those; the rest are estimated from their extension log events.
+pub fn top_domains_by_time(
+    activities: &[ActivityRecord],
+    logs: &[ExtensionLog],
+    limit: usize,
+) -> Vec<DomainTimeRanking> {
+    #[derive(Default)]
+    struct Tally {
+        duration_ms: Option<f64>,
+        activity_count: u32,
+        productive_count: u32,
+        log_count: u32,
+    }
+
+    let mut domains: HashMap<&str, Tally> = HashMap::new();
+    for activity in activities {
+        let Some(domain) = activity.domain.as_deref().filter(|domain| !domain.is_empty()) else {
+            continue;
+        };
+        let tally = domains.entry(domain).or_default();
+        tally.activity_count += 1;
+        if activity.productive {
+            tally.productive_count += 1;
+        }
+        if let Some(duration_ms) = activity.duration_ms {
+            *tally.duration_ms.get_or_insert(0.0) += duration_ms;
+        }
+    }
+    for log in logs.iter().filter(|log| !log.domain.is_empty()) {
+        domains.entry(log.domain.as_str()).or_default().log_count += 1;
+    }
+
+    let mut rankings: Vec<DomainTimeRanking> = domains
+        .into_iter()
+        .map(|(domain, tally)| {
+            let (estimated_time_ms, visit_count) = match tally.duration_ms {
+                Some(duration_ms) => (duration_ms, tally.activity_count),
+                None => (tally.log_count as f64 * ESTIMATED_MS_PER_LOG_EVENT, tally.log_count),
+            };
+            DomainTimeRanking {
+                domain: domain.to_string(),
+                estimated_time_ms,
+                visit_count,
+                productive_fraction: if tally.activity_count == 0 {
+                    0.0
+                } else {
+                    tally.productive_count as f32 / tally.activity_count as f32
+                },
+            }
+        })
+        .collect();
+    rankings.sort_by(|a, b| {
+        b.estimated_time_ms.total_cmp(&a.estimated_time_ms).then_with(|| a.domain.cmp(&b.domain))
+    });
+    rankings.truncate(limit);
+    rankings
+}
+
+// Each event lasts until the next one, or until `until_ms` for the last, and
+// no single event is credited more than `max_dwell_seconds` so idle gaps
+// don't count. Consecutive events on one domain make a single visit; events
+// without a domain only end the one before.
+fn domain_time_stats(
+    mut events: Vec<(f64, Option<&str>)>,
+    until_ms: f64,
+    max_dwell_seconds: u32,
+) -> Vec<DomainTimeStat> {
+    events.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let max_dwell_ms = max_dwell_seconds as f64 * 1000.0;
+
+    let mut stats: HashMap<&str, DomainTimeStat> = HashMap::new();
+    let mut previous_domain = None;
+    for (index, (timestamp, domain)) in events.iter().enumerate() {
+        let end = events.get(index + 1).map_or(until_ms, |next| next.0);
+        if let Some(domain) = *domain {
+            let stat = stats.entry(domain).or_insert_with(|| DomainTimeStat {
+                domain: domain.to_string(),
+                total_seconds: 0.0,
+                visit_count: 0,
+                last_visit_ms: *timestamp,
+            });
+            stat.total_seconds += (end - timestamp).clamp(0.0, max_dwell_ms) / 1000.0;
+            stat.last_visit_ms = stat.last_visit_ms.max(*timestamp);
+            if previous_domain != Some(domain) {
+                stat.visit_count += 1;
+            }
+        }
+        previous_domain = *domain;
+    }
+
+    let mut stats: Vec<DomainTimeStat> = stats.into_values().collect();
+    stats.sort_by(|a, b| {
+        b.total_seconds.total_cmp(&a.total_seconds).then_with(|| a.domain.cmp(&b.domain))
+    });
+    stats
+}
+
+pub fn domain_time_stats_from_logs(
+    logs: &[ExtensionLog],
+    until_ms: f64,
+    max_dwell_seconds: u32,
+) -> Vec<DomainTimeStat> {
+    let events = logs
+        .iter()
+        .map(|log| (log.timestamp, Some(log.domain.as_str()).filter(|domain| !domain.is_empty())))
+        .collect();
+    domain_time_stats(events, until_ms, max_dwell_seconds)
+}
+
+pub fn domain_time_stats_from_activities(
+    activities: &[ActivityRecord],
+    until_ms: f64,
+    max_dwell_seconds: u32,
+) -> Vec<DomainTimeStat> {
+    let events = activities
+        .iter()
+        .map(|activity| {
+            let domain = activity.domain.as_deref().filter(|domain| !domain.is_empty());
+            (activity.timestamp, domain)
+        })
+        .collect();
+    domain_time_stats(events, until_ms, max_dwell_seconds)
+}
+
+// A record lasts for its recorded duration, or else until the next record
+// (or `until_ms`) unless that gap is idle time
+pub fn productivity_summary(
+    activities: &[ActivityRecord],
+    until_ms: f64,
+    categories: &DomainCategoryMap,
+) -> ProductivitySummary {
+    let mut activities: Vec<&ActivityRecord> = activities.iter().collect();
+    activities.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+
+    let mut productive_seconds = 0.0;
+    let mut unproductive_seconds = 0.0;
+    let mut neutral_seconds = 0.0;
+    let mut apps: HashMap<&str, f64> = HashMap::new();
+    let mut domains: HashMap<&str, DomainTimeStat> = HashMap::new();
+    let mut previous_domain = None;
+    for (index, activity) in activities.iter().enumerate() {
+        let next = activities.get(index + 1).map_or(until_ms, |next| next.timestamp);
+        let gap_ms = next - activity.timestamp;
+        let seconds = match activity.duration_ms {
+            Some(duration_ms) => duration_ms / 1000.0,
+            None if (0.0..=PRODUCTIVITY_IDLE_GAP_MS).contains(&gap_ms) => gap_ms / 1000.0,
+            None => 0.0,
+        };
+        let domain = activity
+            .domain
+            .as_deref()
+            .map(|domain| domain.trim_start_matches("www."))
+            .filter(|domain| !domain.is_empty());
+
+        if activity.productive {
+            productive_seconds += seconds;
+            *apps.entry(activity.app.as_str()).or_insert(0.0) += seconds;
+        } else if let Some(domain) = domain.filter(|domain| categories.is_distraction(domain)) {
+            unproductive_seconds += seconds;
+            let stat = domains.entry(domain).or_insert_with(|| DomainTimeStat {
+                domain: domain.to_string(),
+                total_seconds: 0.0,
+                visit_count: 0,
+                last_visit_ms: activity.timestamp,
+            });
+            stat.total_seconds += seconds;
+            stat.last_visit_ms = activity.timestamp;
+            if previous_domain != Some(domain) {
+                stat.visit_count += 1;
+            }
+        } else {
+            neutral_seconds += seconds;
+        }
+        previous_domain = domain;
+    }
+
+    let counted = productive_seconds + unproductive_seconds;
+    let productivity_score = if counted > 0.0 {
+        (productive_seconds / counted).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+
+    let mut top_productive_apps: Vec<AppTimeStat> = apps
+        .into_iter()
+        .map(|(app, total_seconds)| AppTimeStat {
+            app: app.to_string(),
+            total_seconds,
+        })
+        .collect();
+    top_productive_apps.sort_by(|a, b| {
+        b.total_seconds.total_cmp(&a.total_seconds).then_with(|| a.app.cmp(&b.app))
+    });
+    top_productive_apps.truncate(PRODUCTIVITY_TOP_LIMIT);
+
+    let mut top_unproductive_domains: Vec<DomainTimeStat> = domains.into_values().collect();
+    top_unproductive_domains.sort_by(|a, b| {
+        b.total_seconds.total_cmp(&a.total_seconds).then_with(|| a.domain.cmp(&b.domain))
+    });
+    top_unproductive_domains.truncate(PRODUCTIVITY_TOP_LIMIT);
+
+    ProductivitySummary {
+        productive_seconds,
+        unproductive_seconds,
+        neutral_seconds,
+        productivity_score,
+        top_productive_apps,
+        top_unproductive_domains,
+    }
+}
+
+// Predicts the next trigger as the latest match plus the median gap between
+// recent matches. `matches` should only hold the rule's own records.
+pub fn trigger_forecast(matches: &[RuleMatchRecord]) -> TriggerForecast {
+    let mut times: Vec<i64> = matches.iter().map(|record| record.matched_at).collect();
+    times.sort_unstable();
+    let recent = &times[times.len().saturating_sub(FORECAST_MAX_MATCHES)..];
+
+    let mut intervals: Vec<i64> = recent.windows(2).map(|pair| pair[1] - pair[0]).collect();
+    intervals.sort_unstable();
+    let median = intervals.get(intervals.len() / 2).copied();
+
+    let count = recent.len();
+    let confidence = if count < FORECAST_MIN_CONFIDENT_MATCHES {
+        count as f32 * 0.05
+    } else {
+        let extra = (count - FORECAST_MIN_CONFIDENT_MATCHES) as f32;
+        0.3 + 0.7 * extra / (FORECAST_MAX_MATCHES - FORECAST_MIN_CONFIDENT_MATCHES) as f32
+    };
+
+    TriggerForecast {
+        predicted_next_trigger: median.zip(recent.last()).map(|(median, last)| last + median),
+        confidence,
+        based_on_records: count as u32,
+        typical_triggers_per_day: median.map_or(0.0, |median| 86_400.0 / median.max(1) as f32),
+    }
+}
+
+// A rule that never fired counts as fully reliable
+pub fn action_success_rate(logs: &[ActionLog]) -> ActionSuccessRate {
+    let total_fires = logs.len() as u32;
+    let succeeded = logs.iter().filter(|log| log.succeeded).count() as u32;
+    let most_recent_error = logs
+        .iter()
+        .filter(|log| !log.succeeded)
+        .max_by_key(|log| (log.fired_at, log.id))
+        .and_then(|log| log.error.clone());
+
+    let latencies: Vec<f64> = logs
+        .iter()
+        .filter(|log| matches!(log.action_type.as_str(), "webhook" | "send_webhook"))
+        .filter_map(|log| log.latency_ms)
+        .collect();
+    let average_latency_ms = (!latencies.is_empty())
+        .then(|| latencies.iter().sum::<f64>() / latencies.len() as f64);
+
+    ActionSuccessRate {
+        total_fires,
+        succeeded,
+        failed: total_fires - succeeded,
+        success_rate: if total_fires == 0 { 1.0 } else { succeeded as f32 / total_fires as f32 },
+        most_recent_error,
+        average_latency_ms,
+    }
+}
+
+// Deleted rules and past toggles are not recorded, so a rule counts as active
+// at `timestamp_ms` if it existed then, is active now and its schedule allowed
+// it. `logs` should be in arrival order.
+pub fn historical_context(
+    timestamp_ms: f64,
+    rules: &[Rule],
+    logs: &[ExtensionLog],
+    activities: &[ActivityRecord],
+) -> HistoricalContext {
+    let timestamp_seconds = (timestamp_ms / 1000.0).floor() as i64;
+    let active_rules_at_time = rules
+        .iter()
+        .filter(|rule| rule.is_active && rule.created_at <= timestamp_seconds)
+        .filter(|rule| !rule.is_expired(timestamp_seconds))
+        .filter(|rule| schedule_allows(rule, timestamp_ms))
+        .cloned()
+        .collect();
+
+    let before: Vec<&ExtensionLog> =
+        logs.iter().filter(|log| log.timestamp <= timestamp_ms).collect();
+    let logs_before_timestamp = before[before.len().saturating_sub(HISTORICAL_CONTEXT_LOGS)..]
+        .iter()
+        .map(|log| (*log).clone())
+        .collect();
+
+    let window_start = timestamp_ms - HISTORICAL_PRODUCTIVITY_WINDOW_MS;
+    let window: Vec<&ActivityRecord> = activities
+        .iter()
+        .filter(|activity| activity.timestamp > window_start && activity.timestamp <= timestamp_ms)
+        .collect();
+    let productive = window.iter().filter(|activity| activity.productive).count();
+    let productivity_score_at_time = if window.is_empty() {
+        0.0
+    } else {
+        productive as f32 / window.len() as f32
+    };
+
+    HistoricalContext {
+        active_rules_at_time,
+        logs_before_timestamp,
+        productivity_score_at_time,
+    }
+}
+
+// Always 24 buckets, by UTC hour of `matched_at`. `matches` should only hold
+// the rule's own records for the period of interest.
+pub fn hourly_trigger_heatmap(matches: &[RuleMatchRecord]) -> Vec<HourlyTriggerCount> {
+    let mut counts = [0u32; 24];
+    for record in matches {
+        counts[(record.matched_at.rem_euclid(86_400) / 3600) as usize] += 1;
+    }
+    counts
+        .iter()
+        .enumerate()
+        .map(|(hour, count)| HourlyTriggerCount {
+            hour: hour as u8,
+            count: *count,
+        })
+        .collect()
+}
+
+fn z_scores(values: &[f64]) -> Vec<f64> {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let std_dev = (values.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / n).sqrt();
+    values
+        .iter()
+        .map(|value| if std_dev == 0.0 { 0.0 } else { (value - mean) / std_dev })
+        .collect()
+}
+
+fn dominant_domain<'a>(domains: impl Iterator<Item = &'a str>) -> String {
+    let mut counts: HashMap<&str, u32> = HashMap::new();
+    for domain in domains {
+        *counts.entry(domain).or_insert(0) += 1;
+    }
+    counts
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(a.0)))
+        .map(|(domain, _)| domain.to_string())
+        .unwrap_or_default()
+}
+
+// Flags hours with unusually many events, nights busier than the user's
+// other nights, and sessions far longer than usual. `offset` is the user's
+// local time zone, which decides what counts as night.
+pub fn detect_anomalies(
+    logs: &[ExtensionLog],
+    offset: chrono::FixedOffset,
+) -> Vec<ActivityAnomaly> {
+    use chrono::Timelike;
+    const HOUR_MS: f64 = 3_600_000.0;
+
+    let mut sorted: Vec<&ExtensionLog> = logs.iter().collect();
+    sorted.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+    let (Some(first), Some(last)) = (sorted.first(), sorted.last()) else {
+        return Vec::new();
+    };
+    let mut anomalies = Vec::new();
+
+    // Spikes: every hour in the covered range counts, including empty ones
+    let first_hour = (first.timestamp / HOUR_MS).floor() as i64;
+    let last_hour = (last.timestamp / HOUR_MS).floor() as i64;
+    let mut hours: Vec<Vec<&str>> = vec![Vec::new(); (last_hour - first_hour + 1) as usize];
+    for log in &sorted {
+        let hour = (log.timestamp / HOUR_MS).floor() as i64;
+        hours[(hour - first_hour) as usize].push(log.domain.as_str());
+    }
+    let counts: Vec<f64> = hours.iter().map(|domains| domains.len() as f64).collect();
+    for (index, z) in z_scores(&counts).into_iter().enumerate() {
+        if z >= ANOMALY_Z_THRESHOLD {
+            anomalies.push(ActivityAnomaly {
+                domain: dominant_domain(hours[index].iter().copied()),
+                anomaly_type: AnomalyType::UnusualSpike,
+                severity: (z / ANOMALY_MAX_Z).min(1.0) as f32,
+                description: format!(
+                    "{} events in one hour, {:.1} standard deviations above normal",
+                    counts[index], z
+                ),
+            });
+        }
+    }
+
+    // Late nights: compare each night with the average of the other nights
+    let mut nights: BTreeMap<chrono::NaiveDate, Vec<&str>> = BTreeMap::new();
+    for log in &sorted {
+        let Some(time) = chrono::DateTime::from_timestamp_millis(log.timestamp as i64) else {
+            continue;
+        };
+        let local = time.with_timezone(&offset);
+        let night = nights.entry(local.date_naive()).or_default();
+        if local.hour() < NIGHT_END_HOUR {
+            night.push(log.domain.as_str());
+        }
+    }
+    let total_night_events: usize = nights.values().map(Vec::len).sum();
+    for (date, domains) in &nights {
+        let count = domains.len() as u32;
+        if count < LATE_NIGHT_MIN_EVENTS {
+            continue;
+        }
+        let other_nights = nights.len().saturating_sub(1).max(1) as f64;
+        let baseline = (total_night_events - domains.len()) as f64 / other_nights;
+        if count as f64 > 2.0 * baseline {
+            anomalies.push(ActivityAnomaly {
+                domain: dominant_domain(domains.iter().copied()),
+                anomaly_type: AnomalyType::LateNightActivity,
+                severity: (1.0 - baseline / count as f64).clamp(0.0, 1.0) as f32,
+                description: format!(
+                    "{} events between 00:00 and {:02}:00 on {} (typical night: {:.1})",
+                    count, NIGHT_END_HOUR, date, baseline
+                ),
+            });
+        }
+    }
+
+    // Long sessions: runs of one domain without a gap longer than five minutes
+    let mut sessions: Vec<(&str, f64, f64)> = Vec::new();
+    for log in &sorted {
+        match sessions.last_mut() {
+            Some((domain, _, end))
+                if *domain == log.domain && log.timestamp - *end <= FOCUS_SESSION_GAP_MS =>
+            {
+                *end = log.timestamp;
+            }
+            _ => sessions.push((log.domain.as_str(), log.timestamp, log.timestamp)),
+        }
+    }
+    let durations: Vec<f64> = sessions.iter().map(|(_, start, end)| end - start).collect();
+    for (index, z) in z_scores(&durations).into_iter().enumerate() {
+        if z >= ANOMALY_Z_THRESHOLD {
+            let (domain, _, _) = sessions[index];
+            anomalies.push(ActivityAnomaly {
+                domain: domain.to_string(),
+                anomaly_type: AnomalyType::LongSessionOutlier,
+                severity: (z / ANOMALY_MAX_Z).min(1.0) as f32,
+                description: format!(
+                    "{:.0} minute session, {:.1} standard deviations above normal",
+                    durations[index] / 60_000.0,
+                    z
+                ),
+            });
+        }
+    }
+
+    anomalies.sort_by(|a, b| b.severity.total_cmp(&a.severity));
+    anomalies.truncate(MAX_ANOMALIES);
+    anomalies
+}
+
+// Hourly average title sentiment since `since_ms`. Logs without a sentiment
+// score are left out, as are hours with no scored logs.
+pub fn sentiment_trend(logs: &[ExtensionLog], since_ms: f64) -> Vec<SentimentBucket> {
+    const HOUR_MS: f64 = 3_600_000.0;
+    let mut hours: BTreeMap<i64, (f32, u32)> = BTreeMap::new();
+    for log in logs.iter().filter(|log| log.timestamp >= since_ms) {
+        if let Some(sentiment) = log.title_sentiment {
+            let hour = (log.timestamp / HOUR_MS).floor() as i64;
+            let bucket = hours.entry(hour).or_insert((0.0, 0));
+            bucket.0 += sentiment;
+            bucket.1 += 1;
+        }
+    }
+
+    hours
+        .into_iter()
+        .map(|(hour, (total, count))| SentimentBucket {
+            hour_start_ms: hour as f64 * HOUR_MS,
+            average_sentiment: total / count as f32,
+            scored_logs: count,
+        })
+        .collect()
+}
+
+// `activities` should cover the last 30 minutes and `recent_triggers` the
+// last 15. Returns None when there is no recent activity to judge.
+pub fn focus_mode_recommendation(
+    activities: &[ActivityRecord],
+    categories: &DomainCategoryMap,
+    recent_triggers: usize,
+) -> Option<FocusRecommendation> {
+    if activities.is_empty() {
+        return None;
+    }
+
+    let mut distracting: Vec<&str> = activities
+        .iter()
+        .filter_map(|activity| activity.domain.as_deref())
+        .filter(|domain| categories.is_distraction(domain))
+        .collect();
+    distracting.sort();
+    distracting.dedup();
+    let productivity = activities.iter().filter(|activity| activity.productive).count() as f32
+        / activities.len() as f32;
+
+    let mut reasons = Vec::new();
+    if distracting.len() > FOCUS_MAX_DISTRACTING_DOMAINS {
+        reasons.push(format!("visited {} distracting sites", distracting.len()));
+    }
+    if productivity < FOCUS_MIN_PRODUCTIVITY {
+        reasons.push(format!("productivity is {:.0}%", productivity * 100.0));
+    }
+    if recent_triggers > FOCUS_MAX_RECENT_TRIGGERS {
+        reasons.push(format!("{} rules triggered in 15 minutes", recent_triggers));
+    }
+
+    Some(if reasons.is_empty() {
+        FocusRecommendation {
+            recommended: false,
+            suggested_duration_minutes: 0,
+            reason: "Recent activity looks focused".to_string(),
+        }
+    } else {
+        FocusRecommendation {
+            recommended: true,
+            suggested_duration_minutes: FOCUS_BASE_MINUTES
+                + FOCUS_EXTRA_MINUTES_PER_REASON * (reasons.len() as u32 - 1),
+            reason: format!("In the last 30 minutes you {}", reasons.join(", ")),
+        }
+    })
+}
+
+// An edge counts each time the user moved from one domain straight to a
+// different one. Edges seen fewer than `min_edge_weight` times are dropped.
+pub fn domain_navigation_graph(logs: &[ExtensionLog], min_edge_weight: u32) -> DomainGraph {
+    let mut sorted: Vec<&ExtensionLog> = logs.iter().filter(|log| !log.domain.is_empty()).collect();
+    sorted.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+
+    let mut visits: HashMap<&str, u32> = HashMap::new();
+    let mut transitions: HashMap<(&str, &str), u32> = HashMap::new();
+    let mut previous: Option<&str> = None;
+    for log in sorted {
+        let domain = log.domain.as_str();
+        *visits.entry(domain).or_insert(0) += 1;
+        if let Some(from) = previous.filter(|from| *from != domain) {
+            *transitions.entry((from, domain)).or_insert(0) += 1;
+        }
+        previous = Some(domain);
+    }
+
+    let mut nodes: Vec<DomainNode> = visits
+        .into_iter()
+        .map(|(domain, visit_count)| DomainNode {
+            domain: domain.to_string(),
+            visit_count,
+        })
+        .collect();
+    nodes.sort_by(|a, b| b.visit_count.cmp(&a.visit_count).then_with(|| a.domain.cmp(&b.domain)));
+
+    let mut edges: Vec<DomainEdge> = transitions
+        .into_iter()
+        .filter(|(_, count)| *count >= min_edge_weight)
+        .map(|((from, to), count)| DomainEdge {
+            from_domain: from.to_string(),
+            to_domain: to.to_string(),
+            count,
+        })
+        .collect();
+    edges.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then_with(|| a.from_domain.cmp(&b.from_domain))
+            .then_with(|| a.to_domain.cmp(&b.to_domain))
+    });
+
+    DomainGraph { nodes, edges }
+}
+
+// One entry per UTC weekday, Sunday first. Callers pass only the activities
+// from the last `weeks` weeks.
+pub fn activity_by_day_of_week(activities: &[ActivityRecord], weeks: u32) -> Vec<DayOfWeekSummary> {
+    use chrono::Datelike;
+    const LABELS: [&str; 7] =
+        ["Sunday", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday"];
+
+    let mut counts = [(0u32, 0u32); 7];
+    for activity in activities {
+        let Some(time) = chrono::DateTime::from_timestamp_millis(activity.timestamp as i64) else {
+            continue;
+        };
+        let day = &mut counts[time.weekday().num_days_from_sunday() as usize];
+        day.0 += 1;
+        if activity.productive {
+            day.1 += 1;
+        }
+    }
+
+    counts
+        .iter()
+        .zip(LABELS)
+        .enumerate()
+        .map(|(day, (&(total, productive), label))| DayOfWeekSummary {
+            day: day as u8,
+            label: label.to_string(),
+            total_records: total,
+            productive_records: productive,
+            avg_records_per_week: total as f32 / weeks.max(1) as f32,
+        })
+        .collect()
+}
+
+// The `top_n` busiest one-minute buckets since `since_ms`, busiest first. Ties
+// go to the earlier minute.
+pub fn most_active_minutes(
+    logs: &[ExtensionLog],
+    since_ms: f64,
+    top_n: usize,
+) -> Vec<ActiveMinuteBucket> {
+    const MINUTE_MS: f64 = 60_000.0;
+    let mut minutes: HashMap<i64, HashMap<&str, u32>> = HashMap::new();
+    for log in logs.iter().filter(|log| log.timestamp >= since_ms) {
+        let minute = (log.timestamp / MINUTE_MS).floor() as i64;
+        *minutes.entry(minute).or_default().entry(log.domain.as_str()).or_insert(0) += 1;
+    }
+
+    let mut buckets: Vec<ActiveMinuteBucket> = minutes
+        .into_iter()
+        .map(|(minute, domains)| {
+            let dominant_domain = domains
+                .iter()
+                .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+                .map(|(domain, _)| domain.to_string())
+                .unwrap_or_default();
+            ActiveMinuteBucket {
+                minute_start: minute as f64 * MINUTE_MS,
+                event_count: domains.values().sum(),
+                dominant_domain,
+            }
+        })
+        .collect();
+    buckets.sort_by(|a, b| {
+        b.event_count.cmp(&a.event_count).then_with(|| a.minute_start.total_cmp(&b.minute_start))
+    });
+    buckets.truncate(top_n);
+    buckets
+}
+
+// Counts the top-level keys of each log's `elements` object, most common
+// first, along with how many distinct domains use each key
+pub fn elements_key_frequency(
+    logs: &[ExtensionLog],
+    domain: Option<&str>,
+    limit: usize,
+) -> Vec<ElementsKeyFrequency> {
+    let mut keys: HashMap<&str, (u32, HashSet<&str>)> = HashMap::new();
+    for log in logs.iter().filter(|log| domain.is_none_or(|domain| log.domain == domain)) {
+        let Some(elements) = log.elements.as_ref().and_then(|elements| elements.as_object()) else {
+            continue;
+        };
+        for key in elements.keys() {
+            let entry = keys.entry(key.as_str()).or_default();
+            entry.0 += 1;
+            entry.1.insert(log.domain.as_str());
+        }
+    }
+
+    let mut frequencies: Vec<ElementsKeyFrequency> = keys
+        .into_iter()
+        .map(|(key, (occurrence_count, domains))| ElementsKeyFrequency {
+            key: key.to_string(),
+            occurrence_count,
+            domain_count: domains.len() as u32,
+        })
+        .collect();
+    frequencies.sort_by(|a, b| {
+        b.occurrence_count.cmp(&a.occurrence_count).then_with(|| a.key.cmp(&b.key))
+    });
+    frequencies.truncate(limit);
+    frequencies
+}
+
+pub fn compile_normalization(
+    patterns: &BTreeMap<String, String>,
+) -> Result<Vec<(Regex, String)>, String> {
+    patterns
+        .iter()
+        .map(|(pattern, replacement)| {
+            Regex::new(pattern)
+                .map(|regex| (regex, replacement.clone()))
+                .map_err(|e| format!("Invalid activity normalization pattern '{}': {}", pattern, e))
+        })
+        .collect()
+}
+
+pub fn activity_type_breakdown(
+    logs: &[ExtensionLog],
+    normalization: &[(Regex, String)],
+) -> Vec<ActivityTypeBreakdown> {
+    struct Tally<'a> {
+        count: u32,
+        domains: HashMap<&'a str, u32>,
+        latest: &'a ExtensionLog,
+    }
+
+    let mut tallies: HashMap<String, Tally> = HashMap::new();
+    for log in logs {
+        let mut activity = log.activity.clone();
+        for (regex, replacement) in normalization {
+            activity = regex.replace_all(&activity, replacement.as_str()).into_owned();
+        }
+
+        let tally = tallies.entry(activity).or_insert_with(|| Tally {
+            count: 0,
+            domains: HashMap::new(),
+            latest: log,
+        });
+        tally.count += 1;
+        *tally.domains.entry(&log.domain).or_insert(0) += 1;
+        if log.timestamp >= tally.latest.timestamp {
+            tally.latest = log;
+        }
+    }
+
+    let total = logs.len() as f32;
+    let mut breakdown: Vec<ActivityTypeBreakdown> = tallies
+        .into_iter()
+        .map(|(activity, tally)| {
+            let top_domain = tally
+                .domains
+                .into_iter()
+                .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(a.0)))
+                .map(|(domain, _)| domain.to_string())
+                .unwrap_or_default();
+            ActivityTypeBreakdown {
+                activity,
+                count: tally.count,
+                fraction: tally.count as f32 / total,
+                top_domain,
+                example_title: tally.latest.title.clone(),
+            }
+        })
+        .collect();
+
+    breakdown.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.activity.cmp(&b.activity)));
+    breakdown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY_MS: f64 = 86_400_000.0;
+
+    fn activity(timestamp: f64, domain: &str, productive: bool) -> ActivityRecord {
+        ActivityRecord {
+            id: 0,
+            timestamp,
+            activity: "browsing".to_string(),
+            productive,
+            app: "Chrome".to_string(),
+            bundle_id: None,
+            domain: Some(domain.to_string()),
+            notes: None,
+            duration_ms: None,
+        }
+    }
+
+    #[test]
+    fn productivity_history_is_sparse_and_grouped_by_day() {
+        let activities = vec![
+            activity(0.0, "github.com", true),
+            activity(1_000.0, "github.com", false),
+            activity(1_000.0, "reddit.com", false),
+            activity(2.0 * DAY_MS, "GitHub.com", true),
+        ];
+
+        let history = domain_productivity_history(&activities, "github.com");
+
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].date, "1970-01-01");
+        assert_eq!(history[0].record_count, 2);
+        assert_eq!(history[0].productive_fraction, 0.5);
+        assert_eq!(history[1].date, "1970-01-03");
+        assert_eq!(history[1].productive_count, 1);
+    }
+
+    #[test]
+    fn day_of_week_summary_always_has_seven_days() {
+        const DAY_MS: f64 = 86_400_000.0;
+        // 1970-01-01 was a Thursday
+        let activities = vec![
+            activity(0.0, "github.com", true),
+            activity(7.0 * DAY_MS, "reddit.com", false),
+            activity(3.0 * DAY_MS, "github.com", true),
+        ];
+
+        let summary = activity_by_day_of_week(&activities, 2);
+
+        assert_eq!(summary.len(), 7);
+        assert_eq!(summary[4].label, "Thursday");
+        assert_eq!(summary[4].total_records, 2);
+        assert_eq!(summary[4].productive_records, 1);
+        assert_eq!(summary[4].avg_records_per_week, 1.0);
+        assert_eq!(summary[0].total_records, 1);
+        assert_eq!(summary[1].total_records, 0);
+    }
+
+    #[test]
+    fn daily_report_splits_focus_sessions_on_gaps_and_distractions() {
+        const MINUTE_MS: f64 = 60_000.0;
+        let activities = vec![
+            activity(0.0, "github.com", true),
+            activity(4.0 * MINUTE_MS, "github.com", true),
+            activity(8.0 * MINUTE_MS, "github.com", true),
+            activity(9.0 * MINUTE_MS, "reddit.com", false),
+            activity(10.0 * MINUTE_MS, "github.com", true),
+            activity(30.0 * MINUTE_MS, "docs.rs", true),
+            activity(32.0 * MINUTE_MS, "docs.rs", true),
+        ];
+        let date = chrono::NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+
+        let report = daily_focus_report(date, &activities, 3, Some(20));
+
+        let minutes: Vec<u32> = report.focus_sessions.iter().map(|session| session.minutes).collect();
+        assert_eq!(minutes, vec![8, 0, 2]);
+        assert_eq!(report.total_focus_minutes, 10);
+        assert_eq!(report.rules_triggered, 3);
+        assert_eq!(report.top_distracting_domain.as_deref(), Some("reddit.com"));
+        assert_eq!(report.goal_progress.unwrap().fraction_complete, 0.5);
+    }
+
+    fn log(timestamp: f64, url: &str, title: &str) -> ExtensionLog {
+        ExtensionLog {
+            timestamp,
+            domain: "youtube.com".to_string(),
+            activity: "watching_videos".to_string(),
+            url: url.to_string(),
+            title: title.to_string(),
+            elements: None,
+            title_sentiment: None,
+            url_category: None,
+        }
+    }
+
+    #[test]
+    fn most_active_minutes_ranks_buckets_by_event_count() {
+        let mut logs = vec![
+            log(60_000.0, "https://youtube.com/", "YouTube"),
+            log(61_000.0, "https://reddit.com/", "Reddit"),
+            log(62_000.0, "https://reddit.com/r/rust", "r/rust"),
+            log(180_500.0, "https://youtube.com/", "YouTube"),
+            log(181_000.0, "https://youtube.com/", "YouTube"),
+            log(300_000.0, "https://youtube.com/", "YouTube"),
+        ];
+        logs[1].domain = "reddit.com".to_string();
+        logs[2].domain = "reddit.com".to_string();
+
+        let buckets = most_active_minutes(&logs, 0.0, 2);
+
+        let ranked: Vec<(f64, u32, &str)> = buckets
+            .iter()
+            .map(|bucket| (bucket.minute_start, bucket.event_count, bucket.dominant_domain.as_str()))
+            .collect();
+        assert_eq!(ranked, vec![(60_000.0, 3, "reddit.com"), (180_000.0, 2, "youtube.com")]);
+    }
+
+    #[test]
+    fn elements_keys_are_counted_per_log_and_domain() {
+        let mut logs = vec![
+            log(1.0, "https://youtube.com/watch?v=a", "Video"),
+            log(2.0, "https://youtube.com/watch?v=b", "Video"),
+            log(3.0, "https://reddit.com/", "Reddit"),
+        ];
+        logs[0].elements = Some(serde_json::json!({ "video_title": "a", "channel": "x" }));
+        logs[1].elements = Some(serde_json::json!({ "video_title": "b" }));
+        logs[2].domain = "reddit.com".to_string();
+        logs[2].elements = Some(serde_json::json!({ "video_title": "c", "subreddit": "rust" }));
+
+        let all = elements_key_frequency(&logs, None, 10);
+        let youtube = elements_key_frequency(&logs, Some("youtube.com"), 1);
+
+        let counts: Vec<(&str, u32, u32)> = all
+            .iter()
+            .map(|key| (key.key.as_str(), key.occurrence_count, key.domain_count))
+            .collect();
+        assert_eq!(counts, vec![("video_title", 3, 2), ("channel", 1, 1), ("subreddit", 1, 1)]);
+        assert_eq!(youtube.len(), 1);
+        assert_eq!(youtube[0].occurrence_count, 2);
+    }
+
+    #[test]
+    fn domain_time_prefers_durations_over_log_counts() {
+        let activities = vec![
+            ActivityRecord {
+                duration_ms: Some(7_200_000.0),
+                ..activity(0.0, "docs.rs", true)
+            },
+            activity(1.0, "docs.rs", false),
+        ];
+        let mut logs: Vec<ExtensionLog> =
+            (0..20).map(|i| log(i as f64, "https://youtube.com/", "YouTube")).collect();
+        logs[0].domain = "docs.rs".to_string();
+
+        let rankings = top_domains_by_time(&activities, &logs, 10);
+
+        let ranked: Vec<(&str, f64, u32)> = rankings
+            .iter()
+            .map(|ranking| (ranking.domain.as_str(), ranking.estimated_time_ms, ranking.visit_count))
+            .collect();
+        assert_eq!(ranked, vec![("docs.rs", 7_200_000.0, 2), ("youtube.com", 570_000.0, 19)]);
+        assert_eq!(rankings[0].productive_fraction, 0.5);
+        assert_eq!(rankings[1].productive_fraction, 0.0);
+    }
+
+    #[test]
+    fn domain_time_stats_cap_idle_gaps_and_count_visits() {
+        let mut logs: Vec<ExtensionLog> = [0.0, 60_000.0, 90_000.0, 150_000.0, 900_000.0]
+            .iter()
+            .map(|timestamp| log(*timestamp, "https://youtube.com/", "YouTube"))
+            .collect();
+        logs[2].domain = "github.com".to_string();
+
+        let stats = domain_time_stats_from_logs(&logs, 960_000.0, 300);
+
+        let summary: Vec<(&str, f64, u32, f64)> = stats
+            .iter()
+            .map(|stat| {
+                (stat.domain.as_str(), stat.total_seconds, stat.visit_count, stat.last_visit_ms)
+            })
+            .collect();
+        // youtube: 60s + 30s, then 750s capped to 300s, then 60s until the end
+        assert_eq!(
+            summary,
+            vec![("youtube.com", 450.0, 2, 900_000.0), ("github.com", 60.0, 1, 90_000.0)]
+        );
+    }
+
+    #[test]
+    fn domain_time_stats_from_activities_skip_records_without_a_domain() {
+        let activities = vec![
+            activity(0.0, "docs.rs", true),
+            ActivityRecord {
+                domain: None,
+                ..activity(120_000.0, "", true)
+            },
+            activity(180_000.0, "docs.rs", true),
+        ];
+
+        let stats = domain_time_stats_from_activities(&activities, 200_000.0, 300);
+
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].total_seconds, 140.0);
+        assert_eq!(stats[0].visit_count, 2);
+        assert_eq!(stats[0].last_visit_ms, 180_000.0);
+    }
+
+    #[test]
+    fn productivity_summary_scores_productive_time_and_skips_idle_gaps() {
+        let activities = vec![
+            ActivityRecord {
+                app: "Zed".to_string(),
+                domain: None,
+                ..activity(0.0, "", true)
+            },
+            activity(120_000.0, "docs.rs", true),
+            activity(180_000.0, "www.reddit.com", false),
+            activity(240_000.0, "example.com", false),
+            // Reached after a 10 minute idle gap, so example.com gets nothing
+            activity(840_000.0, "reddit.com", false),
+            ActivityRecord {
+                duration_ms: Some(30_000.0),
+                ..activity(900_000.0, "youtube.com", false)
+            },
+        ];
+
+        let summary =
+            productivity_summary(&activities, 1_000_000.0, &DomainCategoryMap::default());
+
+        assert_eq!(summary.productive_seconds, 180.0);
+        assert_eq!(summary.unproductive_seconds, 150.0);
+        assert_eq!(summary.neutral_seconds, 0.0);
+        assert_eq!(summary.productivity_score, 180.0 / 330.0);
+
+        let apps: Vec<(&str, f64)> = summary
+            .top_productive_apps
+            .iter()
+            .map(|app| (app.app.as_str(), app.total_seconds))
+            .collect();
+        assert_eq!(apps, vec![("Zed", 120.0), ("Chrome", 60.0)]);
+
+        let domains: Vec<(&str, f64, u32)> = summary
+            .top_unproductive_domains
+            .iter()
+            .map(|stat| (stat.domain.as_str(), stat.total_seconds, stat.visit_count))
+            .collect();
+        assert_eq!(domains, vec![("reddit.com", 120.0, 2), ("youtube.com", 30.0, 1)]);
+    }
+
+    #[test]
+    fn productivity_score_is_zero_without_classified_time() {
+        let activities = vec![activity(0.0, "example.com", false)];
+
+        let summary = productivity_summary(&activities, 60_000.0, &DomainCategoryMap::default());
+
+        assert_eq!(summary.neutral_seconds, 60.0);
+        assert_eq!(summary.productivity_score, 0.0);
+        assert!(summary.top_productive_apps.is_empty());
+    }
+
+    #[test]
+    fn anomalies_flag_spikes_long_sessions_and_late_nights() {
+        const HOUR_MS: f64 = 3_600_000.0;
+        // Day two at 10:00 UTC, well clear of the night hours
+        let start = 24.0 * HOUR_MS + 10.0 * HOUR_MS;
+        let mut logs: Vec<ExtensionLog> = (0..10)
+            .filter(|hour| *hour != 5)
+            .map(|hour| log(start + hour as f64 * HOUR_MS, "https://youtube.com/", "YouTube"))
+            .collect();
+        for minute in 0..10 {
+            let mut reddit = log(start + 5.0 * HOUR_MS + minute as f64 * 60_000.0, "", "");
+            reddit.domain = "reddit.com".to_string();
+            logs.push(reddit);
+        }
+        let utc = chrono::FixedOffset::east_opt(0).unwrap();
+
+        let anomalies = detect_anomalies(&logs, utc);
+
+        let found: Vec<(AnomalyType, &str)> = anomalies
+            .iter()
+            .map(|anomaly| (anomaly.anomaly_type, anomaly.domain.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (AnomalyType::UnusualSpike, "reddit.com"),
+                (AnomalyType::LongSessionOutlier, "reddit.com"),
+            ]
+        );
+        assert_eq!(anomalies[0].severity, 0.75);
+
+        // Three events at 02:00 local time (UTC+2) on an otherwise quiet day
+        let mut night: Vec<ExtensionLog> =
+            (0..3).map(|i| log(i as f64 * 60_000.0, "https://reddit.com/", "Reddit")).collect();
+        night.push(log(2.0 * 24.0 * HOUR_MS + 10.0 * HOUR_MS, "https://youtube.com/", "YouTube"));
+        let plus_two = chrono::FixedOffset::east_opt(2 * 3600).unwrap();
+        let anomalies = detect_anomalies(&night, plus_two);
+        assert!(anomalies
+            .iter()
+            .any(|anomaly| anomaly.anomaly_type == AnomalyType::LateNightActivity));
+    }
+
+    #[test]
+    fn activity_breakdown_normalizes_site_suffixes() {
+        let mut logs = vec![
+            log(1.0, "https://instagram.com/", "Instagram"),
+            log(2.0, "https://youtube.com/", "YouTube"),
+            log(3.0, "https://youtube.com/watch?v=a", "Video"),
+        ];
+        logs[0].activity = "scrolling_instagram".to_string();
+        logs[0].domain = "instagram.com".to_string();
+        logs[1].activity = "scrolling_youtube".to_string();
+        let patterns = crate::config::UserConfig::default().activity_normalization;
+        let normalization = compile_normalization(&patterns).unwrap();
+
+        let breakdown = activity_type_breakdown(&logs, &normalization);
+
+        assert_eq!(breakdown.len(), 2);
+        assert_eq!(breakdown[0].activity, "scrolling");
+        assert_eq!(breakdown[0].count, 2);
+        assert_eq!(breakdown[0].example_title, "YouTube");
+        assert_eq!(breakdown[1].activity, "watching_videos");
+        assert!((breakdown[1].fraction - 1.0 / 3.0).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn most_visited_urls_are_query_sensitive_and_keep_latest_title() {
+        let logs = vec![
+            log(1.0, "https://youtube.com/watch?v=a", "Old title"),
+            log(2.0, "https://youtube.com/watch?v=b", "Video B"),
+            log(3.0, "https://youtube.com/watch?v=a", "New title"),
+            log(4.0, "https://youtube.com/watch?v=c", "Video C"),
+        ];
+
+        let summaries = most_visited_urls(&logs, 0.0, 2);
+
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[0].url, "https://youtube.com/watch?v=a");
+        assert_eq!(summaries[0].visit_count, 2);
+        assert_eq!(summaries[0].title, "New title");
+        assert_eq!(summaries[0].last_visited, 3.0);
+        assert_eq!(summaries[1].url, "https://youtube.com/watch?v=c");
+    }
+
+    #[test]
+    fn cooldowns_are_recommended_for_rapidly_retriggering_rules() {
+        let rule = |id: i64| Rule {
+            id,
+            name: format!("rule {}", id),
+            natural_language: String::new(),
+            rule_json: "{}".to_string(),
+            is_active: true,
+            created_at: 0,
+            last_triggered_at: None,
+            hit_count: 0,
+            priority: 0,
+            tags: Vec::new(),
+            schedule: None,
+            expires_at: None,
+        };
+        let rule_match = |rule_id: i64, matched_at: i64| RuleMatchRecord {
+            id: 0,
+            rule_id,
+            matched_at,
+            domain: "reddit.com".to_string(),
+        };
+        let matches = vec![
+            rule_match(1, 100),
+            rule_match(1, 110),
+            rule_match(1, 300),
+            rule_match(1, 120),
+            rule_match(2, 0),
+            rule_match(2, 600),
+            rule_match(3, 50),
+        ];
+
+        let recommendations = cooldown_recommendations(&[rule(1), rule(2), rule(3)], &matches);
+
+        assert_eq!(recommendations.len(), 1);
+        assert_eq!(recommendations[0].rule_id, 1);
+        assert_eq!(recommendations[0].recommended_cooldown_seconds, 50);
+    }
+
+    #[test]
+    fn trigger_forecast_uses_median_gap_of_recent_matches() {
+        let rule_match = |matched_at: i64| RuleMatchRecord {
+            id: 0,
+            rule_id: 1,
+            matched_at,
+            domain: "reddit.com".to_string(),
+        };
+
+        let sparse = trigger_forecast(&[rule_match(0), rule_match(3600)]);
+        assert_eq!(sparse.predicted_next_trigger, Some(7200));
+        assert!(sparse.confidence < 0.3);
+        assert_eq!(sparse.typical_triggers_per_day, 24.0);
+
+        let matches: Vec<RuleMatchRecord> = (0..40).map(|i| rule_match(i * 600)).collect();
+        let dense = trigger_forecast(&matches);
+        assert_eq!(dense.based_on_records, 30);
+        assert_eq!(dense.predicted_next_trigger, Some(39 * 600 + 600));
+        assert_eq!(dense.confidence, 1.0);
+
+        assert_eq!(trigger_forecast(&[]).predicted_next_trigger, None);
+    }
+
+    #[test]
+    fn action_success_rate_tracks_failures_and_webhook_latency() {
+        let action_log = |id: i64, action_type: &str, error: Option<&str>, latency_ms: f64| {
+            ActionLog {
+                id,
+                rule_id: 1,
+                action_type: action_type.to_string(),
+                fired_at: id * 60,
+                succeeded: error.is_none(),
+                error: error.map(String::from),
+                domain: "reddit.com".to_string(),
+                latency_ms: Some(latency_ms),
+            }
+        };
+        let logs = vec![
+            action_log(1, "webhook", Some("timeout"), 100.0),
+            action_log(2, "webhook", None, 300.0),
+            action_log(3, "popup", Some("no window"), 5.0),
+            action_log(4, "popup", None, 1.0),
+        ];
+
+        let rate = action_success_rate(&logs);
+
+        assert_eq!(rate.total_fires, 4);
+        assert_eq!(rate.succeeded, 2);
+        assert_eq!(rate.failed, 2);
+        assert_eq!(rate.success_rate, 0.5);
+        assert_eq!(rate.most_recent_error.as_deref(), Some("no window"));
+        assert_eq!(rate.average_latency_ms, Some(200.0));
+
+        let idle = action_success_rate(&[]);
+        assert_eq!(idle.success_rate, 1.0);
+        assert_eq!(idle.average_latency_ms, None);
+    }
+
+    #[test]
+    fn historical_context_only_sees_the_past() {
+        let rule = |id: i64, created_at: i64, is_active: bool| Rule {
+            id,
+            name: format!("rule {}", id),
+            natural_language: String::new(),
+            rule_json: "{}".to_string(),
+            is_active,
+            created_at,
+            last_triggered_at: None,
+            hit_count: 0,
+            priority: 0,
+            tags: Vec::new(),
+            schedule: None,
+            expires_at: None,
+        };
+        let rules = vec![rule(1, 100, true), rule(2, 100, false), rule(3, 5000, true)];
+        let logs: Vec<ExtensionLog> =
+            (0..30).map(|i| log(i as f64 * 100_000.0, "https://youtube.com/", "")).collect();
+        let activities = vec![
+            activity(-2_000_000.0, "docs.rs", true),
+            activity(1_000_000.0, "docs.rs", true),
+            activity(1_500_000.0, "reddit.com", false),
+            activity(3_000_000.0, "reddit.com", false),
+        ];
+
+        let context = historical_context(2_000_000.0, &rules, &logs, &activities);
+
+        let rule_ids: Vec<i64> = context.active_rules_at_time.iter().map(|rule| rule.id).collect();
+        assert_eq!(rule_ids, [1]);
+        assert_eq!(context.logs_before_timestamp.len(), 20);
+        assert_eq!(context.logs_before_timestamp[0].timestamp, 100_000.0);
+        assert_eq!(context.logs_before_timestamp[19].timestamp, 2_000_000.0);
+        assert_eq!(context.productivity_score_at_time, 0.5);
+    }
+
+    #[test]
+    fn trigger_heatmap_buckets_matches_by_utc_hour() {
+        let rule_match = |matched_at: i64| RuleMatchRecord {
+            id: 0,
+            rule_id: 1,
+            matched_at,
+            domain: "reddit.com".to_string(),
+        };
+        let evening = 20 * 3600 + 59;
+        let matches = vec![rule_match(evening), rule_match(86_400 + evening), rule_match(3600)];
+
+        let heatmap = hourly_trigger_heatmap(&matches);
+
+        assert_eq!(heatmap.len(), 24);
+        assert_eq!(heatmap[20].hour, 20);
+        assert_eq!(heatmap[20].count, 2);
+        assert_eq!(heatmap[1].count, 1);
+        assert_eq!(heatmap.iter().map(|bucket| bucket.count).sum::<u32>(), 3);
+        assert!(hourly_trigger_heatmap(&[]).iter().all(|bucket| bucket.count == 0));
+    }
+
+    #[test]
+    fn relevance_weighs_triggers_above_domain_coverage() {
+        let rule = |id: i64, domain: &str, created_at: i64| Rule {
+            id,
+            name: format!("rule {}", id),
+            natural_language: String::new(),
+            rule_json: format!(
+                r#"{{"actions":[],"conditions":[{{"field":"domain","operator":"equals","value":"{}"}}],"name":"r","type":"basic"}}"#,
+                domain
+            ),
+            is_active: true,
+            created_at,
+            last_triggered_at: None,
+            hit_count: 0,
+            priority: 0,
+            tags: Vec::new(),
+            schedule: None,
+            expires_at: None,
+        };
+        let rules = vec![
+            rule(1, "reddit.com", 0),
+            rule(2, "news.com", 0),
+            rule(3, "example.com", 30 * DAY_MS as i64 / 1000),
+            rule(4, "youtube.com", 0),
+        ];
+        let matches = vec![RuleMatchRecord {
+            id: 1,
+            rule_id: 2,
+            matched_at: 0,
+            domain: "news.com".to_string(),
+        }];
+        let activities = vec![
+            activity(0.0, "reddit.com", false),
+            activity(0.0, "reddit.com", false),
+            activity(0.0, "youtube.com", false),
+        ];
+
+        let sorted = rules_by_relevance(rules, &matches, &activities, 30 * DAY_MS as i64 / 1000);
+
+        let ids: Vec<i64> = sorted.iter().map(|rule| rule.id).collect();
+        assert_eq!(ids, vec![2, 1, 4, 3]);
+    }
+
+    #[test]
+    fn correlation_requires_three_shared_windows() {
+        const MINUTE_MS: f64 = 60_000.0;
+        let named = |timestamp: f64, name: &str| ActivityRecord {
+            activity: name.to_string(),
+            ..activity(timestamp, "example.com", false)
+        };
+        let mut activities = Vec::new();
+        for window in 0..4 {
+            let start = window as f64 * 10.0 * MINUTE_MS;
+            activities.push(named(start, "email"));
+            activities.push(named(start + MINUTE_MS, "slack"));
+            if window < 2 {
+                activities.push(named(start + 2.0 * MINUTE_MS, "news"));
+            }
+        }
+        activities.push(named(100.0 * MINUTE_MS, "email"));
+
+        let correlations = activity_correlation(&activities, 10);
+
+        assert_eq!(correlations.len(), 1);
+        assert_eq!(correlations[0].activity_a, "email");
+        assert_eq!(correlations[0].activity_b, "slack");
+        assert_eq!(correlations[0].co_occurrence_count, 4);
+        assert_eq!(correlations[0].correlation_score, 0.8);
+    }
+
+    #[test]
+    fn focus_recommendation_lists_each_reason() {
+        let categories = DomainCategoryMap::default();
+        let distracted: Vec<ActivityRecord> = ["reddit.com", "m.youtube.com", "x.com", "amazon.com"]
+            .iter()
+            .map(|domain| activity(0.0, domain, false))
+            .collect();
+
+        let recommendation = focus_mode_recommendation(&distracted, &categories, 6).unwrap();
+        assert!(recommendation.recommended);
+        assert_eq!(recommendation.suggested_duration_minutes, 55);
+        assert_eq!(
+            recommendation.reason,
+            "In the last 30 minutes you visited 4 distracting sites, productivity is 0%, \
+             6 rules triggered in 15 minutes"
+        );
+
+        let focused = vec![activity(0.0, "github.com", true)];
+        assert!(!focus_mode_recommendation(&focused, &categories, 0).unwrap().recommended);
+        assert!(focus_mode_recommendation(&[], &categories, 0).is_none());
+    }
+
+    #[test]
+    fn navigation_graph_counts_consecutive_distinct_domains() {
+        let on = |timestamp: f64, domain: &str| ExtensionLog {
+            domain: domain.to_string(),
+            ..log(timestamp, "https://example.com", "")
+        };
+        let logs = vec![
+            on(5.0, "github.com"),
+            on(1.0, "reddit.com"),
+            on(2.0, "reddit.com"),
+            on(3.0, "github.com"),
+            on(4.0, "reddit.com"),
+        ];
+
+        let graph = domain_navigation_graph(&logs, 2);
+
+        assert_eq!(graph.nodes[0].domain, "reddit.com");
+        assert_eq!(graph.nodes[0].visit_count, 3);
+        assert_eq!(graph.edges.len(), 1);
+        assert_eq!(graph.edges[0].from_domain, "reddit.com");
+        assert_eq!(graph.edges[0].to_domain, "github.com");
+        assert_eq!(graph.edges[0].count, 2);
+    }
+}
@@ -0,0 +1,157 @@
+use crate::database::NewActivityRecord;
+use serde::{Deserialize, Serialize};
+
+// Chrome stores times as microseconds since 1601-01-01
+const WEBKIT_EPOCH_OFFSET_MS: f64 = 11_644_473_600_000.0;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum BrowserHistoryFormat {
+    ChromeCsv,
+    FirefoxJson,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImportSummary {
+    pub imported: u32,
+    pub skipped: u32,
+    pub errors: u32,
+}
+
+// Records ready to be stored, plus counts of entries that were left out.
+// Entries without a web URL are skipped; malformed entries are errors.
+#[derive(Debug, Default)]
+pub struct ParsedHistory {
+    pub records: Vec<NewActivityRecord>,
+    pub skipped: u32,
+    pub errors: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChromeHistoryRow {
+    url: String,
+    last_visit_time: i64,
+}
+
+pub fn parse_history(
+    contents: &str,
+    format: BrowserHistoryFormat,
+) -> Result<ParsedHistory, String> {
+    match format {
+        BrowserHistoryFormat::ChromeCsv => Ok(parse_chrome_csv(contents)),
+        BrowserHistoryFormat::FirefoxJson => parse_firefox_json(contents),
+    }
+}
+
+fn parse_chrome_csv(contents: &str) -> ParsedHistory {
+    let mut parsed = ParsedHistory::default();
+    let mut reader = csv::Reader::from_reader(contents.as_bytes());
+
+    for row in reader.deserialize::<ChromeHistoryRow>() {
+        match row {
+            Ok(row) => {
+                let timestamp = row.last_visit_time as f64 / 1000.0 - WEBKIT_EPOCH_OFFSET_MS;
+                push_entry(&mut parsed, &row.url, timestamp, "Chrome");
+            }
+            Err(e) => {
+                println!("Skipping malformed history row: {}", e);
+                parsed.errors += 1;
+            }
+        }
+    }
+    parsed
+}
+
+fn parse_firefox_json(contents: &str) -> Result<ParsedHistory, String> {
+    let root: serde_json::Value =
+        serde_json::from_str(contents).map_err(|e| format!("Invalid Firefox history JSON: {}", e))?;
+    let mut parsed = ParsedHistory::default();
+    collect_firefox_places(&root, &mut parsed);
+    Ok(parsed)
+}
+
+// places.json is a tree of folders (`children`) and entries (`uri`); times are
+// microseconds since the Unix epoch
+fn collect_firefox_places(node: &serde_json::Value, parsed: &mut ParsedHistory) {
+    if let Some(uri) = node.get("uri").and_then(|uri| uri.as_str()) {
+        let visited_at = ["lastVisitDate", "lastModified", "dateAdded"]
+            .iter()
+            .find_map(|key| node.get(*key).and_then(|time| time.as_f64()));
+        match visited_at {
+            Some(micros) => push_entry(parsed, uri, micros / 1000.0, "Firefox"),
+            None => parsed.errors += 1,
+        }
+    }
+
+    if let Some(children) = node.get("children").and_then(|children| children.as_array()) {
+        for child in children {
+            collect_firefox_places(child, parsed);
+        }
+    }
+}
+
+fn push_entry(parsed: &mut ParsedHistory, url: &str, timestamp: f64, app: &str) {
+    let Ok(url) = url::Url::parse(url) else {
+        parsed.errors += 1;
+        return;
+    };
+    let domain = match (url.scheme(), url.host_str()) {
+        ("http" | "https", Some(host)) => host.trim_start_matches("www.").to_string(),
+        _ => {
+            parsed.skipped += 1;
+            return;
+        }
+    };
+
+    parsed.records.push(NewActivityRecord {
+        timestamp,
+        activity: "browsing".to_string(),
+        productive: false,
+        app: app.to_string(),
+        bundle_id: None,
+        domain: Some(domain),
+        duration_ms: None,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chrome_csv_converts_webkit_times_and_skips_non_web_urls() {
+        let csv = "url,title,visit_count,typed_count,last_visit_time,hidden\n\
+                   https://www.github.com/rust-lang,\"Rust, the language\",3,1,13345000000000000,0\n\
+                   chrome://settings,Settings,1,0,13345000000000000,0\n\
+                   https://docs.rs,Docs,1,0,not-a-time,0\n";
+
+        let parsed = parse_history(csv, BrowserHistoryFormat::ChromeCsv).unwrap();
+
+        assert_eq!(parsed.records.len(), 1);
+        assert_eq!(parsed.records[0].domain.as_deref(), Some("github.com"));
+        assert_eq!(parsed.records[0].timestamp, 1_700_526_400_000.0);
+        assert_eq!(parsed.skipped, 1);
+        assert_eq!(parsed.errors, 1);
+    }
+
+    #[test]
+    fn firefox_json_walks_nested_folders() {
+        let json = r#"{
+            "title": "root",
+            "children": [
+                { "title": "Menu", "children": [
+                    { "uri": "https://reddit.com/r/rust", "lastModified": 1700000000000000 }
+                ]},
+                { "uri": "place:sort=8" },
+                { "uri": "https://news.ycombinator.com", "dateAdded": 1700000001000000 }
+            ]
+        }"#;
+
+        let parsed = parse_history(json, BrowserHistoryFormat::FirefoxJson).unwrap();
+
+        let domains: Vec<&str> =
+            parsed.records.iter().filter_map(|record| record.domain.as_deref()).collect();
+        assert_eq!(domains, vec!["reddit.com", "news.ycombinator.com"]);
+        assert_eq!(parsed.records[0].timestamp, 1_700_000_000_000.0);
+        assert_eq!(parsed.errors, 1);
+    }
+}
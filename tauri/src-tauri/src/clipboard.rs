@@ -0,0 +1,34 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+// The app only targets macOS, so the system pasteboard tools are enough
+pub fn write_text(text: &str) -> Result<(), String> {
+    let mut child = Command::new("pbcopy")
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to open clipboard: {}", e))?;
+    child
+        .stdin
+        .take()
+        .ok_or("Failed to open clipboard: no stdin")?
+        .write_all(text.as_bytes())
+        .map_err(|e| format!("Failed to write to clipboard: {}", e))?;
+
+    let status = child
+        .wait()
+        .map_err(|e| format!("Failed to write to clipboard: {}", e))?;
+    if !status.success() {
+        return Err(format!("Failed to write to clipboard: pbcopy exited with {}", status));
+    }
+    Ok(())
+}
+
+pub fn read_text() -> Result<String, String> {
+    let output = Command::new("pbpaste")
+        .output()
+        .map_err(|e| format!("Failed to read clipboard: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("Failed to read clipboard: pbpaste exited with {}", output.status));
+    }
+    String::from_utf8(output.stdout).map_err(|e| format!("Clipboard does not contain text: {}", e))
+}
@@ -0,0 +1,241 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::Path;
+
+const SERVER_CONFIG_FILE: &str = "server_config.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserConfig {
+    pub max_db_size_mb: u64,
+    pub max_rules: u64,
+    // Warning threshold for the activity count, 0 for none. Activities past
+    // `database::MAX_ACTIVITIES` are evicted, so the count settles there and
+    // any threshold at or below it warns for good once reached.
+    pub max_activities: u64,
+    pub daily_focus_goal_minutes: Option<u32>,
+    // Regex pattern -> replacement, applied in key order to activity names
+    // before they are grouped (e.g. `scrolling_instagram` -> `scrolling`)
+    pub activity_normalization: BTreeMap<String, String>,
+    pub domain_categories: DomainCategoryMap,
+    // Longest time a single visit is credited with in domain time stats
+    pub max_dwell_seconds: u32,
+    // Productivity score a day needs to extend the streak
+    pub streak_threshold: f64,
+    // A rule shows at most one notification within this window
+    pub notification_debounce_seconds: u32,
+}
+
+pub const DEFAULT_MAX_BODY_BYTES: usize = 1024 * 1024;
+
+// Where the extension bridge listens. `CORTEX_SERVER_PORT` overrides the
+// default port at startup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerConfig {
+    pub port: u16,
+    pub host: [u8; 4],
+    // Serve HTTPS with the app's self-signed certificate
+    #[serde(default)]
+    pub tls_enabled: bool,
+    // Largest extension data body accepted, measured after decompression
+    #[serde(default = "default_max_body_bytes")]
+    pub max_body_bytes: usize,
+}
+
+fn default_max_body_bytes() -> usize {
+    DEFAULT_MAX_BODY_BYTES
+}
+
+impl ServerConfig {
+    pub fn from_env() -> Self {
+        Self::with_port_override(std::env::var("CORTEX_SERVER_PORT").ok().as_deref())
+    }
+
+    fn with_port_override(port: Option<&str>) -> Self {
+        ServerConfig::default().override_port(port)
+    }
+
+    fn override_port(mut self, port: Option<&str>) -> Self {
+        match port.map(|port| port.trim().parse::<u16>()) {
+            Some(Ok(port)) if port != 0 => self.port = port,
+            Some(_) => eprintln!("Ignoring invalid CORTEX_SERVER_PORT, using {}", self.port),
+            None => {}
+        }
+        self
+    }
+
+    pub fn socket_addr(&self) -> SocketAddr {
+        (self.host, self.port).into()
+    }
+
+    pub fn scheme(&self) -> &'static str {
+        if self.tls_enabled {
+            "https"
+        } else {
+            "http"
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!("{}://{}", self.scheme(), self.socket_addr())
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            port: 8080,
+            host: [127, 0, 0, 1],
+            tls_enabled: false,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+        }
+    }
+}
+
+// A missing file means the defaults. `CORTEX_SERVER_PORT` still overrides
+// the saved port.
+pub fn load_server_config(data_dir: &Path) -> Result<ServerConfig, String> {
+    let saved = match std::fs::read_to_string(data_dir.join(SERVER_CONFIG_FILE)) {
+        Ok(json) => serde_json::from_str(&json)
+            .map_err(|e| format!("Failed to parse server config: {}", e))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => ServerConfig::default(),
+        Err(e) => return Err(format!("Failed to read server config: {}", e)),
+    };
+    Ok(saved.override_port(std::env::var("CORTEX_SERVER_PORT").ok().as_deref()))
+}
+
+pub fn save_server_config(data_dir: &Path, config: &ServerConfig) -> Result<(), String> {
+    std::fs::create_dir_all(data_dir)
+        .map_err(|e| format!("Failed to create app data directory: {}", e))?;
+    let json = serde_json::to_string_pretty(config)
+        .map_err(|e| format!("Failed to serialize server config: {}", e))?;
+    std::fs::write(data_dir.join(SERVER_CONFIG_FILE), json)
+        .map_err(|e| format!("Failed to save server config: {}", e))
+}
+
+// How many extension logs are kept in memory
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct LogsConfig {
+    pub capacity: usize,
+}
+
+pub const MAX_LOGS_CAPACITY: usize = 10_000;
+
+impl Default for LogsConfig {
+    fn default() -> Self {
+        LogsConfig { capacity: 100 }
+    }
+}
+
+// Domain -> category (e.g. `youtube.com` -> `video`). Subdomains inherit the
+// category of their parent domain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct DomainCategoryMap(pub BTreeMap<String, String>);
+
+// Categories whose domains count as distractions
+pub const DISTRACTION_CATEGORIES: [&str; 4] = ["news", "shopping", "social", "video"];
+
+impl DomainCategoryMap {
+    pub fn is_distraction(&self, domain: &str) -> bool {
+        self.category_for(domain)
+            .is_some_and(|category| DISTRACTION_CATEGORIES.contains(&category))
+    }
+
+    pub fn category_for(&self, domain: &str) -> Option<&str> {
+        let mut candidate = domain.trim_start_matches("www.").to_lowercase();
+        loop {
+            if let Some(category) = self.0.get(&candidate) {
+                return Some(category);
+            }
+            let (_, parent) = candidate.split_once('.')?;
+            candidate = parent.to_string();
+        }
+    }
+}
+
+impl Default for DomainCategoryMap {
+    fn default() -> Self {
+        let categories = [
+            ("facebook.com", "social"),
+            ("instagram.com", "social"),
+            ("linkedin.com", "social"),
+            ("reddit.com", "social"),
+            ("tiktok.com", "social"),
+            ("twitter.com", "social"),
+            ("x.com", "social"),
+            ("netflix.com", "video"),
+            ("twitch.tv", "video"),
+            ("youtube.com", "video"),
+            ("cnn.com", "news"),
+            ("news.ycombinator.com", "news"),
+            ("nytimes.com", "news"),
+            ("amazon.com", "shopping"),
+            ("ebay.com", "shopping"),
+            ("github.com", "development"),
+            ("stackoverflow.com", "development"),
+        ];
+        DomainCategoryMap(
+            categories
+                .iter()
+                .map(|(domain, category)| (domain.to_string(), category.to_string()))
+                .collect(),
+        )
+    }
+}
+
+impl Default for UserConfig {
+    fn default() -> Self {
+        UserConfig {
+            max_db_size_mb: 500,
+            max_rules: 500,
+            max_activities: 0,
+            daily_focus_goal_minutes: Some(120),
+            activity_normalization: BTreeMap::from([(
+                "_(instagram|youtube|twitter|x|facebook|reddit|tiktok|linkedin)$".to_string(),
+                String::new(),
+            )]),
+            domain_categories: DomainCategoryMap::default(),
+            max_dwell_seconds: 300,
+            streak_threshold: crate::streaks::DEFAULT_STREAK_THRESHOLD,
+            notification_debounce_seconds: 30,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_port_override_falls_back_to_default() {
+        assert_eq!(ServerConfig::with_port_override(None).url(), "http://127.0.0.1:8080");
+        assert_eq!(ServerConfig::with_port_override(Some(" 9090")).port, 9090);
+        assert_eq!(ServerConfig::with_port_override(Some("0")).port, 8080);
+        assert_eq!(ServerConfig::with_port_override(Some("http")).port, 8080);
+        let tls = ServerConfig {
+            tls_enabled: true,
+            ..ServerConfig::default()
+        };
+        assert_eq!(tls.url(), "https://127.0.0.1:8080");
+    }
+
+    #[test]
+    fn saved_server_config_is_loaded_back() {
+        let dir = std::env::temp_dir().join(format!(
+            "cortex-server-config-{}",
+            crate::websocket_server::generate_api_key()
+        ));
+        assert_eq!(load_server_config(&dir).unwrap(), ServerConfig::from_env());
+
+        let config = ServerConfig {
+            tls_enabled: true,
+            max_body_bytes: 4096,
+            ..ServerConfig::from_env()
+        };
+        save_server_config(&dir, &config).unwrap();
+        assert_eq!(load_server_config(&dir).unwrap(), config);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
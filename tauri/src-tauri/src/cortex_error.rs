@@ -0,0 +1,121 @@
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::sync::PoisonError;
+
+// Errors returned by the database and by every Tauri command. They reach the
+// frontend as `{ "type": "NotFound", "message": "Rule not found" }`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CortexError {
+    NotFound(String),
+    DatabaseError(String),
+    ValidationError(String),
+    IoError(String),
+    LockError,
+    // The schema could not be brought up to date; nothing was applied
+    MigrationFailed(String),
+}
+
+impl CortexError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CortexError::NotFound(_) => "NotFound",
+            CortexError::DatabaseError(_) => "DatabaseError",
+            CortexError::ValidationError(_) => "ValidationError",
+            CortexError::IoError(_) => "IoError",
+            CortexError::LockError => "LockError",
+            CortexError::MigrationFailed(_) => "MigrationFailed",
+        }
+    }
+
+    // Prefixes the message ("Failed to get rules: ...") and keeps the variant
+    pub fn context(self, context: &str) -> Self {
+        let wrap = |message: String| format!("{}: {}", context, message);
+        match self {
+            CortexError::NotFound(message) => CortexError::NotFound(wrap(message)),
+            CortexError::DatabaseError(message) => CortexError::DatabaseError(wrap(message)),
+            CortexError::ValidationError(message) => CortexError::ValidationError(wrap(message)),
+            CortexError::IoError(message) => CortexError::IoError(wrap(message)),
+            CortexError::LockError => CortexError::LockError,
+            CortexError::MigrationFailed(message) => CortexError::MigrationFailed(wrap(message)),
+        }
+    }
+}
+
+impl fmt::Display for CortexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CortexError::NotFound(message)
+            | CortexError::DatabaseError(message)
+            | CortexError::ValidationError(message)
+            | CortexError::IoError(message)
+            | CortexError::MigrationFailed(message) => write!(f, "{}", message),
+            CortexError::LockError => write!(f, "Lock poisoned"),
+        }
+    }
+}
+
+impl std::error::Error for CortexError {}
+
+impl Serialize for CortexError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("CortexError", 2)?;
+        state.serialize_field("type", self.kind())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
+
+impl<T> From<PoisonError<T>> for CortexError {
+    fn from(_: PoisonError<T>) -> Self {
+        CortexError::LockError
+    }
+}
+
+impl From<sqlx::Error> for CortexError {
+    fn from(e: sqlx::Error) -> Self {
+        CortexError::DatabaseError(e.to_string())
+    }
+}
+
+impl From<std::io::Error> for CortexError {
+    fn from(e: std::io::Error) -> Self {
+        CortexError::IoError(e.to_string())
+    }
+}
+
+// For internal helpers that still report plain strings
+impl From<CortexError> for String {
+    fn from(e: CortexError) -> Self {
+        e.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_with_type_and_message() {
+        let error =
+            CortexError::NotFound("Rule not found".to_string()).context("Failed to toggle rule");
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            serde_json::json!({
+                "type": "NotFound",
+                "message": "Failed to toggle rule: Rule not found",
+            })
+        );
+
+        let poisoned = std::sync::Mutex::new(0);
+        let _ = std::panic::catch_unwind(|| {
+            let _guard = poisoned.lock().unwrap();
+            panic!("poison the lock");
+        });
+        let error = CortexError::from(poisoned.lock().unwrap_err());
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            serde_json::json!({ "type": "LockError", "message": "Lock poisoned" })
+        );
+    }
+}
@@ -1,6 +1,24 @@
+use crate::cortex_error::CortexError;
+use crate::migrations::{MigrationRunner, MIGRATIONS};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use sqlx::sqlite::{
+    SqliteConnectOptions, SqliteConnection, SqlitePool, SqlitePoolOptions, SqliteRow,
+};
+use sqlx::Row;
+use std::collections::HashSet;
+use std::path::Path;
 use std::sync::Mutex;
+use tokio::sync::broadcast;
+
+const MAX_NOTE_LENGTH: usize = 500;
+pub const MAX_ACTIVITIES: usize = 1000;
+pub const DATABASE_FILE: &str = "cortex.db";
+const DATABASE_EVENT_CAPACITY: usize = 100;
+
+// Hit times kept per rule for `get_rule_stats`; older ones are dropped
+const MAX_RULE_HITS: usize = 10_000;
+pub const DEFAULT_SESSION_GAP_MINUTES: u32 = 5;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Rule {
@@ -10,13 +28,104 @@ pub struct Rule {
     pub rule_json: String,
     pub is_active: bool,
     pub created_at: i64,
+    #[serde(default)]
+    pub last_triggered_at: Option<i64>,
+    #[serde(default)]
+    pub hit_count: u64,
+    // Lower numbers are evaluated first
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub schedule: Option<RuleSchedule>,
+    // Unix seconds; the rule is deactivated once this has passed
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+}
+
+impl Rule {
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at < now)
+    }
+}
+
+// Times are minutes since local midnight. A window whose end is before its
+// start runs past midnight; `days_of_week` (0 = Sunday) is checked against
+// the current day, and an empty list means every day.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuleSchedule {
+    pub days_of_week: Vec<u8>,
+    pub start_time_minutes: u32,
+    pub end_time_minutes: u32,
 }
 
+impl RuleSchedule {
+    pub fn validate(&self) -> Result<(), CortexError> {
+        if let Some(day) = self.days_of_week.iter().find(|day| **day > 6) {
+            return Err(CortexError::ValidationError(format!(
+                "Invalid day of week: {} (expected 0-6)",
+                day
+            )));
+        }
+        if self.start_time_minutes >= 1440 || self.end_time_minutes > 1440 {
+            return Err(CortexError::ValidationError(
+                "Schedule times must be within a day (0-1440 minutes)".to_string(),
+            ));
+        }
+        if self.start_time_minutes == self.end_time_minutes {
+            return Err(CortexError::ValidationError(
+                "Schedule start and end times must differ".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn is_active_at(&self, local: chrono::NaiveDateTime) -> bool {
+        use chrono::{Datelike, Timelike};
+
+        let day = local.weekday().num_days_from_sunday() as u8;
+        if !self.days_of_week.is_empty() && !self.days_of_week.contains(&day) {
+            return false;
+        }
+        let minutes = local.hour() * 60 + local.minute();
+        if self.start_time_minutes <= self.end_time_minutes {
+            minutes >= self.start_time_minutes && minutes < self.end_time_minutes
+        } else {
+            minutes >= self.start_time_minutes || minutes < self.end_time_minutes
+        }
+    }
+}
+
+// Profiles are switched on by the same kind of window
+pub type ProfileSchedule = RuleSchedule;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NewRule {
     pub name: String,
     pub natural_language: String,
     pub rule_json: String,
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+}
+
+// Tags compare case-insensitively; the first spelling added is kept
+fn has_tag(tags: &[String], tag: &str) -> bool {
+    tags.iter().any(|existing| existing.eq_ignore_ascii_case(tag))
+}
+
+fn normalized_tags(tags: &[String]) -> Vec<String> {
+    tags.iter().fold(Vec::new(), |mut normalized, tag| {
+        let tag = tag.trim();
+        if !tag.is_empty() && !has_tag(&normalized, tag) {
+            normalized.push(tag.to_string());
+        }
+        normalized
+    })
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,6 +137,10 @@ pub struct ActivityRecord {
     pub app: String,
     pub bundle_id: Option<String>,
     pub domain: Option<String>,
+    pub notes: Option<String>,
+    // How long the activity lasted, when the reporter knows
+    #[serde(default)]
+    pub duration_ms: Option<f64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -38,137 +151,2571 @@ pub struct NewActivityRecord {
     pub app: String,
     pub bundle_id: Option<String>,
     pub domain: Option<String>,
+    #[serde(default)]
+    pub duration_ms: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ActivitySession {
+    pub start_ms: f64,
+    pub end_ms: f64,
+    pub duration_seconds: f64,
+    // Activity ids, oldest first
+    pub records: Vec<i64>,
+    pub productive_fraction: f64,
+}
+
+// Groups records into sessions, starting a new one whenever the gap since the
+// previous record ends is `gap_minutes` or more. A record ends after its
+// duration, when known. Newest session first.
+pub fn detect_sessions(activities: &[ActivityRecord], gap_minutes: u32) -> Vec<ActivitySession> {
+    let mut sorted: Vec<&ActivityRecord> = activities.iter().collect();
+    sorted.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+    let gap_ms = gap_minutes as f64 * 60_000.0;
+
+    let mut runs: Vec<Vec<&ActivityRecord>> = Vec::new();
+    let mut run_end = f64::NEG_INFINITY;
+    for activity in sorted {
+        match runs.last_mut() {
+            Some(run) if activity.timestamp - run_end < gap_ms => run.push(activity),
+            _ => runs.push(vec![activity]),
+        }
+        run_end = run_end.max(activity.timestamp + activity.duration_ms.unwrap_or(0.0));
+    }
+
+    let mut sessions: Vec<ActivitySession> = runs
+        .into_iter()
+        .map(|run| {
+            let start_ms = run[0].timestamp;
+            let end_ms = run
+                .iter()
+                .map(|activity| activity.timestamp + activity.duration_ms.unwrap_or(0.0))
+                .fold(start_ms, f64::max);
+            let productive = run.iter().filter(|activity| activity.productive).count();
+            ActivitySession {
+                start_ms,
+                end_ms,
+                duration_seconds: (end_ms - start_ms) / 1000.0,
+                records: run.iter().map(|activity| activity.id).collect(),
+                productive_fraction: productive as f64 / run.len() as f64,
+            }
+        })
+        .collect();
+    sessions.reverse();
+    sessions
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleMatchRecord {
+    pub id: i64,
+    pub rule_id: i64,
+    pub matched_at: i64,
+    pub domain: String,
+}
+
+// Today is the current UTC day; the week is the last seven days
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuleStats {
+    pub rule_id: i64,
+    pub hit_count: u64,
+    pub last_triggered_at: Option<i64>,
+    pub hits_today: u64,
+    pub hits_this_week: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleGroup {
+    pub id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    pub rule_ids: Vec<i64>,
+    pub created_at: i64,
+}
+
+// A named set of rules, e.g. "work" or "study". Activating it enables
+// exactly these rules and disables every other one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    pub id: i64,
+    pub name: String,
+    pub description: String,
+    pub rule_ids: Vec<i64>,
+    // Activated automatically when this window starts
+    #[serde(default)]
+    pub schedule: Option<ProfileSchedule>,
+}
+
+// Profiles whose schedule covers `local`, lowest id first; the first one wins
+// when schedules overlap
+pub fn scheduled_profiles(profiles: &[Profile], local: chrono::NaiveDateTime) -> Vec<&Profile> {
+    let mut scheduled: Vec<&Profile> = profiles
+        .iter()
+        .filter(|profile| {
+            profile.schedule.as_ref().is_some_and(|schedule| schedule.is_active_at(local))
+        })
+        .collect();
+    scheduled.sort_by_key(|profile| profile.id);
+    scheduled
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleGroupCreateResult {
+    pub group: RuleGroup,
+    pub assigned_rules: Vec<i64>,
+    pub not_found_rules: Vec<i64>,
+}
+
+// Ids that matched no rule are skipped rather than failing the whole batch
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BulkResult {
+    pub affected: usize,
+    pub skipped: Vec<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "payload")]
+pub enum AuditAction {
+    Created,
+    Updated,
+    // The rule's new is_active
+    Toggled(bool),
+    Deleted,
+    // Snooze length in minutes
+    Snoozed(u32),
+    // Id of the template the rule was created from
+    TemplateApplied(String),
+}
+
+// A change made to a rule. Entries outlive the rule; only
+// `clear_audit_log` removes them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub rule_id: i64,
+    pub action: AuditAction,
+    pub performed_at: i64,
+    pub details: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActionLog {
+    pub id: i64,
+    pub rule_id: i64,
+    pub action_type: String,
+    pub fired_at: i64,
+    pub succeeded: bool,
+    pub error: Option<String>,
+    pub domain: String,
+    // How long a webhook's request took; for other actions, the time from the
+    // triggering event to the action being fired
+    #[serde(default)]
+    pub latency_ms: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewActionLog {
+    pub rule_id: i64,
+    pub action_type: String,
+    pub error: Option<String>,
+    pub domain: String,
+    #[serde(default)]
+    pub latency_ms: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntegrityIssue {
+    pub table: String,
+    pub record_id: i64,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IntegrityReport {
+    pub issues_found: u32,
+    pub issues: Vec<IntegrityIssue>,
+    pub repaired: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledActivation {
+    pub rule_id: i64,
+    pub activate_at: i64,
+    pub created_at: i64,
+}
+
+// Broadcast after every write so other subsystems can react without polling
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "payload")]
+pub enum DatabaseEvent {
+    RuleCreated(Rule),
+    RuleUpdated(Rule),
+    RuleDeleted(i64),
+    ActivityLogged(i64),
+    // Sent once an extension log is saved to disk, with its timestamp (ms)
+    ExtensionLogPersisted(i64),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DatabaseConfig {
+    // SQLCipher key the database file is encrypted with. A file created
+    // without a key stays unencrypted. The in-memory backend ignores it.
+    pub encryption_key: Option<String>,
+}
+
+// PRAGMA values can't be bound as parameters
+fn sql_string_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
 }
 
 pub struct Database {
-    rules: Mutex<HashMap<i64, Rule>>,
-    activities: Mutex<Vec<ActivityRecord>>,
-    next_rule_id: Mutex<i64>,
-    next_activity_id: Mutex<i64>,
+    config: Mutex<DatabaseConfig>,
+    pool: SqlitePool,
+    events: broadcast::Sender<DatabaseEvent>,
+}
+
+fn rule_not_found() -> CortexError {
+    CortexError::NotFound("Rule not found".to_string())
+}
+
+// Tags, schedules and id lists are stored as JSON text
+fn to_json_column<T: Serialize>(value: &T) -> Result<String, CortexError> {
+    serde_json::to_string(value)
+        .map_err(|e| CortexError::DatabaseError(format!("Failed to encode column: {}", e)))
+}
+
+fn from_json_column<T: DeserializeOwned>(json: &str) -> Result<T, CortexError> {
+    serde_json::from_str(json)
+        .map_err(|e| CortexError::DatabaseError(format!("Failed to decode column: {}", e)))
+}
+
+fn rule_from_row(row: &SqliteRow) -> Result<Rule, CortexError> {
+    let schedule: Option<String> = row.try_get("schedule")?;
+    Ok(Rule {
+        id: row.try_get("id")?,
+        name: row.try_get("name")?,
+        natural_language: row.try_get("natural_language")?,
+        rule_json: row.try_get("rule_json")?,
+        is_active: row.try_get("is_active")?,
+        created_at: row.try_get("created_at")?,
+        last_triggered_at: row.try_get("last_triggered_at")?,
+        hit_count: row.try_get::<i64, _>("hit_count")? as u64,
+        priority: row.try_get("priority")?,
+        tags: from_json_column(row.try_get("tags")?)?,
+        schedule: schedule.as_deref().map(from_json_column).transpose()?,
+        expires_at: row.try_get("expires_at")?,
+    })
+}
+
+fn activity_from_row(row: &SqliteRow) -> Result<ActivityRecord, CortexError> {
+    Ok(ActivityRecord {
+        id: row.try_get("id")?,
+        timestamp: row.try_get("timestamp")?,
+        activity: row.try_get("activity")?,
+        productive: row.try_get("productive")?,
+        app: row.try_get("app")?,
+        bundle_id: row.try_get("bundle_id")?,
+        domain: row.try_get("domain")?,
+        notes: row.try_get("notes")?,
+        duration_ms: row.try_get("duration_ms")?,
+    })
+}
+
+fn profile_from_row(row: &SqliteRow) -> Result<Profile, CortexError> {
+    let schedule: Option<String> = row.try_get("schedule")?;
+    Ok(Profile {
+        id: row.try_get("id")?,
+        name: row.try_get("name")?,
+        description: row.try_get("description")?,
+        rule_ids: from_json_column(row.try_get("rule_ids")?)?,
+        schedule: schedule.as_deref().map(from_json_column).transpose()?,
+    })
+}
+
+fn action_log_from_row(row: &SqliteRow) -> Result<ActionLog, CortexError> {
+    Ok(ActionLog {
+        id: row.try_get("id")?,
+        rule_id: row.try_get("rule_id")?,
+        action_type: row.try_get("action_type")?,
+        fired_at: row.try_get("fired_at")?,
+        succeeded: row.try_get("succeeded")?,
+        error: row.try_get("error")?,
+        domain: row.try_get("domain")?,
+        latency_ms: row.try_get("latency_ms")?,
+    })
+}
+
+fn audit_entry_from_row(row: &SqliteRow) -> Result<AuditEntry, CortexError> {
+    Ok(AuditEntry {
+        id: row.try_get("id")?,
+        rule_id: row.try_get("rule_id")?,
+        action: from_json_column(row.try_get("action")?)?,
+        performed_at: row.try_get("performed_at")?,
+        details: row.try_get("details")?,
+    })
+}
+
+fn rule_match_from_row(row: &SqliteRow) -> Result<RuleMatchRecord, CortexError> {
+    Ok(RuleMatchRecord {
+        id: row.try_get("id")?,
+        rule_id: row.try_get("rule_id")?,
+        matched_at: row.try_get("matched_at")?,
+        domain: row.try_get("domain")?,
+    })
+}
+
+async fn fetch_rule(
+    conn: &mut SqliteConnection,
+    rule_id: i64,
+) -> Result<Option<Rule>, CortexError> {
+    sqlx::query("SELECT * FROM rules WHERE id = ?")
+        .bind(rule_id)
+        .fetch_optional(conn)
+        .await?
+        .map(|row| rule_from_row(&row))
+        .transpose()
+}
+
+// Returns the new rule's id; `rule.id` is ignored
+async fn insert_rule(conn: &mut SqliteConnection, rule: &Rule) -> Result<i64, CortexError> {
+    let result = sqlx::query(
+        "INSERT INTO rules (name, natural_language, rule_json, is_active, created_at,
+            last_triggered_at, hit_count, priority, tags, schedule, expires_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&rule.name)
+    .bind(&rule.natural_language)
+    .bind(&rule.rule_json)
+    .bind(rule.is_active)
+    .bind(rule.created_at)
+    .bind(rule.last_triggered_at)
+    .bind(rule.hit_count as i64)
+    .bind(rule.priority)
+    .bind(to_json_column(&rule.tags)?)
+    .bind(rule.schedule.as_ref().map(to_json_column).transpose()?)
+    .bind(rule.expires_at)
+    .execute(conn)
+    .await?;
+    Ok(result.last_insert_rowid())
+}
+
+async fn save_rule(conn: &mut SqliteConnection, rule: &Rule) -> Result<(), CortexError> {
+    sqlx::query(
+        "UPDATE rules SET name = ?, natural_language = ?, rule_json = ?, is_active = ?,
+            created_at = ?, last_triggered_at = ?, hit_count = ?, priority = ?, tags = ?,
+            schedule = ?, expires_at = ?
+         WHERE id = ?",
+    )
+    .bind(&rule.name)
+    .bind(&rule.natural_language)
+    .bind(&rule.rule_json)
+    .bind(rule.is_active)
+    .bind(rule.created_at)
+    .bind(rule.last_triggered_at)
+    .bind(rule.hit_count as i64)
+    .bind(rule.priority)
+    .bind(to_json_column(&rule.tags)?)
+    .bind(rule.schedule.as_ref().map(to_json_column).transpose()?)
+    .bind(rule.expires_at)
+    .bind(rule.id)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+async fn rule_exists(conn: &mut SqliteConnection, rule_id: i64) -> Result<bool, CortexError> {
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM rules WHERE id = ?)")
+        .bind(rule_id)
+        .fetch_one(conn)
+        .await?;
+    Ok(exists)
+}
+
+async fn insert_activity(
+    conn: &mut SqliteConnection,
+    id: Option<i64>,
+    activity: &ActivityRecord,
+) -> Result<i64, CortexError> {
+    let result = sqlx::query(
+        "INSERT INTO activities (id, timestamp, activity, productive, app, bundle_id, domain,
+            notes, duration_ms)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(id)
+    .bind(activity.timestamp)
+    .bind(&activity.activity)
+    .bind(activity.productive)
+    .bind(&activity.app)
+    .bind(&activity.bundle_id)
+    .bind(&activity.domain)
+    .bind(&activity.notes)
+    .bind(activity.duration_ms)
+    .execute(conn)
+    .await?;
+    Ok(result.last_insert_rowid())
+}
+
+// Keeps only the most recent activities
+async fn evict_old_activities(conn: &mut SqliteConnection) -> Result<(), CortexError> {
+    sqlx::query(
+        "DELETE FROM activities WHERE id NOT IN (
+            SELECT id FROM activities ORDER BY timestamp DESC, id DESC LIMIT ?
+        )",
+    )
+    .bind(MAX_ACTIVITIES as i64)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+async fn count_hits_since(
+    conn: &mut SqliteConnection,
+    rule_id: i64,
+    since: i64,
+) -> Result<u64, CortexError> {
+    let count: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM rule_hits WHERE rule_id = ? AND hit_at >= ?")
+            .bind(rule_id)
+            .bind(since)
+            .fetch_one(conn)
+            .await?;
+    Ok(count as u64)
+}
+
+async fn audit(
+    conn: &mut SqliteConnection,
+    rule_id: i64,
+    action: AuditAction,
+    details: Option<String>,
+) -> Result<(), CortexError> {
+    sqlx::query(
+        "INSERT INTO audit_log (rule_id, action, performed_at, details) VALUES (?, ?, ?, ?)",
+    )
+    .bind(rule_id)
+    .bind(to_json_column(&action)?)
+    .bind(chrono::Utc::now().timestamp())
+    .bind(details)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+// Returns the rule if there was one
+async fn remove_rule(
+    conn: &mut SqliteConnection,
+    rule_id: i64,
+) -> Result<Option<Rule>, CortexError> {
+    let Some(rule) = fetch_rule(&mut *conn, rule_id).await? else {
+        return Ok(None);
+    };
+    sqlx::query("DELETE FROM rules WHERE id = ?").bind(rule_id).execute(&mut *conn).await?;
+    sqlx::query("DELETE FROM rule_hits WHERE rule_id = ?")
+        .bind(rule_id)
+        .execute(&mut *conn)
+        .await?;
+    audit(conn, rule_id, AuditAction::Deleted, Some(rule.name.clone())).await?;
+    println!("Deleted rule: {}", rule.name);
+    Ok(Some(rule))
 }
 
 impl Database {
-    pub fn new() -> Self {
-        Database {
-            rules: Mutex::new(HashMap::new()),
-            activities: Mutex::new(Vec::new()),
-            next_rule_id: Mutex::new(1),
-            next_activity_id: Mutex::new(1),
+    // Opens the database file, creating it and its directory if needed
+    pub async fn new(path: &Path) -> Result<Self, CortexError> {
+        Self::with_config(path, DatabaseConfig::default()).await
+    }
+
+    pub async fn with_config(path: &Path, config: DatabaseConfig) -> Result<Self, CortexError> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut options = SqliteConnectOptions::new().filename(path).create_if_missing(true);
+        if let Some(key) = &config.encryption_key {
+            options = options.pragma("key", sql_string_literal(key));
         }
+        // With the wrong key (or none, for an encrypted file) the first query
+        // fails, so this also verifies the key
+        Self::connect(options, config).await
     }
 
-    pub async fn create_rule(&self, new_rule: NewRule) -> Result<Rule, String> {
+    // Nothing is written to disk, so there is nothing to encrypt
+    #[cfg(test)]
+    pub async fn in_memory() -> Result<Self, CortexError> {
+        let options: SqliteConnectOptions = "sqlite::memory:".parse()?;
+        Self::connect(options, DatabaseConfig::default()).await
+    }
+
+    async fn connect(
+        options: SqliteConnectOptions,
+        config: DatabaseConfig,
+    ) -> Result<Self, CortexError> {
+        // One connection that is never recycled: writes are serialized by the
+        // caller anyway, and an in-memory database lives only as long as it
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_with(options)
+            .await?;
+        MigrationRunner::new(MIGRATIONS.to_vec()).run(&pool).await?;
+
+        Ok(Database {
+            config: Mutex::new(config),
+            pool,
+            events: broadcast::channel(DATABASE_EVENT_CAPACITY).0,
+        })
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<DatabaseEvent> {
+        self.events.subscribe()
+    }
+
+    fn notify(&self, event: DatabaseEvent) {
+        // Sending only fails when nobody is subscribed, which is fine
+        let _ = self.events.send(event);
+    }
+
+    // Extension logs are saved by `LogStore` rather than in a table, so the
+    // code that saves them reports each one here
+    pub fn extension_log_persisted(&self, timestamp_ms: i64) {
+        self.notify(DatabaseEvent::ExtensionLogPersisted(timestamp_ms));
+    }
+
+    pub async fn change_password(&self, old_key: &str, new_key: &str) -> Result<(), CortexError> {
+        if new_key.is_empty() {
+            return Err(CortexError::ValidationError("New key must not be empty".to_string()));
+        }
+
+        match self.config.lock()?.encryption_key.as_deref() {
+            Some(current) if current == old_key => {}
+            Some(_) => {
+                return Err(CortexError::ValidationError("Current key is incorrect".to_string()))
+            }
+            None => {
+                return Err(CortexError::ValidationError(
+                    "Database encryption is not enabled".to_string(),
+                ))
+            }
+        }
+
+        // Re-encrypts the whole file with the new key
+        sqlx::query(&format!("PRAGMA rekey = {}", sql_string_literal(new_key)))
+            .execute(&self.pool)
+            .await?;
+        // So a reconnect uses the new key too
+        let options = (*self.pool.connect_options())
+            .clone()
+            .pragma("key", sql_string_literal(new_key));
+        self.pool.set_connect_options(options);
+        self.config.lock()?.encryption_key = Some(new_key.to_string());
+        println!("Database encryption key changed");
+        Ok(())
+    }
+
+    // The id the next created rule will get. Ids of deleted rules are never
+    // reused.
+    pub async fn next_rule_id(&self) -> Result<i64, CortexError> {
+        let last_id: Option<i64> =
+            sqlx::query_scalar("SELECT seq FROM sqlite_sequence WHERE name = 'rules'")
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(last_id.unwrap_or(0) + 1)
+    }
+
+    pub async fn create_rule(&self, new_rule: NewRule) -> Result<Rule, CortexError> {
         let now = chrono::Utc::now().timestamp();
         
-        let mut next_id = self.next_rule_id.lock().unwrap();
-        let rule_id = *next_id;
-        *next_id += 1;
-        
-        let rule = Rule {
-            id: rule_id,
+        let mut rule = Rule {
+            id: 0,
             name: new_rule.name,
             natural_language: new_rule.natural_language,
             rule_json: new_rule.rule_json,
             is_active: true,
             created_at: now,
+            last_triggered_at: None,
+            hit_count: 0,
+            priority: new_rule.priority,
+            tags: normalized_tags(&new_rule.tags),
+            schedule: None,
+            expires_at: new_rule.expires_at,
         };
 
-        let mut rules = self.rules.lock().unwrap();
-        rules.insert(rule_id, rule.clone());
+        let mut tx = self.pool.begin().await?;
+        rule.id = insert_rule(&mut tx, &rule).await?;
+        audit(&mut tx, rule.id, AuditAction::Created, Some(rule.name.clone())).await?;
+        tx.commit().await?;
         
         println!("Created rule: {} (ID: {})", rule.name, rule.id);
+        self.notify(DatabaseEvent::RuleCreated(rule.clone()));
+        Ok(rule)
+    }
+
+    // Inserts each `(rule, is_active)` pair in one transaction. With
+    // `overwrite`, a rule whose name matches an existing rule replaces it in
+    // place, keeping its id and created_at. Returns the saved rules.
+    pub async fn import_rules(
+        &self,
+        imported: Vec<(NewRule, bool)>,
+        overwrite: bool,
+    ) -> Result<Vec<Rule>, CortexError> {
+        let now = chrono::Utc::now().timestamp();
+        let mut saved = Vec::with_capacity(imported.len());
+        let mut events = Vec::new();
+
+        let mut tx = self.pool.begin().await?;
+        for (new_rule, is_active) in imported {
+            let existing = if overwrite {
+                sqlx::query("SELECT * FROM rules WHERE name = ? ORDER BY id LIMIT 1")
+                    .bind(&new_rule.name)
+                    .fetch_optional(&mut *tx)
+                    .await?
+                    .map(|row| rule_from_row(&row))
+                    .transpose()?
+            } else {
+                None
+            };
+
+            let mut rule = Rule {
+                id: 0,
+                name: new_rule.name,
+                natural_language: new_rule.natural_language,
+                rule_json: new_rule.rule_json,
+                is_active,
+                created_at: now,
+                last_triggered_at: None,
+                hit_count: 0,
+                priority: new_rule.priority,
+                tags: normalized_tags(&new_rule.tags),
+                schedule: None,
+                expires_at: new_rule.expires_at,
+            };
+            let details = Some(format!("Imported {}", rule.name));
+            match existing {
+                Some(existing) => {
+                    rule.id = existing.id;
+                    rule.created_at = existing.created_at;
+                    rule.last_triggered_at = existing.last_triggered_at;
+                    rule.hit_count = existing.hit_count;
+                    save_rule(&mut tx, &rule).await?;
+                    audit(&mut tx, rule.id, AuditAction::Updated, details).await?;
+                    events.push(DatabaseEvent::RuleUpdated(rule.clone()));
+                }
+                None => {
+                    rule.id = insert_rule(&mut tx, &rule).await?;
+                    audit(&mut tx, rule.id, AuditAction::Created, details).await?;
+                    events.push(DatabaseEvent::RuleCreated(rule.clone()));
+                }
+            }
+            saved.push(rule);
+        }
+        tx.commit().await?;
+
+        for event in events {
+            self.notify(event);
+        }
+        println!("Imported {} rules", saved.len());
+        Ok(saved)
+    }
+
+    // Runs in one transaction, so the group is created with exactly the
+    // members that existed at that moment and nothing is left behind on
+    // failure
+    pub async fn create_rule_group_with_rules(
+        &self,
+        name: String,
+        description: Option<String>,
+        rule_ids: Vec<i64>,
+    ) -> Result<RuleGroupCreateResult, CortexError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(CortexError::ValidationError(
+                "Rule group name must not be empty".to_string(),
+            ));
+        }
+
+        let mut tx = self.pool.begin().await?;
+        let mut assigned_rules = Vec::new();
+        let mut not_found_rules = Vec::new();
+        for rule_id in rule_ids {
+            if assigned_rules.contains(&rule_id) || not_found_rules.contains(&rule_id) {
+                continue;
+            }
+            if rule_exists(&mut tx, rule_id).await? {
+                assigned_rules.push(rule_id);
+            } else {
+                not_found_rules.push(rule_id);
+            }
+        }
+
+        let mut group = RuleGroup {
+            id: 0,
+            name: name.to_string(),
+            description,
+            rule_ids: assigned_rules.clone(),
+            created_at: chrono::Utc::now().timestamp(),
+        };
+        group.id = sqlx::query(
+            "INSERT INTO rule_groups (name, description, rule_ids, created_at)
+             VALUES (?, ?, ?, ?)",
+        )
+        .bind(&group.name)
+        .bind(&group.description)
+        .bind(to_json_column(&group.rule_ids)?)
+        .bind(group.created_at)
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+        tx.commit().await?;
+
+        println!("Created rule group: {} (ID: {})", group.name, group.id);
+        Ok(RuleGroupCreateResult {
+            group,
+            assigned_rules,
+            not_found_rules,
+        })
+    }
+
+    pub async fn create_profile(
+        &self,
+        name: String,
+        description: String,
+        rule_ids: Vec<i64>,
+    ) -> Result<Profile, CortexError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(CortexError::ValidationError(
+                "Profile name must not be empty".to_string(),
+            ));
+        }
+
+        let mut tx = self.pool.begin().await?;
+        let mut seen = HashSet::new();
+        let rule_ids: Vec<i64> =
+            rule_ids.into_iter().filter(|rule_id| seen.insert(*rule_id)).collect();
+        let mut not_found = Vec::new();
+        for rule_id in &rule_ids {
+            if !rule_exists(&mut tx, *rule_id).await? {
+                not_found.push(*rule_id);
+            }
+        }
+        if !not_found.is_empty() {
+            return Err(CortexError::NotFound(format!("Rules not found: {:?}", not_found)));
+        }
+
+        let mut profile = Profile {
+            id: 0,
+            name: name.to_string(),
+            description,
+            rule_ids,
+            schedule: None,
+        };
+        profile.id = sqlx::query(
+            "INSERT INTO profiles (name, description, rule_ids) VALUES (?, ?, ?)",
+        )
+        .bind(&profile.name)
+        .bind(&profile.description)
+        .bind(to_json_column(&profile.rule_ids)?)
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+        tx.commit().await?;
+
+        println!("Created profile: {} (ID: {})", profile.name, profile.id);
+        Ok(profile)
+    }
+
+    pub async fn get_profiles(&self) -> Result<Vec<Profile>, CortexError> {
+        let rows = sqlx::query("SELECT * FROM profiles ORDER BY id")
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(profile_from_row).collect()
+    }
+
+    pub async fn get_profile(&self, profile_id: i64) -> Result<Option<Profile>, CortexError> {
+        sqlx::query("SELECT * FROM profiles WHERE id = ?")
+            .bind(profile_id)
+            .fetch_optional(&self.pool)
+            .await?
+            .map(|row| profile_from_row(&row))
+            .transpose()
+    }
+
+    pub async fn set_profile_schedule(
+        &self,
+        profile_id: i64,
+        schedule: Option<ProfileSchedule>,
+    ) -> Result<(), CortexError> {
+        if let Some(schedule) = &schedule {
+            schedule.validate()?;
+        }
+        let profile = self
+            .get_profile(profile_id)
+            .await?
+            .ok_or_else(|| CortexError::NotFound("Profile not found".to_string()))?;
+        sqlx::query("UPDATE profiles SET schedule = ? WHERE id = ?")
+            .bind(schedule.as_ref().map(to_json_column).transpose()?)
+            .bind(profile_id)
+            .execute(&self.pool)
+            .await?;
+        println!("Updated schedule of profile {}", profile.name);
+        Ok(())
+    }
+
+    pub async fn delete_profile(&self, profile_id: i64) -> Result<(), CortexError> {
+        let profile = self
+            .get_profile(profile_id)
+            .await?
+            .ok_or_else(|| CortexError::NotFound("Profile not found".to_string()))?;
+        sqlx::query("DELETE FROM profiles WHERE id = ?")
+            .bind(profile_id)
+            .execute(&self.pool)
+            .await?;
+        println!("Deleted profile: {}", profile.name);
+        Ok(())
+    }
+
+    // Enables the profile's rules and disables all others. Rules deleted since
+    // the profile was created are ignored.
+    pub async fn activate_profile(&self, profile_id: i64) -> Result<Profile, CortexError> {
+        let profile = self
+            .get_profile(profile_id)
+            .await?
+            .ok_or_else(|| CortexError::NotFound("Profile not found".to_string()))?;
+
+        let mut tx = self.pool.begin().await?;
+        let rows = sqlx::query("SELECT * FROM rules ORDER BY id").fetch_all(&mut *tx).await?;
+        let mut toggled = Vec::new();
+        for row in &rows {
+            let mut rule = rule_from_row(row)?;
+            let is_active = profile.rule_ids.contains(&rule.id);
+            if rule.is_active != is_active {
+                rule.is_active = is_active;
+                save_rule(&mut tx, &rule).await?;
+                audit(&mut tx, rule.id, AuditAction::Toggled(is_active), None).await?;
+                toggled.push(rule);
+            }
+        }
+        tx.commit().await?;
+
+        for rule in toggled {
+            self.notify(DatabaseEvent::RuleUpdated(rule));
+        }
+        println!("Activated profile: {}", profile.name);
+        Ok(profile)
+    }
+
+    // Newest first
+    pub async fn get_all_rules(&self) -> Result<Vec<Rule>, CortexError> {
+        let rows = sqlx::query("SELECT * FROM rules ORDER BY created_at DESC, id DESC")
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(rule_from_row).collect()
+    }
+
+    pub async fn get_rule_by_id(&self, rule_id: i64) -> Result<Option<Rule>, CortexError> {
+        let mut conn = self.pool.acquire().await?;
+        fetch_rule(&mut conn, rule_id).await
+    }
+
+    pub async fn get_active_rules(&self) -> Result<Vec<Rule>, CortexError> {
+        let rows = sqlx::query(
+            "SELECT * FROM rules WHERE is_active = 1 ORDER BY priority, created_at, id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(rule_from_row).collect()
+    }
+
+    // Applies `change` to the rule and saves it, returning the saved rule
+    async fn modify_rule(
+        &self,
+        rule_id: i64,
+        change: impl FnOnce(&mut Rule),
+    ) -> Result<Rule, CortexError> {
+        let mut conn = self.pool.acquire().await?;
+        let mut rule = fetch_rule(&mut conn, rule_id)
+            .await?
+            .ok_or_else(rule_not_found)?;
+        change(&mut rule);
+        save_rule(&mut conn, &rule).await?;
         Ok(rule)
     }
 
-    pub async fn get_all_rules(&self) -> Result<Vec<Rule>, String> {
-        let rules = self.rules.lock().unwrap();
-        let mut rule_list: Vec<Rule> = rules.values().cloned().collect();
-        rule_list.sort_by(|a, b| b.created_at.cmp(&a.created_at));
-        Ok(rule_list)
+    pub async fn set_rule_priority(&self, rule_id: i64, priority: i32) -> Result<(), CortexError> {
+        let rule = self.modify_rule(rule_id, |rule| rule.priority = priority).await?;
+        println!("Set priority of rule {} to: {}", rule.name, priority);
+        self.notify(DatabaseEvent::RuleUpdated(rule));
+        Ok(())
+    }
+
+    pub async fn toggle_rule(&self, rule_id: i64) -> Result<(), CortexError> {
+        let mut tx = self.pool.begin().await?;
+        let mut rule = fetch_rule(&mut tx, rule_id)
+            .await?
+            .ok_or_else(rule_not_found)?;
+        rule.is_active = !rule.is_active;
+        save_rule(&mut tx, &rule).await?;
+        audit(&mut tx, rule_id, AuditAction::Toggled(rule.is_active), None).await?;
+        tx.commit().await?;
+
+        println!("Toggled rule {} to: {}", rule.name, rule.is_active);
+        self.notify(DatabaseEvent::RuleUpdated(rule));
+        Ok(())
+    }
+
+    // Keeps the rule's id, created_at and is_active
+    pub async fn update_rule(
+        &self,
+        rule_id: i64,
+        name: String,
+        natural_language: String,
+        rule_json: String,
+    ) -> Result<Rule, CortexError> {
+        let mut tx = self.pool.begin().await?;
+        let mut rule = fetch_rule(&mut tx, rule_id)
+            .await?
+            .ok_or_else(rule_not_found)?;
+        rule.name = name;
+        rule.natural_language = natural_language;
+        rule.rule_json = rule_json;
+        save_rule(&mut tx, &rule).await?;
+        audit(&mut tx, rule_id, AuditAction::Updated, Some(rule.name.clone())).await?;
+        tx.commit().await?;
+
+        println!("Updated rule: {} (ID: {})", rule.name, rule.id);
+        self.notify(DatabaseEvent::RuleUpdated(rule.clone()));
+        Ok(rule)
+    }
+
+    pub async fn update_rule_json(
+        &self,
+        rule_id: i64,
+        rule_json: String,
+    ) -> Result<Rule, CortexError> {
+        let mut tx = self.pool.begin().await?;
+        let mut rule = fetch_rule(&mut tx, rule_id)
+            .await?
+            .ok_or_else(rule_not_found)?;
+        rule.rule_json = rule_json;
+        save_rule(&mut tx, &rule).await?;
+        audit(&mut tx, rule_id, AuditAction::Updated, Some(rule.name.clone())).await?;
+        tx.commit().await?;
+
+        self.notify(DatabaseEvent::RuleUpdated(rule.clone()));
+        Ok(rule)
+    }
+
+    pub async fn schedule_rule_activation(
+        &self,
+        rule_id: i64,
+        activate_at: i64,
+    ) -> Result<ScheduledActivation, CortexError> {
+        let mut conn = self.pool.acquire().await?;
+        if !rule_exists(&mut conn, rule_id).await? {
+            return Err(rule_not_found());
+        }
+
+        let activation = ScheduledActivation {
+            rule_id,
+            activate_at,
+            created_at: chrono::Utc::now().timestamp(),
+        };
+        sqlx::query(
+            "INSERT INTO scheduled_activations (rule_id, activate_at, created_at)
+             VALUES (?, ?, ?)",
+        )
+        .bind(activation.rule_id)
+        .bind(activation.activate_at)
+        .bind(activation.created_at)
+        .execute(&mut *conn)
+        .await?;
+        Ok(activation)
+    }
+
+    // Activates every rule whose scheduled time has passed, removing the
+    // schedules, and returns the rules that were activated
+    pub async fn apply_due_activations(&self, now: i64) -> Result<Vec<Rule>, CortexError> {
+        let mut tx = self.pool.begin().await?;
+        let due: Vec<i64> = sqlx::query_scalar(
+            "SELECT rule_id FROM scheduled_activations WHERE activate_at <= ? ORDER BY id",
+        )
+        .bind(now)
+        .fetch_all(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM scheduled_activations WHERE activate_at <= ?")
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+
+        let mut activated = Vec::new();
+        for rule_id in due {
+            if let Some(mut rule) = fetch_rule(&mut tx, rule_id).await? {
+                rule.is_active = true;
+                save_rule(&mut tx, &rule).await?;
+                let details = Some("Scheduled activation".to_string());
+                audit(&mut tx, rule_id, AuditAction::Toggled(true), details).await?;
+                activated.push(rule);
+            }
+        }
+        tx.commit().await?;
+
+        for rule in &activated {
+            self.notify(DatabaseEvent::RuleUpdated(rule.clone()));
+        }
+        Ok(activated)
+    }
+
+    pub async fn set_rule_expiry(
+        &self,
+        rule_id: i64,
+        expires_at: Option<i64>,
+    ) -> Result<(), CortexError> {
+        let rule = self.modify_rule(rule_id, |rule| rule.expires_at = expires_at).await?;
+        println!("Set expiry of rule {} to: {:?}", rule.name, expires_at);
+        self.notify(DatabaseEvent::RuleUpdated(rule));
+        Ok(())
+    }
+
+    // Returns how many active rules were switched off
+    pub async fn deactivate_expired_rules(&self) -> Result<usize, CortexError> {
+        let now = chrono::Utc::now().timestamp();
+        let mut tx = self.pool.begin().await?;
+        let rows = sqlx::query(
+            "SELECT * FROM rules WHERE is_active = 1 AND expires_at < ? ORDER BY id",
+        )
+        .bind(now)
+        .fetch_all(&mut *tx)
+        .await?;
+        let mut deactivated = Vec::new();
+        for row in &rows {
+            let mut rule = rule_from_row(row)?;
+            rule.is_active = false;
+            save_rule(&mut tx, &rule).await?;
+            let details = Some("Expired".to_string());
+            audit(&mut tx, rule.id, AuditAction::Toggled(false), details).await?;
+            deactivated.push(rule);
+        }
+        tx.commit().await?;
+
+        let count = deactivated.len();
+        for rule in deactivated {
+            println!("Deactivated expired rule: {}", rule.name);
+            self.notify(DatabaseEvent::RuleUpdated(rule));
+        }
+        Ok(count)
     }
 
-    pub async fn get_active_rules(&self) -> Result<Vec<Rule>, String> {
-        let rules = self.rules.lock().unwrap();
-        let mut active_rules: Vec<Rule> = rules
-            .values()
-            .filter(|rule| rule.is_active)
-            .cloned()
+    // Adding a tag the rule already has is a no-op
+    pub async fn add_rule_tag(&self, rule_id: i64, tag: &str) -> Result<(), CortexError> {
+        let tag = tag.trim();
+        if tag.is_empty() {
+            return Err(CortexError::ValidationError("Tag cannot be empty".to_string()));
+        }
+        let rule = self.get_rule_by_id(rule_id).await?.ok_or_else(rule_not_found)?;
+        if !has_tag(&rule.tags, tag) {
+            let rule = self.modify_rule(rule_id, |rule| rule.tags.push(tag.to_string())).await?;
+            println!("Tagged rule {} with: {}", rule.name, tag);
+            self.notify(DatabaseEvent::RuleUpdated(rule));
+        }
+        Ok(())
+    }
+
+    // Removing a tag the rule doesn't have is a no-op
+    pub async fn remove_rule_tag(&self, rule_id: i64, tag: &str) -> Result<(), CortexError> {
+        let tag = tag.trim();
+        let rule = self.get_rule_by_id(rule_id).await?.ok_or_else(rule_not_found)?;
+        if has_tag(&rule.tags, tag) {
+            let rule = self
+                .modify_rule(rule_id, |rule| {
+                    rule.tags.retain(|existing| !existing.eq_ignore_ascii_case(tag))
+                })
+                .await?;
+            println!("Removed tag {} from rule {}", tag, rule.name);
+            self.notify(DatabaseEvent::RuleUpdated(rule));
+        }
+        Ok(())
+    }
+
+    pub async fn get_rules_by_tag(&self, tag: &str) -> Result<Vec<Rule>, CortexError> {
+        let tag = tag.trim();
+        let mut tagged: Vec<Rule> = self
+            .get_all_rules()
+            .await?
+            .into_iter()
+            .filter(|rule| has_tag(&rule.tags, tag))
             .collect();
-        active_rules.sort_by(|a, b| b.created_at.cmp(&a.created_at));
-        Ok(active_rules)
+        tagged.sort_by_key(|rule| (rule.priority, rule.created_at, rule.id));
+        Ok(tagged)
     }
 
-    pub async fn toggle_rule(&self, rule_id: i64) -> Result<(), String> {
-        let mut rules = self.rules.lock().unwrap();
-        if let Some(rule) = rules.get_mut(&rule_id) {
-            rule.is_active = !rule.is_active;
-            println!("Toggled rule {} to: {}", rule.name, rule.is_active);
-            Ok(())
-        } else {
-            Err("Rule not found".to_string())
+    pub async fn set_rule_schedule(
+        &self,
+        rule_id: i64,
+        schedule: Option<RuleSchedule>,
+    ) -> Result<(), CortexError> {
+        if let Some(schedule) = &schedule {
+            schedule.validate()?;
         }
+        let rule = self.modify_rule(rule_id, |rule| rule.schedule = schedule).await?;
+        println!("Updated schedule of rule {}", rule.name);
+        self.notify(DatabaseEvent::RuleUpdated(rule));
+        Ok(())
     }
 
-    pub async fn delete_rule(&self, rule_id: i64) -> Result<(), String> {
-        let mut rules = self.rules.lock().unwrap();
-        if let Some(rule) = rules.remove(&rule_id) {
-            println!("Deleted rule: {}", rule.name);
+    pub async fn delete_rule(&self, rule_id: i64) -> Result<(), CortexError> {
+        let mut tx = self.pool.begin().await?;
+        let removed = remove_rule(&mut tx, rule_id).await?;
+        tx.commit().await?;
+        if removed.is_some() {
+            self.notify(DatabaseEvent::RuleDeleted(rule_id));
             Ok(())
         } else {
-            Err("Rule not found".to_string())
+            Err(rule_not_found())
+        }
+    }
+
+    // Rules that already have the requested state still count as affected
+    pub async fn set_rules_active(
+        &self,
+        rule_ids: &[i64],
+        is_active: bool,
+    ) -> Result<BulkResult, CortexError> {
+        let mut tx = self.pool.begin().await?;
+        let mut result = BulkResult {
+            affected: 0,
+            skipped: Vec::new(),
+        };
+        let mut toggled = Vec::new();
+        let mut seen = HashSet::new();
+        for &rule_id in rule_ids.iter().filter(|rule_id| seen.insert(**rule_id)) {
+            let Some(mut rule) = fetch_rule(&mut tx, rule_id).await? else {
+                result.skipped.push(rule_id);
+                continue;
+            };
+            result.affected += 1;
+            if rule.is_active != is_active {
+                rule.is_active = is_active;
+                save_rule(&mut tx, &rule).await?;
+                audit(&mut tx, rule_id, AuditAction::Toggled(is_active), None).await?;
+                toggled.push(rule);
+            }
+        }
+        tx.commit().await?;
+
+        for rule in toggled {
+            self.notify(DatabaseEvent::RuleUpdated(rule));
+        }
+        println!("Set {} rules active: {}", result.affected, is_active);
+        Ok(result)
+    }
+
+    pub async fn delete_rules(&self, rule_ids: &[i64]) -> Result<BulkResult, CortexError> {
+        let mut tx = self.pool.begin().await?;
+        let mut result = BulkResult {
+            affected: 0,
+            skipped: Vec::new(),
+        };
+        let mut deleted = Vec::new();
+        let mut seen = HashSet::new();
+        for &rule_id in rule_ids.iter().filter(|rule_id| seen.insert(**rule_id)) {
+            if remove_rule(&mut tx, rule_id).await?.is_some() {
+                result.affected += 1;
+                deleted.push(rule_id);
+            } else {
+                result.skipped.push(rule_id);
+            }
+        }
+        tx.commit().await?;
+
+        for rule_id in deleted {
+            self.notify(DatabaseEvent::RuleDeleted(rule_id));
+        }
+        Ok(result)
+    }
+
+    pub async fn delete_rules_by_tag(&self, tag: &str) -> Result<BulkResult, CortexError> {
+        let tagged: Vec<i64> =
+            self.get_rules_by_tag(tag).await?.into_iter().map(|rule| rule.id).collect();
+        let mut tx = self.pool.begin().await?;
+        for rule_id in &tagged {
+            remove_rule(&mut tx, *rule_id).await?;
+        }
+        tx.commit().await?;
+
+        for rule_id in &tagged {
+            self.notify(DatabaseEvent::RuleDeleted(*rule_id));
+        }
+        Ok(BulkResult {
+            affected: tagged.len(),
+            skipped: Vec::new(),
+        })
+    }
+
+    // Replaces any existing limit for the domain
+    pub async fn set_domain_limit(
+        &self,
+        domain: &str,
+        daily_limit_seconds: u32,
+    ) -> Result<(), CortexError> {
+        let domain = crate::domain_limits::normalize_limit_domain(domain);
+        if domain.is_empty() {
+            return Err(CortexError::ValidationError("Domain cannot be empty".to_string()));
+        }
+        if daily_limit_seconds == 0 {
+            return Err(CortexError::ValidationError(
+                "Daily limit must be at least one second".to_string(),
+            ));
+        }
+        println!("Set daily limit for {} to {}s", domain, daily_limit_seconds);
+        sqlx::query(
+            "INSERT OR REPLACE INTO domain_limits (domain, daily_limit_seconds) VALUES (?, ?)",
+        )
+        .bind(&domain)
+        .bind(daily_limit_seconds as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    // Sorted by domain
+    pub async fn get_domain_limits(&self) -> Result<Vec<(String, u32)>, CortexError> {
+        let limits: Vec<(String, i64)> = sqlx::query_as(
+            "SELECT domain, daily_limit_seconds FROM domain_limits ORDER BY domain",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(limits.into_iter().map(|(domain, limit)| (domain, limit as u32)).collect())
+    }
+
+    pub async fn remove_domain_limit(&self, domain: &str) -> Result<(), CortexError> {
+        let domain = crate::domain_limits::normalize_limit_domain(domain);
+        let removed = sqlx::query("DELETE FROM domain_limits WHERE domain = ?")
+            .bind(&domain)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        if removed == 0 {
+            return Err(CortexError::NotFound(format!("No limit set for {}", domain)));
+        }
+        println!("Removed daily limit for {}", domain);
+        Ok(())
+    }
+
+    pub async fn count_rules(&self) -> Result<u64, CortexError> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM rules")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count as u64)
+    }
+
+    pub async fn count_activities(&self) -> Result<u64, CortexError> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM activities")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count as u64)
+    }
+
+    // Reports inconsistent records along with anything SQLite's own integrity
+    // check finds. Rule matches whose rule no longer exists are deleted and
+    // counted as repaired; everything else is left as is.
+    pub async fn check_integrity(&self) -> Result<IntegrityReport, CortexError> {
+        let mut report = IntegrityReport::default();
+        let mut issue = |table: &str, record_id: i64, message: String| {
+            report.issues.push(IntegrityIssue {
+                table: table.to_string(),
+                record_id,
+                message,
+            })
+        };
+
+        let mut tx = self.pool.begin().await?;
+        let problems: Vec<String> = sqlx::query_scalar("PRAGMA integrity_check")
+            .fetch_all(&mut *tx)
+            .await?;
+        for problem in problems.into_iter().filter(|problem| problem != "ok") {
+            issue("database", 0, problem);
         }
+
+        let rows = sqlx::query("SELECT * FROM rules ORDER BY id")
+            .fetch_all(&mut *tx)
+            .await?;
+        for row in &rows {
+            let rule = rule_from_row(row)?;
+            if rule.created_at < 0 {
+                issue("rules", rule.id, format!("Negative created_at {}", rule.created_at));
+            }
+            if let Err(e) = crate::rule_definition::RuleDefinition::from_json(&rule.rule_json) {
+                issue("rules", rule.id, format!("rule_json does not parse: {}", e));
+            }
+        }
+
+        let orphans: Vec<(i64, i64)> = sqlx::query_as(
+            "SELECT id, rule_id FROM rule_matches
+             WHERE rule_id NOT IN (SELECT id FROM rules) ORDER BY id",
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        for (match_id, rule_id) in &orphans {
+            issue("rule_matches", *match_id, format!("Refers to missing rule {}", rule_id));
+        }
+        sqlx::query("DELETE FROM rule_matches WHERE rule_id NOT IN (SELECT id FROM rules)")
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        report.issues_found = report.issues.len() as u32;
+        report.repaired = orphans.len() as u32;
+        Ok(report)
     }
 
-    pub async fn log_activity(&self, new_activity: NewActivityRecord) -> Result<i64, String> {
-        let mut next_id = self.next_activity_id.lock().unwrap();
-        let activity_id = *next_id;
-        *next_id += 1;
+    // Size of the backing database file, or None when nothing is stored on disk
+    pub async fn file_size_bytes(&self) -> Result<Option<u64>, CortexError> {
+        let path = self.pool.connect_options().get_filename().to_path_buf();
+        match tokio::fs::metadata(&path).await {
+            Ok(metadata) => Ok(Some(metadata.len())),
+            // In-memory databases are named, but have no file
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
 
-        let activity = ActivityRecord {
-            id: activity_id,
+    pub async fn log_activity(
+        &self,
+        new_activity: NewActivityRecord,
+    ) -> Result<ActivityRecord, CortexError> {
+        let mut activity = ActivityRecord {
+            id: 0,
             timestamp: new_activity.timestamp,
             activity: new_activity.activity,
             productive: new_activity.productive,
             app: new_activity.app,
             bundle_id: new_activity.bundle_id,
             domain: new_activity.domain,
+            notes: None,
+            duration_ms: new_activity.duration_ms,
         };
 
-        let mut activities = self.activities.lock().unwrap();
-        activities.push(activity);
-        
-        // Keep only last 1000 activities to prevent memory bloat
-        if activities.len() > 1000 {
-            let excess = activities.len() - 1000;
-            activities.drain(0..excess);
+        let mut tx = self.pool.begin().await?;
+        activity.id = insert_activity(&mut tx, None, &activity).await?;
+        evict_old_activities(&mut tx).await?;
+        tx.commit().await?;
+
+        self.notify(DatabaseEvent::ActivityLogged(activity.id));
+        Ok(activity)
+    }
+
+    pub async fn delete_activities(&self, activity_ids: &[i64]) -> Result<u32, CortexError> {
+        let mut tx = self.pool.begin().await?;
+        let mut deleted = 0;
+        for activity_id in activity_ids.iter().collect::<HashSet<_>>() {
+            deleted += sqlx::query("DELETE FROM activities WHERE id = ?")
+                .bind(*activity_id)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
         }
+        tx.commit().await?;
+        Ok(deleted as u32)
+    }
+
+    // Re-inserts archived activities. Records already present (same id and
+    // timestamp) are skipped; records whose id has since been reused get a new
+    // one. Returns how many were restored.
+    // Stores imported history without evicting anything already recorded:
+    // only the newest records that fit in the free space are kept. Returns
+    // how many were stored.
+    pub async fn import_activities(
+        &self,
+        mut records: Vec<NewActivityRecord>,
+    ) -> Result<u32, CortexError> {
+        let mut tx = self.pool.begin().await?;
+        let existing: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM activities")
+            .fetch_one(&mut *tx)
+            .await?;
+        let room = MAX_ACTIVITIES.saturating_sub(existing as usize);
+        records.sort_by(|a, b| b.timestamp.total_cmp(&a.timestamp));
+        records.truncate(room);
 
-        Ok(activity_id)
+        for record in &records {
+            let activity = ActivityRecord {
+                id: 0,
+                timestamp: record.timestamp,
+                activity: record.activity.clone(),
+                productive: record.productive,
+                app: record.app.clone(),
+                bundle_id: record.bundle_id.clone(),
+                domain: record.domain.clone(),
+                notes: None,
+                duration_ms: record.duration_ms,
+            };
+            insert_activity(&mut tx, None, &activity).await?;
+        }
+        tx.commit().await?;
+        Ok(records.len() as u32)
     }
 
-    pub async fn get_recent_activities(&self, limit: i64) -> Result<Vec<ActivityRecord>, String> {
-        let activities = self.activities.lock().unwrap();
-        let start_index = if activities.len() > limit as usize {
-            activities.len() - limit as usize
-        } else {
-            0
-        };
-        
-        let recent: Vec<ActivityRecord> = activities[start_index..].to_vec();
-        Ok(recent)
+    pub async fn restore_activities(
+        &self,
+        records: Vec<ActivityRecord>,
+    ) -> Result<u32, CortexError> {
+        let mut tx = self.pool.begin().await?;
+        let mut restored_ids = Vec::new();
+        for record in records {
+            let existing: Option<f64> =
+                sqlx::query_scalar("SELECT timestamp FROM activities WHERE id = ?")
+                    .bind(record.id)
+                    .fetch_optional(&mut *tx)
+                    .await?;
+            let id = match existing {
+                Some(timestamp) if timestamp == record.timestamp => continue,
+                Some(_) => None,
+                None => Some(record.id),
+            };
+            restored_ids.push(insert_activity(&mut tx, id, &record).await?);
+        }
+        evict_old_activities(&mut tx).await?;
+
+        let mut kept = 0;
+        for activity_id in restored_ids {
+            let exists: bool =
+                sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM activities WHERE id = ?)")
+                    .bind(activity_id)
+                    .fetch_one(&mut *tx)
+                    .await?;
+            kept += exists as u32;
+        }
+        tx.commit().await?;
+        Ok(kept)
+    }
+
+    pub async fn get_activity(
+        &self,
+        activity_id: i64,
+    ) -> Result<Option<ActivityRecord>, CortexError> {
+        sqlx::query("SELECT * FROM activities WHERE id = ?")
+            .bind(activity_id)
+            .fetch_optional(&self.pool)
+            .await?
+            .map(|row| activity_from_row(&row))
+            .transpose()
+    }
+
+    // The latest `limit` activities, oldest first
+    pub async fn get_recent_activities(
+        &self,
+        limit: i64,
+    ) -> Result<Vec<ActivityRecord>, CortexError> {
+        let rows = sqlx::query(
+            "SELECT * FROM (
+                SELECT * FROM activities ORDER BY timestamp DESC, id DESC LIMIT ?
+            ) ORDER BY timestamp, id",
+        )
+        .bind(limit.max(0))
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(activity_from_row).collect()
     }
 
     pub async fn get_activities_in_range(
         &self,
         start_time: f64,
         end_time: f64,
-    ) -> Result<Vec<ActivityRecord>, String> {
-        let activities = self.activities.lock().unwrap();
-        let filtered: Vec<ActivityRecord> = activities
+    ) -> Result<Vec<ActivityRecord>, CortexError> {
+        let rows = sqlx::query(
+            "SELECT * FROM activities WHERE timestamp >= ? AND timestamp <= ?
+             ORDER BY timestamp, id",
+        )
+        .bind(start_time)
+        .bind(end_time)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(activity_from_row).collect()
+    }
+
+    pub async fn record_rule_match(
+        &self,
+        rule_id: i64,
+        domain: String,
+    ) -> Result<i64, CortexError> {
+        let match_id = sqlx::query(
+            "INSERT INTO rule_matches (rule_id, matched_at, domain) VALUES (?, ?, ?)",
+        )
+        .bind(rule_id)
+        .bind(chrono::Utc::now().timestamp())
+        .bind(domain)
+        .execute(&self.pool)
+        .await?
+        .last_insert_rowid();
+        Ok(match_id)
+    }
+
+    // Counts a match of the rule and updates its last_triggered_at
+    pub async fn increment_rule_hit(&self, rule_id: i64) -> Result<(), CortexError> {
+        self.increment_rule_hit_at(rule_id, chrono::Utc::now().timestamp()).await
+    }
+
+    async fn increment_rule_hit_at(&self, rule_id: i64, hit_at: i64) -> Result<(), CortexError> {
+        let mut tx = self.pool.begin().await?;
+        let mut rule = fetch_rule(&mut tx, rule_id).await?.ok_or_else(rule_not_found)?;
+        rule.hit_count += 1;
+        rule.last_triggered_at = Some(hit_at);
+        save_rule(&mut tx, &rule).await?;
+
+        sqlx::query("INSERT INTO rule_hits (rule_id, hit_at) VALUES (?, ?)")
+            .bind(rule_id)
+            .bind(hit_at)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "DELETE FROM rule_hits WHERE rule_id = ? AND id NOT IN (
+                SELECT id FROM rule_hits WHERE rule_id = ? ORDER BY id DESC LIMIT ?
+            )",
+        )
+        .bind(rule_id)
+        .bind(rule_id)
+        .bind(MAX_RULE_HITS as i64)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        self.notify(DatabaseEvent::RuleUpdated(rule));
+        Ok(())
+    }
+
+    pub async fn get_rule_stats(&self, rule_id: i64, now: i64) -> Result<RuleStats, CortexError> {
+        let mut conn = self.pool.acquire().await?;
+        let rule = fetch_rule(&mut conn, rule_id).await?.ok_or_else(rule_not_found)?;
+        Ok(RuleStats {
+            rule_id,
+            hit_count: rule.hit_count,
+            last_triggered_at: rule.last_triggered_at,
+            hits_today: count_hits_since(&mut conn, rule_id, now - now.rem_euclid(86_400)).await?,
+            hits_this_week: count_hits_since(&mut conn, rule_id, now - 7 * 86_400).await?,
+        })
+    }
+
+    pub async fn record_audit(
+        &self,
+        rule_id: i64,
+        action: AuditAction,
+        details: Option<String>,
+    ) -> Result<(), CortexError> {
+        audit(&mut *self.pool.acquire().await?, rule_id, action, details).await
+    }
+
+    // Most recent first
+    pub async fn get_audit_log(
+        &self,
+        rule_id: Option<i64>,
+        limit: usize,
+    ) -> Result<Vec<AuditEntry>, CortexError> {
+        let rows = sqlx::query(
+            "SELECT * FROM audit_log WHERE ? IS NULL OR rule_id = ? ORDER BY id DESC LIMIT ?",
+        )
+        .bind(rule_id)
+        .bind(rule_id)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(audit_entry_from_row).collect()
+    }
+
+    pub async fn clear_audit_log(&self) -> Result<(), CortexError> {
+        sqlx::query("DELETE FROM audit_log").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    // An action succeeded when it was recorded without an error
+    pub async fn log_action(&self, new_log: NewActionLog) -> Result<ActionLog, CortexError> {
+        let mut log = ActionLog {
+            id: 0,
+            rule_id: new_log.rule_id,
+            action_type: new_log.action_type,
+            fired_at: chrono::Utc::now().timestamp(),
+            succeeded: new_log.error.is_none(),
+            error: new_log.error,
+            domain: new_log.domain,
+            latency_ms: new_log.latency_ms,
+        };
+        log.id = sqlx::query(
+            "INSERT INTO action_logs (rule_id, action_type, fired_at, succeeded, error, domain,
+                latency_ms)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(log.rule_id)
+        .bind(&log.action_type)
+        .bind(log.fired_at)
+        .bind(log.succeeded)
+        .bind(&log.error)
+        .bind(&log.domain)
+        .bind(log.latency_ms)
+        .execute(&self.pool)
+        .await?
+        .last_insert_rowid();
+        Ok(log)
+    }
+
+    // Most recent first
+    pub async fn get_action_log(
+        &self,
+        rule_id: Option<i64>,
+        limit: usize,
+    ) -> Result<Vec<ActionLog>, CortexError> {
+        let rows = sqlx::query(
+            "SELECT * FROM action_logs WHERE ? IS NULL OR rule_id = ? ORDER BY id DESC LIMIT ?",
+        )
+        .bind(rule_id)
+        .bind(rule_id)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(action_log_from_row).collect()
+    }
+
+    // Bounds are Unix timestamps in seconds, inclusive on both ends
+    pub async fn get_rule_matches_in_range(
+        &self,
+        start_time: i64,
+        end_time: i64,
+    ) -> Result<Vec<RuleMatchRecord>, CortexError> {
+        let rows = sqlx::query(
+            "SELECT * FROM rule_matches WHERE matched_at >= ? AND matched_at <= ? ORDER BY id",
+        )
+        .bind(start_time)
+        .bind(end_time)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(rule_match_from_row).collect()
+    }
+
+    pub async fn update_activity_note(
+        &self,
+        activity_id: i64,
+        note: String,
+    ) -> Result<(), CortexError> {
+        let note = note.trim();
+        if note.chars().count() > MAX_NOTE_LENGTH {
+            return Err(CortexError::ValidationError(format!(
+                "Note exceeds {} characters",
+                MAX_NOTE_LENGTH
+            )));
+        }
+
+        // An empty note clears the annotation
+        let updated = sqlx::query("UPDATE activities SET notes = ? WHERE id = ?")
+            .bind(if note.is_empty() { None } else { Some(note) })
+            .bind(activity_id)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        if updated == 0 {
+            return Err(CortexError::NotFound("Activity not found".to_string()));
+        }
+        Ok(())
+    }
+
+    pub async fn get_activities_with_notes(&self) -> Result<Vec<ActivityRecord>, CortexError> {
+        let rows = sqlx::query(
+            "SELECT * FROM activities WHERE notes IS NOT NULL ORDER BY timestamp, id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(activity_from_row).collect()
+    }
+
+    pub async fn search_activity_notes(
+        &self,
+        query: &str,
+    ) -> Result<Vec<ActivityRecord>, CortexError> {
+        let query = query.trim();
+        if query.is_empty() {
+            return self.get_activities_with_notes().await;
+        }
+
+        // Quoted as one phrase so FTS5 operators in the query are taken
+        // literally; the trailing * lets the last word match as a prefix
+        let phrase = format!("\"{}\" *", query.replace('"', "\"\""));
+        let rows = sqlx::query(
+            "SELECT activities.* FROM activity_notes
+             JOIN activities ON activities.id = activity_notes.rowid
+             WHERE activity_notes MATCH ?
+             ORDER BY activities.timestamp, activities.id",
+        )
+        .bind(phrase)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(activity_from_row).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rule_definition::RuleDefinition;
+
+    fn round_trip_cases() -> Vec<(&'static str, &'static str)> {
+        vec![
+            (
+                "generated",
+                r#"{"actions":[{"parameters":{"message":"Rule triggered: no instagram"},"type":"popup"}],"conditions":[{"field":"activity","operator":"contains","value":"instagram_activity"}],"name":"Rule from: no instagram","type":"basic"}"#,
+            ),
+            (
+                "unicode",
+                r#"{"actions":[{"parameters":{"message":"Fokus! 集中して 🚫📱"},"type":"popup"}],"conditions":[{"field":"title","operator":"contains","value":"Ünïcödé — ñ"}],"name":"Ünïcödé rule ✓","type":"basic"}"#,
+            ),
+            (
+                "escaped quotes",
+                r#"{"actions":[{"parameters":{"message":"Stop \"doomscrolling\" now"},"type":"popup"}],"conditions":[{"field":"title","operator":"equals","value":"\"quoted\" \\ backslash"}],"name":"Quote \"test\"","type":"basic"}"#,
+            ),
+            (
+                "control characters",
+                r#"{"actions":[{"parameters":{"message":"line one\nline two\ttabbed"},"type":"popup"}],"conditions":[{"field":"url","operator":"starts_with","value":"https://example.com/"}],"name":"Escapes","type":"basic"}"#,
+            ),
+            (
+                "deeply nested",
+                r#"{"actions":[{"parameters":{"payload":{"a":{"b":{"c":{"d":{"e":[1,2,{"f":null}]}}}}}},"type":"webhook"}],"conditions":[{"field":"elements","operator":"equals","value":{"headings":{"level1":{"level2":{"level3":{"level4":["Stories",{"deep":true}]}}}}}}],"name":"Nested","type":"basic"}"#,
+            ),
+            (
+                "empty actions",
+                r#"{"actions":[],"conditions":[{"field":"domain","operator":"equals","value":"reddit.com"}],"name":"Silent","type":"basic"}"#,
+            ),
+            (
+                "empty conditions",
+                r#"{"actions":[{"parameters":{},"type":"popup"}],"conditions":[],"name":"Always","type":"basic"}"#,
+            ),
+            (
+                "multiple conditions",
+                r#"{"actions":[{"parameters":{"message":"a"},"type":"popup"},{"parameters":{"url":"https://hooks.example.com"},"type":"webhook"}],"conditions":[{"field":"domain","operator":"contains","value":"youtube"},{"field":"title","operator":"contains","value":"shorts"}],"name":"Multi","type":"basic"}"#,
+            ),
+            (
+                "numeric values",
+                r#"{"actions":[{"parameters":{"minutes":30,"ratio":0.5},"type":"popup"}],"conditions":[{"field":"elements","operator":"equals","value":15}],"name":"Numbers","type":"basic"}"#,
+            ),
+            (
+                "boolean and null values",
+                r#"{"actions":[{"parameters":{"sticky":false,"timeout":null},"type":"popup"}],"conditions":[{"field":"elements","operator":"equals","value":true}],"name":"Literals","type":"basic"}"#,
+            ),
+        ]
+    }
+
+    fn session_activity(id: i64, minute: f64, productive: bool) -> ActivityRecord {
+        ActivityRecord {
+            id,
+            timestamp: minute * 60_000.0,
+            activity: "coding".to_string(),
+            productive,
+            app: "Zed".to_string(),
+            bundle_id: None,
+            domain: None,
+            notes: None,
+            duration_ms: None,
+        }
+    }
+
+    #[test]
+    fn a_lone_record_is_a_zero_length_session() {
+        let sessions = detect_sessions(&[session_activity(1, 10.0, true)], 5);
+
+        assert_eq!(
+            sessions,
+            vec![ActivitySession {
+                start_ms: 600_000.0,
+                end_ms: 600_000.0,
+                duration_seconds: 0.0,
+                records: vec![1],
+                productive_fraction: 1.0,
+            }]
+        );
+        assert!(detect_sessions(&[], 5).is_empty());
+    }
+
+    #[test]
+    fn records_closer_than_the_gap_share_a_session() {
+        let activities = vec![session_activity(2, 14.5, false), session_activity(1, 10.0, true)];
+
+        let sessions = detect_sessions(&activities, 5);
+
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].records, vec![1, 2]);
+        assert_eq!(sessions[0].duration_seconds, 270.0);
+        assert_eq!(sessions[0].productive_fraction, 0.5);
+    }
+
+    #[test]
+    fn gaps_split_sessions_newest_first() {
+        let activities = vec![
+            session_activity(1, 0.0, true),
+            ActivityRecord {
+                duration_ms: Some(600_000.0),
+                ..session_activity(2, 3.0, true)
+            },
+            // Within five minutes of the end of record 2's ten minutes
+            session_activity(3, 17.0, false),
+            session_activity(4, 22.0, true),
+            session_activity(5, 40.0, true),
+        ];
+
+        let sessions = detect_sessions(&activities, 5);
+
+        let records: Vec<Vec<i64>> =
+            sessions.iter().map(|session| session.records.clone()).collect();
+        assert_eq!(records, vec![vec![5], vec![4], vec![1, 2, 3]]);
+        assert_eq!(sessions[2].end_ms, 17.0 * 60_000.0);
+        assert_eq!(sessions[2].productive_fraction, 2.0 / 3.0);
+    }
+
+    async fn store_round_trip_cases(db: &Database) {
+        for (label, rule_json) in round_trip_cases() {
+            db.create_rule(NewRule {
+                name: label.to_string(),
+                natural_language: format!("round trip: {}", label),
+                rule_json: rule_json.to_string(),
+                priority: 0,
+                tags: Vec::new(),
+                expires_at: None,
+            })
+            .await
+            .unwrap();
+        }
+    }
+
+    async fn assert_round_trip_cases(db: &Database) {
+        let cases = round_trip_cases();
+        let rules = db.get_all_rules().await.unwrap();
+        assert_eq!(rules.len(), cases.len());
+
+        for (label, rule_json) in &cases {
+            let stored = rules.iter().find(|rule| rule.name == *label).unwrap();
+            assert_eq!(stored.rule_json, *rule_json, "storage altered rule_json for {}", label);
+
+            let definition = RuleDefinition::from_json(&stored.rule_json)
+                .unwrap_or_else(|e| panic!("{} did not parse: {}", label, e));
+            let reserialized = definition.to_json().unwrap();
+            assert_eq!(reserialized, *rule_json, "round trip changed rule_json for {}", label);
+        }
+    }
+
+    fn temp_db_path() -> std::path::PathBuf {
+        static NEXT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let n = NEXT.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        std::env::temp_dir().join(format!("cortex-db-{}-{}.sqlite", std::process::id(), n))
+    }
+
+    #[tokio::test]
+    async fn rule_json_round_trips_through_in_memory_storage() {
+        let db = Database::in_memory().await.unwrap();
+        store_round_trip_cases(&db).await;
+        assert_round_trip_cases(&db).await;
+    }
+
+    #[tokio::test]
+    async fn rule_json_round_trips_through_a_sqlite_file() {
+        let path = temp_db_path();
+        let db = Database::new(&path).await.unwrap();
+        store_round_trip_cases(&db).await;
+        db.pool.close().await;
+
+        let db = Database::new(&path).await.unwrap();
+        assert_round_trip_cases(&db).await;
+        db.pool.close().await;
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn rules_and_activities_survive_reopening_the_file() {
+        let path = temp_db_path();
+        let db = Database::new(&path).await.unwrap();
+        let rule = db
+            .create_rule(NewRule {
+                name: "Block reddit".to_string(),
+                natural_language: "no reddit".to_string(),
+                rule_json: "{}".to_string(),
+                priority: 3,
+                tags: vec!["social".to_string()],
+                expires_at: None,
+            })
+            .await
+            .unwrap();
+        db.toggle_rule(rule.id).await.unwrap();
+        db.log_activity(NewActivityRecord {
+            timestamp: 1.0,
+            activity: "browsing".to_string(),
+            productive: false,
+            app: "Chrome".to_string(),
+            bundle_id: None,
+            domain: Some("reddit.com".to_string()),
+            duration_ms: Some(1500.0),
+        })
+        .await
+        .unwrap();
+        db.pool.close().await;
+
+        // The schema is already up to date, so opening again applies nothing
+        let db = Database::new(&path).await.unwrap();
+        let stored = db.get_rule_by_id(rule.id).await.unwrap().unwrap();
+        assert_eq!(stored.name, "Block reddit");
+        assert_eq!(stored.priority, 3);
+        assert_eq!(stored.tags, ["social"]);
+        assert!(!stored.is_active);
+        let activities = db.get_recent_activities(10).await.unwrap();
+        assert_eq!(activities.len(), 1);
+        assert_eq!(activities[0].duration_ms, Some(1500.0));
+        assert_eq!(db.next_rule_id().await.unwrap(), rule.id + 1);
+        let size = db.file_size_bytes().await.unwrap().unwrap();
+        assert_eq!(size, std::fs::metadata(&path).unwrap().len());
+        assert!(Database::in_memory().await.unwrap().file_size_bytes().await.unwrap().is_none());
+        db.pool.close().await;
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn writes_broadcast_database_events() {
+        let db = Database::in_memory().await.unwrap();
+        let mut events = db.subscribe();
+
+        let rule = db
+            .create_rule(NewRule {
+                name: "rule".to_string(),
+                natural_language: String::new(),
+                rule_json: "{}".to_string(),
+                priority: 0,
+                tags: Vec::new(),
+                expires_at: None,
+            })
+            .await
+            .unwrap();
+        db.toggle_rule(rule.id).await.unwrap();
+        db.delete_rule(rule.id).await.unwrap();
+        db.extension_log_persisted(1_700_000_000_000);
+
+        let event = events.recv().await.unwrap();
+        assert!(matches!(event, DatabaseEvent::RuleCreated(created) if created.id == rule.id));
+        let event = events.recv().await.unwrap();
+        assert!(matches!(event, DatabaseEvent::RuleUpdated(updated) if !updated.is_active));
+        let event = events.recv().await.unwrap();
+        assert!(matches!(event, DatabaseEvent::RuleDeleted(id) if id == rule.id));
+        let event = events.recv().await.unwrap();
+        assert!(matches!(event, DatabaseEvent::ExtensionLogPersisted(1_700_000_000_000)));
+    }
+
+    #[tokio::test]
+    async fn active_rules_are_ordered_by_priority() {
+        let db = Database::in_memory().await.unwrap();
+        for (name, priority) in [("low", 10), ("high", -5), ("default", 0)] {
+            db.create_rule(NewRule {
+                name: name.to_string(),
+                natural_language: String::new(),
+                rule_json: "{}".to_string(),
+                priority,
+                tags: Vec::new(),
+                expires_at: None,
+            })
+            .await
+            .unwrap();
+        }
+
+        let names = |rules: Vec<Rule>| rules.into_iter().map(|rule| rule.name).collect::<Vec<_>>();
+        assert_eq!(names(db.get_active_rules().await.unwrap()), ["high", "default", "low"]);
+
+        let low = db.get_rule_by_id(1).await.unwrap().unwrap();
+        db.set_rule_priority(low.id, -10).await.unwrap();
+        assert_eq!(names(db.get_active_rules().await.unwrap()), ["low", "high", "default"]);
+        assert!(db.set_rule_priority(99, 0).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rule_tags_are_case_insensitive_and_idempotent() {
+        let db = Database::in_memory().await.unwrap();
+        for (name, tags) in [("reddit", vec!["Social-Media", "social-media"]), ("jira", vec![])] {
+            db.create_rule(NewRule {
+                name: name.to_string(),
+                natural_language: String::new(),
+                rule_json: "{}".to_string(),
+                priority: 0,
+                tags: tags.into_iter().map(String::from).collect(),
+                expires_at: None,
+            })
+            .await
+            .unwrap();
+        }
+        let reddit = db.get_rule_by_id(1).await.unwrap().unwrap();
+        assert_eq!(reddit.tags, ["Social-Media"]);
+
+        db.add_rule_tag(2, "work").await.unwrap();
+        db.add_rule_tag(2, "WORK").await.unwrap();
+        db.remove_rule_tag(2, "gaming").await.unwrap();
+        assert_eq!(db.get_rule_by_id(2).await.unwrap().unwrap().tags, ["work"]);
+        assert!(db.add_rule_tag(2, " ").await.is_err());
+        assert!(db.add_rule_tag(99, "work").await.is_err());
+
+        let tagged = db.get_rules_by_tag("SOCIAL-media").await.unwrap();
+        assert_eq!(tagged.len(), 1);
+        assert_eq!(tagged[0].name, "reddit");
+        assert!(db.get_rules_by_tag("gaming").await.unwrap().is_empty());
+
+        db.remove_rule_tag(1, "social-MEDIA").await.unwrap();
+        assert!(db.get_rules_by_tag("social-media").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn imported_rules_overwrite_by_name_only_when_asked() {
+        let db = Database::in_memory().await.unwrap();
+        let new_rule = |name: &str, rule_json: &str| NewRule {
+            name: name.to_string(),
+            natural_language: String::new(),
+            rule_json: rule_json.to_string(),
+            priority: 0,
+            tags: Vec::new(),
+            expires_at: None,
+        };
+        let original = db.create_rule(new_rule("reddit", "{}")).await.unwrap();
+
+        let imported = vec![(new_rule("reddit", "[]"), false), (new_rule("news", "{}"), true)];
+        assert_eq!(db.import_rules(imported.clone(), false).await.unwrap().len(), 2);
+        assert_eq!(db.count_rules().await.unwrap(), 3);
+
+        assert_eq!(db.import_rules(imported, true).await.unwrap().len(), 2);
+        assert_eq!(db.count_rules().await.unwrap(), 3);
+        let replaced = db.get_rule_by_id(original.id).await.unwrap().unwrap();
+        assert_eq!(replaced.rule_json, "[]");
+        assert!(!replaced.is_active);
+        assert_eq!(replaced.created_at, original.created_at);
+    }
+
+    #[test]
+    fn rule_schedules_check_day_and_time_window() {
+        let at = |day: u32, hour: u32, minute: u32| {
+            // 2024-01-07 was a Sunday
+            chrono::NaiveDate::from_ymd_opt(2024, 1, 7 + day)
+                .unwrap()
+                .and_hms_opt(hour, minute, 0)
+                .unwrap()
+        };
+        let work_hours = RuleSchedule {
+            days_of_week: vec![1, 2, 3, 4, 5],
+            start_time_minutes: 9 * 60,
+            end_time_minutes: 17 * 60,
+        };
+        assert!(work_hours.is_active_at(at(1, 9, 0)));
+        assert!(work_hours.is_active_at(at(5, 16, 59)));
+        assert!(!work_hours.is_active_at(at(5, 17, 0)));
+        assert!(!work_hours.is_active_at(at(6, 12, 0)));
+
+        let overnight = RuleSchedule {
+            days_of_week: Vec::new(),
+            start_time_minutes: 22 * 60,
+            end_time_minutes: 2 * 60,
+        };
+        assert!(overnight.is_active_at(at(0, 23, 30)));
+        assert!(overnight.is_active_at(at(3, 1, 0)));
+        assert!(!overnight.is_active_at(at(3, 12, 0)));
+
+        assert!(RuleSchedule { days_of_week: vec![7], ..work_hours.clone() }.validate().is_err());
+        assert!(RuleSchedule { end_time_minutes: 540, ..work_hours }.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn expired_rules_are_deactivated() {
+        let db = Database::in_memory().await.unwrap();
+        let now = chrono::Utc::now().timestamp();
+        for expires_at in [Some(now - 60), Some(now + 3600), None] {
+            db.create_rule(NewRule {
+                name: format!("expires {:?}", expires_at),
+                natural_language: String::new(),
+                rule_json: "{}".to_string(),
+                priority: 0,
+                tags: Vec::new(),
+                expires_at,
+            })
+            .await
+            .unwrap();
+        }
+
+        assert_eq!(db.deactivate_expired_rules().await.unwrap(), 1);
+        assert_eq!(db.deactivate_expired_rules().await.unwrap(), 0);
+        let active = db.get_active_rules().await.unwrap();
+        assert_eq!(active.iter().map(|rule| rule.id).collect::<Vec<_>>(), [2, 3]);
+
+        db.set_rule_expiry(2, Some(now - 1)).await.unwrap();
+        assert_eq!(db.deactivate_expired_rules().await.unwrap(), 1);
+        assert!(db.set_rule_expiry(99, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rule_can_be_fetched_by_id() {
+        let db = Database::in_memory().await.unwrap();
+        let created = db
+            .create_rule(NewRule {
+                name: "Block reddit".to_string(),
+                natural_language: "no reddit".to_string(),
+                rule_json: r#"{"actions":[],"conditions":[],"name":"Block reddit","type":"basic"}"#
+                    .to_string(),
+                priority: 0,
+                tags: Vec::new(),
+                expires_at: None,
+            })
+            .await
+            .unwrap();
+
+        let fetched = db.get_rule_by_id(created.id).await.unwrap().unwrap();
+
+        assert_eq!(fetched.id, created.id);
+        assert_eq!(fetched.name, "Block reddit");
+        assert_eq!(fetched.natural_language, "no reddit");
+        assert_eq!(fetched.rule_json, created.rule_json);
+        assert!(fetched.is_active);
+        assert_eq!(fetched.created_at, created.created_at);
+        assert_eq!(fetched.last_triggered_at, None);
+        assert!(db.get_rule_by_id(created.id + 1).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn notes_are_searched_through_the_full_text_index() {
+        let db = Database::in_memory().await.unwrap();
+        let mut ids = Vec::new();
+        for minute in 0..3 {
+            let activity = db
+                .log_activity(NewActivityRecord {
+                    timestamp: minute as f64 * 60_000.0,
+                    activity: "coding".to_string(),
+                    productive: true,
+                    app: "Zed".to_string(),
+                    bundle_id: None,
+                    domain: None,
+                    duration_ms: None,
+                })
+                .await
+                .unwrap();
+            ids.push(activity.id);
+        }
+        db.update_activity_note(ids[0], "Refactored the parser".to_string()).await.unwrap();
+        db.update_activity_note(ids[1], "Parser \"bug\" hunt: AND OR".to_string()).await.unwrap();
+        db.update_activity_note(ids[2], "Lunch".to_string()).await.unwrap();
+        let found = |query: &str| {
+            let db = &db;
+            let query = query.to_string();
+            async move {
+                let matches = db.search_activity_notes(&query).await.unwrap();
+                matches.into_iter().map(|activity| activity.id).collect::<Vec<_>>()
+            }
+        };
+
+        assert_eq!(found("parser").await, vec![ids[0], ids[1]]);
+        assert_eq!(found("pars").await, vec![ids[0], ids[1]]);
+        assert_eq!(found("\"bug\" hunt: AND").await, vec![ids[1]]);
+        assert_eq!(found("").await, ids);
+
+        // Edits and deletions reach the index through the triggers
+        db.update_activity_note(ids[0], "Wrote docs".to_string()).await.unwrap();
+        db.delete_activities(&[ids[1]]).await.unwrap();
+        assert!(found("parser").await.is_empty());
+        assert_eq!(found("docs").await, vec![ids[0]]);
+    }
+
+    #[tokio::test]
+    async fn domain_limits_survive_reopening_the_file() {
+        let path = temp_db_path();
+        let db = Database::new(&path).await.unwrap();
+        db.set_domain_limit("www.Reddit.com", 1800).await.unwrap();
+        db.set_domain_limit("youtube.com", 600).await.unwrap();
+        db.set_domain_limit("youtube.com", 900).await.unwrap();
+        db.remove_domain_limit("youtube.com").await.unwrap();
+        db.set_domain_limit("twitter.com", 300).await.unwrap();
+        db.pool.close().await;
+
+        let db = Database::new(&path).await.unwrap();
+        assert_eq!(
+            db.get_domain_limits().await.unwrap(),
+            vec![("reddit.com".to_string(), 1800), ("twitter.com".to_string(), 300)]
+        );
+        db.pool.close().await;
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn encrypted_database_needs_its_key_to_reopen() {
+        let path = temp_db_path();
+        let keyed = |key: &str| DatabaseConfig {
+            encryption_key: Some(key.to_string()),
+        };
+        let db = Database::with_config(&path, keyed("hunter2")).await.unwrap();
+        let rule = db
+            .create_rule(NewRule {
+                name: "Block reddit".to_string(),
+                natural_language: "no reddit".to_string(),
+                rule_json: "{}".to_string(),
+                priority: 0,
+                tags: Vec::new(),
+                expires_at: None,
+            })
+            .await
+            .unwrap();
+        db.pool.close().await;
+
+        let on_disk = std::fs::read(&path).unwrap();
+        assert!(!on_disk.windows(12).any(|window| window == b"Block reddit"));
+        assert!(Database::new(&path).await.is_err());
+        assert!(Database::with_config(&path, keyed("wrong")).await.is_err());
+
+        let db = Database::with_config(&path, keyed("hunter2")).await.unwrap();
+        let stored = db.get_rule_by_id(rule.id).await.unwrap().unwrap();
+        assert_eq!(stored.name, "Block reddit");
+        assert!(db.change_password("wrong", "it's new").await.is_err());
+        db.change_password("hunter2", "it's new").await.unwrap();
+        db.pool.close().await;
+
+        assert!(Database::with_config(&path, keyed("hunter2")).await.is_err());
+        let db = Database::with_config(&path, keyed("it's new")).await.unwrap();
+        assert_eq!(db.count_rules().await.unwrap(), 1);
+        db.pool.close().await;
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn update_rule_preserves_identity_and_state() {
+        let db = Database::in_memory().await.unwrap();
+        let created = db
+            .create_rule(NewRule {
+                name: "Blcok reddit".to_string(),
+                natural_language: "no reddit".to_string(),
+                rule_json: "{}".to_string(),
+                priority: 0,
+                tags: Vec::new(),
+                expires_at: None,
+            })
+            .await
+            .unwrap();
+        db.toggle_rule(created.id).await.unwrap();
+
+        let updated = db
+            .update_rule(
+                created.id,
+                "Block reddit".to_string(),
+                "no reddit".to_string(),
+                "{}".to_string(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(updated.name, "Block reddit");
+        assert_eq!(updated.created_at, created.created_at);
+        assert!(!updated.is_active);
+        assert!(db
+            .update_rule(created.id + 1, String::new(), String::new(), String::new())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn imported_history_never_evicts_recorded_activity() {
+        let db = Database::in_memory().await.unwrap();
+        let record = |timestamp: f64| NewActivityRecord {
+            timestamp,
+            activity: "browsing".to_string(),
+            productive: false,
+            app: "Chrome".to_string(),
+            bundle_id: None,
+            domain: Some("reddit.com".to_string()),
+            duration_ms: None,
+        };
+        for index in 0..MAX_ACTIVITIES - 2 {
+            db.log_activity(record(1_000.0 + index as f64)).await.unwrap();
+        }
+
+        let history = (1..=5).map(|timestamp| record(timestamp as f64)).collect();
+        assert_eq!(db.import_activities(history).await.unwrap(), 2);
+
+        let stored = db.get_activities_in_range(0.0, f64::INFINITY).await.unwrap();
+        assert_eq!(stored.len(), MAX_ACTIVITIES);
+        assert_eq!(stored[0].timestamp, 4.0);
+        assert_eq!(stored[1].timestamp, 5.0);
+        assert_eq!(stored[2].timestamp, 1_000.0);
+        assert_eq!(db.import_activities(vec![record(6.0)]).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn restored_activities_are_not_duplicated() {
+        let db = Database::in_memory().await.unwrap();
+        for timestamp in [1.0, 2.0] {
+            db.log_activity(NewActivityRecord {
+                timestamp,
+                activity: "browsing".to_string(),
+                productive: false,
+                app: "Chrome".to_string(),
+                bundle_id: None,
+                domain: Some("reddit.com".to_string()),
+                duration_ms: None,
+            })
+            .await
+            .unwrap();
+        }
+        let archived = db.get_activities_in_range(0.0, 1.0).await.unwrap();
+
+        assert_eq!(db.delete_activities(&[archived[0].id]).await.unwrap(), 1);
+        assert_eq!(db.restore_activities(archived.clone()).await.unwrap(), 1);
+        assert_eq!(db.restore_activities(archived).await.unwrap(), 0);
+
+        let timestamps: Vec<f64> = db
+            .get_activities_in_range(0.0, 10.0)
+            .await
+            .unwrap()
             .iter()
-            .filter(|activity| activity.timestamp >= start_time && activity.timestamp <= end_time)
-            .cloned()
+            .map(|activity| activity.timestamp)
+            .collect();
+        assert_eq!(timestamps, vec![1.0, 2.0]);
+    }
+
+    #[tokio::test]
+    async fn integrity_check_reports_bad_rules_and_removes_orphaned_matches() {
+        let db = Database::in_memory().await.unwrap();
+        let rule = db
+            .create_rule(NewRule {
+                name: "broken".to_string(),
+                natural_language: String::new(),
+                rule_json: "not json".to_string(),
+                priority: 0,
+                tags: Vec::new(),
+                expires_at: None,
+            })
+            .await
+            .unwrap();
+        db.record_rule_match(rule.id, "reddit.com".to_string()).await.unwrap();
+        let orphan = db.record_rule_match(rule.id + 1, "reddit.com".to_string()).await.unwrap();
+
+        let report = db.check_integrity().await.unwrap();
+
+        let issues: Vec<(&str, i64)> =
+            report.issues.iter().map(|issue| (issue.table.as_str(), issue.record_id)).collect();
+        assert_eq!(issues, vec![("rules", rule.id), ("rule_matches", orphan)]);
+        assert_eq!(report.issues_found, 2);
+        assert_eq!(report.repaired, 1);
+        assert_eq!(db.check_integrity().await.unwrap().repaired, 0);
+    }
+
+    #[tokio::test]
+    async fn rule_hits_are_counted_by_day_and_week() {
+        let db = Database::in_memory().await.unwrap();
+        let rule = db
+            .create_rule(NewRule {
+                name: "Block reddit".to_string(),
+                natural_language: "no reddit".to_string(),
+                rule_json: "{}".to_string(),
+                priority: 0,
+                tags: Vec::new(),
+                expires_at: None,
+            })
+            .await
+            .unwrap();
+        // Thursday 2023-11-16 12:00 UTC
+        let now = 1_700_136_000;
+        for hit_at in [now - 8 * 86_400, now - 2 * 86_400, now - 13 * 3_600, now - 60, now] {
+            db.increment_rule_hit_at(rule.id, hit_at).await.unwrap();
+        }
+
+        let stats = db.get_rule_stats(rule.id, now).await.unwrap();
+
+        assert_eq!(
+            stats,
+            RuleStats {
+                rule_id: rule.id,
+                hit_count: 5,
+                last_triggered_at: Some(now),
+                hits_today: 2,
+                hits_this_week: 4,
+            }
+        );
+        assert_eq!(db.get_rule_by_id(rule.id).await.unwrap().unwrap().hit_count, 5);
+        assert!(db.increment_rule_hit(rule.id + 1).await.is_err());
+        db.delete_rule(rule.id).await.unwrap();
+        assert!(db.get_rule_stats(rule.id, now).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn audit_log_outlives_deleted_rules() {
+        let db = Database::in_memory().await.unwrap();
+        let rule = db
+            .create_rule(NewRule {
+                name: "Block reddit".to_string(),
+                natural_language: "no reddit".to_string(),
+                rule_json: "{}".to_string(),
+                priority: 0,
+                tags: Vec::new(),
+                expires_at: None,
+            })
+            .await
+            .unwrap();
+        db.toggle_rule(rule.id).await.unwrap();
+        db.record_audit(rule.id, AuditAction::Snoozed(15), None).await.unwrap();
+        db.delete_rule(rule.id).await.unwrap();
+
+        let actions: Vec<AuditAction> = db
+            .get_audit_log(Some(rule.id), 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.action)
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                AuditAction::Deleted,
+                AuditAction::Snoozed(15),
+                AuditAction::Toggled(false),
+                AuditAction::Created,
+            ]
+        );
+        assert_eq!(db.get_audit_log(None, 2).await.unwrap().len(), 2);
+        assert!(db.get_audit_log(Some(rule.id + 1), 10).await.unwrap().is_empty());
+
+        db.clear_audit_log().await.unwrap();
+        assert!(db.get_audit_log(None, 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn automatic_rule_changes_are_audited() {
+        let db = Database::in_memory().await.unwrap();
+        let new_rule = |name: &str, expires_at| NewRule {
+            name: name.to_string(),
+            natural_language: String::new(),
+            rule_json: "{}".to_string(),
+            priority: 0,
+            tags: Vec::new(),
+            expires_at,
+        };
+        let now = chrono::Utc::now().timestamp();
+        let expired = db.create_rule(new_rule("Expired", Some(now - 60))).await.unwrap();
+        let scheduled = db.create_rule(new_rule("Scheduled", None)).await.unwrap();
+        db.toggle_rule(scheduled.id).await.unwrap();
+        db.schedule_rule_activation(scheduled.id, now - 1).await.unwrap();
+
+        assert_eq!(db.deactivate_expired_rules().await.unwrap(), 1);
+        assert_eq!(db.apply_due_activations(now).await.unwrap().len(), 1);
+        db.update_rule_json(scheduled.id, "{\"name\":\"x\"}".to_string()).await.unwrap();
+
+        let expiry = db.get_audit_log(Some(expired.id), 1).await.unwrap().remove(0);
+        assert_eq!(expiry.action, AuditAction::Toggled(false));
+        assert_eq!(expiry.details.as_deref(), Some("Expired"));
+        let actions: Vec<AuditAction> = db
+            .get_audit_log(Some(scheduled.id), 2)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.action)
             .collect();
-        Ok(filtered)
+        assert_eq!(actions, vec![AuditAction::Updated, AuditAction::Toggled(true)]);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn bulk_operations_skip_unknown_rules() {
+        let db = Database::in_memory().await.unwrap();
+        let mut ids = Vec::new();
+        for (name, tag) in [("Block reddit", "social"), ("Block x", "Social"), ("Focus", "work")] {
+            let rule = db
+                .create_rule(NewRule {
+                    name: name.to_string(),
+                    natural_language: name.to_string(),
+                    rule_json: "{}".to_string(),
+                    priority: 0,
+                    tags: vec![tag.to_string()],
+                    expires_at: None,
+                })
+                .await
+                .unwrap();
+            ids.push(rule.id);
+        }
+        let missing = ids[2] + 1;
+
+        let disabled =
+            db.set_rules_active(&[ids[0], missing, ids[1], ids[0]], false).await.unwrap();
+        assert_eq!(disabled, BulkResult { affected: 2, skipped: vec![missing] });
+        let active: Vec<i64> =
+            db.get_active_rules().await.unwrap().into_iter().map(|rule| rule.id).collect();
+        assert_eq!(active, vec![ids[2]]);
+
+        let deleted = db.delete_rules(&[missing, ids[2]]).await.unwrap();
+        assert_eq!(deleted, BulkResult { affected: 1, skipped: vec![missing] });
+
+        let by_tag = db.delete_rules_by_tag(" social ").await.unwrap();
+        assert_eq!(by_tag.affected, 2);
+        assert!(db.get_all_rules().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn activating_a_profile_enables_only_its_rules() {
+        let db = Database::in_memory().await.unwrap();
+        let mut ids = Vec::new();
+        for name in ["Block reddit", "Block youtube", "Slack reminder"] {
+            let rule = db
+                .create_rule(NewRule {
+                    name: name.to_string(),
+                    natural_language: name.to_string(),
+                    rule_json: "{}".to_string(),
+                    priority: 0,
+                    tags: Vec::new(),
+                    expires_at: None,
+                })
+                .await
+                .unwrap();
+            ids.push(rule.id);
+        }
+        let work = db
+            .create_profile("Work".to_string(), String::new(), vec![ids[0], ids[1]])
+            .await
+            .unwrap();
+        let relax = db
+            .create_profile(" Relax ".to_string(), String::new(), vec![ids[2], ids[2]])
+            .await
+            .unwrap();
+        assert_eq!(relax.name, "Relax");
+        assert_eq!(relax.rule_ids, vec![ids[2]]);
+        assert!(db.create_profile("Study".to_string(), String::new(), vec![99]).await.is_err());
+        let active_ids = |rules: Vec<Rule>| -> Vec<i64> {
+            let mut active: Vec<i64> = rules.into_iter().map(|rule| rule.id).collect();
+            active.sort();
+            active
+        };
+
+        db.activate_profile(work.id).await.unwrap();
+        assert_eq!(active_ids(db.get_active_rules().await.unwrap()), vec![ids[0], ids[1]]);
+
+        db.activate_profile(relax.id).await.unwrap();
+        assert_eq!(active_ids(db.get_active_rules().await.unwrap()), vec![ids[2]]);
+
+        db.delete_profile(work.id).await.unwrap();
+        assert!(db.activate_profile(work.id).await.is_err());
+        assert_eq!(db.get_profiles().await.unwrap(), vec![relax]);
+    }
+
+    #[tokio::test]
+    async fn overlapping_profile_schedules_pick_the_lowest_id() {
+        let db = Database::in_memory().await.unwrap();
+        let mut profiles = Vec::new();
+        for name in ["Work", "Study", "Relax"] {
+            let profile =
+                db.create_profile(name.to_string(), String::new(), Vec::new()).await.unwrap();
+            profiles.push(profile.id);
+        }
+        let window = |start_time_minutes, end_time_minutes| ProfileSchedule {
+            days_of_week: vec![1, 2, 3, 4, 5],
+            start_time_minutes,
+            end_time_minutes,
+        };
+        db.set_profile_schedule(profiles[2], Some(window(18 * 60, 23 * 60))).await.unwrap();
+        db.set_profile_schedule(profiles[1], Some(window(16 * 60, 20 * 60))).await.unwrap();
+        db.set_profile_schedule(profiles[0], Some(window(9 * 60, 17 * 60))).await.unwrap();
+        assert!(db.set_profile_schedule(profiles[0], Some(window(60, 60))).await.is_err());
+        assert!(db.set_profile_schedule(99, None).await.is_err());
+        let all = db.get_profiles().await.unwrap();
+        let scheduled_at = |time: &str| -> Vec<i64> {
+            // 2024-01-15 is a Monday
+            let local = chrono::NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M").unwrap();
+            scheduled_profiles(&all, local).into_iter().map(|profile| profile.id).collect()
+        };
+
+        assert_eq!(scheduled_at("2024-01-15 08:59"), Vec::<i64>::new());
+        assert_eq!(scheduled_at("2024-01-15 16:30"), vec![profiles[0], profiles[1]]);
+        assert_eq!(scheduled_at("2024-01-15 19:00"), vec![profiles[1], profiles[2]]);
+        assert_eq!(scheduled_at("2024-01-13 19:00"), Vec::<i64>::new());
+    }
+}
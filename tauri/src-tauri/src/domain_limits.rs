@@ -0,0 +1,143 @@
+use crate::analytics::{domain_time_stats_from_logs, DomainTimeStat};
+use crate::ExtensionLog;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DomainLimit {
+    pub domain: String,
+    pub daily_limit_seconds: u32,
+    pub used_seconds: f64,
+    pub breached: bool,
+}
+
+// Limits are stored lowercase without a leading "www."
+pub fn normalize_limit_domain(domain: &str) -> String {
+    domain.trim().trim_start_matches("www.").to_lowercase()
+}
+
+// A limit on "reddit.com" also covers its subdomains
+pub fn domain_covers(limit_domain: &str, domain: &str) -> bool {
+    let domain = normalize_limit_domain(domain);
+    domain == limit_domain || domain.ends_with(&format!(".{}", limit_domain))
+}
+
+// Time spent on each domain, from logs since `day_start_ms`
+pub fn domain_time_today(
+    logs: &[ExtensionLog],
+    day_start_ms: f64,
+    now_ms: f64,
+    max_dwell_seconds: u32,
+) -> Vec<DomainTimeStat> {
+    let today: Vec<ExtensionLog> = logs
+        .iter()
+        .filter(|log| log.timestamp >= day_start_ms)
+        .cloned()
+        .collect();
+    domain_time_stats_from_logs(&today, now_ms, max_dwell_seconds)
+}
+
+// Seconds spent on `limit_domain` and its subdomains
+pub fn seconds_on_domain(stats: &[DomainTimeStat], limit_domain: &str) -> f64 {
+    stats
+        .iter()
+        .filter(|stat| domain_covers(limit_domain, &stat.domain))
+        .map(|stat| stat.total_seconds)
+        .sum()
+}
+
+// Today's usage for each `(domain, daily_limit_seconds)`, from logs since
+// `day_start_ms`
+pub fn domain_limit_usage(
+    limits: &[(String, u32)],
+    logs: &[ExtensionLog],
+    day_start_ms: f64,
+    now_ms: f64,
+    max_dwell_seconds: u32,
+) -> Vec<DomainLimit> {
+    let stats = domain_time_today(logs, day_start_ms, now_ms, max_dwell_seconds);
+
+    limits
+        .iter()
+        .map(|(domain, daily_limit_seconds)| {
+            let used_seconds = seconds_on_domain(&stats, domain);
+            DomainLimit {
+                domain: domain.clone(),
+                daily_limit_seconds: *daily_limit_seconds,
+                used_seconds,
+                breached: used_seconds >= *daily_limit_seconds as f64,
+            }
+        })
+        .collect()
+}
+
+// Remembers which limits were already reported so each breach is announced
+// once per domain per day
+#[derive(Debug, Default)]
+pub struct DomainLimitAlerts {
+    alerted_on: HashMap<String, NaiveDate>,
+}
+
+impl DomainLimitAlerts {
+    pub fn newly_breached(&mut self, limits: &[DomainLimit], today: NaiveDate) -> Vec<DomainLimit> {
+        limits
+            .iter()
+            .filter(|limit| limit.breached)
+            .filter(|limit| self.alerted_on.insert(limit.domain.clone(), today) != Some(today))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(timestamp: f64, domain: &str) -> ExtensionLog {
+        ExtensionLog {
+            timestamp,
+            domain: domain.to_string(),
+            activity: "browsing".to_string(),
+            url: format!("https://{}/", domain),
+            title: domain.to_string(),
+            elements: None,
+            title_sentiment: None,
+            url_category: None,
+        }
+    }
+
+    #[test]
+    fn breach_is_announced_once_per_day() {
+        const DAY_MS: f64 = 86_400_000.0;
+        let limits = vec![("reddit.com".to_string(), 120)];
+        let first_day = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+        let second_day = NaiveDate::from_ymd_opt(1970, 1, 2).unwrap();
+        let mut alerts = DomainLimitAlerts::default();
+        let mut logs = vec![log(0.0, "www.reddit.com"), log(60_000.0, "old.reddit.com")];
+
+        let usage = domain_limit_usage(&limits, &logs, 0.0, 90_000.0, 300);
+        assert_eq!(usage[0].used_seconds, 90.0);
+        assert!(!usage[0].breached);
+        assert!(alerts.newly_breached(&usage, first_day).is_empty());
+
+        logs.push(log(120_000.0, "reddit.com"));
+        let usage = domain_limit_usage(&limits, &logs, 0.0, 130_000.0, 300);
+        assert!(usage[0].breached);
+        assert_eq!(alerts.newly_breached(&usage, first_day).len(), 1);
+
+        logs.push(log(180_000.0, "reddit.com"));
+        let usage = domain_limit_usage(&limits, &logs, 0.0, 200_000.0, 300);
+        assert!(alerts.newly_breached(&usage, first_day).is_empty());
+
+        logs.push(log(DAY_MS, "reddit.com"));
+        let usage = domain_limit_usage(&limits, &logs, DAY_MS, DAY_MS + 60_000.0, 300);
+        assert!(!usage[0].breached);
+        assert!(alerts.newly_breached(&usage, second_day).is_empty());
+
+        logs.push(log(DAY_MS + 150_000.0, "github.com"));
+        let usage = domain_limit_usage(&limits, &logs, DAY_MS, DAY_MS + 200_000.0, 300);
+        assert_eq!(usage[0].used_seconds, 150.0);
+        assert_eq!(alerts.newly_breached(&usage, second_day).len(), 1);
+    }
+}
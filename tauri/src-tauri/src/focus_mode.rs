@@ -0,0 +1,148 @@
+use crate::domain_limits::{domain_covers, normalize_limit_domain};
+use crate::ExtensionLog;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FocusSession {
+    pub id: u64,
+    pub started_at_ms: f64,
+    pub allowed_domains: Vec<String>,
+    pub duration_minutes: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FocusSessionSummary {
+    pub session_id: u64,
+    pub violations: u32,
+    pub compliant_minutes: f64,
+    // Sorted and without duplicates
+    pub violation_domains: Vec<String>,
+}
+
+// A running session. Each log counts until the next one arrives, so time on a
+// disallowed domain is only known once the user moves on or the session ends.
+#[derive(Debug, Clone)]
+pub struct ActiveFocusSession {
+    pub session: FocusSession,
+    violations: u32,
+    violation_domains: BTreeSet<String>,
+    violation_ms: f64,
+    // When the current disallowed visit started
+    violating_since: Option<f64>,
+}
+
+impl ActiveFocusSession {
+    pub fn new(
+        id: u64,
+        started_at_ms: f64,
+        allowed_domains: &[String],
+        duration_minutes: Option<u32>,
+    ) -> Self {
+        let mut allowed_domains: Vec<String> = allowed_domains
+            .iter()
+            .map(|domain| normalize_limit_domain(domain))
+            .filter(|domain| !domain.is_empty())
+            .collect();
+        allowed_domains.sort();
+        allowed_domains.dedup();
+
+        ActiveFocusSession {
+            session: FocusSession {
+                id,
+                started_at_ms,
+                allowed_domains,
+                duration_minutes,
+            },
+            violations: 0,
+            violation_domains: BTreeSet::new(),
+            violation_ms: 0.0,
+            violating_since: None,
+        }
+    }
+
+    pub fn allows(&self, domain: &str) -> bool {
+        self.session
+            .allowed_domains
+            .iter()
+            .any(|allowed| domain_covers(allowed, domain))
+    }
+
+    // Returns whether the log is a violation
+    pub fn record_log(&mut self, log: &ExtensionLog) -> bool {
+        self.close_violation(log.timestamp);
+        if self.allows(&log.domain) {
+            return false;
+        }
+
+        self.violations += 1;
+        self.violation_domains
+            .insert(normalize_limit_domain(&log.domain));
+        self.violating_since = Some(log.timestamp.max(self.session.started_at_ms));
+        true
+    }
+
+    pub fn end(mut self, ended_at_ms: f64) -> FocusSessionSummary {
+        self.close_violation(ended_at_ms);
+        let elapsed_ms = (ended_at_ms - self.session.started_at_ms).max(0.0);
+        FocusSessionSummary {
+            session_id: self.session.id,
+            violations: self.violations,
+            compliant_minutes: (elapsed_ms - self.violation_ms).max(0.0) / 60_000.0,
+            violation_domains: self.violation_domains.into_iter().collect(),
+        }
+    }
+
+    fn close_violation(&mut self, now_ms: f64) {
+        if let Some(since) = self.violating_since.take() {
+            self.violation_ms += (now_ms - since).max(0.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(timestamp: f64, domain: &str) -> ExtensionLog {
+        ExtensionLog {
+            timestamp,
+            domain: domain.to_string(),
+            activity: "browsing".to_string(),
+            url: format!("https://{}/", domain),
+            title: domain.to_string(),
+            elements: None,
+            title_sentiment: None,
+            url_category: None,
+        }
+    }
+
+    #[test]
+    fn summary_counts_violations_and_compliant_time() {
+        let allowed = vec!["GitHub.com".to_string(), "www.docs.rs".to_string()];
+        let mut session = ActiveFocusSession::new(7, 0.0, &allowed, Some(25));
+        assert_eq!(
+            session.session.allowed_domains,
+            vec!["docs.rs", "github.com"]
+        );
+
+        assert!(!session.record_log(&log(0.0, "gist.github.com")));
+        assert!(session.record_log(&log(60_000.0, "www.reddit.com")));
+        assert!(session.record_log(&log(120_000.0, "reddit.com")));
+        assert!(!session.record_log(&log(180_000.0, "docs.rs")));
+        assert!(session.record_log(&log(540_000.0, "youtube.com")));
+
+        let summary = session.end(600_000.0);
+
+        assert_eq!(
+            summary,
+            FocusSessionSummary {
+                session_id: 7,
+                violations: 3,
+                // Ten minutes, less two on reddit and one on youtube
+                compliant_minutes: 7.0,
+                violation_domains: vec!["reddit.com".to_string(), "youtube.com".to_string()],
+            }
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use crate::action_executor::MAX_POPUP_MESSAGE_LENGTH;
+use crate::rule_definition::{validate_rule_schema, CURRENT_RULE_SCHEMA_VERSION};
+use async_openai::error::OpenAIError;
+use async_openai::types::{
+    ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs,
+    CreateChatCompletionRequestArgs, ResponseFormat,
+};
+use async_openai::Client;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+const OPENAI_CONFIG_FILE: &str = "openai_config.json";
+const DEFAULT_OPENAI_MODEL: &str = "gpt-4o-mini";
+const DEFAULT_MAX_TOKENS: u32 = 1024;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+// Only rate-limited requests are retried; anything else falls back at once
+const MAX_ATTEMPTS: u32 = 3;
+const RATE_LIMIT_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OpenAIConfig {
+    pub api_key: String,
+    pub model: String,
+    pub max_tokens: u32,
+}
+
+impl OpenAIConfig {
+    // Configured from `OPENAI_API_KEY` with the default model, if it is set
+    pub fn from_env() -> Option<Self> {
+        Self::with_api_key(std::env::var("OPENAI_API_KEY").ok().as_deref())
+    }
+
+    fn with_api_key(api_key: Option<&str>) -> Option<Self> {
+        match api_key.map(str::trim) {
+            Some(api_key) if !api_key.is_empty() => Some(OpenAIConfig {
+                api_key: api_key.to_string(),
+                model: DEFAULT_OPENAI_MODEL.to_string(),
+                max_tokens: DEFAULT_MAX_TOKENS,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.api_key.trim().is_empty() {
+            return Err("OpenAI API key must not be empty".to_string());
+        }
+        if self.model.trim().is_empty() {
+            return Err("OpenAI model must not be empty".to_string());
+        }
+        if self.max_tokens == 0 {
+            return Err("OpenAI max tokens must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+// A missing file means no saved config
+pub fn load_openai_config(data_dir: &Path) -> Result<Option<OpenAIConfig>, String> {
+    match std::fs::read_to_string(data_dir.join(OPENAI_CONFIG_FILE)) {
+        Ok(json) => serde_json::from_str(&json)
+            .map(Some)
+            .map_err(|e| format!("Failed to parse OpenAI config: {}", e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("Failed to read OpenAI config: {}", e)),
+    }
+}
+
+pub fn save_openai_config(data_dir: &Path, config: &OpenAIConfig) -> Result<(), String> {
+    std::fs::create_dir_all(data_dir)
+        .map_err(|e| format!("Failed to create app data directory: {}", e))?;
+    let json = serde_json::to_string_pretty(config)
+        .map_err(|e| format!("Failed to serialize OpenAI config: {}", e))?;
+    std::fs::write(data_dir.join(OPENAI_CONFIG_FILE), json)
+        .map_err(|e| format!("Failed to save OpenAI config: {}", e))
+}
+
+fn system_prompt() -> String {
+    format!(
+        r#"You turn a user's description of a browsing rule into JSON for the Cortex rule engine.
+Reply with a single JSON object and nothing else. The object has these keys:
+- "name": a short title for the rule
+- "type": "basic"
+- "schema_version": {}
+- "conditions": a non-empty array of {{"field": string, "operator": string, "value": any}}, where field is one of "domain", "activity", "url" or "title" and operator is one of "equals", "contains", "starts_with" or "regex"
+- "actions": a non-empty array of {{"type": string, "parameters": object}}, where type is "popup" with {{"message": string}} of at most {} characters, "redirect" with {{"target_url": string}}, "close_tab" with {{}}, "notification" with {{"title": string, "body": string}} or "webhook" with {{"url": string}}"#,
+        CURRENT_RULE_SCHEMA_VERSION, MAX_POPUP_MESSAGE_LENGTH
+    )
+}
+
+fn is_rate_limited(error: &OpenAIError) -> bool {
+    match error {
+        OpenAIError::ApiError(api_error) => [&api_error.r#type, &api_error.code]
+            .into_iter()
+            .flatten()
+            .any(|value| value.contains("rate_limit") || value == "requests"),
+        _ => false,
+    }
+}
+
+// Asks the model for a rule and returns it once it validates. Errors mean the
+// caller should fall back to the local generator.
+pub async fn generate_rule_json(
+    config: &OpenAIConfig,
+    natural_language: &str,
+) -> Result<String, String> {
+    let client = Client::with_config(
+        async_openai::config::OpenAIConfig::new().with_api_key(config.api_key.clone()),
+    )
+    // Rate limits are retried below so the number of attempts stays bounded
+    .with_backoff(backoff::ExponentialBackoff {
+        max_elapsed_time: Some(Duration::ZERO),
+        ..Default::default()
+    });
+    let request = CreateChatCompletionRequestArgs::default()
+        .model(config.model.clone())
+        .max_completion_tokens(config.max_tokens)
+        .response_format(ResponseFormat::JsonObject)
+        .messages([
+            ChatCompletionRequestSystemMessageArgs::default()
+                .content(system_prompt())
+                .build()
+                .map_err(|e| format!("Failed to build OpenAI request: {}", e))?
+                .into(),
+            ChatCompletionRequestUserMessageArgs::default()
+                .content(natural_language)
+                .build()
+                .map_err(|e| format!("Failed to build OpenAI request: {}", e))?
+                .into(),
+        ])
+        .build()
+        .map_err(|e| format!("Failed to build OpenAI request: {}", e))?;
+
+    let mut attempt = 1;
+    let response = loop {
+        match tokio::time::timeout(REQUEST_TIMEOUT, client.chat().create(request.clone())).await {
+            Err(_) => return Err("OpenAI request timed out".to_string()),
+            Ok(Ok(response)) => break response,
+            Ok(Err(e)) if is_rate_limited(&e) && attempt < MAX_ATTEMPTS => {
+                eprintln!("OpenAI rate limited (attempt {}), retrying", attempt);
+                tokio::time::sleep(RATE_LIMIT_RETRY_DELAY * attempt).await;
+                attempt += 1;
+            }
+            Ok(Err(e)) => return Err(format!("OpenAI request failed: {}", e)),
+        }
+    };
+
+    let content = response
+        .choices
+        .into_iter()
+        .next()
+        .and_then(|choice| choice.message.content)
+        .ok_or_else(|| "OpenAI returned no content".to_string())?;
+    validate_rule_schema(content.trim())?.to_json()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_openai::error::ApiError;
+
+    fn api_error(r#type: Option<&str>, code: Option<&str>) -> OpenAIError {
+        OpenAIError::ApiError(ApiError {
+            message: "error".to_string(),
+            r#type: r#type.map(str::to_string),
+            param: None,
+            code: code.map(str::to_string),
+        })
+    }
+
+    #[test]
+    fn config_needs_an_api_key_and_rate_limits_are_retryable() {
+        assert_eq!(OpenAIConfig::with_api_key(None), None);
+        assert_eq!(OpenAIConfig::with_api_key(Some("  ")), None);
+        let config = OpenAIConfig::with_api_key(Some(" sk-test ")).unwrap();
+        assert_eq!(config.api_key, "sk-test");
+        assert!(config.validate().is_ok());
+        assert!(OpenAIConfig { max_tokens: 0, ..config }.validate().is_err());
+
+        assert!(is_rate_limited(&api_error(None, Some("rate_limit_exceeded"))));
+        assert!(is_rate_limited(&api_error(Some("requests"), None)));
+        assert!(!is_rate_limited(&api_error(Some("insufficient_quota"), None)));
+        assert!(!is_rate_limited(&api_error(None, None)));
+    }
+}
@@ -0,0 +1,235 @@
+use crate::ExtensionLog;
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+pub const LOG_STORE_FILE: &str = "extension_logs.jsonl";
+
+// `load` rewrites the file once it holds more than this many times
+// `capacity` logs
+const COMPACTION_FACTOR: usize = 4;
+
+// Compaction keeps every log from the past week, since stats and domain
+// limits read them back with `load_since`
+const COMPACTION_KEEPS_MS: f64 = 7.0 * 86_400_000.0;
+
+// Extension logs as newline-delimited JSON, oldest first. Writes are
+// serialized so a prune never races an append.
+pub struct LogStore {
+    path: PathBuf,
+    write_lock: Mutex<()>,
+}
+
+impl LogStore {
+    pub fn new(path: PathBuf) -> Self {
+        LogStore {
+            path,
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    pub fn in_dir(data_dir: &Path) -> Self {
+        Self::new(data_dir.join(LOG_STORE_FILE))
+    }
+
+    // Returns the newest `capacity` logs, reading the file a line at a time.
+    // A missing file is an empty store, and lines that don't parse (e.g. one
+    // cut short by a crash) are skipped.
+    pub fn load(&self, capacity: usize) -> Result<VecDeque<ExtensionLog>, String> {
+        let keep_since = chrono::Utc::now().timestamp_millis() as f64 - COMPACTION_KEEPS_MS;
+        let _guard = self.write_lock.lock().unwrap();
+        let mut kept = VecDeque::new();
+        let mut stored = 0;
+        self.for_each_log(|log| {
+            stored += 1;
+            kept.push_back(log);
+            while kept.len() > capacity
+                && kept
+                    .front()
+                    .is_some_and(|oldest| oldest.timestamp < keep_since)
+            {
+                kept.pop_front();
+            }
+        })?;
+
+        if stored > capacity.saturating_mul(COMPACTION_FACTOR) && kept.len() < stored {
+            match self.rewrite(kept.iter()) {
+                Ok(()) => println!("Compacted extension log store to {} logs", kept.len()),
+                Err(e) => eprintln!("Failed to compact extension log store: {}", e),
+            }
+        }
+        Ok(kept.split_off(kept.len().saturating_sub(capacity)))
+    }
+
+    // Every stored log from `timestamp` (ms) on, oldest first
+    pub fn load_since(&self, timestamp: f64) -> Result<Vec<ExtensionLog>, String> {
+        let mut logs = Vec::new();
+        self.for_each_log(|log| {
+            if log.timestamp >= timestamp {
+                logs.push(log);
+            }
+        })?;
+        Ok(logs)
+    }
+
+    pub fn append(&self, log: &ExtensionLog) -> Result<(), String> {
+        let line = serde_json::to_string(log)
+            .map_err(|e| format!("Failed to serialize extension log: {}", e))?;
+        let _guard = self.write_lock.lock().unwrap();
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create log directory: {}", e))?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| format!("Failed to open log store: {}", e))?;
+        writeln!(file, "{}", line).map_err(|e| format!("Failed to append to log store: {}", e))
+    }
+
+    // Rewrites the file without logs older than `timestamp` and returns how
+    // many were dropped
+    pub fn prune_before(&self, timestamp: f64) -> Result<usize, String> {
+        let _guard = self.write_lock.lock().unwrap();
+        let mut before = 0;
+        let mut kept = Vec::new();
+        self.for_each_log(|log| {
+            before += 1;
+            if log.timestamp >= timestamp {
+                kept.push(log);
+            }
+        })?;
+        if kept.len() == before {
+            return Ok(0);
+        }
+
+        self.rewrite(kept.iter())?;
+        Ok(before - kept.len())
+    }
+
+    // Replaces the file's contents. Callers hold `write_lock`.
+    fn rewrite<'a>(&self, logs: impl Iterator<Item = &'a ExtensionLog>) -> Result<(), String> {
+        // Write to a temporary file first so a failed rewrite keeps the old logs
+        let temp_path = self.path.with_extension("jsonl.tmp");
+        let mut contents = String::new();
+        for log in logs {
+            let line = serde_json::to_string(log)
+                .map_err(|e| format!("Failed to serialize extension log: {}", e))?;
+            contents.push_str(&line);
+            contents.push('\n');
+        }
+        std::fs::write(&temp_path, contents)
+            .map_err(|e| format!("Failed to rewrite log store: {}", e))?;
+        std::fs::rename(&temp_path, &self.path)
+            .map_err(|e| format!("Failed to rewrite log store: {}", e))
+    }
+
+    fn for_each_log(&self, mut visit: impl FnMut(ExtensionLog)) -> Result<(), String> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(format!("Failed to open log store: {}", e)),
+        };
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(|e| format!("Failed to read log store: {}", e))?;
+            if let Ok(log) = serde_json::from_str(&line) {
+                visit(log);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(timestamp: f64) -> ExtensionLog {
+        ExtensionLog {
+            timestamp,
+            domain: "github.com".to_string(),
+            activity: "coding".to_string(),
+            url: "https://github.com/".to_string(),
+            title: "GitHub".to_string(),
+            elements: None,
+            title_sentiment: None,
+            url_category: None,
+        }
+    }
+
+    #[test]
+    fn appended_logs_load_and_prune() {
+        let dir = std::env::temp_dir().join(format!(
+            "cortex-log-store-{}",
+            crate::websocket_server::generate_api_key()
+        ));
+        let store = LogStore::in_dir(&dir);
+        assert!(store.load(10).unwrap().is_empty());
+
+        for timestamp in 0..5 {
+            store.append(&log(timestamp as f64)).unwrap();
+        }
+        let mut file = OpenOptions::new().append(true).open(&store.path).unwrap();
+        writeln!(file, "{{\"timestamp\":").unwrap();
+
+        let loaded = store.load(3).unwrap();
+        let timestamps: Vec<f64> = loaded.iter().map(|log| log.timestamp).collect();
+        assert_eq!(timestamps, vec![2.0, 3.0, 4.0]);
+
+        assert_eq!(store.prune_before(2.0).unwrap(), 2);
+        assert_eq!(store.prune_before(2.0).unwrap(), 0);
+        let timestamps: Vec<f64> = store
+            .load(10)
+            .unwrap()
+            .iter()
+            .map(|log| log.timestamp)
+            .collect();
+        assert_eq!(timestamps, vec![2.0, 3.0, 4.0]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn loading_compacts_an_oversized_file() {
+        let dir = std::env::temp_dir().join(format!(
+            "cortex-log-store-{}",
+            crate::websocket_server::generate_api_key()
+        ));
+        let store = LogStore::in_dir(&dir);
+        for timestamp in 0..8 {
+            store.append(&log(timestamp as f64)).unwrap();
+        }
+        let lines = || {
+            std::fs::read_to_string(&store.path)
+                .unwrap()
+                .lines()
+                .count()
+        };
+
+        // 8 logs is within four times a capacity of 2, so nothing is rewritten
+        assert_eq!(store.load(2).unwrap().len(), 2);
+        assert_eq!(lines(), 8);
+
+        store.append(&log(8.0)).unwrap();
+        let loaded = store.load(2).unwrap();
+        let timestamps: Vec<f64> = loaded.iter().map(|log| log.timestamp).collect();
+        assert_eq!(timestamps, vec![7.0, 8.0]);
+        assert_eq!(lines(), 2);
+
+        // Logs from the past week survive compaction and can be read back
+        let now = chrono::Utc::now().timestamp_millis() as f64;
+        for age_hours in (0..10).rev() {
+            store
+                .append(&log(now - age_hours as f64 * 3_600_000.0))
+                .unwrap();
+        }
+        assert_eq!(store.load(2).unwrap().len(), 2);
+        assert_eq!(lines(), 10);
+        assert_eq!(store.load_since(now - 4.5 * 3_600_000.0).unwrap().len(), 5);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
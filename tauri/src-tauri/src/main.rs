@@ -1,16 +1,99 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod action_executor;
+mod analytics;
+mod browser_import;
+mod clipboard;
+mod config;
+mod cortex_error;
 mod database;
+mod domain_limits;
+mod focus_mode;
+mod llm;
+mod log_store;
+mod migrations;
+mod rate_limiter;
+mod rule_definition;
+mod rule_engine;
+mod rule_export;
+mod sentiment;
+mod streaks;
+mod templates;
+mod tls;
+mod url_classifier;
+mod watch_time;
 mod websocket_server;
 
-use database::{Database, NewRule};
-use websocket_server::WebSocketServer;
+use action_executor::{ActionExecutor, ActionPreview, MAX_POPUP_MESSAGE_LENGTH};
+use analytics::{
+    ActionSuccessRate, ActiveMinuteBucket, ActivityAnomaly, ActivityCorrelation,
+    ActivityTypeBreakdown, CooldownRecommendation, DailyFocusReport, DayOfWeekSummary, DomainGraph,
+    DomainProductivityDay, DomainRuleMatrix, DomainTimeRanking, DomainTimeStat,
+    ElementsKeyFrequency, FocusRecommendation, HistoricalContext, HourlyTriggerCount, InactiveRule,
+    ProductivitySummary, RuleRecommendation, SentimentBucket, TriggerForecast, UrlVisitSummary,
+};
+use browser_import::{BrowserHistoryFormat, ImportSummary};
+use config::{LogsConfig, ServerConfig, UserConfig};
+use cortex_error::CortexError;
+use database::{
+    ActionLog, ActivitySession, AuditAction, AuditEntry, BulkResult, Database, DatabaseConfig,
+    DatabaseEvent, IntegrityReport, NewActivityRecord, NewRule, Profile, RuleGroupCreateResult,
+    RuleStats,
+};
+use domain_limits::{DomainLimit, DomainLimitAlerts};
+use focus_mode::{ActiveFocusSession, FocusSession, FocusSessionSummary};
+use llm::OpenAIConfig;
+use log_store::LogStore;
+use rate_limiter::{RateLimitStats, RateLimiter};
+use rule_definition::{
+    ConditionLogic, FieldCondition, RuleAction, RuleCondition, RuleDefinition, RuleValidationResult,
+    TypedCondition, UrlPatternCondition, CURRENT_RULE_SCHEMA_VERSION,
+};
+use rule_engine::{
+    RuleComplexityScore, RuleDependencyGraph, RuleEngine, RuleSimilarityResult, RuleTestResult,
+};
+use streaks::Streaks;
+use templates::RuleTemplate;
+use url_classifier::UrlCategory;
+use watch_time::WatchTimeEstimate;
+use websocket_server::{
+    AccessLogEntry, ExtensionCommand, ExtensionMessage, ExtensionMessageData, WebSocketServer,
+};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use std::collections::VecDeque;
-use tauri::{Manager, State};
-use tokio::sync::{broadcast, Mutex};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+use tauri::{Emitter, Manager, State};
+use tokio::sync::{broadcast, Mutex, OnceCell};
+
+// How often buffered logs are flushed to each live stream
+const LOG_STREAM_BATCH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+// Activities older than this when logged (e.g. imported history) are not
+// evaluated against rules
+const RULE_EVALUATION_MAX_AGE_MS: f64 = 5.0 * 60_000.0;
+// How often scheduled rule activations are checked
+const ACTIVATION_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+// How often expired rules are switched off
+const EXPIRY_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+// How often profile schedules are compared with the local time
+const PROFILE_SCHEDULE_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+// The extension counts as connected while it has posted within this window
+const EXTENSION_CONNECTED_WINDOW_MS: f64 = 60_000.0;
+// How often the connection is re-checked when no logs arrive
+const EXTENSION_HEARTBEAT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+// Delay between OpenAI calls when a batch of rules is generated
+const OPENAI_BATCH_CALL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+// How often tasks that follow database events retry opening it
+const DATABASE_OPEN_RETRY_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+// Parses per rule in benchmark_rule_json_parsing; larger requests are capped
+const MAX_BENCHMARK_ITERATIONS: u32 = 10_000;
+
+static NEXT_LOG_STREAM_ID: AtomicU64 = AtomicU64::new(1);
+static NEXT_FOCUS_SESSION_ID: AtomicU64 = AtomicU64::new(1);
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExtensionLog {
@@ -20,14 +103,202 @@ pub struct ExtensionLog {
     pub url: String,
     pub title: String,
     pub elements: Option<serde_json::Value>,
+    // Keyword-based tone of the title, from -1.0 to 1.0; computed at ingestion
+    #[serde(default)]
+    pub title_sentiment: Option<f32>,
+    #[serde(default)]
+    pub url_category: Option<UrlCategory>,
 }
 
 // App State
 pub struct AppState {
-    pub db: Arc<Mutex<Database>>,
+    // Created on first use so startup does not wait on them; use `db()` and
+    // `websocket_server()`
+    db: OnceCell<Arc<Mutex<Database>>>,
+    // Set during setup; until then, or if it is never known, the database
+    // is kept in memory
+    db_path: std::sync::OnceLock<std::path::PathBuf>,
     pub extension_logs: Arc<Mutex<VecDeque<ExtensionLog>>>,
-    pub websocket_server: Arc<WebSocketServer>,
-    pub extension_receiver: Arc<Mutex<Option<broadcast::Receiver<ExtensionLog>>>>,
+    // Set once the app data directory is known; logs are not saved until then
+    log_store: OnceCell<LogStore>,
+    pub logs_config: Arc<Mutex<LogsConfig>>,
+    websocket_server: OnceCell<Arc<WebSocketServer>>,
+    pub rate_limiter: Arc<std::sync::Mutex<RateLimiter>>,
+    pub server_config: Arc<Mutex<ServerConfig>>,
+    // The running extension server, if any; see `start_extension_server`
+    extension_server: Mutex<Option<ExtensionServerHandle>>,
+    pub user_config: Arc<Mutex<UserConfig>>,
+    pub rule_engine: Arc<Mutex<RuleEngine>>,
+    pub feature_flags: Arc<Mutex<HashMap<String, bool>>>,
+    pub foreground_started_at: Arc<Mutex<HashMap<String, f64>>>,
+    // Rule id to the moment its snooze ends; not persisted
+    pub snoozed_rules: Arc<Mutex<HashMap<i64, Instant>>>,
+    pub log_streams: Arc<Mutex<HashMap<String, tauri::async_runtime::JoinHandle<()>>>>,
+    // Kept up to date by `update_extension_connection`
+    pub extension_connected: Arc<AtomicBool>,
+    pub streaks: Arc<Mutex<Streaks>>,
+    pub domain_limit_alerts: Arc<Mutex<DomainLimitAlerts>>,
+    // Every log since the start of today (UTC), for domain limits; unlike
+    // `extension_logs` it isn't capped. Seeded from the log store at startup.
+    pub todays_logs: Arc<Mutex<Vec<ExtensionLog>>>,
+    pub focus_session: Arc<Mutex<Option<ActiveFocusSession>>>,
+    // When unset, natural language rules are generated locally
+    pub openai_config: Arc<Mutex<Option<OpenAIConfig>>>,
+    // Rule id to when it last showed a notification; see
+    // `UserConfig::notification_debounce_seconds`
+    pub notification_debounce: Arc<Mutex<HashMap<i64, Instant>>>,
+    // Close and redirect commands waiting for the browser extension to poll
+    pub pending_commands: Arc<Mutex<VecDeque<ExtensionCommand>>>,
+    // The profile last activated; rules may have been toggled since
+    pub active_profile_id: Arc<Mutex<Option<i64>>>,
+}
+
+pub struct ExtensionServerHandle {
+    // The address it was asked to listen on
+    addr: std::net::SocketAddr,
+    shutdown: CancellationToken,
+    task: tauri::async_runtime::JoinHandle<()>,
+}
+
+impl ExtensionServerHandle {
+    // Waits for the server to release its port
+    async fn stop(self) {
+        self.shutdown.cancel();
+        if let Err(e) = self.task.await {
+            eprintln!("Extension server task failed: {}", e);
+        }
+    }
+}
+
+impl AppState {
+    // A failure to open (e.g. a wrong CORTEX_DB_KEY, a locked file or a failed
+    // migration) is returned to the caller, and opening is retried next time
+    pub async fn db(&self) -> Result<&Arc<Mutex<Database>>, CortexError> {
+        self.db
+            .get_or_try_init(|| async {
+                let path = self.db_path.get().ok_or_else(|| {
+                    CortexError::IoError("The app data directory is unknown".to_string())
+                })?;
+                let opened = match std::env::var("CORTEX_DB_KEY") {
+                    Ok(key) => {
+                        let config = DatabaseConfig {
+                            encryption_key: Some(key),
+                        };
+                        Database::with_config(path, config).await
+                    }
+                    Err(_) => Database::new(path).await,
+                };
+                let db = opened.map_err(|e| {
+                    eprintln!("Failed to open database: {}", e);
+                    e.context("Failed to open database")
+                })?;
+                println!("Database initialized");
+                Ok(Arc::new(Mutex::new(db)))
+            })
+            .await
+    }
+    
+    // For background tasks that cannot run without the database
+    async fn wait_for_db(&self) -> &Arc<Mutex<Database>> {
+        loop {
+            if let Ok(db) = self.db().await {
+                return db;
+            }
+            tokio::time::sleep(DATABASE_OPEN_RETRY_INTERVAL).await;
+        }
+    }
+    
+    pub async fn websocket_server(&self) -> &Arc<WebSocketServer> {
+        self.websocket_server
+            .get_or_init(|| async {
+                Arc::new(WebSocketServer::new(
+                    self.rate_limiter.clone(),
+                    self.pending_commands.clone(),
+                ))
+            })
+            .await
+    }
+    
+    pub fn is_fully_initialized(&self) -> bool {
+        self.db.initialized() && self.websocket_server.initialized()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchmarkResult {
+    pub total_duration_ms: f64,
+    pub average_per_parse_us: f64,
+    pub slowest_rule_id: i64,
+    pub slowest_rule_ms: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleMigrationStatus {
+    pub current_schema_version: u8,
+    pub rules_on_current_version: u32,
+    pub rules_needing_migration: Vec<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MigrationSummary {
+    pub upgraded: u32,
+    pub failed: Vec<(i64, String)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageWarning {
+    pub component: String,
+    pub current: u64,
+    pub limit: u64,
+    pub fraction_used: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveResult {
+    pub archived_count: u32,
+    pub file_size_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryUsageEstimate {
+    pub extension_logs_estimated_bytes: usize,
+    pub rules_estimated_bytes: usize,
+    pub activities_estimated_bytes: usize,
+    pub nl_cache_estimated_bytes: usize,
+    pub rule_match_records_estimated_bytes: usize,
+    pub total_estimated_bytes: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtensionIntegrationGuide {
+    pub endpoint_url: String,
+    pub method: String,
+    pub required_headers: BTreeMap<String, String>,
+    pub message_schema: serde_json::Value,
+    pub example_payload: ExtensionMessage,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtensionLogPage {
+    pub logs: Vec<ExtensionLog>,
+    // Pass as `after_timestamp` to get the next older page
+    pub next_cursor: Option<f64>,
+    pub total: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CurlCommandExamples {
+    pub post_extension_data: String,
+    pub health_check: String,
+    pub status_check: String,
+}
+
+// The engine compares fields and operators case-sensitively, so legacy rules
+// (e.g. "operator":"EQUALS") are stored migrated to the current schema
+fn validated_rule_json(rule_json: &str) -> Result<(RuleDefinition, String), String> {
+    rule_definition::validate_rule_schema(rule_json)?;
+    let rule_json = rule_definition::migrate_rule_json(rule_json)?;
+    Ok((RuleDefinition::from_json(&rule_json)?, rule_json))
 }
 
 // Tauri commands
@@ -37,13 +308,53 @@ async fn add_rule(
     name: String,
     natural_language: String,
     rule_json: String,
-) -> Result<database::Rule, String> {
-    let db = state.db.lock().await;
+    priority: Option<i32>,
+) -> Result<database::Rule, CortexError> {
+    let priority = priority.unwrap_or(0);
+    let (definition, rule_json) = validated_rule_json(&rule_json).map_err(|e| {
+        println!("Failed to add rule: {}", e);
+        CortexError::ValidationError(format!("Failed to add rule: {}", e))
+    })?;
+    let score = rule_engine::get_rule_complexity_score(&definition);
+    if score.estimated_eval_cost >= 8 {
+        eprintln!(
+            "Rule '{}' is expensive to evaluate (cost {}/10, {} regex conditions)",
+            name,
+            score.estimated_eval_cost,
+            score.regex_condition_count
+        );
+    }
+    
+    let db = state.db().await?.lock().await;
+    
+    if !rule_engine::referenced_rule_ids(&definition).is_empty() {
+        let candidate = database::Rule {
+            id: db.next_rule_id().await?,
+            name: name.clone(),
+            natural_language: natural_language.clone(),
+            rule_json: rule_json.clone(),
+            is_active: true,
+            created_at: chrono::Utc::now().timestamp(),
+            last_triggered_at: None,
+            hit_count: 0,
+            priority,
+            tags: Vec::new(),
+            schedule: None,
+            expires_at: None,
+        };
+        rule_engine::check_rule_dependencies(&db.get_all_rules().await?, candidate).map_err(|e| {
+            println!("Failed to add rule: {}", e);
+            CortexError::ValidationError(format!("Failed to add rule: {}", e))
+        })?;
+    }
     
     let new_rule = NewRule {
         name,
         natural_language,
         rule_json,
+        priority,
+        tags: Vec::new(),
+        expires_at: None,
     };
     
     match db.create_rule(new_rule).await {
@@ -53,167 +364,2721 @@ async fn add_rule(
         }
         Err(e) => {
             println!("Failed to add rule: {}", e);
-            Err(format!("Failed to add rule: {}", e))
+            Err(e.context("Failed to add rule"))
+        }
+    }
+}
+
+#[tauri::command]
+async fn get_rule_templates() -> Result<Vec<RuleTemplate>, CortexError> {
+    Ok(templates::RULE_TEMPLATES.to_vec())
+}
+
+// The rule is named after the template unless `name` is given
+#[tauri::command]
+async fn create_rule_from_template(
+    state: State<'_, AppState>,
+    template_id: String,
+    name: Option<String>,
+) -> Result<database::Rule, CortexError> {
+    let template = templates::find_template(&template_id)
+        .ok_or_else(|| CortexError::NotFound(format!("Template not found: {}", template_id)))?;
+    let name = name
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| template.display_name.to_string());
+    let mut definition =
+        RuleDefinition::from_json(template.rule_json).map_err(CortexError::ValidationError)?;
+    definition.name = name.clone();
+    
+    let new_rule = NewRule {
+        name,
+        natural_language: template.description.to_string(),
+        rule_json: definition.to_json().map_err(CortexError::ValidationError)?,
+        priority: 0,
+        tags: Vec::new(),
+        expires_at: None,
+    };
+    
+    let db = state.db().await?.lock().await;
+    match db.create_rule(new_rule).await {
+        Ok(rule) => {
+            db.record_audit(rule.id, AuditAction::TemplateApplied(template.id.to_string()), None)
+                .await?;
+            println!("Added rule from template {}: {}", template.id, rule.name);
+            Ok(rule)
+        }
+        Err(e) => {
+            println!("Failed to add rule from template: {}", e);
+            Err(e.context("Failed to add rule from template"))
+        }
+    }
+}
+
+#[tauri::command]
+async fn create_rule_from_url(
+    state: State<'_, AppState>,
+    url: String,
+    action_type: String,
+) -> Result<database::Rule, CortexError> {
+    let parsed = url::Url::parse(&url)
+        .map_err(|e| CortexError::ValidationError(format!("Failed to parse URL: {}", e)))?;
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| CortexError::ValidationError(format!("URL has no host: {}", url)))?
+        .trim_start_matches("www.")
+        .to_string();
+    let path_prefix = parsed.path().to_string();
+    
+    let category = state
+        .user_config
+        .lock()
+        .await
+        .domain_categories
+        .category_for(&host)
+        .unwrap_or("uncategorized")
+        .to_string();
+    
+    let parameters = match action_type.as_str() {
+        "popup" => serde_json::json!({ "message": format!("You are on {}{}", host, path_prefix) }),
+        _ => serde_json::json!({}),
+    };
+    let name = format!("{} {}{}", action_type, host, path_prefix.trim_end_matches('/'));
+    let definition = RuleDefinition {
+        actions: vec![RuleAction {
+            parameters,
+            action_type: action_type.clone(),
+        }],
+        conditions: vec![RuleCondition::Typed(TypedCondition::UrlPattern(UrlPatternCondition {
+            host: host.clone(),
+            path_prefix: path_prefix.clone(),
+        }))],
+        logic: ConditionLogic::And,
+        name: name.clone(),
+        schema_version: Some(CURRENT_RULE_SCHEMA_VERSION),
+        rule_type: "basic".to_string(),
+    };
+    
+    let new_rule = NewRule {
+        name,
+        natural_language: format!(
+            "{} when visiting {}{} ({})",
+            action_type, host, path_prefix, category
+        ),
+        rule_json: definition.to_json().map_err(CortexError::ValidationError)?,
+        priority: 0,
+        tags: Vec::new(),
+        expires_at: None,
+    };
+    
+    let db = state.db().await?.lock().await;
+    match db.create_rule(new_rule).await {
+        Ok(rule) => {
+            println!("Added rule from URL: {}", rule.name);
+            Ok(rule)
+        }
+        Err(e) => Err(e.context("Failed to add rule")),
+    }
+}
+
+#[tauri::command]
+async fn get_similar_rules(
+    state: State<'_, AppState>,
+    query: String,
+    threshold: f32,
+    limit: usize,
+) -> Result<Vec<RuleSimilarityResult>, CortexError> {
+    let rules = state.db().await?.lock().await.get_all_rules().await?;
+    Ok(rule_engine::similar_rules(rules, &query, threshold, limit))
+}
+
+#[tauri::command]
+async fn schedule_rule_activation(
+    state: State<'_, AppState>,
+    rule_id: i64,
+    activate_at: i64,
+) -> Result<(), CortexError> {
+    let db = state.db().await?.lock().await;
+    match db.schedule_rule_activation(rule_id, activate_at).await {
+        Ok(_) => {
+            println!("Scheduled rule {} to activate at {}", rule_id, activate_at);
+            Ok(())
+        }
+        Err(e) => Err(e.context("Failed to schedule rule activation")),
+    }
+}
+
+#[tauri::command]
+async fn export_rules_encrypted(
+    state: State<'_, AppState>,
+    output_path: String,
+    password: String,
+) -> Result<(), CortexError> {
+    let rules = state.db().await?.lock().await.get_all_rules().await?;
+    let json = serde_json::to_vec(&rules)
+        .map_err(|e| CortexError::DatabaseError(format!("Failed to serialize rules: {}", e)))?;
+    let encrypted =
+        rule_export::encrypt_export(&json, &password).map_err(CortexError::ValidationError)?;
+    
+    std::fs::write(&output_path, encrypted)
+        .map_err(|e| CortexError::IoError(format!("Failed to write export file: {}", e)))?;
+    println!("Exported {} encrypted rules to {}", rules.len(), output_path);
+    Ok(())
+}
+
+#[tauri::command]
+async fn export_rules(state: State<'_, AppState>) -> Result<String, CortexError> {
+    let rules = state.db().await?.lock().await.get_all_rules().await?;
+    serde_json::to_string_pretty(&rules)
+        .map_err(|e| CortexError::DatabaseError(format!("Failed to serialize rules: {}", e)))
+}
+
+// Checks imported rules for circular dependencies one by one, as each would be
+// saved: new rules get the ids the database will assign and, with `overwrite`,
+// a rule replaces the first one with its name.
+async fn check_imported_rule_dependencies(
+    db: &Database,
+    imported: &[(NewRule, bool)],
+    overwrite: bool,
+) -> Result<(), String> {
+    let mut rules = db.get_all_rules().await?;
+    let mut next_id = db.next_rule_id().await?;
+    for (new_rule, is_active) in imported {
+        let replaced = rules
+            .iter()
+            .filter(|rule| overwrite && rule.name == new_rule.name)
+            .map(|rule| rule.id)
+            .min();
+        let id = replaced.unwrap_or_else(|| {
+            next_id += 1;
+            next_id - 1
+        });
+        let candidate = database::Rule {
+            id,
+            name: new_rule.name.clone(),
+            natural_language: new_rule.natural_language.clone(),
+            rule_json: new_rule.rule_json.clone(),
+            is_active: *is_active,
+            created_at: chrono::Utc::now().timestamp(),
+            last_triggered_at: None,
+            hit_count: 0,
+            priority: new_rule.priority,
+            tags: new_rule.tags.clone(),
+            schedule: None,
+            expires_at: new_rule.expires_at,
+        };
+        rule_engine::check_rule_dependencies(&rules, candidate.clone())
+            .map_err(|e| format!("'{}': {}", new_rule.name, e))?;
+        rules.retain(|rule| rule.id != id);
+        rules.push(candidate);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn import_rules(
+    state: State<'_, AppState>,
+    json: String,
+    overwrite: bool,
+) -> Result<usize, CortexError> {
+    let rules = rule_export::parse_rule_import(&json).map_err(|e| {
+        println!("Failed to import rules: {}", e);
+        CortexError::ValidationError(format!("Failed to import rules: {}", e))
+    })?;
+    
+    let db = state.db().await?.lock().await;
+    check_imported_rule_dependencies(&db, &rules, overwrite).await.map_err(|e| {
+        println!("Failed to import rules: {}", e);
+        CortexError::ValidationError(format!("Failed to import rules: {}", e))
+    })?;
+    Ok(db.import_rules(rules, overwrite).await?.len())
+}
+
+#[tauri::command]
+async fn copy_rule_to_clipboard(
+    state: State<'_, AppState>,
+    rule_id: i64,
+) -> Result<(), CortexError> {
+    let rule = state
+        .db()
+        .await?
+        .lock()
+        .await
+        .get_rule_by_id(rule_id)
+        .await?
+        .ok_or_else(|| CortexError::NotFound(format!("Rule not found: {}", rule_id)))?;
+    let json = serde_json::to_string(&rule)
+        .map_err(|e| CortexError::DatabaseError(format!("Failed to serialize rule: {}", e)))?;
+    
+    clipboard::write_text(&json).map_err(CortexError::IoError)?;
+    println!("Copied rule {} to clipboard", rule.name);
+    Ok(())
+}
+
+#[tauri::command]
+async fn paste_rule_from_clipboard(
+    state: State<'_, AppState>,
+) -> Result<database::Rule, CortexError> {
+    let text = clipboard::read_text().map_err(CortexError::IoError)?;
+    let new_rule = rule_export::parse_shared_rule(&text).map_err(|e| {
+        println!("Failed to paste rule: {}", e);
+        CortexError::ValidationError(format!("Failed to paste rule: {}", e))
+    })?;
+    
+    let db = state.db().await?.lock().await;
+    check_imported_rule_dependencies(&db, &[(new_rule.clone(), true)], false).await.map_err(
+        |e| {
+            println!("Failed to paste rule: {}", e);
+            CortexError::ValidationError(format!("Failed to paste rule: {}", e))
+        },
+    )?;
+    let rule = db.create_rule(new_rule).await?;
+    println!("Pasted rule from clipboard: {}", rule.name);
+    Ok(rule)
+}
+
+#[tauri::command]
+async fn import_rules_encrypted(
+    state: State<'_, AppState>,
+    input_path: String,
+    password: String,
+) -> Result<Vec<database::Rule>, CortexError> {
+    let encrypted = std::fs::read(&input_path)
+        .map_err(|e| CortexError::IoError(format!("Failed to read export file: {}", e)))?;
+    let json =
+        rule_export::decrypt_export(&encrypted, &password).map_err(CortexError::ValidationError)?;
+    // Every rule is decoded and validated before any is saved
+    let rules = String::from_utf8(json)
+        .map_err(|e| e.to_string())
+        .and_then(|json| rule_export::parse_rule_import(&json))
+        .map_err(|e| {
+            CortexError::ValidationError(format!("Failed to parse exported rules: {}", e))
+        })?;
+    
+    let db = state.db().await?.lock().await;
+    check_imported_rule_dependencies(&db, &rules, false).await.map_err(|e| {
+        CortexError::ValidationError(format!("Failed to import rules: {}", e))
+    })?;
+    let imported = db.import_rules(rules, false).await?;
+    
+    println!("Imported {} encrypted rules from {}", imported.len(), input_path);
+    Ok(imported)
+}
+
+#[tauri::command]
+async fn get_inactive_rules(state: State<'_, AppState>) -> Result<Vec<InactiveRule>, CortexError> {
+    let rules = state.db().await?.lock().await.get_all_rules().await?;
+    Ok(analytics::inactive_rules(rules, chrono::Utc::now().timestamp()))
+}
+
+#[tauri::command]
+async fn set_feature_flag(
+    state: State<'_, AppState>,
+    flag: String,
+    enabled: bool,
+) -> Result<(), CortexError> {
+    let mut feature_flags = state.feature_flags.lock().await;
+    feature_flags.insert(flag, enabled);
+    state.rule_engine.lock().await.set_feature_flags(feature_flags.clone());
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_feature_flags(
+    state: State<'_, AppState>,
+) -> Result<HashMap<String, bool>, CortexError> {
+    Ok(state.feature_flags.lock().await.clone())
+}
+
+#[tauri::command]
+async fn create_rule_group_with_rules(
+    state: State<'_, AppState>,
+    name: String,
+    description: Option<String>,
+    rule_ids: Vec<i64>,
+) -> Result<RuleGroupCreateResult, CortexError> {
+    let db = state.db().await?.lock().await;
+    
+    match db.create_rule_group_with_rules(name, description, rule_ids).await {
+        Ok(result) => Ok(result),
+        Err(e) => {
+            println!("Failed to create rule group: {}", e);
+            Err(e.context("Failed to create rule group"))
+        }
+    }
+}
+
+#[tauri::command]
+async fn create_profile(
+    state: State<'_, AppState>,
+    name: String,
+    description: String,
+    rule_ids: Vec<i64>,
+) -> Result<Profile, CortexError> {
+    let db = state.db().await?.lock().await;
+    
+    match db.create_profile(name, description, rule_ids).await {
+        Ok(profile) => Ok(profile),
+        Err(e) => {
+            println!("Failed to create profile: {}", e);
+            Err(e.context("Failed to create profile"))
+        }
+    }
+}
+
+#[tauri::command]
+async fn get_profiles(state: State<'_, AppState>) -> Result<Vec<Profile>, CortexError> {
+    state.db().await?.lock().await.get_profiles().await
+}
+
+#[tauri::command]
+async fn activate_profile(
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+    profile_id: i64,
+) -> Result<(), CortexError> {
+    apply_profile(&app_handle, &state, profile_id).await?;
+    Ok(())
+}
+
+async fn apply_profile(
+    app_handle: &tauri::AppHandle,
+    state: &AppState,
+    profile_id: i64,
+) -> Result<Profile, CortexError> {
+    let profile = state
+        .db()
+        .await?
+        .lock()
+        .await
+        .activate_profile(profile_id)
+        .await
+        .map_err(|e| e.context("Failed to activate profile"))?;
+    *state.active_profile_id.lock().await = Some(profile.id);
+    
+    if let Err(e) = app_handle.emit("profile-activated", &profile) {
+        eprintln!("Failed to emit profile-activated: {}", e);
+    }
+    Ok(profile)
+}
+
+#[tauri::command]
+async fn set_profile_schedule(
+    state: State<'_, AppState>,
+    profile_id: i64,
+    schedule: Option<database::ProfileSchedule>,
+) -> Result<(), CortexError> {
+    let db = state.db().await?.lock().await;
+    db.set_profile_schedule(profile_id, schedule).await.map_err(|e| {
+        println!("Failed to set profile schedule: {}", e);
+        e.context("Failed to set profile schedule")
+    })
+}
+
+#[tauri::command]
+async fn get_active_profile(state: State<'_, AppState>) -> Result<Option<Profile>, CortexError> {
+    let Some(profile_id) = *state.active_profile_id.lock().await else {
+        return Ok(None);
+    };
+    state.db().await?.lock().await.get_profile(profile_id).await
+}
+
+#[tauri::command]
+async fn delete_profile(state: State<'_, AppState>, profile_id: i64) -> Result<(), CortexError> {
+    state
+        .db()
+        .await?
+        .lock()
+        .await
+        .delete_profile(profile_id)
+        .await
+        .map_err(|e| e.context("Failed to delete profile"))?;
+    
+    let mut active_profile_id = state.active_profile_id.lock().await;
+    if *active_profile_id == Some(profile_id) {
+        *active_profile_id = None;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_access_log(
+    state: State<'_, AppState>,
+    limit: usize,
+) -> Result<Vec<AccessLogEntry>, CortexError> {
+    Ok(state.websocket_server().await.get_access_log(limit))
+}
+
+#[tauri::command]
+async fn clear_access_log(state: State<'_, AppState>) -> Result<(), CortexError> {
+    state.websocket_server().await.clear_access_log();
+    Ok(())
+}
+
+// Leaves the queue alone so the extension still receives the commands
+#[tauri::command]
+async fn get_pending_commands(
+    state: State<'_, AppState>,
+) -> Result<Vec<ExtensionCommand>, CortexError> {
+    Ok(state.pending_commands.lock().await.iter().cloned().collect())
+}
+
+#[tauri::command]
+async fn get_rule_trigger_forecast(
+    state: State<'_, AppState>,
+    rule_id: i64,
+) -> Result<TriggerForecast, CortexError> {
+    let matches: Vec<database::RuleMatchRecord> = state
+        .db()
+        .await?
+        .lock()
+        .await
+        .get_rule_matches_in_range(0, chrono::Utc::now().timestamp())
+        .await?
+        .into_iter()
+        .filter(|record| record.rule_id == rule_id)
+        .collect();
+    
+    Ok(analytics::trigger_forecast(&matches))
+}
+
+#[tauri::command]
+async fn get_rule_action_success_rate(
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+    rule_id: i64,
+    days: u32,
+) -> Result<ActionSuccessRate, CortexError> {
+    let start_time = chrono::Utc::now().timestamp() - days as i64 * 86_400;
+    let logs: Vec<ActionLog> = state
+        .db()
+        .await?
+        .lock()
+        .await
+        .get_action_log(Some(rule_id), usize::MAX)
+        .await?
+        .into_iter()
+        .filter(|log| log.fired_at >= start_time)
+        .collect();
+    
+    let rate = analytics::action_success_rate(&logs);
+    if rate.success_rate < 0.5 {
+        let warning = serde_json::json!({ "rule_id": rule_id, "stats": rate });
+        if let Err(e) = app_handle.emit("rule-action-reliability-warning", warning) {
+            eprintln!("Failed to emit rule-action-reliability-warning: {}", e);
+        }
+    }
+    Ok(rate)
+}
+
+#[tauri::command]
+async fn get_context_at_timestamp(
+    state: State<'_, AppState>,
+    timestamp: f64,
+) -> Result<HistoricalContext, CortexError> {
+    let logs: Vec<ExtensionLog> = state.extension_logs.lock().await.iter().cloned().collect();
+    let db = state.db().await?.lock().await;
+    let rules = db.get_all_rules().await?;
+    let activities = db.get_activities_in_range(0.0, timestamp).await?;
+    
+    Ok(analytics::historical_context(timestamp, &rules, &logs, &activities))
+}
+
+#[tauri::command]
+async fn get_rule_trigger_heatmap(
+    state: State<'_, AppState>,
+    rule_id: i64,
+    days: u32,
+) -> Result<Vec<HourlyTriggerCount>, CortexError> {
+    let now = chrono::Utc::now().timestamp();
+    let start_time = now - days as i64 * 86_400;
+    let matches: Vec<database::RuleMatchRecord> = state
+        .db()
+        .await?
+        .lock()
+        .await
+        .get_rule_matches_in_range(start_time, now)
+        .await?
+        .into_iter()
+        .filter(|record| record.rule_id == rule_id)
+        .collect();
+    
+    Ok(analytics::hourly_trigger_heatmap(&matches))
+}
+
+#[tauri::command]
+async fn get_rule_dependency_graph(
+    state: State<'_, AppState>,
+) -> Result<RuleDependencyGraph, CortexError> {
+    let rules = state.db().await?.lock().await.get_all_rules().await?;
+    Ok(rule_engine::rule_dependency_graph(&rules))
+}
+
+#[tauri::command]
+async fn get_recommended_rule_cooldowns(
+    state: State<'_, AppState>,
+) -> Result<Vec<CooldownRecommendation>, CortexError> {
+    let db = state.db().await?.lock().await;
+    let rules = db.get_all_rules().await?;
+    let matches = db.get_rule_matches_in_range(0, chrono::Utc::now().timestamp()).await?;
+    
+    Ok(analytics::cooldown_recommendations(&rules, &matches))
+}
+
+#[tauri::command]
+async fn suggest_fix_for_rule(
+    state: State<'_, AppState>,
+    rule_id: i64,
+) -> Result<String, CortexError> {
+    let rules = state.db().await?.lock().await.get_all_rules().await?;
+    let rule = rules
+        .iter()
+        .find(|rule| rule.id == rule_id)
+        .ok_or_else(|| CortexError::NotFound("Rule not found".to_string()))?;
+    
+    Ok(rule_engine::suggest_fix(rule, &rules))
+}
+
+// Rules whose JSON does not parse are listed as needing migration, so that
+// migrate_all_rules reports them as failures
+#[tauri::command]
+async fn get_rule_migration_status(
+    state: State<'_, AppState>,
+) -> Result<RuleMigrationStatus, CortexError> {
+    let rules = state.db().await?.lock().await.get_all_rules().await?;
+    
+    let mut status = RuleMigrationStatus {
+        current_schema_version: CURRENT_RULE_SCHEMA_VERSION,
+        rules_on_current_version: 0,
+        rules_needing_migration: Vec::new(),
+    };
+    for rule in &rules {
+        match RuleDefinition::from_json(&rule.rule_json) {
+            Ok(definition) if definition.schema_version() == CURRENT_RULE_SCHEMA_VERSION => {
+                status.rules_on_current_version += 1;
+            }
+            _ => status.rules_needing_migration.push(rule.id),
         }
     }
+    Ok(status)
+}
+
+#[tauri::command]
+async fn migrate_all_rules(state: State<'_, AppState>) -> Result<MigrationSummary, CortexError> {
+    let db = state.db().await?.lock().await;
+    let rules = db.get_all_rules().await?;
+    
+    let mut summary = MigrationSummary {
+        upgraded: 0,
+        failed: Vec::new(),
+    };
+    for rule in rules {
+        let current = RuleDefinition::from_json(&rule.rule_json)
+            .is_ok_and(|definition| definition.schema_version() == CURRENT_RULE_SCHEMA_VERSION);
+        if current {
+            continue;
+        }
+        
+        match rule_definition::migrate_rule_json(&rule.rule_json) {
+            Ok(rule_json) => {
+                db.update_rule_json(rule.id, rule_json).await?;
+                summary.upgraded += 1;
+            }
+            Err(e) => summary.failed.push((rule.id, e)),
+        }
+    }
+    
+    println!("Migrated {} rules, {} failed", summary.upgraded, summary.failed.len());
+    Ok(summary)
+}
+
+// Rules whose JSON does not parse are left out
+#[tauri::command]
+async fn get_all_rule_complexity_scores(
+    state: State<'_, AppState>,
+) -> Result<Vec<(i64, RuleComplexityScore)>, CortexError> {
+    let rules = state.db().await?.lock().await.get_all_rules().await?;
+    Ok(rules
+        .iter()
+        .filter_map(|rule| {
+            RuleDefinition::from_json(&rule.rule_json)
+                .ok()
+                .map(|definition| (rule.id, rule_engine::get_rule_complexity_score(&definition)))
+        })
+        .collect())
+}
+
+#[tauri::command]
+async fn get_rules(state: State<'_, AppState>) -> Result<Vec<database::Rule>, CortexError> {
+    let db = state.db().await?.lock().await;
+    
+    match db.get_all_rules().await {
+        Ok(rules) => Ok(rules),
+        Err(e) => {
+            println!("Failed to get rules: {}", e);
+            Err(e.context("Failed to get rules"))
+        }
+    }
+}
+
+#[tauri::command]
+async fn get_rule_by_id(
+    state: State<'_, AppState>,
+    rule_id: i64,
+) -> Result<database::Rule, CortexError> {
+    let db = state.db().await?.lock().await;
+    
+    db.get_rule_by_id(rule_id)
+        .await?
+        .ok_or_else(|| CortexError::NotFound(format!("Rule not found: {}", rule_id)))
+}
+
+#[tauri::command]
+async fn get_rule_stats(
+    state: State<'_, AppState>,
+    rule_id: i64,
+) -> Result<RuleStats, CortexError> {
+    let now = chrono::Utc::now().timestamp();
+    let db = state.db().await?.lock().await;
+    
+    db.get_rule_stats(rule_id, now).await.map_err(|e| e.context("Failed to get rule stats"))
+}
+
+// Lets the frontend flag problems while a rule is being written
+#[tauri::command]
+async fn validate_rule_json(rule_json: String) -> Result<RuleValidationResult, CortexError> {
+    let errors = rule_definition::rule_json_errors(&rule_json);
+    Ok(RuleValidationResult {
+        valid: errors.is_empty(),
+        errors,
+    })
+}
+
+#[tauri::command]
+async fn update_rule(
+    state: State<'_, AppState>,
+    rule_id: i64,
+    name: String,
+    natural_language: String,
+    rule_json: String,
+) -> Result<database::Rule, CortexError> {
+    let (definition, rule_json) = validated_rule_json(&rule_json).map_err(|e| {
+        println!("Failed to update rule: {}", e);
+        CortexError::ValidationError(format!("Failed to update rule: {}", e))
+    })?;
+    let db = state.db().await?.lock().await;
+    
+    if !rule_engine::referenced_rule_ids(&definition).is_empty() {
+        if let Some(existing) = db.get_rule_by_id(rule_id).await? {
+            let candidate = database::Rule {
+                rule_json: rule_json.clone(),
+                ..existing
+            };
+            rule_engine::check_rule_dependencies(&db.get_all_rules().await?, candidate).map_err(
+                |e| {
+                    println!("Failed to update rule: {}", e);
+                    CortexError::ValidationError(format!("Failed to update rule: {}", e))
+                },
+            )?;
+        }
+    }
+    
+    match db.update_rule(rule_id, name, natural_language, rule_json).await {
+        Ok(rule) => Ok(rule),
+        Err(e) => {
+            println!("Failed to update rule: {}", e);
+            Err(e.context("Failed to update rule"))
+        }
+    }
+}
+
+#[tauri::command]
+async fn toggle_rule(state: State<'_, AppState>, rule_id: i64) -> Result<(), CortexError> {
+    let db = state.db().await?.lock().await;
+    
+    match db.toggle_rule(rule_id).await {
+        Ok(_) => {
+            println!("Toggled rule: {}", rule_id);
+            Ok(())
+        }
+        Err(e) => {
+            println!("Failed to toggle rule: {}", e);
+            Err(e.context("Failed to toggle rule"))
+        }
+    }
+}
+
+#[tauri::command]
+async fn set_rule_priority(
+    state: State<'_, AppState>,
+    rule_id: i64,
+    priority: i32,
+) -> Result<(), CortexError> {
+    let db = state.db().await?.lock().await;
+    
+    match db.set_rule_priority(rule_id, priority).await {
+        Ok(_) => {
+            println!("Set priority of rule {} to {}", rule_id, priority);
+            Ok(())
+        }
+        Err(e) => {
+            println!("Failed to set rule priority: {}", e);
+            Err(e.context("Failed to set rule priority"))
+        }
+    }
+}
+
+#[tauri::command]
+async fn add_rule_tag(
+    state: State<'_, AppState>,
+    rule_id: i64,
+    tag: String,
+) -> Result<(), CortexError> {
+    let db = state.db().await?.lock().await;
+    db.add_rule_tag(rule_id, &tag).await.map_err(|e| {
+        println!("Failed to tag rule: {}", e);
+        e.context("Failed to tag rule")
+    })
+}
+
+#[tauri::command]
+async fn remove_rule_tag(
+    state: State<'_, AppState>,
+    rule_id: i64,
+    tag: String,
+) -> Result<(), CortexError> {
+    let db = state.db().await?.lock().await;
+    db.remove_rule_tag(rule_id, &tag).await.map_err(|e| {
+        println!("Failed to untag rule: {}", e);
+        e.context("Failed to untag rule")
+    })
+}
+
+#[tauri::command]
+async fn get_rules_by_tag(
+    state: State<'_, AppState>,
+    tag: String,
+) -> Result<Vec<database::Rule>, CortexError> {
+    let db = state.db().await?.lock().await;
+    db.get_rules_by_tag(&tag).await
+}
+
+#[tauri::command]
+async fn set_rule_schedule(
+    state: State<'_, AppState>,
+    rule_id: i64,
+    schedule: Option<database::RuleSchedule>,
+) -> Result<(), CortexError> {
+    let db = state.db().await?.lock().await;
+    db.set_rule_schedule(rule_id, schedule).await.map_err(|e| {
+        println!("Failed to set rule schedule: {}", e);
+        e.context("Failed to set rule schedule")
+    })
+}
+
+#[tauri::command]
+async fn set_rule_expiry(
+    state: State<'_, AppState>,
+    rule_id: i64,
+    expires_at: Option<i64>,
+) -> Result<(), CortexError> {
+    let db = state.db().await?.lock().await;
+    db.set_rule_expiry(rule_id, expires_at).await.map_err(|e| {
+        println!("Failed to set rule expiry: {}", e);
+        e.context("Failed to set rule expiry")
+    })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnoozedRule {
+    pub rule_id: i64,
+    pub remaining_seconds: u64,
+}
+
+#[tauri::command]
+async fn snooze_rule(
+    state: State<'_, AppState>,
+    rule_id: i64,
+    minutes: u32,
+) -> Result<(), CortexError> {
+    if minutes == 0 {
+        return Err(CortexError::ValidationError(
+            "Snooze duration must be at least one minute".to_string(),
+        ));
+    }
+    let db = state.db().await?.lock().await;
+    if db.get_rule_by_id(rule_id).await?.is_none() {
+        return Err(CortexError::NotFound(format!("Rule not found: {}", rule_id)));
+    }
+    
+    let until = Instant::now() + Duration::from_secs(minutes as u64 * 60);
+    state.snoozed_rules.lock().await.insert(rule_id, until);
+    db.record_audit(rule_id, AuditAction::Snoozed(minutes), None).await?;
+    println!("Snoozed rule {} for {} minutes", rule_id, minutes);
+    Ok(())
+}
+
+#[tauri::command]
+async fn unsnooze_rule(state: State<'_, AppState>, rule_id: i64) -> Result<(), CortexError> {
+    state.snoozed_rules.lock().await.remove(&rule_id);
+    println!("Unsnoozed rule {}", rule_id);
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_snoozed_rules(state: State<'_, AppState>) -> Result<Vec<SnoozedRule>, CortexError> {
+    let now = Instant::now();
+    let mut snoozed_rules = state.snoozed_rules.lock().await;
+    snoozed_rules.retain(|_, until| *until > now);
+    
+    let mut snoozed: Vec<SnoozedRule> = snoozed_rules
+        .iter()
+        .map(|(rule_id, until)| SnoozedRule {
+            rule_id: *rule_id,
+            remaining_seconds: until.duration_since(now).as_secs(),
+        })
+        .collect();
+    snoozed.sort_by_key(|rule| rule.rule_id);
+    Ok(snoozed)
+}
+
+#[tauri::command]
+async fn bulk_enable_rules(
+    state: State<'_, AppState>,
+    rule_ids: Vec<i64>,
+) -> Result<BulkResult, CortexError> {
+    let db = state.db().await?.lock().await;
+    db.set_rules_active(&rule_ids, true).await.map_err(|e| e.context("Failed to enable rules"))
+}
+
+#[tauri::command]
+async fn bulk_disable_rules(
+    state: State<'_, AppState>,
+    rule_ids: Vec<i64>,
+) -> Result<BulkResult, CortexError> {
+    let db = state.db().await?.lock().await;
+    db.set_rules_active(&rule_ids, false).await.map_err(|e| e.context("Failed to disable rules"))
+}
+
+#[tauri::command]
+async fn bulk_delete_rules(
+    state: State<'_, AppState>,
+    rule_ids: Vec<i64>,
+) -> Result<BulkResult, CortexError> {
+    let db = state.db().await?.lock().await;
+    db.delete_rules(&rule_ids).await.map_err(|e| e.context("Failed to delete rules"))
+}
+
+#[tauri::command]
+async fn bulk_delete_rules_by_tag(
+    state: State<'_, AppState>,
+    tag: String,
+) -> Result<BulkResult, CortexError> {
+    if tag.trim().is_empty() {
+        return Err(CortexError::ValidationError("Tag must not be empty".to_string()));
+    }
+    let db = state.db().await?.lock().await;
+    db.delete_rules_by_tag(&tag).await.map_err(|e| e.context("Failed to delete rules"))
+}
+
+#[tauri::command]
+async fn delete_rule(state: State<'_, AppState>, rule_id: i64) -> Result<(), CortexError> {
+    let db = state.db().await?.lock().await;
+    
+    match db.delete_rule(rule_id).await {
+        Ok(_) => {
+            println!("Deleted rule: {}", rule_id);
+            Ok(())
+        }
+        Err(e) => {
+            println!("Failed to delete rule: {}", e);
+            Err(e.context("Failed to delete rule"))
+        }
+    }
+}
+
+#[tauri::command]
+async fn benchmark_rule_json_parsing(
+    state: State<'_, AppState>,
+    iterations: u32,
+) -> Result<BenchmarkResult, CortexError> {
+    let rules = state.db().await?.lock().await.get_all_rules().await?;
+    if rules.is_empty() {
+        return Err(CortexError::ValidationError("No rules to benchmark".to_string()));
+    }
+    let iterations = iterations.clamp(1, MAX_BENCHMARK_ITERATIONS);
+    
+    // The parsing loop is CPU-bound, so it runs off the async runtime
+    tokio::task::spawn_blocking(move || {
+        let mut total = std::time::Duration::ZERO;
+        let mut slowest: Option<(i64, std::time::Duration)> = None;
+        for rule in &rules {
+            let started = std::time::Instant::now();
+            for _ in 0..iterations {
+                // Invalid rules are timed too; a failed parse still costs time
+                let _ = std::hint::black_box(RuleDefinition::from_json(&rule.rule_json));
+            }
+            let elapsed = started.elapsed();
+            total += elapsed;
+            
+            let per_parse = elapsed / iterations;
+            if slowest.is_none_or(|(_, slowest_time)| per_parse > slowest_time) {
+                slowest = Some((rule.id, per_parse));
+            }
+        }
+        
+        let parses = rules.len() as f64 * iterations as f64;
+        let (slowest_rule_id, slowest_time) = slowest.unwrap_or_default();
+        BenchmarkResult {
+            total_duration_ms: total.as_secs_f64() * 1000.0,
+            average_per_parse_us: total.as_secs_f64() * 1_000_000.0 / parses,
+            slowest_rule_id,
+            slowest_rule_ms: slowest_time.as_secs_f64() * 1000.0,
+        }
+    })
+    .await
+    .map_err(|e| CortexError::IoError(format!("Rule parsing benchmark failed: {}", e)))
+}
+
+#[tauri::command]
+async fn get_rule_action_preview(
+    state: State<'_, AppState>,
+    rule_id: i64,
+    sample_log: ExtensionLog,
+) -> Result<Vec<ActionPreview>, CortexError> {
+    let db = state.db().await?.lock().await;
+    
+    let rules = db.get_all_rules().await?;
+    let rule = rules
+        .iter()
+        .find(|rule| rule.id == rule_id)
+        .ok_or_else(|| CortexError::NotFound("Rule not found".to_string()))?;
+    let definition =
+        RuleDefinition::from_json(&rule.rule_json).map_err(CortexError::ValidationError)?;
+    
+    Ok(action_executor::preview_actions(&definition, &sample_log))
+}
+
+// Re-encrypts the database file. The key is read from CORTEX_DB_KEY at
+// startup, so that has to be updated too or the next launch can't open it.
+#[tauri::command]
+async fn change_database_password(
+    state: State<'_, AppState>,
+    old_key: String,
+    new_key: String,
+) -> Result<(), CortexError> {
+    let db = state.db().await?.lock().await;
+    
+    db.change_password(&old_key, &new_key)
+        .await
+        .map_err(|e| e.context("Failed to change database password"))
+}
+
+#[tauri::command]
+async fn get_storage_quota_warning(
+    state: State<'_, AppState>,
+) -> Result<Option<StorageWarning>, CortexError> {
+    let config = state.user_config.lock().await.clone();
+    let db = state.db().await?.lock().await;
+    
+    let mut usage = Vec::new();
+    if let Some(size_bytes) = db.file_size_bytes().await? {
+        usage.push(("database_size_bytes", size_bytes, config.max_db_size_mb * 1024 * 1024));
+    }
+    usage.push(("rules", db.count_rules().await?, config.max_rules));
+    usage.push(("activities", db.count_activities().await?, config.max_activities));
+    
+    // Report the first component above 80% of its limit
+    let warning = usage
+        .into_iter()
+        .filter(|(_, _, limit)| *limit > 0)
+        .map(|(component, current, limit)| StorageWarning {
+            component: component.to_string(),
+            current,
+            limit,
+            fraction_used: current as f32 / limit as f32,
+        })
+        .find(|warning| warning.fraction_used > 0.8);
+    
+    Ok(warning)
+}
+
+// Sum of the serialized sizes, a rough stand-in for heap usage
+fn serialized_bytes<'a, T: Serialize + 'a>(items: impl IntoIterator<Item = &'a T>) -> usize {
+    items
+        .into_iter()
+        .map(|item| serde_json::to_string(item).map_or(0, |json| json.len()))
+        .sum()
+}
+
+#[tauri::command]
+async fn get_memory_usage_estimate(
+    state: State<'_, AppState>,
+) -> Result<MemoryUsageEstimate, CortexError> {
+    let extension_logs_estimated_bytes = serialized_bytes(state.extension_logs.lock().await.iter());
+    
+    let db = state.db().await?.lock().await;
+    let rules_estimated_bytes = serialized_bytes(&db.get_all_rules().await?);
+    let activities_estimated_bytes = serialized_bytes(
+        &db.get_activities_in_range(f64::NEG_INFINITY, f64::INFINITY).await?,
+    );
+    let rule_match_records_estimated_bytes =
+        serialized_bytes(&db.get_rule_matches_in_range(i64::MIN, i64::MAX).await?);
+    
+    // Natural language rules are generated on demand and never cached
+    let nl_cache_estimated_bytes = 0;
+    
+    Ok(MemoryUsageEstimate {
+        extension_logs_estimated_bytes,
+        rules_estimated_bytes,
+        activities_estimated_bytes,
+        nl_cache_estimated_bytes,
+        rule_match_records_estimated_bytes,
+        total_estimated_bytes: extension_logs_estimated_bytes
+            + rules_estimated_bytes
+            + activities_estimated_bytes
+            + nl_cache_estimated_bytes
+            + rule_match_records_estimated_bytes,
+    })
+}
+
+#[tauri::command]
+async fn check_db_integrity(state: State<'_, AppState>) -> Result<IntegrityReport, CortexError> {
+    let report = state.db().await?.lock().await.check_integrity().await?;
+    if report.issues_found > 0 {
+        println!(
+            "Database integrity check found {} issues, repaired {}",
+            report.issues_found, report.repaired
+        );
+    }
+    Ok(report)
+}
+
+#[tauri::command]
+async fn log_activity(
+    state: State<'_, AppState>,
+    activity: NewActivityRecord,
+) -> Result<database::ActivityRecord, CortexError> {
+    // Rules are evaluated by the rule engine task once the database announces
+    // the new activity
+    state.db().await?.lock().await.log_activity(activity).await
+}
+
+// Marks expired rules inactive without dropping them, since other rules'
+// conditions may still refer to them
+fn without_expired(mut rules: Vec<database::Rule>, now: i64) -> Vec<database::Rule> {
+    for rule in rules.iter_mut().filter(|rule| rule.is_expired(now)) {
+        rule.is_active = false;
+    }
+    rules
+}
+
+// Like `without_expired`, for rules whose snooze hasn't ended
+fn without_snoozed(
+    mut rules: Vec<database::Rule>,
+    snoozed_rules: &HashMap<i64, Instant>,
+) -> Vec<database::Rule> {
+    let now = Instant::now();
+    for rule in rules.iter_mut() {
+        if snoozed_rules.get(&rule.id).is_some_and(|until| *until > now) {
+            rule.is_active = false;
+        }
+    }
+    rules
+}
+
+async fn evaluate_logged_activity(
+    db: &Mutex<Database>,
+    rule_engine: &Mutex<RuleEngine>,
+    foreground_started_at: &Mutex<HashMap<String, f64>>,
+    snoozed_rules: &Mutex<HashMap<i64, Instant>>,
+    executor: &ActionExecutor,
+    activity_id: i64,
+) -> Result<(), String> {
+    let db = db.lock().await;
+    let Some(activity) = db.get_activity(activity_id).await? else {
+        return Ok(());
+    };
+    let now = chrono::Utc::now();
+    if (now.timestamp_millis() as f64) - activity.timestamp > RULE_EVALUATION_MAX_AGE_MS {
+        return Ok(());
+    }
+    
+    let foreground_started_at = {
+        let mut foreground = foreground_started_at.lock().await;
+        rule_engine::track_foreground(&mut foreground, &activity);
+        foreground.clone()
+    };
+    
+    let rules = without_expired(db.get_all_rules().await?, now.timestamp());
+    let rules = without_snoozed(rules, &*snoozed_rules.lock().await);
+    let recent_matches = db
+        .get_rule_matches_in_range(now.timestamp() - 3600, now.timestamp())
+        .await?;
+    let matches = rule_engine.lock().await.evaluate_activity(
+        &activity,
+        &rules,
+        &foreground_started_at,
+        &recent_matches,
+    );
+    
+    let context = action_executor::activity_context(&activity);
+    for rule_match in matches {
+        println!("Rule triggered: {} by {}", rule_match.rule_name, activity.app);
+        db.record_rule_match(rule_match.rule_id, activity.domain.clone().unwrap_or_default())
+            .await?;
+        db.increment_rule_hit(rule_match.rule_id).await?;
+        if let Some(rule) = rules.iter().find(|rule| rule.id == rule_match.rule_id) {
+            executor.execute(&db, rule, &context).await?;
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn archive_activities(
+    state: State<'_, AppState>,
+    older_than_days: u32,
+    archive_path: String,
+) -> Result<ArchiveResult, CortexError> {
+    let now = chrono::Utc::now().timestamp_millis() as f64;
+    let cutoff = now - older_than_days as f64 * 86_400_000.0;
+    let db = state.db().await?.lock().await;
+    let archived: Vec<database::ActivityRecord> = db
+        .get_activities_in_range(f64::NEG_INFINITY, cutoff)
+        .await?
+        .into_iter()
+        .filter(|activity| activity.timestamp < cutoff)
+        .collect();
+    
+    // Write the archive before deleting anything so a failed write loses nothing
+    let json = serde_json::to_vec(&archived)
+        .map_err(|e| CortexError::DatabaseError(format!("Failed to serialize activities: {}", e)))?;
+    std::fs::write(&archive_path, &json)
+        .map_err(|e| CortexError::IoError(format!("Failed to write activity archive: {}", e)))?;
+    
+    let ids: Vec<i64> = archived.iter().map(|activity| activity.id).collect();
+    let archived_count = db.delete_activities(&ids).await?;
+    println!("Archived {} activities to {}", archived_count, archive_path);
+    
+    Ok(ArchiveResult {
+        archived_count,
+        file_size_bytes: json.len() as u64,
+    })
+}
+
+#[tauri::command]
+async fn restore_from_activity_archive(
+    state: State<'_, AppState>,
+    archive_path: String,
+) -> Result<u32, CortexError> {
+    let json = std::fs::read(&archive_path)
+        .map_err(|e| CortexError::IoError(format!("Failed to read activity archive: {}", e)))?;
+    let records: Vec<database::ActivityRecord> = serde_json::from_slice(&json)
+        .map_err(|e| {
+            CortexError::ValidationError(format!("Failed to parse activity archive: {}", e))
+        })?;
+    
+    let restored = state.db().await?.lock().await.restore_activities(records).await?;
+    println!("Restored {} activities from {}", restored, archive_path);
+    Ok(restored)
+}
+
+// Payload of the "rule-triggered" event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleTriggeredEvent {
+    pub rule_id: i64,
+    pub log: ExtensionLog,
+}
+
+async fn evaluate_extension_log(
+    app_handle: &tauri::AppHandle,
+    db: &Mutex<Database>,
+    rule_engine: &Mutex<RuleEngine>,
+    snoozed_rules: &Mutex<HashMap<i64, Instant>>,
+    executor: &ActionExecutor,
+    log: &ExtensionLog,
+    domain_time_today: &[DomainTimeStat],
+) -> Result<(), String> {
+    let db = db.lock().await;
+    let now = chrono::Utc::now().timestamp();
+    let rules = without_expired(db.get_all_rules().await?, now);
+    let rules = without_snoozed(rules, &*snoozed_rules.lock().await);
+    let recent_matches = db.get_rule_matches_in_range(now - 3600, now).await?;
+    let matches = rule_engine
+        .lock()
+        .await
+        .evaluate(log, &rules, &recent_matches, domain_time_today);
+    
+    for rule_match in matches {
+        println!("Rule triggered: {} on {}", rule_match.rule_name, log.domain);
+        db.record_rule_match(rule_match.rule_id, log.domain.clone()).await?;
+        db.increment_rule_hit(rule_match.rule_id).await?;
+        let event = RuleTriggeredEvent {
+            rule_id: rule_match.rule_id,
+            log: log.clone(),
+        };
+        if let Err(e) = app_handle.emit("rule-triggered", &event) {
+            eprintln!("Failed to emit rule-triggered: {}", e);
+        }
+        if let Some(rule) = rules.iter().find(|rule| rule.id == rule_match.rule_id) {
+            executor.execute(&db, rule, log).await?;
+        }
+    }
+    Ok(())
+}
+
+// Runs an unsaved rule against each log without recording matches, emitting
+// events or running actions
+async fn test_rule_on_logs(
+    state: &AppState,
+    rule_json: &str,
+    logs: &[ExtensionLog],
+) -> Result<Vec<RuleTestResult>, CortexError> {
+    let definition = RuleDefinition::from_json(rule_json).map_err(CortexError::ValidationError)?;
+    let db = state.db().await?.lock().await;
+    let now = chrono::Utc::now().timestamp();
+    let rules = db.get_all_rules().await?;
+    let recent_matches = db.get_rule_matches_in_range(now - 3600, now).await?;
+    let domain_time_today = todays_domain_time(state).await;
+    
+    let rule_engine = state.rule_engine.lock().await;
+    Ok(logs
+        .iter()
+        .map(|log| {
+            rule_engine.test_log(&definition, log, &rules, &recent_matches, &domain_time_today)
+        })
+        .collect())
+}
+
+#[tauri::command]
+async fn test_rule(
+    state: State<'_, AppState>,
+    rule_json: String,
+    log: ExtensionLog,
+) -> Result<RuleTestResult, CortexError> {
+    let mut results = test_rule_on_logs(&state, &rule_json, std::slice::from_ref(&log)).await?;
+    Ok(results.remove(0))
+}
+
+// One result per log, oldest first
+#[tauri::command]
+async fn test_rule_against_recent_logs(
+    state: State<'_, AppState>,
+    rule_json: String,
+    limit: usize,
+) -> Result<Vec<RuleTestResult>, CortexError> {
+    let logs: Vec<ExtensionLog> = {
+        let logs = state.extension_logs.lock().await;
+        logs.iter().skip(logs.len().saturating_sub(limit)).cloned().collect()
+    };
+    test_rule_on_logs(&state, &rule_json, &logs).await
+}
+
+#[tauri::command]
+async fn import_browser_history(
+    state: State<'_, AppState>,
+    file_path: String,
+    source_format: BrowserHistoryFormat,
+) -> Result<ImportSummary, CortexError> {
+    let contents = std::fs::read_to_string(&file_path)
+        .map_err(|e| CortexError::IoError(format!("Failed to read history file: {}", e)))?;
+    let parsed = browser_import::parse_history(&contents, source_format)
+        .map_err(CortexError::ValidationError)?;
+    
+    let total = parsed.records.len() as u32;
+    let imported = state
+        .db()
+        .await?
+        .lock()
+        .await
+        .import_activities(parsed.records)
+        .await
+        .map_err(|e| e.context("Failed to import browser history"))?;
+    if imported < total {
+        println!(
+            "Activity storage is full; skipped {} older history entries",
+            total - imported
+        );
+    }
+    let summary = ImportSummary {
+        imported,
+        skipped: parsed.skipped + total - imported,
+        errors: parsed.errors,
+    };
+    
+    println!(
+        "Imported browser history: {} imported, {} skipped, {} errors",
+        summary.imported, summary.skipped, summary.errors
+    );
+    Ok(summary)
+}
+
+#[tauri::command]
+async fn update_activity_note(
+    state: State<'_, AppState>,
+    activity_id: i64,
+    note: String,
+) -> Result<(), CortexError> {
+    let db = state.db().await?.lock().await;
+    
+    match db.update_activity_note(activity_id, note).await {
+        Ok(_) => {
+            println!("Updated note for activity: {}", activity_id);
+            Ok(())
+        }
+        Err(e) => {
+            println!("Failed to update activity note: {}", e);
+            Err(e.context("Failed to update activity note"))
+        }
+    }
+}
+
+#[tauri::command]
+async fn get_activities_with_notes(
+    state: State<'_, AppState>,
+) -> Result<Vec<database::ActivityRecord>, CortexError> {
+    let db = state.db().await?.lock().await;
+    
+    db.get_activities_with_notes()
+        .await
+        .map_err(|e| e.context("Failed to get annotated activities"))
+}
+
+#[tauri::command]
+async fn search_activity_notes(
+    state: State<'_, AppState>,
+    query: String,
+) -> Result<Vec<database::ActivityRecord>, CortexError> {
+    let db = state.db().await?.lock().await;
+    
+    db.search_activity_notes(&query)
+        .await
+        .map_err(|e| e.context("Failed to search activity notes"))
+}
+
+#[tauri::command]
+async fn get_domain_productivity_history(
+    state: State<'_, AppState>,
+    domain: String,
+    days: u32,
+) -> Result<Vec<DomainProductivityDay>, CortexError> {
+    let db = state.db().await?.lock().await;
+    
+    let now = chrono::Utc::now().timestamp_millis() as f64;
+    let start = now - days as f64 * 86_400_000.0;
+    let activities = db.get_activities_in_range(start, now).await?;
+    
+    Ok(analytics::domain_productivity_history(&activities, &domain))
+}
+
+#[tauri::command]
+async fn get_top_domains_by_time(
+    state: State<'_, AppState>,
+    hours: u32,
+    limit: usize,
+) -> Result<Vec<DomainTimeRanking>, CortexError> {
+    let now = chrono::Utc::now().timestamp_millis() as f64;
+    let since = now - hours as f64 * 3_600_000.0;
+    let logs: Vec<ExtensionLog> = state
+        .extension_logs
+        .lock()
+        .await
+        .iter()
+        .filter(|log| log.timestamp >= since)
+        .cloned()
+        .collect();
+    let activities = state.db().await?.lock().await.get_activities_in_range(since, now).await?;
+    
+    Ok(analytics::top_domains_by_time(&activities, &logs, limit))
+}
+
+// Extends the streak once today's productivity score reaches the threshold,
+// saving it to `data_dir` when known
+async fn update_streaks(
+    app_handle: &tauri::AppHandle,
+    state: &AppState,
+    data_dir: Option<&std::path::Path>,
+) -> Result<(), CortexError> {
+    let now = chrono::Utc::now();
+    let today = now.date_naive();
+    if state.streaks.lock().await.last_productive_date == Some(today) {
+        return Ok(());
+    }
+    
+    let day_start = today.and_time(chrono::NaiveTime::MIN).and_utc().timestamp_millis() as f64;
+    let now_ms = now.timestamp_millis() as f64;
+    let (threshold, categories) = {
+        let config = state.user_config.lock().await;
+        (config.streak_threshold, config.domain_categories.clone())
+    };
+    let db = state.db().await?;
+    let activities = db.lock().await.get_activities_in_range(day_start, now_ms).await?;
+    let summary = analytics::productivity_summary(&activities, now_ms, &categories);
+    if summary.productivity_score < threshold {
+        return Ok(());
+    }
+    
+    let mut streaks = state.streaks.lock().await;
+    if !streaks.record_productive_day(today) {
+        return Ok(());
+    }
+    if let Some(dir) = data_dir {
+        streaks::save_streaks(dir, &streaks).map_err(CortexError::IoError)?;
+    }
+    println!("Productive day streak is now {} days", streaks.current_streak_days);
+    if let Err(e) = app_handle.emit("streak-updated", &*streaks) {
+        eprintln!("Failed to emit streak-updated: {}", e);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_streaks(state: State<'_, AppState>) -> Result<Streaks, CortexError> {
+    let today = chrono::Utc::now().date_naive();
+    Ok(state.streaks.lock().await.as_of(today))
+}
+
+#[tauri::command]
+async fn reset_streaks(
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), CortexError> {
+    let data_dir = app_handle.path().app_data_dir().map_err(|e| {
+        CortexError::IoError(format!("Failed to locate app data directory: {}", e))
+    })?;
+    let mut streaks = state.streaks.lock().await;
+    *streaks = Streaks::default();
+    streaks::save_streaks(&data_dir, &streaks).map_err(CortexError::IoError)?;
+    
+    println!("Reset productive day streaks");
+    if let Err(e) = app_handle.emit("streak-updated", &*streaks) {
+        eprintln!("Failed to emit streak-updated: {}", e);
+    }
+    Ok(())
+}
+
+fn utc_day_start_ms(now: chrono::DateTime<chrono::Utc>) -> f64 {
+    let day_start = now.date_naive().and_time(chrono::NaiveTime::MIN).and_utc();
+    day_start.timestamp_millis() as f64
+}
+
+// Today's (UTC) usage of every domain with a limit
+// Time spent on each domain so far today, as counted for domain limits
+async fn todays_domain_time(state: &AppState) -> Vec<DomainTimeStat> {
+    let now = chrono::Utc::now();
+    let max_dwell_seconds = state.user_config.lock().await.max_dwell_seconds;
+    let logs = state.todays_logs.lock().await;
+    domain_limits::domain_time_today(
+        &logs,
+        utc_day_start_ms(now),
+        now.timestamp_millis() as f64,
+        max_dwell_seconds,
+    )
+}
+
+async fn current_domain_limits(state: &AppState) -> Result<Vec<DomainLimit>, CortexError> {
+    let limits = state.db().await?.lock().await.get_domain_limits().await?;
+    if limits.is_empty() {
+        return Ok(Vec::new());
+    }
+    
+    let now = chrono::Utc::now();
+    let now_ms = now.timestamp_millis() as f64;
+    let max_dwell_seconds = state.user_config.lock().await.max_dwell_seconds;
+    let logs = state.todays_logs.lock().await;
+    
+    Ok(domain_limits::domain_limit_usage(
+        &limits,
+        &logs,
+        utc_day_start_ms(now),
+        now_ms,
+        max_dwell_seconds,
+    ))
+}
+
+// Emits "domain-limit-breached" for each limit crossed for the first time today
+async fn check_domain_limits(
+    app_handle: &tauri::AppHandle,
+    state: &AppState,
+) -> Result<(), CortexError> {
+    let limits = current_domain_limits(state).await?;
+    let today = chrono::Utc::now().date_naive();
+    let breached = state.domain_limit_alerts.lock().await.newly_breached(&limits, today);
+    for limit in breached {
+        println!(
+            "Daily limit breached for {}: {:.0}s of {}s",
+            limit.domain, limit.used_seconds, limit.daily_limit_seconds
+        );
+        if let Err(e) = app_handle.emit("domain-limit-breached", &limit) {
+            eprintln!("Failed to emit domain-limit-breached: {}", e);
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_domain_limit(
+    state: State<'_, AppState>,
+    domain: String,
+    daily_limit_seconds: u32,
+) -> Result<(), CortexError> {
+    let db = state.db().await?.lock().await;
+    db.set_domain_limit(&domain, daily_limit_seconds).await.map_err(|e| {
+        println!("Failed to set domain limit: {}", e);
+        e.context("Failed to set domain limit")
+    })
+}
+
+#[tauri::command]
+async fn get_domain_limits(state: State<'_, AppState>) -> Result<Vec<DomainLimit>, CortexError> {
+    current_domain_limits(&state).await
+}
+
+#[tauri::command]
+async fn remove_domain_limit(
+    state: State<'_, AppState>,
+    domain: String,
+) -> Result<(), CortexError> {
+    let db = state.db().await?.lock().await;
+    db.remove_domain_limit(&domain).await.map_err(|e| {
+        println!("Failed to remove domain limit: {}", e);
+        e.context("Failed to remove domain limit")
+    })
+}
+
+// Emits "focus-violation" with the log when it breaks the active session's
+// whitelist
+async fn check_focus_session(app_handle: &tauri::AppHandle, state: &AppState, log: &ExtensionLog) {
+    let violation = match state.focus_session.lock().await.as_mut() {
+        Some(session) => session.record_log(log),
+        None => false,
+    };
+    if violation {
+        println!("Focus violation: {}", log.domain);
+        if let Err(e) = app_handle.emit("focus-violation", log) {
+            eprintln!("Failed to emit focus-violation: {}", e);
+        }
+    }
+}
+
+#[tauri::command]
+async fn start_focus_session(
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+    allowed_domains: Vec<String>,
+    duration_minutes: Option<u32>,
+) -> Result<FocusSession, CortexError> {
+    if duration_minutes == Some(0) {
+        return Err(CortexError::ValidationError(
+            "Focus session must last at least one minute".to_string(),
+        ));
+    }
+    let mut active = state.focus_session.lock().await;
+    if active.is_some() {
+        return Err(CortexError::ValidationError(
+            "A focus session is already active".to_string(),
+        ));
+    }
+    
+    let id = NEXT_FOCUS_SESSION_ID.fetch_add(1, Ordering::Relaxed);
+    let now = chrono::Utc::now().timestamp_millis() as f64;
+    let session = ActiveFocusSession::new(id, now, &allowed_domains, duration_minutes);
+    let started = session.session.clone();
+    *active = Some(session);
+    println!("Started focus session {} allowing {:?}", id, started.allowed_domains);
+    
+    // End the session once its time is up, unless it was ended or replaced
+    if let Some(minutes) = duration_minutes {
+        tauri::async_runtime::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(minutes as u64 * 60)).await;
+            let state = app_handle.state::<AppState>();
+            let mut active = state.focus_session.lock().await;
+            if active.as_ref().is_none_or(|session| session.session.id != id) {
+                return;
+            }
+            if let Some(session) = active.take() {
+                let summary = session.end(chrono::Utc::now().timestamp_millis() as f64);
+                println!("Focus session {} ended after {} minutes", id, minutes);
+                if let Err(e) = app_handle.emit("focus-session-ended", &summary) {
+                    eprintln!("Failed to emit focus-session-ended: {}", e);
+                }
+            }
+        });
+    }
+    Ok(started)
+}
+
+#[tauri::command]
+async fn end_focus_session(state: State<'_, AppState>) -> Result<FocusSessionSummary, CortexError> {
+    let session = state
+        .focus_session
+        .lock()
+        .await
+        .take()
+        .ok_or_else(|| CortexError::NotFound("No focus session is active".to_string()))?;
+    let summary = session.end(chrono::Utc::now().timestamp_millis() as f64);
+    println!("Ended focus session {} with {} violations", summary.session_id, summary.violations);
+    Ok(summary)
+}
+
+#[tauri::command]
+async fn get_sessions(
+    state: State<'_, AppState>,
+    window_hours: u32,
+    gap_minutes: Option<u32>,
+) -> Result<Vec<ActivitySession>, CortexError> {
+    let now = chrono::Utc::now().timestamp_millis() as f64;
+    let since = now - window_hours as f64 * 3_600_000.0;
+    let activities = state.db().await?.lock().await.get_activities_in_range(since, now).await?;
+    
+    Ok(database::detect_sessions(
+        &activities,
+        gap_minutes.unwrap_or(database::DEFAULT_SESSION_GAP_MINUTES),
+    ))
+}
+
+#[tauri::command]
+async fn get_productivity_summary(
+    state: State<'_, AppState>,
+    window_hours: u32,
+) -> Result<ProductivitySummary, CortexError> {
+    let now = chrono::Utc::now().timestamp_millis() as f64;
+    let since = now - window_hours as f64 * 3_600_000.0;
+    let categories = state.user_config.lock().await.domain_categories.clone();
+    let activities = state.db().await?.lock().await.get_activities_in_range(since, now).await?;
+    
+    Ok(analytics::productivity_summary(&activities, now, &categories))
+}
+
+// Uses recorded activities when any have a domain, otherwise this session's
+// extension logs
+#[tauri::command]
+async fn get_domain_time_stats(
+    state: State<'_, AppState>,
+    window_hours: u32,
+) -> Result<Vec<DomainTimeStat>, CortexError> {
+    let now = chrono::Utc::now().timestamp_millis() as f64;
+    let since = now - window_hours as f64 * 3_600_000.0;
+    let max_dwell_seconds = state.user_config.lock().await.max_dwell_seconds;
+    
+    let activities = state.db().await?.lock().await.get_activities_in_range(since, now).await?;
+    if activities.iter().any(|activity| activity.domain.is_some()) {
+        return Ok(analytics::domain_time_stats_from_activities(
+            &activities,
+            now,
+            max_dwell_seconds,
+        ));
+    }
+    
+    let logs: Vec<ExtensionLog> = state
+        .extension_logs
+        .lock()
+        .await
+        .iter()
+        .filter(|log| log.timestamp >= since)
+        .cloned()
+        .collect();
+    Ok(analytics::domain_time_stats_from_logs(&logs, now, max_dwell_seconds))
+}
+
+#[tauri::command]
+async fn get_daily_focus_report(
+    state: State<'_, AppState>,
+    date_utc: String,
+) -> Result<DailyFocusReport, CortexError> {
+    let date = chrono::NaiveDate::parse_from_str(&date_utc, "%Y-%m-%d")
+        .map_err(|e| {
+            CortexError::ValidationError(format!("Invalid date '{}': {}", date_utc, e))
+        })?;
+    let goal_minutes = state.user_config.lock().await.daily_focus_goal_minutes;
+    let db = state.db().await?.lock().await;
+    
+    let day_start = date.and_time(chrono::NaiveTime::MIN).and_utc();
+    let day_end = day_start + chrono::Duration::days(1);
+    let activities = db
+        .get_activities_in_range(
+            day_start.timestamp_millis() as f64,
+            day_end.timestamp_millis() as f64 - 1.0,
+        )
+        .await?;
+    let rule_matches = db
+        .get_rule_matches_in_range(day_start.timestamp(), day_end.timestamp() - 1)
+        .await?;
+    
+    Ok(analytics::daily_focus_report(
+        date,
+        &activities,
+        rule_matches.len() as u32,
+        goal_minutes,
+    ))
+}
+
+#[tauri::command]
+async fn get_activity_correlation(
+    state: State<'_, AppState>,
+    window_minutes: u32,
+) -> Result<Vec<ActivityCorrelation>, CortexError> {
+    let now = chrono::Utc::now().timestamp_millis() as f64;
+    let week_ago = now - 7.0 * 86_400_000.0;
+    let activities = state.db().await?.lock().await.get_activities_in_range(week_ago, now).await?;
+    
+    Ok(analytics::activity_correlation(&activities, window_minutes))
+}
+
+#[tauri::command]
+async fn get_activity_by_day_of_week(
+    state: State<'_, AppState>,
+    weeks: u32,
+) -> Result<Vec<DayOfWeekSummary>, CortexError> {
+    let now = chrono::Utc::now().timestamp_millis() as f64;
+    let since = now - weeks as f64 * 7.0 * 86_400_000.0;
+    let activities = state.db().await?.lock().await.get_activities_in_range(since, now).await?;
+    
+    Ok(analytics::activity_by_day_of_week(&activities, weeks))
+}
+
+#[tauri::command]
+async fn get_focus_mode_recommendation(
+    state: State<'_, AppState>,
+) -> Result<Option<FocusRecommendation>, CortexError> {
+    let now = chrono::Utc::now();
+    let categories = state.user_config.lock().await.domain_categories.clone();
+    let db = state.db().await?.lock().await;
+    
+    let activities = db
+        .get_activities_in_range(
+            (now - chrono::Duration::minutes(30)).timestamp_millis() as f64,
+            now.timestamp_millis() as f64,
+        )
+        .await?;
+    let fifteen_minutes_ago = (now - chrono::Duration::minutes(15)).timestamp();
+    let recent_triggers = db
+        .get_rule_matches_in_range(fifteen_minutes_ago, now.timestamp())
+        .await?
+        .len();
+    
+    Ok(analytics::focus_mode_recommendation(&activities, &categories, recent_triggers))
+}
+
+#[tauri::command]
+async fn get_rule_recommendations(
+    state: State<'_, AppState>,
+) -> Result<Vec<RuleRecommendation>, CortexError> {
+    let week_ago = chrono::Utc::now().timestamp_millis() as f64 - 7.0 * 86_400_000.0;
+    // The store keeps a week of logs; the in-memory buffer only the latest
+    let logs: Vec<ExtensionLog> = match state.log_store.get() {
+        Some(store) => store.load_since(week_ago).map_err(CortexError::IoError)?,
+        None => state
+            .extension_logs
+            .lock()
+            .await
+            .iter()
+            .filter(|log| log.timestamp >= week_ago)
+            .cloned()
+            .collect(),
+    };
+    let rules = state.db().await?.lock().await.get_all_rules().await?;
+    
+    Ok(analytics::rule_recommendations(&logs, &rules))
+}
+
+#[tauri::command]
+async fn get_rules_sorted_by_relevance(
+    state: State<'_, AppState>,
+) -> Result<Vec<database::Rule>, CortexError> {
+    let now = chrono::Utc::now();
+    let week_ago = now - chrono::Duration::days(7);
+    let db = state.db().await?.lock().await;
+    
+    let rules = db.get_all_rules().await?;
+    let matches = db
+        .get_rule_matches_in_range(week_ago.timestamp(), now.timestamp())
+        .await?;
+    let activities = db
+        .get_activities_in_range(week_ago.timestamp_millis() as f64, now.timestamp_millis() as f64)
+        .await?;
+    
+    Ok(analytics::rules_by_relevance(rules, &matches, &activities, now.timestamp()))
+}
+
+#[tauri::command]
+async fn get_domain_rule_matrix(
+    state: State<'_, AppState>,
+) -> Result<DomainRuleMatrix, CortexError> {
+    let logs: Vec<ExtensionLog> = state.extension_logs.lock().await.iter().cloned().collect();
+    let db = state.db().await?.lock().await;
+    let rules = db.get_all_rules().await?;
+    let matches = db
+        .get_rule_matches_in_range(0, chrono::Utc::now().timestamp())
+        .await?;
+    
+    Ok(analytics::domain_rule_matrix(&logs, &rules, &matches))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchRuleResult {
+    pub index: usize,
+    pub input: String,
+    pub success: bool,
+    pub rule_json: Option<String>,
+    pub error: Option<String>,
+}
+
+// Uses OpenAI when configured, falling back to the local generator if the
+// request fails or the model's rule doesn't validate
+#[tauri::command]
+async fn process_natural_language_rule(
+    state: State<'_, AppState>,
+    natural_language: String,
+) -> Result<String, CortexError> {
+    let openai_config = state.openai_config.lock().await.clone();
+    natural_language_rule_json(openai_config.as_ref(), &natural_language)
+        .await
+        .map_err(CortexError::ValidationError)
+}
+
+// Uses OpenAI when configured, falling back to the local generator
+async fn natural_language_rule_json(
+    openai_config: Option<&OpenAIConfig>,
+    natural_language: &str,
+) -> Result<String, String> {
+    if let Some(config) = openai_config {
+        match llm::generate_rule_json(config, natural_language).await {
+            Ok(rule_json) => return Ok(rule_json),
+            Err(e) => eprintln!("Falling back to the local rule generator: {}", e),
+        }
+    }
+    generate_rule_json(natural_language)
+}
+
+// Saved to the app data directory, where it takes precedence over
+// `OPENAI_API_KEY` on the next start
+#[tauri::command]
+async fn set_openai_config(
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+    config: OpenAIConfig,
+) -> Result<(), CortexError> {
+    config.validate().map_err(CortexError::ValidationError)?;
+    let data_dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| CortexError::IoError(format!("Failed to locate app data directory: {}", e)))?;
+    llm::save_openai_config(&data_dir, &config).map_err(CortexError::IoError)?;
+    
+    *state.openai_config.lock().await = Some(config);
+    println!("Updated OpenAI config");
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_openai_status(state: State<'_, AppState>) -> Result<bool, CortexError> {
+    Ok(state.openai_config.lock().await.is_some())
+}
+
+// Processes one rule per line; `index` is the line number (from 0) so results
+// can be matched back to the input. Blank lines are ignored. Calls to OpenAI
+// are spaced out by `OPENAI_BATCH_CALL_INTERVAL`.
+#[tauri::command]
+async fn process_batch_natural_language_rules(
+    state: State<'_, AppState>,
+    rules_text: String,
+) -> Result<Vec<BatchRuleResult>, CortexError> {
+    let openai_config = state.openai_config.lock().await.clone();
+    
+    let mut results = Vec::new();
+    for (index, line) in rules_text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if openai_config.is_some() && !results.is_empty() {
+            tokio::time::sleep(OPENAI_BATCH_CALL_INTERVAL).await;
+        }
+        
+        let input = line.trim().to_string();
+        results.push(match natural_language_rule_json(openai_config.as_ref(), &input).await {
+            Ok(rule_json) => BatchRuleResult {
+                index,
+                input,
+                success: true,
+                rule_json: Some(rule_json),
+                error: None,
+            },
+            Err(e) => BatchRuleResult {
+                index,
+                input,
+                success: false,
+                rule_json: None,
+                error: Some(e),
+            },
+        });
+    }
+    
+    println!("Processed {} batch rules", results.len());
+    Ok(results)
+}
+
+fn generate_rule_json(natural_language: &str) -> Result<String, String> {
+    // Basic LLM processing - in a real implementation this would call an actual LLM
+    println!("Processing rule: {}", natural_language);
+    
+    // Generate a basic rule structure based on the input, keeping the message
+    // short enough to pass validation
+    let message: String = format!("Rule triggered: {}", natural_language.trim())
+        .chars()
+        .take(MAX_POPUP_MESSAGE_LENGTH)
+        .collect();
+    let rule = RuleDefinition {
+        actions: vec![RuleAction {
+            parameters: serde_json::json!({ "message": message }),
+            action_type: "popup".to_string(),
+        }],
+        conditions: vec![RuleCondition::Field(FieldCondition {
+            field: "activity".to_string(),
+            not: false,
+            operator: "contains".to_string(),
+            value: serde_json::json!(extract_activity_from_text(natural_language)),
+        })],
+        logic: ConditionLogic::And,
+        name: format!("Rule from: {}", natural_language.chars().take(30).collect::<String>()),
+        schema_version: Some(CURRENT_RULE_SCHEMA_VERSION),
+        rule_type: "basic".to_string(),
+    };
+    
+    rule.to_json()
+}
+
+fn extract_activity_from_text(text: &str) -> String {
+    let text_lower = text.to_lowercase();
+    
+    if text_lower.contains("instagram") || text_lower.contains("insta") {
+        "instagram_activity".to_string()
+    } else if text_lower.contains("youtube") {
+        "youtube_activity".to_string()
+    } else if text_lower.contains("twitter") || text_lower.contains("x.com") {
+        "twitter_activity".to_string()
+    } else if text_lower.contains("facebook") {
+        "facebook_activity".to_string()
+    } else if text_lower.contains("reddit") {
+        "reddit_activity".to_string()
+    } else if text_lower.contains("tiktok") {
+        "tiktok_activity".to_string()
+    } else {
+        "general_activity".to_string()
+    }
+}
+
+#[tauri::command]
+async fn log_extension_activity(
+    state: State<'_, AppState>,
+    domain: String,
+    activity: String,
+    url: String,
+    title: String,
+    elements: Option<serde_json::Value>,
+) -> Result<(), CortexError> {
+    let log = ExtensionLog {
+        timestamp: chrono::Utc::now().timestamp_millis() as f64,
+        domain: domain.clone(),
+        activity: activity.clone(),
+        url_category: Some(url_classifier::classify_url(&url, &title, elements.as_ref())),
+        url,
+        title_sentiment: sentiment::score_title(&title),
+        title,
+        elements,
+    };
+    
+    // Goes through the same pipeline as logs from the extension, so it is saved,
+    // evaluated against rules and counted toward domain limits and focus sessions
+    state
+        .websocket_server()
+        .await
+        .sender
+        .send(log)
+        .map_err(|e| CortexError::IoError(format!("Failed to queue extension log: {}", e)))?;
+    
+    println!("Extension activity logged: {} on {}", activity, domain);
+    Ok(())
+}
+
+// Drops the oldest logs beyond `capacity`
+fn trim_logs(logs: &mut VecDeque<ExtensionLog>, capacity: usize) {
+    while logs.len() > capacity {
+        logs.pop_front();
+    }
+}
+
+fn has_recent_extension_activity(logs: &VecDeque<ExtensionLog>, now: f64) -> bool {
+    logs.iter().any(|log| now - log.timestamp < EXTENSION_CONNECTED_WINDOW_MS)
+}
+
+// Updates the shared connected flag, emitting "extension-connected" or
+// "extension-disconnected" when it changes
+async fn update_extension_connection(app_handle: &tauri::AppHandle, state: &AppState) {
+    let now = chrono::Utc::now().timestamp_millis() as f64;
+    let connected = has_recent_extension_activity(&*state.extension_logs.lock().await, now);
+    if state.extension_connected.swap(connected, Ordering::SeqCst) == connected {
+        return;
+    }
+    
+    let event = if connected { "extension-connected" } else { "extension-disconnected" };
+    println!("Browser extension {}", if connected { "connected" } else { "disconnected" });
+    if let Err(e) = app_handle.emit(event, ()) {
+        eprintln!("Failed to emit {}: {}", event, e);
+    }
+}
+
+#[tauri::command]
+async fn set_logs_capacity(state: State<'_, AppState>, capacity: usize) -> Result<(), CortexError> {
+    if capacity == 0 || capacity > config::MAX_LOGS_CAPACITY {
+        return Err(CortexError::ValidationError(format!(
+            "Logs capacity must be between 1 and {}",
+            config::MAX_LOGS_CAPACITY
+        )));
+    }
+    state.logs_config.lock().await.capacity = capacity;
+    trim_logs(&mut *state.extension_logs.lock().await, capacity);
+    println!("Set extension logs capacity to {}", capacity);
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_logs_capacity(state: State<'_, AppState>) -> Result<usize, CortexError> {
+    Ok(state.logs_config.lock().await.capacity)
+}
+
+#[tauri::command]
+async fn get_extension_logs(state: State<'_, AppState>) -> Result<Vec<ExtensionLog>, CortexError> {
+    let logs = state.extension_logs.lock().await;
+    Ok(logs.iter().cloned().collect())
+}
+
+// Time bounds are inclusive. Keeps the newest `limit` matches, oldest first
+// like `get_extension_logs`.
+fn filter_extension_logs(
+    logs: &VecDeque<ExtensionLog>,
+    start_ms: Option<f64>,
+    end_ms: Option<f64>,
+    domain: Option<&str>,
+    activity: Option<&str>,
+    limit: Option<usize>,
+) -> Vec<ExtensionLog> {
+    let domain = domain.map(str::to_lowercase);
+    let mut filtered: Vec<ExtensionLog> = logs
+        .iter()
+        .rev()
+        .filter(|log| start_ms.is_none_or(|start| log.timestamp >= start))
+        .filter(|log| end_ms.is_none_or(|end| log.timestamp <= end))
+        .filter(|log| {
+            domain
+                .as_ref()
+                .is_none_or(|domain| log.domain.to_lowercase().contains(domain.as_str()))
+        })
+        .filter(|log| activity.is_none_or(|activity| log.activity == activity))
+        .take(limit.unwrap_or(usize::MAX))
+        .cloned()
+        .collect();
+    filtered.reverse();
+    filtered
+}
+
+// Newest first. The cursor is a timestamp, so logs sharing the timestamp of
+// a page's last entry are treated as already returned.
+fn extension_logs_page(
+    logs: &VecDeque<ExtensionLog>,
+    after_timestamp: Option<f64>,
+    page_size: usize,
+) -> ExtensionLogPage {
+    let mut older = logs
+        .iter()
+        .rev()
+        .filter(|log| after_timestamp.is_none_or(|cursor| log.timestamp < cursor));
+    let page: Vec<ExtensionLog> = older.by_ref().take(page_size).cloned().collect();
+    let next_cursor = match (page.last(), older.next()) {
+        (Some(last), Some(_)) => Some(last.timestamp),
+        _ => None,
+    };
+    
+    ExtensionLogPage {
+        logs: page,
+        next_cursor,
+        total: logs.len(),
+    }
+}
+
+// Drops logs older than the given number of days from disk and memory, and
+// returns how many were removed from disk
+#[tauri::command]
+async fn prune_old_logs(
+    state: State<'_, AppState>,
+    older_than_days: u32,
+) -> Result<usize, CortexError> {
+    let cutoff = chrono::Utc::now().timestamp_millis() as f64
+        - older_than_days as f64 * 24.0 * 3_600_000.0;
+    let store = state
+        .log_store
+        .get()
+        .ok_or_else(|| CortexError::IoError("Extension log store is not open".to_string()))?;
+    let pruned = store.prune_before(cutoff).map_err(|e| {
+        println!("Failed to prune extension logs: {}", e);
+        CortexError::IoError(e)
+    })?;
+    
+    state.extension_logs.lock().await.retain(|log| log.timestamp >= cutoff);
+    println!("Pruned {} extension logs older than {} days", pruned, older_than_days);
+    Ok(pruned)
+}
+
+#[tauri::command]
+async fn get_extension_logs_page(
+    state: State<'_, AppState>,
+    after_timestamp: Option<f64>,
+    page_size: usize,
+) -> Result<ExtensionLogPage, CortexError> {
+    if page_size == 0 {
+        return Err(CortexError::ValidationError("Page size must be at least 1".to_string()));
+    }
+    let logs = state.extension_logs.lock().await;
+    Ok(extension_logs_page(&logs, after_timestamp, page_size))
+}
+
+#[tauri::command]
+async fn get_extension_logs_filtered(
+    state: State<'_, AppState>,
+    start_ms: Option<f64>,
+    end_ms: Option<f64>,
+    domain: Option<String>,
+    activity: Option<String>,
+    limit: Option<usize>,
+) -> Result<Vec<ExtensionLog>, CortexError> {
+    let logs = state.extension_logs.lock().await;
+    Ok(filter_extension_logs(
+        &logs,
+        start_ms,
+        end_ms,
+        domain.as_deref(),
+        activity.as_deref(),
+        limit,
+    ))
+}
+
+#[tauri::command]
+async fn get_most_visited_urls(
+    state: State<'_, AppState>,
+    hours: u32,
+    limit: usize,
+) -> Result<Vec<UrlVisitSummary>, CortexError> {
+    let logs: Vec<ExtensionLog> = state.extension_logs.lock().await.iter().cloned().collect();
+    let since = chrono::Utc::now().timestamp_millis() as f64 - hours as f64 * 3_600_000.0;
+    
+    Ok(analytics::most_visited_urls(&logs, since, limit))
+}
+
+// Most recent first
+#[tauri::command]
+async fn get_logs_by_url_category(
+    state: State<'_, AppState>,
+    category: String,
+    limit: usize,
+) -> Result<Vec<ExtensionLog>, CortexError> {
+    let category: UrlCategory = serde_json::from_value(serde_json::Value::String(category))
+        .map_err(|e| CortexError::ValidationError(format!("Invalid URL category: {}", e)))?;
+    
+    let logs = state.extension_logs.lock().await;
+    Ok(logs
+        .iter()
+        .rev()
+        .filter(|log| log.url_category == Some(category))
+        .take(limit)
+        .cloned()
+        .collect())
+}
+
+#[tauri::command]
+async fn get_top_elements_keys(
+    state: State<'_, AppState>,
+    domain: Option<String>,
+    limit: usize,
+) -> Result<Vec<ElementsKeyFrequency>, CortexError> {
+    let logs: Vec<ExtensionLog> = state.extension_logs.lock().await.iter().cloned().collect();
+    
+    Ok(analytics::elements_key_frequency(&logs, domain.as_deref(), limit))
+}
+
+#[tauri::command]
+async fn get_domain_navigation_graph(
+    state: State<'_, AppState>,
+    hours: u32,
+    min_edge_weight: u32,
+) -> Result<DomainGraph, CortexError> {
+    let since = chrono::Utc::now().timestamp_millis() as f64 - hours as f64 * 3_600_000.0;
+    let logs: Vec<ExtensionLog> = state
+        .extension_logs
+        .lock()
+        .await
+        .iter()
+        .filter(|log| log.timestamp >= since)
+        .cloned()
+        .collect();
+    
+    Ok(analytics::domain_navigation_graph(&logs, min_edge_weight))
+}
+
+#[tauri::command]
+async fn get_action_log(
+    state: State<'_, AppState>,
+    rule_id: Option<i64>,
+    limit: usize,
+) -> Result<Vec<ActionLog>, CortexError> {
+    state.db().await?.lock().await.get_action_log(rule_id, limit).await
+}
+
+#[tauri::command]
+async fn get_rule_audit_log(
+    state: State<'_, AppState>,
+    rule_id: Option<i64>,
+    limit: usize,
+) -> Result<Vec<AuditEntry>, CortexError> {
+    state.db().await?.lock().await.get_audit_log(rule_id, limit).await
+}
+
+#[tauri::command]
+async fn clear_audit_log(state: State<'_, AppState>) -> Result<(), CortexError> {
+    state.db().await?.lock().await.clear_audit_log().await?;
+    println!("Audit log cleared");
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_most_active_minutes(
+    state: State<'_, AppState>,
+    hours: u32,
+    top_n: u32,
+) -> Result<Vec<ActiveMinuteBucket>, CortexError> {
+    let logs: Vec<ExtensionLog> = state.extension_logs.lock().await.iter().cloned().collect();
+    let since = chrono::Utc::now().timestamp_millis() as f64 - hours as f64 * 3_600_000.0;
+    
+    Ok(analytics::most_active_minutes(&logs, since, top_n as usize))
+}
+
+#[tauri::command]
+async fn get_watch_time_estimate(
+    state: State<'_, AppState>,
+    hours: u32,
+) -> Result<WatchTimeEstimate, CortexError> {
+    let since = chrono::Utc::now().timestamp_millis() as f64 - hours as f64 * 3_600_000.0;
+    let logs: Vec<ExtensionLog> = state
+        .extension_logs
+        .lock()
+        .await
+        .iter()
+        .filter(|log| log.timestamp >= since)
+        .cloned()
+        .collect();
+    
+    Ok(watch_time::estimate_watch_time(&logs))
+}
+
+#[tauri::command]
+async fn detect_activity_anomalies(
+    state: State<'_, AppState>,
+) -> Result<Vec<ActivityAnomaly>, CortexError> {
+    let logs: Vec<ExtensionLog> = state.extension_logs.lock().await.iter().cloned().collect();
+    let offset = *chrono::Local::now().offset();
+    
+    Ok(analytics::detect_anomalies(&logs, offset))
+}
+
+#[tauri::command]
+async fn get_sentiment_trend(
+    state: State<'_, AppState>,
+    hours: u32,
+) -> Result<Vec<SentimentBucket>, CortexError> {
+    let logs: Vec<ExtensionLog> = state.extension_logs.lock().await.iter().cloned().collect();
+    let since = chrono::Utc::now().timestamp_millis() as f64 - hours as f64 * 3_600_000.0;
+    
+    Ok(analytics::sentiment_trend(&logs, since))
+}
+
+#[tauri::command]
+async fn get_activity_type_breakdown(
+    state: State<'_, AppState>,
+    hours: u32,
+) -> Result<Vec<ActivityTypeBreakdown>, CortexError> {
+    let patterns = state.user_config.lock().await.activity_normalization.clone();
+    let normalization =
+        analytics::compile_normalization(&patterns).map_err(CortexError::ValidationError)?;
+    
+    let since = chrono::Utc::now().timestamp_millis() as f64 - hours as f64 * 3_600_000.0;
+    let logs: Vec<ExtensionLog> = state
+        .extension_logs
+        .lock()
+        .await
+        .iter()
+        .filter(|log| log.timestamp >= since)
+        .cloned()
+        .collect();
+    
+    Ok(analytics::activity_type_breakdown(&logs, &normalization))
+}
+
+#[tauri::command]
+async fn start_extension_log_stream(
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+) -> Result<String, CortexError> {
+    let stream_id = format!("stream-{}", NEXT_LOG_STREAM_ID.fetch_add(1, Ordering::Relaxed));
+    let mut receiver = state.websocket_server().await.sender.subscribe();
+    let task_stream_id = stream_id.clone();
+    
+    let handle = tauri::async_runtime::spawn(async move {
+        let mut batch: Vec<ExtensionLog> = Vec::new();
+        let mut ticker = tokio::time::interval(LOG_STREAM_BATCH_INTERVAL);
+        
+        loop {
+            tokio::select! {
+                received = receiver.recv() => match received {
+                    Ok(log) => batch.push(log),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        println!("Log stream {} skipped {} logs", task_stream_id, skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                _ = ticker.tick() => {
+                    if batch.is_empty() {
+                        continue;
+                    }
+                    let payload = serde_json::json!({
+                        "stream_id": task_stream_id,
+                        "logs": std::mem::take(&mut batch),
+                    });
+                    if let Err(e) = app_handle.emit("extension-log-stream", payload) {
+                        eprintln!("Failed to emit log stream batch: {}", e);
+                    }
+                }
+            }
+        }
+    });
+    
+    state.log_streams.lock().await.insert(stream_id.clone(), handle);
+    println!("Started extension log stream: {}", stream_id);
+    Ok(stream_id)
+}
+
+#[tauri::command]
+async fn stop_extension_log_stream(
+    state: State<'_, AppState>,
+    stream_id: String,
+) -> Result<(), CortexError> {
+    match state.log_streams.lock().await.remove(&stream_id) {
+        Some(handle) => {
+            handle.abort();
+            println!("Stopped extension log stream: {}", stream_id);
+            Ok(())
+        }
+        None => Err(CortexError::NotFound(format!("Log stream not found: {}", stream_id))),
+    }
+}
+
+#[tauri::command] 
+async fn clear_extension_logs(state: State<'_, AppState>) -> Result<(), CortexError> {
+    let mut logs = state.extension_logs.lock().await;
+    logs.clear();
+    println!("Extension logs cleared");
+    Ok(())
+}
+
+#[tauri::command]
+async fn is_fully_initialized(state: State<'_, AppState>) -> Result<bool, CortexError> {
+    Ok(state.is_fully_initialized())
+}
+
+#[tauri::command]
+async fn get_extension_status(
+    state: State<'_, AppState>,
+) -> Result<serde_json::Value, CortexError> {
+    let logs_count = state.extension_logs.lock().await.len();
+    let recent_activity = state.extension_connected.load(Ordering::SeqCst);
+    
+    Ok(serde_json::json!({
+        "connected": recent_activity,
+        "server_running": true,
+        "total_logs": logs_count,
+        "server_url": state.server_config.lock().await.url(),
+        "last_activity": recent_activity
+    }))
+}
+
+fn sample_extension_message() -> ExtensionMessage {
+    ExtensionMessage {
+        event_type: "activity".to_string(),
+        data: ExtensionMessageData {
+            domain: "example.com".to_string(),
+            activity: "browsing".to_string(),
+            url: "https://example.com/".to_string(),
+            title: "Example Domain".to_string(),
+            elements: None,
+        },
+        idempotency_key: None,
+    }
 }
 
 #[tauri::command]
-async fn get_rules(state: State<'_, AppState>) -> Result<Vec<database::Rule>, String> {
-    let db = state.db.lock().await;
+async fn get_extension_message_schema(
+    state: State<'_, AppState>,
+) -> Result<serde_json::Value, CortexError> {
+    let message_schema = serde_json::to_value(schemars::schema_for!(ExtensionMessage))
+        .map_err(|e| CortexError::DatabaseError(format!("Failed to build message schema: {}", e)))?;
+    let guide = ExtensionIntegrationGuide {
+        endpoint_url: format!("{}/extension-data", state.server_config.lock().await.url()),
+        method: "POST".to_string(),
+        required_headers: BTreeMap::from([
+            ("Content-Type".to_string(), "application/json".to_string()),
+            ("X-Cortex-Key".to_string(), state.websocket_server().await.api_key()),
+        ]),
+        message_schema,
+        example_payload: sample_extension_message(),
+    };
     
-    match db.get_all_rules().await {
-        Ok(rules) => Ok(rules),
-        Err(e) => {
-            println!("Failed to get rules: {}", e);
-            Err(format!("Failed to get rules: {}", e))
-        }
-    }
+    serde_json::to_value(guide).map_err(|e| {
+        CortexError::DatabaseError(format!("Failed to build integration guide: {}", e))
+    })
 }
 
-#[tauri::command]
-async fn toggle_rule(state: State<'_, AppState>, rule_id: i64) -> Result<(), String> {
-    let db = state.db.lock().await;
+// Binds a server with the current config, then stops the running one. If the
+// new server can't bind, the running one keeps serving; only a restart on the
+// same address has to stop it first.
+async fn start_extension_server(state: &AppState) -> Result<std::net::SocketAddr, String> {
+    let config = *state.server_config.lock().await;
+    let websocket_server = state.websocket_server().await;
+    let tls_cert = if config.tls_enabled {
+        let cert = websocket_server.tls_cert();
+        Some(cert.ok_or_else(|| "TLS is enabled but no certificate has been loaded".to_string())?)
+    } else {
+        None
+    };
+    let mut running = state.extension_server.lock().await;
     
-    match db.toggle_rule(rule_id).await {
-        Ok(_) => {
-            println!("Toggled rule: {}", rule_id);
-            Ok(())
-        }
-        Err(e) => {
-            println!("Failed to toggle rule: {}", e);
-            Err(format!("Failed to toggle rule: {}", e))
+    let requested = config.socket_addr();
+    let shutdown = CancellationToken::new();
+    let bound = match websocket_server.bind(requested, tls_cert.as_ref(), shutdown.clone()) {
+        Err(_) if running.as_ref().is_some_and(|previous| previous.addr == requested) => {
+            if let Some(previous) = running.take() {
+                previous.stop().await;
+            }
+            websocket_server.bind(requested, tls_cert.as_ref(), shutdown.clone())
         }
+        bound => bound,
+    };
+    let (addr, server) = bound
+        .map_err(|e| format!("Failed to start extension server on {}: {}", config.url(), e))?;
+    if let Some(previous) = running.take() {
+        previous.stop().await;
     }
+    
+    websocket_server.set_max_body_bytes(config.max_body_bytes);
+    let task = tauri::async_runtime::spawn(server);
+    *running = Some(ExtensionServerHandle {
+        addr: requested,
+        shutdown,
+        task,
+    });
+    Ok(addr)
 }
 
 #[tauri::command]
-async fn delete_rule(state: State<'_, AppState>, rule_id: i64) -> Result<(), String> {
-    let db = state.db.lock().await;
-    
-    match db.delete_rule(rule_id).await {
-        Ok(_) => {
-            println!("Deleted rule: {}", rule_id);
-            Ok(())
-        }
-        Err(e) => {
-            println!("Failed to delete rule: {}", e);
-            Err(format!("Failed to delete rule: {}", e))
-        }
+async fn set_rate_limit(state: State<'_, AppState>, rps: u32) -> Result<(), CortexError> {
+    if rps == 0 {
+        return Err(CortexError::ValidationError(
+            "Rate limit must allow at least one request per second".to_string(),
+        ));
     }
+    state.rate_limiter.lock()?.set_max_requests_per_second(rps);
+    println!("Set extension data rate limit to {} requests per second", rps);
+    Ok(())
 }
 
 #[tauri::command]
-async fn process_natural_language_rule(natural_language: String) -> Result<String, String> {
-    // Basic LLM processing - in a real implementation this would call an actual LLM
-    println!("Processing rule: {}", natural_language);
-    
-    // Generate a basic rule structure based on the input
-    let rule_json = serde_json::json!({
-        "name": format!("Rule from: {}", &natural_language[..std::cmp::min(natural_language.len(), 30)]),
-        "type": "basic",
-        "conditions": [{
-            "field": "activity",
-            "operator": "contains",
-            "value": extract_activity_from_text(&natural_language)
-        }],
-        "actions": [{
-            "type": "popup",
-            "parameters": {
-                "message": format!("Rule triggered: {}", natural_language)
-            }
-        }]
-    });
-    
-    Ok(rule_json.to_string())
+async fn get_rate_limit_stats(state: State<'_, AppState>) -> Result<RateLimitStats, CortexError> {
+    Ok(state.rate_limiter.lock()?.stats(Instant::now()))
 }
 
-fn extract_activity_from_text(text: &str) -> String {
-    let text_lower = text.to_lowercase();
-    
-    if text_lower.contains("instagram") || text_lower.contains("insta") {
-        "instagram_activity".to_string()
-    } else if text_lower.contains("youtube") {
-        "youtube_activity".to_string()
-    } else if text_lower.contains("twitter") || text_lower.contains("x.com") {
-        "twitter_activity".to_string()
-    } else if text_lower.contains("facebook") {
-        "facebook_activity".to_string()
-    } else if text_lower.contains("reddit") {
-        "reddit_activity".to_string()
-    } else if text_lower.contains("tiktok") {
-        "tiktok_activity".to_string()
-    } else {
-        "general_activity".to_string()
-    }
+#[tauri::command]
+async fn get_api_key(state: State<'_, AppState>) -> Result<String, CortexError> {
+    Ok(state.websocket_server().await.api_key())
 }
 
+// The extension must be given the new key before it can post again
 #[tauri::command]
-async fn log_extension_activity(
+async fn rotate_api_key(
     state: State<'_, AppState>,
-    domain: String,
-    activity: String,
-    url: String,
-    title: String,
-    elements: Option<serde_json::Value>,
-) -> Result<(), String> {
-    let log = ExtensionLog {
-        timestamp: chrono::Utc::now().timestamp_millis() as f64,
-        domain: domain.clone(),
-        activity: activity.clone(),
-        url,
-        title,
-        elements,
-    };
-    
-    let mut logs = state.extension_logs.lock().await;
-    logs.push_back(log);
+    app_handle: tauri::AppHandle,
+) -> Result<String, CortexError> {
+    let key = websocket_server::generate_api_key();
+    let data_dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| CortexError::IoError(format!("Failed to locate app data directory: {}", e)))?;
+    websocket_server::save_api_key(&data_dir, &key).map_err(CortexError::IoError)?;
     
-    // Keep only last 100 logs
-    if logs.len() > 100 {
-        logs.pop_front();
+    state.websocket_server().await.set_api_key(key.clone());
+    println!("Rotated extension API key");
+    Ok(key)
+}
+
+#[tauri::command]
+async fn get_server_config(state: State<'_, AppState>) -> Result<ServerConfig, CortexError> {
+    Ok(*state.server_config.lock().await)
+}
+
+// Applies `change` to the server settings and saves them to the app data
+// directory, where they are loaded on the next start
+async fn update_server_config(
+    state: &AppState,
+    app_handle: &tauri::AppHandle,
+    change: impl FnOnce(&mut ServerConfig),
+) -> Result<(), CortexError> {
+    let data_dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| CortexError::IoError(format!("Failed to locate app data directory: {}", e)))?;
+    let mut config = state.server_config.lock().await;
+    let mut updated = *config;
+    change(&mut updated);
+    config::save_server_config(&data_dir, &updated).map_err(CortexError::IoError)?;
+    *config = updated;
+    Ok(())
+}
+
+// Takes effect on the next `restart_server`
+#[tauri::command]
+async fn set_server_config(
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+    port: u16,
+) -> Result<(), CortexError> {
+    if port == 0 {
+        return Err(CortexError::ValidationError(
+            "Server port must be between 1 and 65535".to_string(),
+        ));
     }
-    
-    println!("Extension activity logged: {} on {}", activity, domain);
+    update_server_config(&state, &app_handle, |config| config.port = port).await?;
+    println!("Set extension server port to {}", port);
     Ok(())
 }
 
+// Takes effect on the next `restart_server`
 #[tauri::command]
-async fn get_extension_logs(state: State<'_, AppState>) -> Result<Vec<ExtensionLog>, String> {
-    let logs = state.extension_logs.lock().await;
-    Ok(logs.iter().cloned().collect())
+async fn set_tls_enabled(
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+    enabled: bool,
+) -> Result<(), CortexError> {
+    update_server_config(&state, &app_handle, |config| config.tls_enabled = enabled).await?;
+    println!("Set extension server TLS to {}", enabled);
+    Ok(())
 }
 
-#[tauri::command] 
-async fn clear_extension_logs(state: State<'_, AppState>) -> Result<(), String> {
-    let mut logs = state.extension_logs.lock().await;
-    logs.clear();
-    println!("Extension logs cleared");
+// Caps extension data bodies after decompression. Takes effect immediately.
+#[tauri::command]
+async fn set_max_body_bytes(
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+    max_body_bytes: usize,
+) -> Result<(), CortexError> {
+    if max_body_bytes == 0 {
+        return Err(CortexError::ValidationError(
+            "Maximum body size must be at least one byte".to_string(),
+        ));
+    }
+    update_server_config(&state, &app_handle, |config| {
+        config.max_body_bytes = max_body_bytes
+    })
+    .await?;
+    state.websocket_server().await.set_max_body_bytes(max_body_bytes);
+    println!("Set extension data body limit to {} bytes", max_body_bytes);
     Ok(())
 }
 
+// For the extension to pin the server's self-signed certificate
 #[tauri::command]
-async fn get_extension_status(state: State<'_, AppState>) -> Result<serde_json::Value, String> {
-    let logs_count = state.extension_logs.lock().await.len();
+async fn get_tls_fingerprint(state: State<'_, AppState>) -> Result<String, CortexError> {
+    let cert = state
+        .websocket_server()
+        .await
+        .tls_cert()
+        .ok_or_else(|| CortexError::NotFound("No TLS certificate has been loaded".to_string()))?;
+    cert.fingerprint().map_err(CortexError::ValidationError)
+}
+
+// Returns the new fingerprint. A server already serving HTTPS is restarted
+// with the new certificate, so the extension must pin it again.
+#[tauri::command]
+async fn regenerate_tls_cert(
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+) -> Result<String, CortexError> {
+    let cert = tls::generate_tls_cert().map_err(CortexError::IoError)?;
+    let fingerprint = cert.fingerprint().map_err(CortexError::ValidationError)?;
+    let data_dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| CortexError::IoError(format!("Failed to locate app data directory: {}", e)))?;
+    tls::save_tls_cert(&data_dir, &cert).map_err(CortexError::IoError)?;
     
-    // Check if we received data recently (within last 60 seconds)
-    let now = chrono::Utc::now().timestamp_millis() as f64;
-    let recent_activity = {
-        let logs = state.extension_logs.lock().await;
-        logs.iter().any(|log| (now - log.timestamp) < 60000.0)  // 60 seconds
-    };
+    state.websocket_server().await.set_tls_cert(cert);
+    if state.server_config.lock().await.tls_enabled {
+        start_extension_server(&state).await.map_err(CortexError::IoError)?;
+    }
+    println!("Regenerated TLS certificate {}", fingerprint);
+    Ok(fingerprint)
+}
+
+#[tauri::command]
+async fn restart_server(
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), CortexError> {
+    let addr = start_extension_server(&state).await.map_err(|e| {
+        println!("Failed to restart server: {}", e);
+        CortexError::IoError(e)
+    })?;
     
-    Ok(serde_json::json!({
-        "connected": recent_activity,
-        "server_running": true,
-        "total_logs": logs_count,
-        "server_url": "http://127.0.0.1:8080",
-        "last_activity": recent_activity
-    }))
+    let server_url = format!("{}://{}", state.server_config.lock().await.scheme(), addr);
+    if let Err(e) = app_handle.emit("server-restarted", &server_url) {
+        eprintln!("Failed to emit server-restarted: {}", e);
+    }
+    println!("Restarted extension server on {}", server_url);
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_extension_server_curl_commands(
+    state: State<'_, AppState>,
+) -> Result<CurlCommandExamples, CortexError> {
+    let sample_message = sample_extension_message();
+    let payload = serde_json::to_string(&sample_message)
+        .map_err(|e| CortexError::DatabaseError(format!("Failed to build sample payload: {}", e)))?;
+    let config = *state.server_config.lock().await;
+    let server_url = config.url();
+    let api_key = state.websocket_server().await.api_key();
+    // The certificate is self-signed
+    let curl = if config.tls_enabled { "curl -k" } else { "curl" };
+    
+    Ok(CurlCommandExamples {
+        post_extension_data: format!(
+            "{} -X POST {}/extension-data -H 'Content-Type: application/json' \
+             -H 'X-Cortex-Key: {}' -d '{}'",
+            curl, server_url, api_key, payload
+        ),
+        health_check: format!("{} {}/health", curl, server_url),
+        status_check: format!("{} {}/status", curl, server_url),
+    })
 }
 
 #[tauri::command]
-async fn simulate_extension_data(state: State<'_, AppState>) -> Result<(), String> {
+async fn simulate_extension_data(state: State<'_, AppState>) -> Result<(), CortexError> {
     // This simulates receiving data from the Chrome extension
     // In a real implementation, this would poll the extension or use native messaging
     
@@ -224,6 +3089,8 @@ async fn simulate_extension_data(state: State<'_, AppState>) -> Result<(), Strin
             activity: "scrolling_instagram".to_string(),
             url: "https://instagram.com/".to_string(),
             title: "Instagram".to_string(),
+            title_sentiment: sentiment::score_title("Instagram"),
+            url_category: Some(UrlCategory::SocialFeed),
             elements: Some(serde_json::json!({
                 "headings": ["Stories", "Reels", "Feed"],
                 "buttons": ["Like", "Comment", "Share"],
@@ -236,6 +3103,8 @@ async fn simulate_extension_data(state: State<'_, AppState>) -> Result<(), Strin
             activity: "watching_videos".to_string(),
             url: "https://youtube.com/watch?v=xyz".to_string(),
             title: "Funny Cat Video - YouTube".to_string(),
+            title_sentiment: sentiment::score_title("Funny Cat Video - YouTube"),
+            url_category: Some(UrlCategory::EntertainmentVideo),
             elements: Some(serde_json::json!({
                 "video_title": "Funny Cat Video",
                 "duration": "5:23",
@@ -244,59 +3113,409 @@ async fn simulate_extension_data(state: State<'_, AppState>) -> Result<(), Strin
         },
     ];
     
+    let capacity = state.logs_config.lock().await.capacity;
     let mut logs = state.extension_logs.lock().await;
     for log in sample_logs {
         logs.push_back(log);
     }
-    
-    // Keep only last 100 logs
-    while logs.len() > 100 {
-        logs.pop_front();
-    }
+    trim_logs(&mut logs, capacity);
     
     println!("Simulated extension data added");
     Ok(())
 }
 
 fn main() {
-    let db = Database::new();
-    let websocket_server = Arc::new(WebSocketServer::new());
-    let extension_receiver = Arc::new(Mutex::new(Some(websocket_server.sender.subscribe())));
-    
     let app_state = AppState {
-        db: Arc::new(Mutex::new(db)),
+        db: OnceCell::new(),
+        db_path: std::sync::OnceLock::new(),
         extension_logs: Arc::new(Mutex::new(VecDeque::new())),
-        websocket_server: websocket_server.clone(),
-        extension_receiver: extension_receiver.clone(),
+        log_store: OnceCell::new(),
+        logs_config: Arc::new(Mutex::new(LogsConfig::default())),
+        websocket_server: OnceCell::new(),
+        rate_limiter: Arc::new(std::sync::Mutex::new(RateLimiter::default())),
+        server_config: Arc::new(Mutex::new(ServerConfig::from_env())),
+        extension_server: Mutex::new(None),
+        user_config: Arc::new(Mutex::new(UserConfig::default())),
+        rule_engine: Arc::new(Mutex::new(RuleEngine::new())),
+        feature_flags: Arc::new(Mutex::new(HashMap::new())),
+        foreground_started_at: Arc::new(Mutex::new(HashMap::new())),
+        snoozed_rules: Arc::new(Mutex::new(HashMap::new())),
+        log_streams: Arc::new(Mutex::new(HashMap::new())),
+        extension_connected: Arc::new(AtomicBool::new(false)),
+        streaks: Arc::new(Mutex::new(Streaks::default())),
+        domain_limit_alerts: Arc::new(Mutex::new(DomainLimitAlerts::default())),
+        todays_logs: Arc::new(Mutex::new(Vec::new())),
+        focus_session: Arc::new(Mutex::new(None)),
+        openai_config: Arc::new(Mutex::new(OpenAIConfig::from_env())),
+        notification_debounce: Arc::new(Mutex::new(HashMap::new())),
+        pending_commands: Arc::new(Mutex::new(VecDeque::new())),
+        active_profile_id: Arc::new(Mutex::new(None)),
     };
     
-    // Clone references before moving into setup
-    let websocket_server_setup = websocket_server.clone();
-    let extension_logs_setup = app_state.extension_logs.clone();
-    let extension_receiver_setup = extension_receiver.clone();
-    
     tauri::Builder::default()
         .manage(app_state)
         .plugin(tauri_plugin_shell::init())
-        .setup(move |_app| {
-            // Start WebSocket server in background
+        .plugin(tauri_plugin_notification::init())
+        .setup(move |app| {
+            let app_handle = app.handle().clone();
+            match app_handle.path().app_data_dir() {
+                Ok(dir) => {
+                    let state = app_handle.state::<AppState>();
+                    let _ = state.db_path.set(dir.join(database::DATABASE_FILE));
+                }
+                Err(e) => eprintln!("Failed to locate app data directory: {}", e),
+            }
+            
+            // The background tasks below initialize the database and WebSocket
+            // server off the main thread unless a command has already done so
+            
+            // Tell the frontend when the database cannot be opened; commands
+            // that need it return the same error until it opens
+            let db_app_handle = app_handle.clone();
+            tauri::async_runtime::spawn(async move {
+                let state = db_app_handle.state::<AppState>();
+                if let Err(error) = state.db().await {
+                    if let Err(e) = db_app_handle.emit("database-error", &error) {
+                        eprintln!("Failed to emit database-error: {}", e);
+                    }
+                }
+            });
+            
+            // Start WebSocket server and extension log receiver in background
+            let receiver_handle = app_handle.clone();
             tauri::async_runtime::spawn(async move {
-                if let Err(e) = websocket_server_setup.start(8080).await {
+                let state = receiver_handle.state::<AppState>();
+                let websocket_server = state.websocket_server().await;
+                let mut receiver = websocket_server.sender.subscribe();
+                let data_dir = receiver_handle
+                    .path()
+                    .app_data_dir()
+                    .map_err(|e| format!("Failed to locate app data directory: {}", e));
+                let api_key = data_dir
+                    .clone()
+                    .and_then(|dir| websocket_server::load_or_create_api_key(&dir));
+                match api_key {
+                    Ok(key) => websocket_server.set_api_key(key),
+                    Err(e) => eprintln!("Using a temporary API key: {}", e),
+                }
+                let tls_cert = data_dir
+                    .clone()
+                    .and_then(|dir| tls::load_or_create_tls_cert(&dir));
+                match tls_cert {
+                    Ok(cert) => websocket_server.set_tls_cert(cert),
+                    Err(e) => eprintln!("HTTPS is unavailable: {}", e),
+                }
+                match data_dir {
+                    Ok(dir) => {
+                        match config::load_server_config(&dir) {
+                            Ok(config) => *state.server_config.lock().await = config,
+                            Err(e) => eprintln!("Using default server settings: {}", e),
+                        }
+                        let store = LogStore::in_dir(&dir);
+                        let capacity = state.logs_config.lock().await.capacity;
+                        match store.load(capacity) {
+                            Ok(mut saved) => {
+                                let mut logs = state.extension_logs.lock().await;
+                                saved.extend(logs.drain(..));
+                                *logs = saved;
+                                trim_logs(&mut logs, capacity);
+                            }
+                            Err(e) => eprintln!("Failed to load saved extension logs: {}", e),
+                        }
+                        match store.load_since(utc_day_start_ms(chrono::Utc::now())) {
+                            Ok(mut saved) => {
+                                let mut today = state.todays_logs.lock().await;
+                                saved.append(&mut today);
+                                *today = saved;
+                            }
+                            Err(e) => eprintln!("Failed to load today's extension logs: {}", e),
+                        }
+                        let _ = state.log_store.set(store);
+                    }
+                    Err(e) => eprintln!("Extension logs will not be saved: {}", e),
+                }
+                if let Err(e) = start_extension_server(&state).await {
                     eprintln!("WebSocket server error: {}", e);
                 }
+                
+                let executor = ActionExecutor::new(receiver_handle.clone());
+                while let Ok(log) = receiver.recv().await {
+                    {
+                        let capacity = state.logs_config.lock().await.capacity;
+                        let mut logs = state.extension_logs.lock().await;
+                        logs.push_back(log.clone());
+                        trim_logs(&mut logs, capacity);
+                    }
+                    {
+                        let day_start_ms = utc_day_start_ms(chrono::Utc::now());
+                        let mut today = state.todays_logs.lock().await;
+                        today.retain(|logged| logged.timestamp >= day_start_ms);
+                        today.push(log.clone());
+                    }
+                    if let Some(store) = state.log_store.get() {
+                        match store.append(&log) {
+                            Ok(()) => {
+                                if let Ok(db) = state.db().await {
+                                    db.lock().await.extension_log_persisted(log.timestamp as i64);
+                                }
+                            }
+                            Err(e) => eprintln!("Failed to save extension log: {}", e),
+                        }
+                    }
+                    
+                    if let Err(e) = receiver_handle.emit("extension-log", &log) {
+                        eprintln!("Failed to emit extension log: {}", e);
+                    }
+                    update_extension_connection(&receiver_handle, &state).await;
+                    if let Err(e) = check_domain_limits(&receiver_handle, &state).await {
+                        eprintln!("Failed to check domain limits: {}", e);
+                    }
+                    check_focus_session(&receiver_handle, &state, &log).await;
+                    
+                    // db() reports the error when the database cannot be opened
+                    let Ok(db) = state.db().await else {
+                        continue;
+                    };
+                    let domain_time_today = todays_domain_time(&state).await;
+                    if let Err(e) = evaluate_extension_log(
+                        &receiver_handle,
+                        db,
+                        &state.rule_engine,
+                        &state.snoozed_rules,
+                        &executor,
+                        &log,
+                        &domain_time_today,
+                    )
+                    .await
+                    {
+                        eprintln!("Failed to evaluate extension log: {}", e);
+                    }
+                }
+            });
+            
+            // Run the rule engine on every newly logged activity
+            let engine_handle = app_handle.clone();
+            tauri::async_runtime::spawn(async move {
+                let state = engine_handle.state::<AppState>();
+                let executor = ActionExecutor::new(engine_handle.clone());
+                let db = state.wait_for_db().await;
+                let (mut engine_events, active_rules) = {
+                    let db = db.lock().await;
+                    (db.subscribe(), db.get_active_rules().await)
+                };
+                match active_rules {
+                    Ok(rules) => {
+                        if let Err(e) = state.rule_engine.lock().await.warm_up(&rules) {
+                            eprintln!("Failed to warm up rule engine: {}", e);
+                        }
+                    }
+                    Err(e) => eprintln!("Failed to load active rules: {}", e),
+                }
+                loop {
+                    match engine_events.recv().await {
+                        Ok(DatabaseEvent::RuleCreated(rule) | DatabaseEvent::RuleUpdated(rule)) => {
+                            if let Err(e) = state.rule_engine.lock().await.warm_up(&[rule]) {
+                                eprintln!("Failed to warm up rule engine: {}", e);
+                            }
+                        }
+                        Ok(DatabaseEvent::ActivityLogged(activity_id)) => {
+                            if let Err(e) = evaluate_logged_activity(
+                                db,
+                                &state.rule_engine,
+                                &state.foreground_started_at,
+                                &state.snoozed_rules,
+                                &executor,
+                                activity_id,
+                            )
+                            .await
+                            {
+                                eprintln!("Failed to evaluate activity {}: {}", activity_id, e);
+                            }
+                        }
+                        Ok(_) => {}
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            println!("Rule engine skipped {} database events", skipped);
+                        }
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+            
+            // Extend the productive day streak as activities are logged
+            let streak_app_handle = app_handle.clone();
+            tauri::async_runtime::spawn(async move {
+                let state = streak_app_handle.state::<AppState>();
+                let data_dir = match streak_app_handle.path().app_data_dir() {
+                    Ok(dir) => Some(dir),
+                    Err(e) => {
+                        eprintln!("Streaks will not be saved: {}", e);
+                        None
+                    }
+                };
+                if let Some(dir) = &data_dir {
+                    match streaks::load_streaks(dir) {
+                        Ok(saved) => *state.streaks.lock().await = saved,
+                        Err(e) => eprintln!("Failed to load streaks: {}", e),
+                    }
+                }
+                let mut streak_events = state.wait_for_db().await.lock().await.subscribe();
+                loop {
+                    match streak_events.recv().await {
+                        Ok(DatabaseEvent::ActivityLogged(_)) => {
+                            let update =
+                                update_streaks(&streak_app_handle, &state, data_dir.as_deref());
+                            if let Err(e) = update.await {
+                                eprintln!("Failed to update streaks: {}", e);
+                            }
+                        }
+                        Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+            
+            // A saved OpenAI config replaces the one from `OPENAI_API_KEY`
+            let openai_app_handle = app_handle.clone();
+            tauri::async_runtime::spawn(async move {
+                let saved = openai_app_handle
+                    .path()
+                    .app_data_dir()
+                    .map_err(|e| format!("Failed to locate app data directory: {}", e))
+                    .and_then(|dir| llm::load_openai_config(&dir));
+                match saved {
+                    Ok(Some(config)) => {
+                        let state = openai_app_handle.state::<AppState>();
+                        *state.openai_config.lock().await = Some(config);
+                    }
+                    Ok(None) => {}
+                    Err(e) => eprintln!("Failed to load OpenAI config: {}", e),
+                }
+            });
+            
+            // Forward database changes to the frontend
+            let event_app_handle = app_handle.clone();
+            tauri::async_runtime::spawn(async move {
+                let state = event_app_handle.state::<AppState>();
+                let mut frontend_events = state.wait_for_db().await.lock().await.subscribe();
+                loop {
+                    match frontend_events.recv().await {
+                        Ok(event) => {
+                            if let Err(e) = event_app_handle.emit("database-event", &event) {
+                                eprintln!("Failed to emit database event: {}", e);
+                            }
+                        }
+                        Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+            
+            // Notice when the extension stops posting
+            let heartbeat_app_handle = app_handle.clone();
+            tauri::async_runtime::spawn(async move {
+                let state = heartbeat_app_handle.state::<AppState>();
+                let mut ticker = tokio::time::interval(EXTENSION_HEARTBEAT_INTERVAL);
+                loop {
+                    ticker.tick().await;
+                    update_extension_connection(&heartbeat_app_handle, &state).await;
+                }
+            });
+            
+            // Switch off rules whose expiry has passed
+            let expiry_app_handle = app_handle.clone();
+            tauri::async_runtime::spawn(async move {
+                let state = expiry_app_handle.state::<AppState>();
+                let mut ticker = tokio::time::interval(EXPIRY_CHECK_INTERVAL);
+                loop {
+                    ticker.tick().await;
+                    let Ok(db) = state.db().await else {
+                        continue;
+                    };
+                    match db.lock().await.deactivate_expired_rules().await {
+                        Ok(0) => {}
+                        Ok(count) => {
+                            if let Err(e) = expiry_app_handle.emit("rules-updated", count) {
+                                eprintln!("Failed to emit rules-updated: {}", e);
+                            }
+                        }
+                        Err(e) => eprintln!("Failed to deactivate expired rules: {}", e),
+                    }
+                }
+            });
+            
+            // Switch profiles as their scheduled windows start. Only the start
+            // of a window switches, so a profile picked by hand stays active.
+            let profile_app_handle = app_handle.clone();
+            tauri::async_runtime::spawn(async move {
+                let state = profile_app_handle.state::<AppState>();
+                let mut ticker = tokio::time::interval(PROFILE_SCHEDULE_CHECK_INTERVAL);
+                let mut scheduled_id = None;
+                loop {
+                    ticker.tick().await;
+                    let Ok(db) = state.db().await else {
+                        continue;
+                    };
+                    let profiles = match db.lock().await.get_profiles().await {
+                        Ok(profiles) => profiles,
+                        Err(e) => {
+                            eprintln!("Failed to load profiles: {}", e);
+                            continue;
+                        }
+                    };
+                    let local = chrono::Local::now().naive_local();
+                    let scheduled = database::scheduled_profiles(&profiles, local);
+                    let current = scheduled.first().map(|profile| profile.id);
+                    if current == scheduled_id {
+                        continue;
+                    }
+                    scheduled_id = current;
+                    let Some(profile_id) = current else {
+                        continue;
+                    };
+                    if scheduled.len() > 1 {
+                        let names: Vec<&str> =
+                            scheduled.iter().map(|profile| profile.name.as_str()).collect();
+                        eprintln!(
+                            "⚠️ Profile schedules overlap ({}); using {}",
+                            names.join(", "),
+                            names[0]
+                        );
+                    }
+                    
+                    match apply_profile(&profile_app_handle, &state, profile_id).await {
+                        Ok(profile) => {
+                            println!("Switched to scheduled profile: {}", profile.name);
+                            let event = profile_app_handle.emit("profile-auto-switched", &profile);
+                            if let Err(e) = event {
+                                eprintln!("Failed to emit profile-auto-switched: {}", e);
+                            }
+                        }
+                        Err(e) => eprintln!("Failed to switch to scheduled profile: {}", e),
+                    }
+                }
             });
             
-            // Start extension log receiver task
+            // Activate scheduled rules once their time has come
             tauri::async_runtime::spawn(async move {
-                let receiver_opt = extension_receiver_setup.lock().await.take();
-                if let Some(mut receiver) = receiver_opt {
-                    while let Ok(log) = receiver.recv().await {
-                        let mut logs = extension_logs_setup.lock().await;
-                        logs.push_back(log);
-                        
-                        // Keep only last 100 logs
-                        while logs.len() > 100 {
-                            logs.pop_front();
+                let state = app_handle.state::<AppState>();
+                let mut ticker = tokio::time::interval(ACTIVATION_CHECK_INTERVAL);
+                loop {
+                    ticker.tick().await;
+                    let now = chrono::Utc::now().timestamp();
+                    let Ok(db) = state.db().await else {
+                        continue;
+                    };
+                    let activated = match db.lock().await.apply_due_activations(now).await {
+                        Ok(activated) => activated,
+                        Err(e) => {
+                            eprintln!("Failed to apply scheduled activations: {}", e);
+                            continue;
+                        }
+                    };
+                    for rule in activated {
+                        println!("Activated scheduled rule: {}", rule.name);
+                        if let Err(e) = app_handle.emit("rule-activated", &rule) {
+                            eprintln!("Failed to emit rule-activated: {}", e);
                         }
                     }
                 }
@@ -308,14 +3527,331 @@ fn main() {
             add_rule,
             get_rules,
             toggle_rule,
+            set_rule_priority,
+            set_rule_schedule,
+            set_rule_expiry,
+            snooze_rule,
+            unsnooze_rule,
+            get_snoozed_rules,
+            add_rule_tag,
+            remove_rule_tag,
+            get_rules_by_tag,
             delete_rule,
+            bulk_enable_rules,
+            bulk_disable_rules,
+            bulk_delete_rules,
+            bulk_delete_rules_by_tag,
+            benchmark_rule_json_parsing,
+            get_rule_action_preview,
+            change_database_password,
+            get_storage_quota_warning,
+            log_activity,
+            update_activity_note,
+            get_activities_with_notes,
+            search_activity_notes,
+            get_domain_productivity_history,
+            get_daily_focus_report,
+            get_rule_recommendations,
             process_natural_language_rule,
+            set_openai_config,
+            get_openai_status,
+            test_rule,
+            test_rule_against_recent_logs,
             log_extension_activity,
             get_extension_logs,
+            get_extension_logs_filtered,
+            get_extension_logs_page,
+            prune_old_logs,
+            get_most_visited_urls,
+            get_activity_type_breakdown,
+            start_extension_log_stream,
+            stop_extension_log_stream,
+            get_rule_templates,
+            create_rule_from_template,
+            create_rule_from_url,
+            get_rules_sorted_by_relevance,
+            get_domain_rule_matrix,
+            get_similar_rules,
+            schedule_rule_activation,
+            get_activity_correlation,
+            import_browser_history,
+            process_batch_natural_language_rules,
+            get_sentiment_trend,
+            get_most_active_minutes,
+            get_activity_by_day_of_week,
+            get_action_log,
+            get_rule_audit_log,
+            clear_audit_log,
+            get_top_elements_keys,
+            get_memory_usage_estimate,
+            check_db_integrity,
+            get_recommended_rule_cooldowns,
+            set_feature_flag,
+            get_feature_flags,
+            get_top_domains_by_time,
+            get_domain_time_stats,
+            get_productivity_summary,
+            get_sessions,
+            get_streaks,
+            reset_streaks,
+            set_domain_limit,
+            get_domain_limits,
+            remove_domain_limit,
+            start_focus_session,
+            end_focus_session,
+            create_rule_group_with_rules,
+            create_profile,
+            get_profiles,
+            activate_profile,
+            set_profile_schedule,
+            get_active_profile,
+            delete_profile,
+            get_access_log,
+            clear_access_log,
+            get_pending_commands,
+            get_extension_message_schema,
+            get_watch_time_estimate,
+            get_rule_trigger_forecast,
+            get_rule_by_id,
+            get_rule_stats,
+            archive_activities,
+            restore_from_activity_archive,
+            validate_rule_json,
+            update_rule,
+            detect_activity_anomalies,
+            get_rule_dependency_graph,
+            get_rule_trigger_heatmap,
+            get_context_at_timestamp,
+            get_rule_action_success_rate,
+            get_rule_migration_status,
+            migrate_all_rules,
+            get_focus_mode_recommendation,
+            get_logs_by_url_category,
+            get_all_rule_complexity_scores,
+            is_fully_initialized,
+            get_domain_navigation_graph,
+            export_rules_encrypted,
+            import_rules_encrypted,
+            export_rules,
+            import_rules,
+            copy_rule_to_clipboard,
+            paste_rule_from_clipboard,
+            get_inactive_rules,
+            suggest_fix_for_rule,
             clear_extension_logs,
             get_extension_status,
-            simulate_extension_data
+            get_extension_server_curl_commands,
+            simulate_extension_data,
+            set_logs_capacity,
+            get_logs_capacity,
+            get_server_config,
+            set_server_config,
+            restart_server,
+            get_api_key,
+            rotate_api_key,
+            set_tls_enabled,
+            set_max_body_bytes,
+            get_tls_fingerprint,
+            regenerate_tls_cert,
+            set_rate_limit,
+            get_rate_limit_stats
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_rules_pass_validation_for_long_input() {
+        let input = "Stop me from scrolling Instagram during work hours ".repeat(10);
+        let rule_json = generate_rule_json(&input).unwrap();
+        assert!(rule_definition::validate_rule_json(&rule_json).is_ok());
+    }
+
+    #[tokio::test]
+    async fn imports_that_close_a_dependency_loop_are_rejected() {
+        let db = Database::in_memory().await.unwrap();
+        let depending_on = |name: &str, rule_id: i64| NewRule {
+            name: name.to_string(),
+            natural_language: String::new(),
+            rule_json: format!(
+                r#"{{"actions":[],"conditions":[{{"condition_type":"time_since_last_rule_trigger","rule_id_ref":{},"min_minutes":5}}],"name":"{}","type":"basic"}}"#,
+                rule_id, name
+            ),
+            priority: 0,
+            tags: Vec::new(),
+            expires_at: None,
+        };
+        db.create_rule(depending_on("first", 2)).await.unwrap();
+
+        let closes_loop = vec![(depending_on("second", 1), true)];
+        assert!(check_imported_rule_dependencies(&db, &closes_loop, false).await.is_err());
+        let chained = vec![(depending_on("second", 3), true), (depending_on("third", 4), true)];
+        assert!(check_imported_rule_dependencies(&db, &chained, false).await.is_ok());
+        let replaces_first = vec![(depending_on("first", 3), true), (depending_on("x", 1), true)];
+        assert!(check_imported_rule_dependencies(&db, &replaces_first, true).await.is_ok());
+        assert!(check_imported_rule_dependencies(&db, &replaces_first, false).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn legacy_cased_rules_are_stored_migrated_and_match() {
+        let legacy = r#"{"actions":[{"parameters":{"message":"Back to work"},"type":"popup"}],"conditions":[{"field":"Domain","operator":"EQUALS","value":"reddit.com"}],"name":"a","type":"basic"}"#;
+        let (_, rule_json) = validated_rule_json(legacy).unwrap();
+        let db = Database::in_memory().await.unwrap();
+        db.create_rule(NewRule {
+            name: "Block reddit".to_string(),
+            natural_language: String::new(),
+            rule_json,
+            priority: 0,
+            tags: Vec::new(),
+            expires_at: None,
+        })
+        .await
+        .unwrap();
+
+        let rules = db.get_all_rules().await.unwrap();
+        let log = extension_log(0.0, "reddit.com", "browsing");
+        assert_eq!(RuleEngine::new().evaluate(&log, &rules, &[], &[]).len(), 1);
+    }
+
+    #[test]
+    fn trim_logs_keeps_the_newest_entries() {
+        let capacity = 5;
+        let mut logs = VecDeque::new();
+        for i in 0..10 {
+            logs.push_back(ExtensionLog {
+                timestamp: i as f64,
+                domain: "reddit.com".to_string(),
+                activity: "browsing".to_string(),
+                url: "https://reddit.com/".to_string(),
+                title: "reddit".to_string(),
+                elements: None,
+                title_sentiment: None,
+                url_category: None,
+            });
+            trim_logs(&mut logs, capacity);
+        }
+
+        assert_eq!(logs.len(), 5);
+        assert_eq!(logs.front().unwrap().timestamp, 5.0);
+        assert_eq!(logs.back().unwrap().timestamp, 9.0);
+    }
+
+    fn extension_log(timestamp: f64, domain: &str, activity: &str) -> ExtensionLog {
+        ExtensionLog {
+            timestamp,
+            domain: domain.to_string(),
+            activity: activity.to_string(),
+            url: format!("https://{}/", domain),
+            title: domain.to_string(),
+            elements: None,
+            title_sentiment: None,
+            url_category: None,
+        }
+    }
+
+    fn sample_logs() -> VecDeque<ExtensionLog> {
+        VecDeque::from(vec![
+            extension_log(1.0, "www.YouTube.com", "watching_videos"),
+            extension_log(2.0, "github.com", "coding"),
+            extension_log(3.0, "youtube.com", "browsing"),
+            extension_log(4.0, "reddit.com", "browsing"),
+            extension_log(5.0, "m.youtube.com", "watching_videos"),
+        ])
+    }
+
+    fn timestamps(logs: &[ExtensionLog]) -> Vec<f64> {
+        logs.iter().map(|log| log.timestamp).collect()
+    }
+
+    #[test]
+    fn filter_extension_logs_without_filters_returns_everything() {
+        let logs = sample_logs();
+        let filtered = filter_extension_logs(&logs, None, None, None, None, None);
+        assert_eq!(timestamps(&filtered), vec![1.0, 2.0, 3.0, 4.0, 5.0]);
+    }
+
+    #[test]
+    fn filter_extension_logs_by_time_range() {
+        let logs = sample_logs();
+        let after = filter_extension_logs(&logs, Some(3.0), None, None, None, None);
+        assert_eq!(timestamps(&after), vec![3.0, 4.0, 5.0]);
+        let before = filter_extension_logs(&logs, None, Some(2.0), None, None, None);
+        assert_eq!(timestamps(&before), vec![1.0, 2.0]);
+        let between = filter_extension_logs(&logs, Some(2.0), Some(4.0), None, None, None);
+        assert_eq!(timestamps(&between), vec![2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn filter_extension_logs_by_domain_ignores_case() {
+        let logs = sample_logs();
+        let filtered = filter_extension_logs(&logs, None, None, Some("YOUTUBE"), None, None);
+        assert_eq!(timestamps(&filtered), vec![1.0, 3.0, 5.0]);
+    }
+
+    #[test]
+    fn filter_extension_logs_by_activity() {
+        let logs = sample_logs();
+        let filtered = filter_extension_logs(&logs, None, None, None, Some("browsing"), None);
+        assert_eq!(timestamps(&filtered), vec![3.0, 4.0]);
+    }
+
+    #[test]
+    fn filter_extension_logs_limit_keeps_the_newest() {
+        let logs = sample_logs();
+        let filtered = filter_extension_logs(&logs, None, None, None, None, Some(2));
+        assert_eq!(timestamps(&filtered), vec![4.0, 5.0]);
+    }
+
+    #[test]
+    fn filter_extension_logs_combines_filters() {
+        let logs = sample_logs();
+        let filtered = filter_extension_logs(
+            &logs,
+            Some(2.0),
+            None,
+            Some("youtube"),
+            Some("watching_videos"),
+            Some(10),
+        );
+        assert_eq!(timestamps(&filtered), vec![5.0]);
+
+        let filtered =
+            filter_extension_logs(&logs, None, Some(4.0), Some("youtube"), None, Some(1));
+        assert_eq!(timestamps(&filtered), vec![3.0]);
+    }
+
+    #[test]
+    fn extension_logs_page_walks_back_through_older_logs() {
+        let logs: VecDeque<ExtensionLog> = (0..10)
+            .map(|i| extension_log(i as f64, "reddit.com", "browsing"))
+            .collect();
+
+        let first = extension_logs_page(&logs, None, 4);
+        assert_eq!(timestamps(&first.logs), vec![9.0, 8.0, 7.0, 6.0]);
+        assert_eq!(first.next_cursor, Some(6.0));
+        assert_eq!(first.total, 10);
+
+        let second = extension_logs_page(&logs, first.next_cursor, 4);
+        assert_eq!(timestamps(&second.logs), vec![5.0, 4.0, 3.0, 2.0]);
+        assert_eq!(second.next_cursor, Some(2.0));
+
+        let third = extension_logs_page(&logs, second.next_cursor, 4);
+        assert_eq!(timestamps(&third.logs), vec![1.0, 0.0]);
+        assert_eq!(third.next_cursor, None);
+        assert_eq!(third.total, 10);
+    }
+
+    #[test]
+    fn extension_activity_is_recent_within_the_connected_window() {
+        let mut logs = VecDeque::new();
+        assert!(!has_recent_extension_activity(&logs, 100_000.0));
+
+        logs.push_back(extension_log(30_000.0, "reddit.com", "browsing"));
+        assert!(has_recent_extension_activity(&logs, 89_999.0));
+        assert!(!has_recent_extension_activity(&logs, 90_000.0));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,325 @@
+use crate::cortex_error::CortexError;
+use sqlx::SqlitePool;
+use std::collections::HashSet;
+
+// A schema change, applied once and recorded by name in `migrations`. Names
+// must never change once released.
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    pub name: &'static str,
+    pub up: &'static str,
+}
+
+// Applied in order. Only ever append; edit a released migration and
+// existing databases never see the change.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        name: "create_initial_tables",
+        up: "
+        CREATE TABLE rules (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            natural_language TEXT NOT NULL,
+            rule_json TEXT NOT NULL,
+            is_active INTEGER NOT NULL,
+            created_at INTEGER NOT NULL,
+            last_triggered_at INTEGER
+        );
+        CREATE TABLE activities (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp REAL NOT NULL,
+            activity TEXT NOT NULL,
+            productive INTEGER NOT NULL,
+            app TEXT NOT NULL,
+            bundle_id TEXT,
+            domain TEXT,
+            notes TEXT,
+            duration_ms REAL
+        );
+        CREATE INDEX activities_timestamp ON activities (timestamp);
+        CREATE TABLE rule_groups (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            description TEXT,
+            rule_ids TEXT NOT NULL,
+            created_at INTEGER NOT NULL
+        );
+        CREATE TABLE rule_matches (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            rule_id INTEGER NOT NULL,
+            matched_at INTEGER NOT NULL,
+            domain TEXT NOT NULL
+        );
+        CREATE TABLE action_logs (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            rule_id INTEGER NOT NULL,
+            action_type TEXT NOT NULL,
+            fired_at INTEGER NOT NULL,
+            succeeded INTEGER NOT NULL,
+            error TEXT,
+            domain TEXT NOT NULL
+        );
+        CREATE TABLE scheduled_activations (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            rule_id INTEGER NOT NULL,
+            activate_at INTEGER NOT NULL,
+            created_at INTEGER NOT NULL
+        );
+    ",
+    },
+    Migration {
+        name: "add_rule_priority",
+        up: "ALTER TABLE rules ADD COLUMN priority INTEGER NOT NULL DEFAULT 0",
+    },
+    Migration {
+        name: "add_rule_tags",
+        up: "ALTER TABLE rules ADD COLUMN tags TEXT NOT NULL DEFAULT '[]'",
+    },
+    Migration {
+        name: "add_action_log_latency",
+        up: "ALTER TABLE action_logs ADD COLUMN latency_ms REAL",
+    },
+    Migration {
+        name: "add_rule_schedule",
+        up: "ALTER TABLE rules ADD COLUMN schedule TEXT",
+    },
+    Migration {
+        name: "add_rule_expiry",
+        up: "ALTER TABLE rules ADD COLUMN expires_at INTEGER",
+    },
+    Migration {
+        name: "create_domain_limits",
+        up: "
+        CREATE TABLE domain_limits (
+            domain TEXT PRIMARY KEY,
+            daily_limit_seconds INTEGER NOT NULL
+        );
+    ",
+    },
+    Migration {
+        name: "add_rule_hits",
+        up: "
+        ALTER TABLE rules ADD COLUMN hit_count INTEGER NOT NULL DEFAULT 0;
+        CREATE TABLE rule_hits (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            rule_id INTEGER NOT NULL,
+            hit_at INTEGER NOT NULL
+        );
+        CREATE INDEX rule_hits_rule_id ON rule_hits (rule_id, hit_at);
+    ",
+    },
+    Migration {
+        name: "create_audit_log",
+        up: "
+        CREATE TABLE audit_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            rule_id INTEGER NOT NULL,
+            action TEXT NOT NULL,
+            performed_at INTEGER NOT NULL,
+            details TEXT
+        );
+    ",
+    },
+    Migration {
+        name: "create_profiles",
+        up: "
+        CREATE TABLE profiles (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            description TEXT NOT NULL,
+            rule_ids TEXT NOT NULL
+        );
+    ",
+    },
+    Migration {
+        name: "add_profile_schedule",
+        up: "ALTER TABLE profiles ADD COLUMN schedule TEXT",
+    },
+    Migration {
+        name: "index_activity_notes",
+        // External-content index: the text lives in `activities` and the
+        // triggers keep the index in step with it
+        up: "
+        CREATE VIRTUAL TABLE activity_notes USING fts5(
+            notes,
+            content = 'activities',
+            content_rowid = 'id'
+        );
+        CREATE TRIGGER activity_notes_insert AFTER INSERT ON activities BEGIN
+            INSERT INTO activity_notes (rowid, notes) VALUES (new.id, new.notes);
+        END;
+        CREATE TRIGGER activity_notes_delete AFTER DELETE ON activities BEGIN
+            INSERT INTO activity_notes (activity_notes, rowid, notes)
+                VALUES ('delete', old.id, old.notes);
+        END;
+        CREATE TRIGGER activity_notes_update AFTER UPDATE OF notes ON activities BEGIN
+            INSERT INTO activity_notes (activity_notes, rowid, notes)
+                VALUES ('delete', old.id, old.notes);
+            INSERT INTO activity_notes (rowid, notes) VALUES (new.id, new.notes);
+        END;
+        INSERT INTO activity_notes (activity_notes) VALUES ('rebuild');
+    ",
+    },
+];
+
+pub struct MigrationRunner {
+    migrations: Vec<Migration>,
+}
+
+impl MigrationRunner {
+    pub fn new(migrations: Vec<Migration>) -> Self {
+        MigrationRunner { migrations }
+    }
+
+    // Applies every migration not yet recorded, in one transaction, and
+    // returns their names. Nothing is applied if any of them fails.
+    pub async fn run(&self, pool: &SqlitePool) -> Result<Vec<&'static str>, CortexError> {
+        let failed = |name: &str, e: sqlx::Error| {
+            CortexError::MigrationFailed(format!("Migration {} failed: {}", name, e))
+        };
+
+        let mut tx = pool.begin().await?;
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS migrations (
+                id INTEGER PRIMARY KEY,
+                name TEXT NOT NULL UNIQUE,
+                applied_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&mut *tx)
+        .await?;
+        let applied: HashSet<String> = sqlx::query_scalar("SELECT name FROM migrations")
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .collect();
+
+        let mut newly_applied = Vec::new();
+        for (index, migration) in self.migrations.iter().enumerate() {
+            if applied.contains(migration.name) {
+                continue;
+            }
+            sqlx::query(migration.up)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| failed(migration.name, e))?;
+            sqlx::query("INSERT INTO migrations (id, name, applied_at) VALUES (?, ?, ?)")
+                .bind(index as i64 + 1)
+                .bind(migration.name)
+                .bind(chrono::Utc::now().timestamp())
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| failed(migration.name, e))?;
+            newly_applied.push(migration.name);
+        }
+        // Dropping the transaction on an early return rolls it back
+        tx.commit().await?;
+
+        for name in &newly_applied {
+            println!("Applied database migration: {}", name);
+        }
+        Ok(newly_applied)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn memory_pool() -> SqlitePool {
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap()
+    }
+
+    async fn table_exists(pool: &SqlitePool, name: &str) -> bool {
+        sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?)",
+        )
+        .bind(name)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn fresh_database_applies_every_migration_once() {
+        let pool = memory_pool().await;
+        let runner = MigrationRunner::new(MIGRATIONS.to_vec());
+
+        let applied = runner.run(&pool).await.unwrap();
+        let names: Vec<&str> = MIGRATIONS.iter().map(|migration| migration.name).collect();
+        assert_eq!(applied, names);
+        assert!(table_exists(&pool, "rules").await);
+        assert!(table_exists(&pool, "activities").await);
+        assert!(runner.run(&pool).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn migrations_apply_in_order_and_only_once() {
+        let pool = memory_pool().await;
+        let mut runner = MigrationRunner::new(vec![
+            Migration {
+                name: "create_notes",
+                up: "CREATE TABLE notes (id INTEGER PRIMARY KEY)",
+            },
+            Migration {
+                name: "add_note_body",
+                up: "ALTER TABLE notes ADD COLUMN body TEXT",
+            },
+        ]);
+
+        assert_eq!(
+            runner.run(&pool).await.unwrap(),
+            ["create_notes", "add_note_body"]
+        );
+        assert!(runner.run(&pool).await.unwrap().is_empty());
+
+        runner.migrations.push(Migration {
+            name: "create_tags",
+            up: "CREATE TABLE tags (id INTEGER PRIMARY KEY)",
+        });
+        assert_eq!(runner.run(&pool).await.unwrap(), ["create_tags"]);
+        assert!(table_exists(&pool, "tags").await);
+        let recorded: Vec<(i64, String)> =
+            sqlx::query_as("SELECT id, name FROM migrations ORDER BY id")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        let expected = [
+            (1, "create_notes"),
+            (2, "add_note_body"),
+            (3, "create_tags"),
+        ];
+        let expected: Vec<(i64, String)> = expected
+            .iter()
+            .map(|(id, name)| (*id, name.to_string()))
+            .collect();
+        assert_eq!(recorded, expected);
+    }
+
+    #[tokio::test]
+    async fn a_failed_migration_rolls_back_the_whole_run() {
+        let pool = memory_pool().await;
+        let runner = MigrationRunner::new(vec![
+            Migration {
+                name: "create_notes",
+                up: "CREATE TABLE notes (id INTEGER PRIMARY KEY)",
+            },
+            Migration {
+                name: "broken",
+                up: "ALTER TABLE missing ADD COLUMN body TEXT",
+            },
+        ]);
+
+        let error = runner.run(&pool).await.unwrap_err();
+        assert!(
+            matches!(error, CortexError::MigrationFailed(message) if message.contains("broken"))
+        );
+        assert!(!table_exists(&pool, "notes").await);
+        assert!(!table_exists(&pool, "migrations").await);
+    }
+}
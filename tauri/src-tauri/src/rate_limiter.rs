@@ -0,0 +1,140 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_MAX_REQUESTS_PER_SECOND: u32 = 50;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateLimitStats {
+    pub max_requests_per_second: u32,
+    pub allowed: u64,
+    pub rejected: u64,
+    // Requests allowed during the last second
+    pub current_rps: u32,
+}
+
+// Token bucket holding up to one second's worth of requests, refilled
+// continuously at `max_requests_per_second`
+#[derive(Debug)]
+pub struct RateLimiter {
+    max_requests_per_second: u32,
+    tokens: f64,
+    last_refill: Instant,
+    allowed: u64,
+    rejected: u64,
+    recent: VecDeque<Instant>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        RateLimiter::new(DEFAULT_MAX_REQUESTS_PER_SECOND)
+    }
+}
+
+impl RateLimiter {
+    pub fn new(max_requests_per_second: u32) -> Self {
+        RateLimiter {
+            max_requests_per_second,
+            tokens: max_requests_per_second as f64,
+            last_refill: Instant::now(),
+            allowed: 0,
+            rejected: 0,
+            recent: VecDeque::new(),
+        }
+    }
+
+    pub fn set_max_requests_per_second(&mut self, max_requests_per_second: u32) {
+        self.max_requests_per_second = max_requests_per_second;
+        self.tokens = self.tokens.min(max_requests_per_second as f64);
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        let capacity = self.max_requests_per_second as f64;
+        self.tokens = (self.tokens + elapsed * capacity).min(capacity);
+        self.last_refill = now;
+    }
+
+    // Takes a token, or returns how long until one is available
+    pub fn check(&mut self, now: Instant) -> Result<(), Duration> {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            self.allowed += 1;
+            self.prune_recent(now);
+            self.recent.push_back(now);
+            return Ok(());
+        }
+
+        self.rejected += 1;
+        let missing = 1.0 - self.tokens;
+        Err(Duration::from_secs_f64(
+            missing / self.max_requests_per_second.max(1) as f64,
+        ))
+    }
+
+    // Forgets requests older than the one-second window
+    fn prune_recent(&mut self, now: Instant) {
+        while self
+            .recent
+            .front()
+            .is_some_and(|at| now.saturating_duration_since(*at) >= Duration::from_secs(1))
+        {
+            self.recent.pop_front();
+        }
+    }
+
+    pub fn stats(&mut self, now: Instant) -> RateLimitStats {
+        self.prune_recent(now);
+        RateLimitStats {
+            max_requests_per_second: self.max_requests_per_second,
+            allowed: self.allowed,
+            rejected: self.rejected,
+            current_rps: self.recent.len() as u32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_rejects_bursts_and_refills_over_time() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(5);
+
+        for _ in 0..5 {
+            assert!(limiter.check(start).is_ok());
+        }
+        let retry_after = limiter.check(start).unwrap_err();
+        assert_eq!(retry_after, Duration::from_millis(200));
+
+        assert!(limiter.check(start + Duration::from_millis(200)).is_ok());
+        assert!(limiter.check(start + Duration::from_millis(200)).is_err());
+
+        let stats = limiter.stats(start + Duration::from_millis(500));
+        assert_eq!(stats.allowed, 6);
+        assert_eq!(stats.rejected, 2);
+        assert_eq!(stats.current_rps, 6);
+        assert_eq!(
+            limiter
+                .stats(start + Duration::from_millis(1100))
+                .current_rps,
+            1
+        );
+    }
+
+    #[test]
+    fn recent_requests_stay_within_the_window() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(10);
+
+        for millis in (0..10_000).step_by(100) {
+            assert!(limiter.check(start + Duration::from_millis(millis)).is_ok());
+        }
+        assert!(limiter.recent.len() <= 10);
+    }
+}
@@ -0,0 +1,507 @@
+use serde::{Deserialize, Serialize};
+
+// Rules without a `schema_version` predate versioning and are version 1
+pub const CURRENT_RULE_SCHEMA_VERSION: u8 = 2;
+
+// How many condition groups may be nested inside one another
+pub const MAX_GROUP_DEPTH: usize = 5;
+
+// Typed form of the `rule_json` string stored on each rule.
+//
+// Fields are declared in alphabetical order so serialization emits keys in the
+// same order as `serde_json::json!`, which keeps stored rules byte-stable when
+// they are parsed and written back.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuleDefinition {
+    pub actions: Vec<RuleAction>,
+    pub conditions: Vec<RuleCondition>,
+    // Omitted when "and" so rules written before it existed stay byte-stable
+    #[serde(default, skip_serializing_if = "ConditionLogic::is_and")]
+    pub logic: ConditionLogic,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<u8>,
+    #[serde(rename = "type")]
+    pub rule_type: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConditionLogic {
+    #[default]
+    And,
+    Or,
+}
+
+impl ConditionLogic {
+    pub fn is_and(&self) -> bool {
+        *self == ConditionLogic::And
+    }
+}
+
+// Conditions carrying a `condition_type` tag are typed, those with
+// `"type": "group"` are groups and anything else is a plain comparison against
+// a field of the incoming record
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RuleCondition {
+    Typed(TypedCondition),
+    Group(ConditionGroup),
+    Field(FieldCondition),
+}
+
+// `not` inverts the result of the comparison
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldCondition {
+    pub field: String,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub not: bool,
+    pub operator: String,
+    pub value: serde_json::Value,
+}
+
+// Conditions combined with their own logic, inverted as a whole by `not`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConditionGroup {
+    pub conditions: Vec<RuleCondition>,
+    #[serde(default)]
+    pub logic: ConditionLogic,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub not: bool,
+    #[serde(rename = "type")]
+    pub group_type: GroupType,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupType {
+    Group,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "condition_type", rename_all = "snake_case")]
+pub enum TypedCondition {
+    AppInForeground(AppForegroundCondition),
+    ForegroundDuration(ForegroundDurationCondition),
+    TimeSinceLastRuleTrigger(TimeSinceLastTriggerCondition),
+    UrlPattern(UrlPatternCondition),
+    RuleTriggerRate(RuleTriggerRateCondition),
+    GlobalTriggerRate(GlobalTriggerRateCondition),
+    DomainTimeToday(DomainTimeTodayCondition),
+}
+
+// Matches when the foreground app has the given name and/or bundle id; fields
+// left unset are not checked
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AppForegroundCondition {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bundle_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ForegroundDurationCondition {
+    pub app_name: String,
+    pub min_seconds: u32,
+}
+
+// Matches when the referenced rule has not fired for at least `min_minutes`,
+// including when it has never fired
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimeSinceLastTriggerCondition {
+    pub rule_id_ref: i64,
+    pub min_minutes: u32,
+}
+
+// Matches when the referenced rule fired more than `max_triggers_per_hour`
+// times in the past 60 minutes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuleTriggerRateCondition {
+    pub rule_id_ref: i64,
+    pub max_triggers_per_hour: u32,
+}
+
+// Like `RuleTriggerRateCondition`, but counts triggers across all rules
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GlobalTriggerRateCondition {
+    pub max_total_triggers_per_hour: u32,
+}
+
+// Matches pages on `host` (or its subdomains) whose path starts with
+// `path_prefix`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UrlPatternCondition {
+    pub host: String,
+    pub path_prefix: String,
+}
+
+// Matches pages on `domain` (or its subdomains) once they have been open for
+// `min_seconds` in total today, counted as for domain limits
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DomainTimeTodayCondition {
+    pub domain: String,
+    pub min_seconds: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuleAction {
+    pub parameters: serde_json::Value,
+    #[serde(rename = "type")]
+    pub action_type: String,
+}
+
+// Parameters of a "notification" action, shown as a desktop notification
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotificationAction {
+    pub title: String,
+    pub body: String,
+    #[serde(default)]
+    pub icon: Option<String>,
+}
+
+// What the browser extension does to the matching tab for a "close_tab" or
+// "redirect" action
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum TabAction {
+    CloseTab,
+    Redirect { url: String },
+}
+
+impl RuleDefinition {
+    pub fn from_json(rule_json: &str) -> Result<Self, String> {
+        serde_json::from_str(rule_json).map_err(|e| format!("Invalid rule JSON: {}", e))
+    }
+
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string(self).map_err(|e| format!("Failed to serialize rule: {}", e))
+    }
+
+    pub fn schema_version(&self) -> u8 {
+        self.schema_version.unwrap_or(1)
+    }
+
+    // Every typed and field condition, including those inside groups
+    pub fn leaf_conditions(&self) -> Vec<&RuleCondition> {
+        fn collect<'a>(conditions: &'a [RuleCondition], leaves: &mut Vec<&'a RuleCondition>) {
+            for condition in conditions {
+                match condition {
+                    RuleCondition::Group(group) => collect(&group.conditions, leaves),
+                    _ => leaves.push(condition),
+                }
+            }
+        }
+
+        let mut leaves = Vec::new();
+        collect(&self.conditions, &mut leaves);
+        leaves
+    }
+}
+
+// Parses a rule and checks its action parameters, describing every problem
+// found in the error
+pub fn validate_rule_json(rule_json: &str) -> Result<RuleDefinition, String> {
+    let definition = RuleDefinition::from_json(rule_json)?;
+    let errors = crate::action_executor::validate_action_config(&definition.actions);
+    if errors.is_empty() {
+        return Ok(definition);
+    }
+    let messages: Vec<String> = errors
+        .iter()
+        .map(|error| format!("action {} {}: {}", error.action_index, error.field, error.message))
+        .collect();
+    Err(format!("Invalid rule actions: {}", messages.join("; ")))
+}
+
+// Operators the rule engine understands for field conditions
+pub const FIELD_OPERATORS: [&str; 4] = ["contains", "equals", "starts_with", "regex"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleValidationResult {
+    pub valid: bool,
+    pub errors: Vec<String>,
+}
+
+// Stricter than `validate_rule_json`: also rejects rules that could never
+// match. Used before a rule is created or edited.
+pub fn validate_rule_schema(rule_json: &str) -> Result<RuleDefinition, String> {
+    let errors = rule_json_errors(rule_json);
+    if errors.is_empty() {
+        RuleDefinition::from_json(rule_json)
+    } else {
+        Err(errors.join("; "))
+    }
+}
+
+// Describes every problem found, each prefixed with where it is (e.g.
+// `condition[0].operator`)
+pub fn rule_json_errors(rule_json: &str) -> Vec<String> {
+    let value: serde_json::Value = match serde_json::from_str(rule_json) {
+        Ok(value) => value,
+        Err(e) => return vec![format!("Invalid rule JSON: {}", e)],
+    };
+
+    // Parse each condition and action on its own so errors say which one is
+    // wrong; the untagged condition enum would otherwise hide the reason
+    let mut errors = Vec::new();
+    let items = |key: &str| {
+        value
+            .get(key)
+            .and_then(|items| items.as_array())
+            .cloned()
+            .unwrap_or_default()
+    };
+    for (index, condition) in items("conditions").iter().enumerate() {
+        condition_parse_errors(&format!("condition[{}]", index), condition, &mut errors);
+    }
+    for (index, action) in items("actions").into_iter().enumerate() {
+        if let Err(e) = serde_json::from_value::<RuleAction>(action) {
+            errors.push(format!("action[{}]: {}", index, e));
+        }
+    }
+    if !errors.is_empty() {
+        return errors;
+    }
+
+    let definition = match serde_json::from_value::<RuleDefinition>(value) {
+        Ok(definition) => definition,
+        Err(e) => return vec![format!("Invalid rule JSON: {}", e)],
+    };
+    if definition.conditions.is_empty() {
+        errors.push("conditions must not be empty".to_string());
+    }
+    let legacy = definition.schema_version() < 2;
+    condition_errors("condition", &definition.conditions, 0, legacy, &mut errors);
+    for error in crate::action_executor::validate_action_config(&definition.actions) {
+        errors.push(format!(
+            "action[{}].{}: {}",
+            error.action_index, error.field, error.message
+        ));
+    }
+    errors
+}
+
+// Parses a condition the way `RuleCondition` would, but reports why it failed
+// and descends into groups so errors point at the innermost culprit
+fn condition_parse_errors(path: &str, condition: &serde_json::Value, errors: &mut Vec<String>) {
+    let result = if condition.get("condition_type").is_some() {
+        serde_json::from_value::<TypedCondition>(condition.clone()).map(|_| ())
+    } else if condition.get("type").and_then(|group_type| group_type.as_str()) == Some("group") {
+        let before = errors.len();
+        let conditions = condition.get("conditions").and_then(|items| items.as_array());
+        for (index, condition) in conditions.into_iter().flatten().enumerate() {
+            condition_parse_errors(&format!("{}.conditions[{}]", path, index), condition, errors);
+        }
+        if errors.len() > before {
+            return;
+        }
+        serde_json::from_value::<ConditionGroup>(condition.clone()).map(|_| ())
+    } else {
+        serde_json::from_value::<FieldCondition>(condition.clone()).map(|_| ())
+    };
+    if let Err(e) = result {
+        errors.push(format!("{}: {}", path, e));
+    }
+}
+
+// `prefix` is the path of the list, e.g. `condition` or
+// `condition[0].conditions`, and `depth` how many groups enclose it
+fn condition_errors(
+    prefix: &str,
+    conditions: &[RuleCondition],
+    depth: usize,
+    legacy: bool,
+    errors: &mut Vec<String>,
+) {
+    for (index, condition) in conditions.iter().enumerate() {
+        let path = format!("{}[{}]", prefix, index);
+        let condition = match condition {
+            RuleCondition::Field(condition) => condition,
+            RuleCondition::Group(group) => {
+                if depth + 1 > MAX_GROUP_DEPTH {
+                    errors.push(format!(
+                        "{} nests groups more than {} levels deep",
+                        path, MAX_GROUP_DEPTH
+                    ));
+                    continue;
+                }
+                if group.conditions.is_empty() {
+                    errors.push(format!("{}.conditions must not be empty", path));
+                }
+                let prefix = format!("{}.conditions", path);
+                condition_errors(&prefix, &group.conditions, depth + 1, legacy, errors);
+                continue;
+            }
+            RuleCondition::Typed(_) => continue,
+        };
+
+        if condition.field.trim().is_empty() {
+            errors.push(format!("{}.field must not be empty", path));
+        }
+        // Version 1 rules may spell operators in any case until migrated
+        let operator = if legacy {
+            condition.operator.to_lowercase()
+        } else {
+            condition.operator.clone()
+        };
+        if !FIELD_OPERATORS.contains(&operator.as_str()) {
+            errors.push(format!(
+                "{}.operator must be one of: {}",
+                path,
+                FIELD_OPERATORS.join(", ")
+            ));
+        }
+        match condition.value.as_str() {
+            None => errors.push(format!("{}.value must be a string", path)),
+            Some(pattern) if operator == "regex" => {
+                if let Err(e) = crate::rule_engine::build_regex(pattern) {
+                    errors.push(format!("{}.value is not a valid regex: {}", path, e));
+                }
+            }
+            Some(_) => {}
+        }
+    }
+}
+
+// Upgrades a stored rule to the current schema version. Version 2 lowercases
+// field names and operators and renames the legacy `send_webhook` action.
+pub fn migrate_rule_json(rule_json: &str) -> Result<String, String> {
+    let mut definition = RuleDefinition::from_json(rule_json)?;
+    if definition.schema_version() > CURRENT_RULE_SCHEMA_VERSION {
+        return Err(format!(
+            "Rule schema version {} is newer than supported version {}",
+            definition.schema_version(),
+            CURRENT_RULE_SCHEMA_VERSION
+        ));
+    }
+
+    fn lowercase_fields(conditions: &mut [RuleCondition]) {
+        for condition in conditions {
+            match condition {
+                RuleCondition::Field(condition) => {
+                    condition.field = condition.field.to_lowercase();
+                    condition.operator = condition.operator.to_lowercase();
+                }
+                RuleCondition::Group(group) => lowercase_fields(&mut group.conditions),
+                RuleCondition::Typed(_) => {}
+            }
+        }
+    }
+
+    if definition.schema_version() < 2 {
+        lowercase_fields(&mut definition.conditions);
+        for action in &mut definition.actions {
+            if action.action_type == "send_webhook" {
+                action.action_type = "webhook".to_string();
+            }
+        }
+    }
+
+    definition.schema_version = Some(CURRENT_RULE_SCHEMA_VERSION);
+    definition.to_json()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migration_upgrades_legacy_rules_to_current_version() {
+        let legacy = r#"{"actions":[{"parameters":{},"type":"send_webhook"}],"conditions":[{"field":"Domain","operator":"EQUALS","value":"reddit.com"}],"name":"a","type":"basic"}"#;
+
+        let migrated = migrate_rule_json(legacy).unwrap();
+
+        assert_eq!(
+            migrated,
+            r#"{"actions":[{"parameters":{},"type":"webhook"}],"conditions":[{"field":"domain","operator":"equals","value":"reddit.com"}],"name":"a","schema_version":2,"type":"basic"}"#
+        );
+        assert_eq!(migrate_rule_json(&migrated).unwrap(), migrated);
+
+        let nested = r#"{"actions":[],"conditions":[{"conditions":[{"field":"Title","operator":"CONTAINS","value":"Shorts"},{"conditions":[{"field":"URL","operator":"Starts_With","value":"https://"}],"logic":"and","type":"group"}],"logic":"or","type":"group"}],"name":"b","type":"basic"}"#;
+        assert_eq!(
+            migrate_rule_json(nested).unwrap(),
+            r#"{"actions":[],"conditions":[{"conditions":[{"field":"title","operator":"contains","value":"Shorts"},{"conditions":[{"field":"url","operator":"starts_with","value":"https://"}],"logic":"and","type":"group"}],"logic":"or","type":"group"}],"name":"b","schema_version":2,"type":"basic"}"#
+        );
+    }
+
+    #[test]
+    fn schema_errors_name_the_missing_field() {
+        let errors = rule_json_errors(
+            r#"{"actions":[{"type":"popup"}],"conditions":[{"field":"domain","value":"reddit.com"}],"name":"a","type":"basic"}"#,
+        );
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with("condition[0]: missing field `operator`"));
+        assert!(errors[1].starts_with("action[0]: missing field `parameters`"));
+
+        let errors = rule_json_errors(
+            r#"{"actions":[],"conditions":[{"field":"domain","operator":"equals","value":"reddit.com"}],"type":"basic"}"#,
+        );
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("missing field `name`"));
+    }
+
+    #[test]
+    fn schema_errors_reject_unknown_operators() {
+        let rule_json = r#"{"actions":[],"conditions":[{"field":"domain","operator":"equals","value":"reddit.com"},{"field":"title","operator":"matches","value":"cats"}],"name":"a","schema_version":2,"type":"basic"}"#;
+        assert_eq!(
+            rule_json_errors(rule_json),
+            vec!["condition[1].operator must be one of: contains, equals, starts_with, regex"]
+        );
+        assert!(validate_rule_schema(rule_json).is_err());
+
+        // Legacy rules are lowercased when migrated
+        let legacy = r#"{"actions":[],"conditions":[{"field":"Domain","operator":"EQUALS","value":"reddit.com"}],"name":"a","type":"basic"}"#;
+        assert!(validate_rule_schema(legacy).is_ok());
+    }
+
+    #[test]
+    fn schema_errors_reject_invalid_regex_patterns() {
+        let valid = r#"{"actions":[],"conditions":[{"field":"url","operator":"regex","value":"youtube\\.com/shorts/\\w+"}],"name":"a","type":"basic"}"#;
+        assert!(rule_json_errors(valid).is_empty());
+
+        let invalid = r#"{"actions":[],"conditions":[{"field":"url","operator":"regex","value":"youtube\\.com/(shorts"}],"name":"a","type":"basic"}"#;
+        let errors = rule_json_errors(invalid);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("condition[0].value is not a valid regex"));
+    }
+
+    #[test]
+    fn schema_errors_limit_group_nesting() {
+        let nested = |depth: usize| {
+            let mut condition =
+                r#"{"field":"domain","operator":"equals","value":"reddit.com"}"#.to_string();
+            for _ in 0..depth {
+                condition = format!(r#"{{"conditions":[{}],"logic":"or","type":"group"}}"#, condition);
+            }
+            format!(r#"{{"actions":[],"conditions":[{}],"name":"a","type":"basic"}}"#, condition)
+        };
+
+        let rule_json = nested(MAX_GROUP_DEPTH);
+        assert!(rule_json_errors(&rule_json).is_empty());
+        // Groups round-trip unchanged
+        assert_eq!(RuleDefinition::from_json(&rule_json).unwrap().to_json().unwrap(), rule_json);
+
+        let errors = rule_json_errors(&nested(MAX_GROUP_DEPTH + 1));
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("more than 5 levels deep"), "{}", errors[0]);
+
+        let errors = rule_json_errors(
+            r#"{"actions":[],"conditions":[{"conditions":[{"field":"domain","value":"x"}],"type":"group"}],"logic":"or","name":"a","type":"basic"}"#,
+        );
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("condition[0].conditions[0]: missing field `operator`"));
+    }
+
+    #[test]
+    fn schema_errors_reject_empty_conditions() {
+        let rule_json = r#"{"actions":[],"conditions":[],"name":"a","type":"basic"}"#;
+        assert_eq!(rule_json_errors(rule_json), vec!["conditions must not be empty"]);
+        // Still accepted where rules are only parsed, e.g. on import
+        assert!(validate_rule_json(rule_json).is_ok());
+    }
+}
@@ -0,0 +1,1259 @@
+use crate::analytics::DomainTimeStat;
+use crate::database::{ActivityRecord, Rule, RuleMatchRecord};
+use crate::domain_limits::{domain_covers, normalize_limit_domain, seconds_on_domain};
+use crate::ExtensionLog;
+use crate::rule_definition::{
+    ConditionLogic, FieldCondition, RuleCondition, RuleDefinition, TypedCondition,
+    UrlPatternCondition, FIELD_OPERATORS,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleMatch {
+    pub rule_id: i64,
+    pub rule_name: String,
+}
+
+// Conditions are described as `field operator value`, or as JSON when typed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleTestResult {
+    pub matched: bool,
+    pub matched_conditions: Vec<String>,
+    pub unmatched_conditions: Vec<String>,
+    pub evaluation_time_us: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleDependencyGraph {
+    pub nodes: Vec<(i64, String)>,
+    pub edges: Vec<(i64, i64)>,
+    pub cycles: Vec<Vec<i64>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleSimilarityResult {
+    pub rule: Rule,
+    pub similarity: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleComplexityScore {
+    pub total_conditions: u8,
+    pub regex_condition_count: u8,
+    // There are no script conditions yet, so this is always 0
+    pub script_condition_count: u8,
+    // 1 (trivial) to 10 (expensive)
+    pub estimated_eval_cost: u8,
+}
+
+// Routes regex conditions through a cache of compiled patterns instead of
+// compiling them on every evaluation
+pub const NEW_REGEX_EVALUATOR_FLAG: &str = "new_regex_evaluator";
+
+#[derive(Debug, Default)]
+pub struct RuleEngine {
+    feature_flags: HashMap<String, bool>,
+    // Patterns that failed to compile are cached as None
+    regex_cache: std::sync::Mutex<HashMap<String, Option<regex::Regex>>>,
+}
+
+impl RuleEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_feature_flags(&mut self, feature_flags: HashMap<String, bool>) {
+        self.feature_flags = feature_flags;
+    }
+
+    fn feature_enabled(&self, flag: &str) -> bool {
+        self.feature_flags.get(flag).copied().unwrap_or(false)
+    }
+
+    // Compiles the regex conditions of all active rules ahead of their first
+    // evaluation. Invalid patterns are reported but do not stop the others
+    // from being compiled.
+    pub fn warm_up(&self, rules: &[Rule]) -> Result<(), String> {
+        let mut cache = self.regex_cache.lock().unwrap();
+        let mut invalid = Vec::new();
+        for rule in rules.iter().filter(|rule| rule.is_active) {
+            let Ok(definition) = RuleDefinition::from_json(&rule.rule_json) else {
+                continue;
+            };
+            for condition in definition.leaf_conditions() {
+                let RuleCondition::Field(condition) = condition else {
+                    continue;
+                };
+                let Some(pattern) = condition.value.as_str() else {
+                    continue;
+                };
+                if condition.operator != "regex" {
+                    continue;
+                }
+                let regex =
+                    cache.entry(pattern.to_string()).or_insert_with(|| compile_regex(pattern));
+                if regex.is_none() {
+                    invalid.push(format!("rule {}: {}", rule.id, pattern));
+                }
+            }
+        }
+
+        if invalid.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("Invalid regex patterns: {}", invalid.join(", ")))
+        }
+    }
+
+    // Returns the active rules whose conditions all hold for the given
+    // activity, in priority order. `rules` should include inactive rules too,
+    // since conditions may refer to them. `foreground_started_at` maps an app name to the timestamp
+    // (ms) at which it most recently came to the foreground, and
+    // `recent_matches` should cover at least the past hour. Conditions on time
+    // spent on a domain today only hold for browser logs.
+    pub fn evaluate_activity(
+        &self,
+        activity: &ActivityRecord,
+        rules: &[Rule],
+        foreground_started_at: &HashMap<String, f64>,
+        recent_matches: &[RuleMatchRecord],
+    ) -> Vec<RuleMatch> {
+        in_priority_order(rules)
+            .into_iter()
+            .filter(|rule| rule.is_active && schedule_allows(rule, activity.timestamp))
+            .filter(|rule| {
+                let definition = match RuleDefinition::from_json(&rule.rule_json) {
+                    Ok(definition) => definition,
+                    Err(e) => {
+                        println!("Skipping rule {}: {}", rule.id, e);
+                        return false;
+                    }
+                };
+                conditions_hold(definition.logic, &definition.conditions, |condition| {
+                    self.activity_condition_matches(
+                        condition,
+                        activity,
+                        rules,
+                        foreground_started_at,
+                        recent_matches,
+                    )
+                })
+            })
+            .map(|rule| RuleMatch {
+                rule_id: rule.id,
+                rule_name: rule.name.clone(),
+            })
+            .collect()
+    }
+
+    // Returns the active rules whose conditions all hold for a browser log, in
+    // priority order.
+    // Conditions about foreground apps never hold, since logs carry no app.
+    // `rules` and `recent_matches` are as for `evaluate_activity`;
+    // `domain_time_today` is today's time per domain.
+    pub fn evaluate(
+        &self,
+        log: &ExtensionLog,
+        rules: &[Rule],
+        recent_matches: &[RuleMatchRecord],
+        domain_time_today: &[DomainTimeStat],
+    ) -> Vec<RuleMatch> {
+        in_priority_order(rules)
+            .into_iter()
+            .filter(|rule| rule.is_active && schedule_allows(rule, log.timestamp))
+            .filter(|rule| {
+                let definition = match RuleDefinition::from_json(&rule.rule_json) {
+                    Ok(definition) => definition,
+                    Err(e) => {
+                        println!("Skipping rule {}: {}", rule.id, e);
+                        return false;
+                    }
+                };
+                conditions_hold(definition.logic, &definition.conditions, |condition| {
+                    self.log_condition_matches(
+                        condition,
+                        log,
+                        rules,
+                        recent_matches,
+                        domain_time_today,
+                    )
+                })
+            })
+            .map(|rule| RuleMatch {
+                rule_id: rule.id,
+                rule_name: rule.name.clone(),
+            })
+            .collect()
+    }
+
+    // Checks each condition of an unsaved rule against a log, as `evaluate`
+    // would but without regard to whether the rule is active or scheduled.
+    // Nothing is recorded.
+    pub fn test_log(
+        &self,
+        definition: &RuleDefinition,
+        log: &ExtensionLog,
+        rules: &[Rule],
+        recent_matches: &[RuleMatchRecord],
+        domain_time_today: &[DomainTimeStat],
+    ) -> RuleTestResult {
+        let started = std::time::Instant::now();
+        let (matched, unmatched): (Vec<&RuleCondition>, Vec<&RuleCondition>) = definition
+            .conditions
+            .iter()
+            .partition(|condition| {
+                self.log_condition_matches(condition, log, rules, recent_matches, domain_time_today)
+            });
+        let evaluation_time_us = started.elapsed().as_micros() as u64;
+
+        RuleTestResult {
+            matched: match definition.logic {
+                ConditionLogic::And => !matched.is_empty() && unmatched.is_empty(),
+                ConditionLogic::Or => !matched.is_empty(),
+            },
+            matched_conditions: matched.into_iter().map(describe_condition).collect(),
+            unmatched_conditions: unmatched.into_iter().map(describe_condition).collect(),
+            evaluation_time_us,
+        }
+    }
+
+    fn log_condition_matches(
+        &self,
+        condition: &RuleCondition,
+        log: &ExtensionLog,
+        rules: &[Rule],
+        recent_matches: &[RuleMatchRecord],
+        domain_time_today: &[DomainTimeStat],
+    ) -> bool {
+        match condition {
+            RuleCondition::Field(condition) => {
+                let value = match condition.field.as_str() {
+                    "activity" => Some(log.activity.as_str()),
+                    "domain" => Some(log.domain.as_str()),
+                    "title" => Some(log.title.as_str()),
+                    "url" => Some(log.url.as_str()),
+                    _ => None,
+                };
+                value.is_some_and(|value| self.field_matches(condition, value)) != condition.not
+            }
+            RuleCondition::Group(group) => {
+                conditions_hold(group.logic, &group.conditions, |condition| {
+                    self.log_condition_matches(
+                        condition,
+                        log,
+                        rules,
+                        recent_matches,
+                        domain_time_today,
+                    )
+                }) != group.not
+            }
+            RuleCondition::Typed(
+                TypedCondition::AppInForeground(_) | TypedCondition::ForegroundDuration(_),
+            ) => false,
+            RuleCondition::Typed(TypedCondition::UrlPattern(condition)) => {
+                match url::Url::parse(&log.url) {
+                    Ok(url) => url.host_str().is_some_and(|host| {
+                        url_pattern_matches(condition, host, Some(url.path()))
+                    }),
+                    Err(_) => url_pattern_matches(condition, &log.domain, None),
+                }
+            }
+            RuleCondition::Typed(TypedCondition::DomainTimeToday(condition)) => {
+                let domain = normalize_limit_domain(&condition.domain);
+                domain_covers(&domain, &log.domain)
+                    && seconds_on_domain(domain_time_today, &domain)
+                        >= condition.min_seconds as f64
+            }
+            RuleCondition::Typed(condition) => {
+                rule_history_matches(condition, log.timestamp, rules, recent_matches)
+            }
+        }
+    }
+
+    fn activity_condition_matches(
+        &self,
+        condition: &RuleCondition,
+        activity: &ActivityRecord,
+        rules: &[Rule],
+        foreground_started_at: &HashMap<String, f64>,
+        recent_matches: &[RuleMatchRecord],
+    ) -> bool {
+        match condition {
+            RuleCondition::Field(condition) => {
+                let value = match condition.field.as_str() {
+                    "activity" => Some(activity.activity.as_str()),
+                    "app" => Some(activity.app.as_str()),
+                    "bundle_id" => activity.bundle_id.as_deref(),
+                    "domain" => activity.domain.as_deref(),
+                    _ => None,
+                };
+                value.is_some_and(|value| self.field_matches(condition, value)) != condition.not
+            }
+            RuleCondition::Group(group) => {
+                conditions_hold(group.logic, &group.conditions, |condition| {
+                    self.activity_condition_matches(
+                        condition,
+                        activity,
+                        rules,
+                        foreground_started_at,
+                        recent_matches,
+                    )
+                }) != group.not
+            }
+            RuleCondition::Typed(TypedCondition::AppInForeground(condition)) => {
+                let app_matches = condition
+                    .app_name
+                    .as_ref()
+                    .is_none_or(|name| name.eq_ignore_ascii_case(&activity.app));
+                let bundle_matches = condition
+                    .bundle_id
+                    .as_ref()
+                    .is_none_or(|bundle_id| activity.bundle_id.as_ref() == Some(bundle_id));
+                app_matches && bundle_matches
+            }
+            RuleCondition::Typed(TypedCondition::ForegroundDuration(condition)) => {
+                condition.app_name.eq_ignore_ascii_case(&activity.app)
+                    && foreground_started_at.get(&activity.app).is_some_and(|started_at| {
+                        activity.timestamp - started_at >= condition.min_seconds as f64 * 1000.0
+                    })
+            }
+            // Activities only carry a domain, so the path prefix is not checked
+            RuleCondition::Typed(TypedCondition::UrlPattern(condition)) => activity
+                .domain
+                .as_deref()
+                .is_some_and(|domain| url_pattern_matches(condition, domain, None)),
+            RuleCondition::Typed(condition) => {
+                rule_history_matches(condition, activity.timestamp, rules, recent_matches)
+            }
+        }
+    }
+
+    fn field_matches(&self, condition: &FieldCondition, value: &str) -> bool {
+        let Some(expected) = condition.value.as_str() else {
+            return false;
+        };
+        if condition.operator == "regex" {
+            return self.regex_matches(expected, value);
+        }
+
+        let value = value.to_lowercase();
+        let expected = expected.to_lowercase();
+
+        match condition.operator.as_str() {
+            "contains" => value.contains(&expected),
+            "equals" => value == expected,
+            "starts_with" => value.starts_with(&expected),
+            _ => false,
+        }
+    }
+
+    // Warmed-up patterns are always served from the cache; other patterns are
+    // only cached under the new evaluator flag
+    fn regex_matches(&self, pattern: &str, value: &str) -> bool {
+        let mut cache = self.regex_cache.lock().unwrap();
+        if let Some(regex) = cache.get(pattern) {
+            return regex.as_ref().is_some_and(|regex| regex.is_match(value));
+        }
+        if !self.feature_enabled(NEW_REGEX_EVALUATOR_FLAG) {
+            return compile_regex(pattern).is_some_and(|regex| regex.is_match(value));
+        }
+        cache
+            .entry(pattern.to_string())
+            .or_insert_with(|| compile_regex(pattern))
+            .as_ref()
+            .is_some_and(|regex| regex.is_match(value))
+    }
+}
+
+// An empty list never holds, so a rule or group without conditions is inert
+fn conditions_hold(
+    logic: ConditionLogic,
+    conditions: &[RuleCondition],
+    holds: impl Fn(&RuleCondition) -> bool,
+) -> bool {
+    match logic {
+        ConditionLogic::And => !conditions.is_empty() && conditions.iter().all(holds),
+        ConditionLogic::Or => conditions.iter().any(holds),
+    }
+}
+
+fn describe_condition(condition: &RuleCondition) -> String {
+    match condition {
+        RuleCondition::Field(condition) => format!(
+            "{}{} {} {}",
+            condition.field,
+            if condition.not { " not" } else { "" },
+            condition.operator,
+            condition.value
+        ),
+        condition => serde_json::to_string(condition).unwrap_or_default(),
+    }
+}
+
+// Lower priorities first, ties broken by age, matching `get_active_rules`.
+// Callers pass every rule in whatever order they were fetched.
+fn in_priority_order(rules: &[Rule]) -> Vec<&Rule> {
+    let mut ordered: Vec<&Rule> = rules.iter().collect();
+    ordered.sort_by_key(|rule| (rule.priority, rule.created_at, rule.id));
+    ordered
+}
+
+// Events are evaluated as they arrive, so their timestamp (ms) stands in for
+// the current time
+pub fn schedule_allows(rule: &Rule, timestamp_ms: f64) -> bool {
+    let Some(schedule) = &rule.schedule else {
+        return true;
+    };
+    chrono::DateTime::from_timestamp_millis(timestamp_ms as i64).is_some_and(|time| {
+        schedule.is_active_at(time.with_timezone(&chrono::Local).naive_local())
+    })
+}
+
+// Regex conditions match case-insensitively, like the other operators
+pub fn build_regex(pattern: &str) -> Result<regex::Regex, regex::Error> {
+    regex::RegexBuilder::new(pattern).case_insensitive(true).build()
+}
+
+fn compile_regex(pattern: &str) -> Option<regex::Regex> {
+    build_regex(pattern).ok()
+}
+
+// Conditions on earlier rule triggers, which hold the same way for activities
+// and browser logs. Other conditions never match here.
+fn rule_history_matches(
+    condition: &TypedCondition,
+    timestamp_ms: f64,
+    rules: &[Rule],
+    recent_matches: &[RuleMatchRecord],
+) -> bool {
+    match condition {
+        TypedCondition::TimeSinceLastRuleTrigger(condition) => {
+            let referenced = rules.iter().find(|rule| rule.id == condition.rule_id_ref);
+            let Some(referenced) = referenced else {
+                return false;
+            };
+            let now_seconds = (timestamp_ms / 1000.0) as i64;
+            referenced.last_triggered_at.is_none_or(|triggered_at| {
+                now_seconds - triggered_at >= condition.min_minutes as i64 * 60
+            })
+        }
+        TypedCondition::RuleTriggerRate(condition) => {
+            let triggers = matches_in_last_hour(recent_matches, timestamp_ms)
+                .filter(|record| record.rule_id == condition.rule_id_ref)
+                .count();
+            triggers > condition.max_triggers_per_hour as usize
+        }
+        TypedCondition::GlobalTriggerRate(condition) => {
+            matches_in_last_hour(recent_matches, timestamp_ms).count()
+                > condition.max_total_triggers_per_hour as usize
+        }
+        _ => false,
+    }
+}
+
+fn matches_in_last_hour(
+    matches: &[RuleMatchRecord],
+    now_ms: f64,
+) -> impl Iterator<Item = &RuleMatchRecord> {
+    let now_seconds = (now_ms / 1000.0) as i64;
+    matches.iter().filter(move |record| {
+        record.matched_at > now_seconds - 3600 && record.matched_at <= now_seconds
+    })
+}
+
+fn url_pattern_matches(condition: &UrlPatternCondition, host: &str, path: Option<&str>) -> bool {
+    let host = host.trim_start_matches("www.").to_lowercase();
+    let expected = condition.host.trim_start_matches("www.").to_lowercase();
+    let host_matches = host == expected || host.ends_with(&format!(".{}", expected));
+    host_matches && path.is_none_or(|path| path.starts_with(&condition.path_prefix))
+}
+
+// Whether a rule names the domain, or a parent of it, in a domain or url field
+// condition, a URL pattern or a time-on-domain condition
+pub fn rule_covers_domain(rule: &Rule, domain: &str) -> bool {
+    let Ok(definition) = RuleDefinition::from_json(&rule.rule_json) else {
+        return false;
+    };
+    definition.leaf_conditions().into_iter().any(|condition| {
+        let host = match condition {
+            RuleCondition::Field(condition) => match condition.field.as_str() {
+                "domain" => condition.value.as_str().map(str::to_string),
+                "url" => condition.value.as_str().map(url_value_host),
+                _ => None,
+            },
+            RuleCondition::Typed(TypedCondition::UrlPattern(condition)) => {
+                Some(condition.host.clone())
+            }
+            RuleCondition::Typed(TypedCondition::DomainTimeToday(condition)) => {
+                Some(condition.domain.clone())
+            }
+            _ => None,
+        };
+        host.is_some_and(|host| {
+            let host = normalize_limit_domain(&host);
+            !host.is_empty() && domain_covers(&host, domain)
+        })
+    })
+}
+
+// URL condition values may leave out the scheme, e.g. "reddit.com/r/all"
+fn url_value_host(value: &str) -> String {
+    match url::Url::parse(value) {
+        Ok(url) => url.host_str().unwrap_or_default().to_string(),
+        Err(_) => value.split('/').next().unwrap_or_default().to_string(),
+    }
+}
+
+// Jaccard similarity of the lowercased whitespace-separated word sets
+pub fn text_similarity(a: &str, b: &str) -> f32 {
+    let words = |text: &str| -> HashSet<String> {
+        text.split_whitespace().map(|word| word.to_lowercase()).collect()
+    };
+    let (a, b) = (words(a), words(b));
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(&b).count() as f32 / union as f32
+}
+
+// Rules whose natural language is at least `threshold` similar to the query,
+// most similar first
+pub fn similar_rules(
+    rules: Vec<Rule>,
+    query: &str,
+    threshold: f32,
+    limit: usize,
+) -> Vec<RuleSimilarityResult> {
+    let mut results: Vec<RuleSimilarityResult> = rules
+        .into_iter()
+        .map(|rule| RuleSimilarityResult {
+            similarity: text_similarity(query, &rule.natural_language),
+            rule,
+        })
+        .filter(|result| result.similarity >= threshold)
+        .collect();
+    results.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+    results.truncate(limit);
+    results
+}
+
+// Ids of the rules whose triggers a definition's conditions depend on
+pub fn referenced_rule_ids(definition: &RuleDefinition) -> Vec<i64> {
+    definition
+        .leaf_conditions()
+        .into_iter()
+        .filter_map(|condition| match condition {
+            RuleCondition::Typed(TypedCondition::TimeSinceLastRuleTrigger(condition)) => {
+                Some(condition.rule_id_ref)
+            }
+            RuleCondition::Typed(TypedCondition::RuleTriggerRate(condition)) => {
+                Some(condition.rule_id_ref)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+// Edges run from the referenced rule to the rule that depends on it. Each
+// cycle is listed once, in edge order, starting from its smallest id.
+pub fn rule_dependency_graph(rules: &[Rule]) -> RuleDependencyGraph {
+    let mut nodes: Vec<(i64, String)> =
+        rules.iter().map(|rule| (rule.id, rule.name.clone())).collect();
+    nodes.sort();
+
+    let mut edges = BTreeSet::new();
+    for rule in rules {
+        if let Ok(definition) = RuleDefinition::from_json(&rule.rule_json) {
+            for depends_on in referenced_rule_ids(&definition) {
+                edges.insert((depends_on, rule.id));
+            }
+        }
+    }
+    let edges: Vec<(i64, i64)> = edges.into_iter().collect();
+
+    RuleDependencyGraph {
+        cycles: find_cycles(&edges),
+        nodes,
+        edges,
+    }
+}
+
+// Rules are few, so a plain depth-first walk from every node is enough
+fn find_cycles(edges: &[(i64, i64)]) -> Vec<Vec<i64>> {
+    fn walk(
+        node: i64,
+        adjacency: &BTreeMap<i64, Vec<i64>>,
+        path: &mut Vec<i64>,
+        cycles: &mut BTreeSet<Vec<i64>>,
+    ) {
+        if let Some(start) = path.iter().position(|visited| *visited == node) {
+            let mut cycle = path[start..].to_vec();
+            let smallest = (0..cycle.len()).min_by_key(|index| cycle[*index]).unwrap_or(0);
+            cycle.rotate_left(smallest);
+            cycles.insert(cycle);
+            return;
+        }
+        path.push(node);
+        for next in adjacency.get(&node).into_iter().flatten() {
+            walk(*next, adjacency, path, cycles);
+        }
+        path.pop();
+    }
+
+    let mut adjacency: BTreeMap<i64, Vec<i64>> = BTreeMap::new();
+    for (from, to) in edges {
+        adjacency.entry(*from).or_default().push(*to);
+    }
+    let mut cycles = BTreeSet::new();
+    for node in adjacency.keys() {
+        walk(*node, &adjacency, &mut Vec::new(), &mut cycles);
+    }
+    cycles.into_iter().collect()
+}
+
+// Fails when saving `candidate` (new or updated) alongside `rules` would make
+// rules depend on each other in a loop
+pub fn check_rule_dependencies(rules: &[Rule], candidate: Rule) -> Result<(), String> {
+    let candidate_id = candidate.id;
+    let mut combined: Vec<Rule> =
+        rules.iter().filter(|rule| rule.id != candidate_id).cloned().collect();
+    combined.push(candidate);
+
+    let graph = rule_dependency_graph(&combined);
+    match graph.cycles.iter().find(|cycle| cycle.contains(&candidate_id)) {
+        Some(cycle) => {
+            let mut ids: Vec<String> = cycle.iter().map(i64::to_string).collect();
+            ids.push(cycle[0].to_string());
+            Err(format!("Rule would create a circular dependency: {}", ids.join(" -> ")))
+        }
+        None => Ok(()),
+    }
+}
+
+// Plain comparisons cost 1; conditions that look up other state and regexes
+// cost 2. Regexes are compiled once by `warm_up`, but matching one still costs
+// more than a string comparison. Conditions inside groups count like any
+// other; groups themselves are free.
+pub fn get_rule_complexity_score(definition: &RuleDefinition) -> RuleComplexityScore {
+    let leaves = definition.leaf_conditions();
+    let mut regex_conditions = 0u32;
+    let mut cost = 0u32;
+    for condition in &leaves {
+        cost += match condition {
+            RuleCondition::Field(condition) if condition.operator == "regex" => {
+                regex_conditions += 1;
+                2
+            }
+            RuleCondition::Field(_)
+            | RuleCondition::Typed(TypedCondition::AppInForeground(_))
+            | RuleCondition::Typed(TypedCondition::UrlPattern(_)) => 1,
+            RuleCondition::Typed(_) => 2,
+            RuleCondition::Group(_) => 0,
+        };
+    }
+
+    RuleComplexityScore {
+        total_conditions: leaves.len().min(u8::MAX as usize) as u8,
+        regex_condition_count: regex_conditions.min(u8::MAX as u32) as u8,
+        script_condition_count: 0,
+        estimated_eval_cost: cost.clamp(1, 10) as u8,
+    }
+}
+
+// Fields of an activity record or browser log that field conditions can
+// compare against
+const RECORDED_FIELDS: [&str; 6] = ["activity", "app", "bundle_id", "domain", "title", "url"];
+
+// Checks a rule for common reasons it would never trigger and describes how to
+// fix each one
+pub fn suggest_fix(rule: &Rule, rules: &[Rule]) -> String {
+    let definition = match RuleDefinition::from_json(&rule.rule_json) {
+        Ok(definition) => definition,
+        Err(e) => return format!("The rule JSON could not be parsed ({}). Recreate the rule.", e),
+    };
+
+    let mut problems = Vec::new();
+    if !rule.is_active {
+        problems.push("The rule is disabled; enable it to start matching.".to_string());
+    }
+    if definition.conditions.is_empty() {
+        problems.push("The rule has no conditions, so it never matches.".to_string());
+    }
+
+    let rule_exists = |id: i64| rules.iter().any(|rule| rule.id == id);
+    for condition in definition.leaf_conditions() {
+        match condition {
+            RuleCondition::Field(condition) => {
+                if !RECORDED_FIELDS.contains(&condition.field.as_str()) {
+                    problems.push(format!(
+                        "Field '{}' is not recorded; use one of: {}.",
+                        condition.field,
+                        RECORDED_FIELDS.join(", ")
+                    ));
+                }
+                if !FIELD_OPERATORS.contains(&condition.operator.as_str()) {
+                    problems.push(format!(
+                        "Operator '{}' is not supported; use one of: {}.",
+                        condition.operator,
+                        FIELD_OPERATORS.join(", ")
+                    ));
+                }
+                if condition.value.as_str().is_none_or(|value| value.trim().is_empty()) {
+                    problems.push(format!(
+                        "The '{}' condition has an empty or non-text value.",
+                        condition.field
+                    ));
+                }
+            }
+            RuleCondition::Typed(TypedCondition::UrlPattern(condition)) => {
+                let host = condition.host.trim();
+                if host.is_empty() || host.contains("://") || host.contains('/') {
+                    problems.push(format!(
+                        "URL pattern host '{}' can never match; use a bare host.",
+                        condition.host
+                    ));
+                }
+                if !condition.path_prefix.is_empty() && !condition.path_prefix.starts_with('/') {
+                    problems.push(format!(
+                        "URL pattern path '{}' should start with '/'.",
+                        condition.path_prefix
+                    ));
+                }
+            }
+            RuleCondition::Typed(TypedCondition::TimeSinceLastRuleTrigger(condition))
+                if !rule_exists(condition.rule_id_ref) =>
+            {
+                problems.push(format!("Referenced rule {} does not exist.", condition.rule_id_ref));
+            }
+            RuleCondition::Typed(TypedCondition::RuleTriggerRate(condition))
+                if !rule_exists(condition.rule_id_ref) =>
+            {
+                problems.push(format!("Referenced rule {} does not exist.", condition.rule_id_ref));
+            }
+            _ => {}
+        }
+    }
+
+    if problems.is_empty() {
+        "No common misconfigurations found.".to_string()
+    } else {
+        problems.join("\n")
+    }
+}
+
+// Records when each app came to the foreground. Only the current foreground
+// app is kept, so switching apps restarts its timer.
+pub fn track_foreground(foreground_started_at: &mut HashMap<String, f64>, activity: &ActivityRecord) {
+    if !foreground_started_at.contains_key(&activity.app) {
+        foreground_started_at.clear();
+        foreground_started_at.insert(activity.app.clone(), activity.timestamp);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::RuleSchedule;
+
+    fn rule(id: i64, rule_json: &str) -> Rule {
+        Rule {
+            id,
+            name: format!("rule {}", id),
+            natural_language: String::new(),
+            rule_json: rule_json.to_string(),
+            is_active: true,
+            created_at: 0,
+            last_triggered_at: None,
+            hit_count: 0,
+            priority: 0,
+            tags: Vec::new(),
+            schedule: None,
+            expires_at: None,
+        }
+    }
+
+    fn activity(timestamp: f64, app: &str, bundle_id: &str) -> ActivityRecord {
+        ActivityRecord {
+            id: 1,
+            timestamp,
+            activity: "using_app".to_string(),
+            productive: false,
+            app: app.to_string(),
+            bundle_id: Some(bundle_id.to_string()),
+            domain: None,
+            notes: None,
+            duration_ms: None,
+        }
+    }
+
+    #[test]
+    fn app_in_foreground_checks_name_and_bundle_id() {
+        let rules = vec![
+            rule(1, r#"{"actions":[],"conditions":[{"condition_type":"app_in_foreground","app_name":"Slack"}],"name":"a","type":"basic"}"#),
+            rule(2, r#"{"actions":[],"conditions":[{"condition_type":"app_in_foreground","app_name":"Slack","bundle_id":"com.other"}],"name":"b","type":"basic"}"#),
+            rule(3, r#"{"actions":[],"conditions":[{"condition_type":"app_in_foreground","bundle_id":"com.tinyspeck.slackmacgap"}],"name":"c","type":"basic"}"#),
+        ];
+
+        let matches = RuleEngine::new().evaluate_activity(
+            &activity(0.0, "slack", "com.tinyspeck.slackmacgap"),
+            &rules,
+            &HashMap::new(),
+            &[],
+        );
+
+        let ids: Vec<i64> = matches.iter().map(|rule_match| rule_match.rule_id).collect();
+        assert_eq!(ids, vec![1, 3]);
+    }
+
+    #[test]
+    fn foreground_duration_requires_continuous_time() {
+        let rules = vec![rule(
+            1,
+            r#"{"actions":[],"conditions":[{"condition_type":"foreground_duration","app_name":"Steam","min_seconds":60}],"name":"a","type":"basic"}"#,
+        )];
+        let engine = RuleEngine::new();
+        let mut foreground = HashMap::new();
+
+        let first = activity(0.0, "Steam", "com.valve.steam");
+        track_foreground(&mut foreground, &first);
+        assert!(engine.evaluate_activity(&first, &rules, &foreground, &[]).is_empty());
+
+        let later = activity(61_000.0, "Steam", "com.valve.steam");
+        track_foreground(&mut foreground, &later);
+        assert_eq!(engine.evaluate_activity(&later, &rules, &foreground, &[]).len(), 1);
+
+        track_foreground(&mut foreground, &activity(62_000.0, "Xcode", "com.apple.dt.Xcode"));
+        let back = activity(63_000.0, "Steam", "com.valve.steam");
+        track_foreground(&mut foreground, &back);
+        assert!(engine.evaluate_activity(&back, &rules, &foreground, &[]).is_empty());
+    }
+
+    #[test]
+    fn time_since_last_trigger_waits_for_referenced_rule() {
+        let mut referenced = rule(1, r#"{"actions":[],"conditions":[],"name":"a","type":"basic"}"#);
+        referenced.is_active = false;
+        let renag = rule(
+            2,
+            r#"{"actions":[],"conditions":[{"condition_type":"time_since_last_rule_trigger","rule_id_ref":1,"min_minutes":30}],"name":"b","type":"basic"}"#,
+        );
+        let engine = RuleEngine::new();
+        let now = activity(3_600_000.0, "Safari", "com.apple.Safari");
+
+        let mut rules = vec![referenced, renag];
+        assert_eq!(engine.evaluate_activity(&now, &rules, &HashMap::new(), &[]).len(), 1);
+
+        rules[0].last_triggered_at = Some(3_600 - 10 * 60);
+        assert!(engine.evaluate_activity(&now, &rules, &HashMap::new(), &[]).is_empty());
+
+        rules[0].last_triggered_at = Some(3_600 - 30 * 60);
+        assert_eq!(engine.evaluate_activity(&now, &rules, &HashMap::new(), &[]).len(), 1);
+    }
+
+    #[test]
+    fn extension_logs_match_field_and_url_conditions() {
+        let rules = vec![
+            rule(
+                1,
+                r#"{"actions":[],"conditions":[{"field":"title","operator":"contains","value":"shorts"},{"field":"domain","operator":"equals","value":"youtube.com"}],"name":"a","type":"basic"}"#,
+            ),
+            rule(
+                2,
+                r#"{"actions":[],"conditions":[{"condition_type":"url_pattern","host":"youtube.com","path_prefix":"/shorts"}],"name":"b","type":"basic"}"#,
+            ),
+            rule(
+                3,
+                r#"{"actions":[],"conditions":[{"condition_type":"app_in_foreground","app_name":"Chrome"}],"name":"c","type":"basic"}"#,
+            ),
+        ];
+        let log = ExtensionLog {
+            timestamp: 0.0,
+            domain: "youtube.com".to_string(),
+            activity: "watching_videos".to_string(),
+            url: "https://www.youtube.com/shorts/abc".to_string(),
+            title: "Cats - YouTube Shorts".to_string(),
+            elements: None,
+            title_sentiment: None,
+            url_category: None,
+        };
+
+        let matched: Vec<i64> = RuleEngine::new()
+            .evaluate(&log, &rules, &[], &[])
+            .iter()
+            .map(|rule_match| rule_match.rule_id)
+            .collect();
+
+        assert_eq!(matched, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_log_reports_each_condition() {
+        let definition = RuleDefinition::from_json(
+            r#"{"actions":[],"conditions":[{"field":"domain","operator":"equals","value":"youtube.com"},{"condition_type":"url_pattern","host":"youtube.com","path_prefix":"/shorts"}],"name":"a","type":"basic"}"#,
+        )
+        .unwrap();
+        let mut log = ExtensionLog {
+            timestamp: 0.0,
+            domain: "youtube.com".to_string(),
+            activity: "watching_videos".to_string(),
+            url: "https://www.youtube.com/shorts/abc".to_string(),
+            title: "Cats - YouTube Shorts".to_string(),
+            elements: None,
+            title_sentiment: None,
+            url_category: None,
+        };
+        let engine = RuleEngine::new();
+
+        let result = engine.test_log(&definition, &log, &[], &[], &[]);
+        assert!(result.matched);
+        assert_eq!(result.matched_conditions[0], r#"domain equals "youtube.com""#);
+        assert!(result.unmatched_conditions.is_empty());
+
+        log.url = "https://www.youtube.com/watch?v=abc".to_string();
+        let result = engine.test_log(&definition, &log, &[], &[], &[]);
+        assert!(!result.matched);
+        assert_eq!(result.matched_conditions.len(), 1);
+        assert_eq!(
+            result.unmatched_conditions,
+            vec![r#"{"condition_type":"url_pattern","host":"youtube.com","path_prefix":"/shorts"}"#]
+        );
+    }
+
+    #[test]
+    fn url_regex_conditions_match_against_the_full_url() {
+        let rules = vec![rule(
+            1,
+            r#"{"actions":[],"conditions":[{"field":"url","operator":"regex","value":"youtube\\.com/shorts/\\w+"}],"name":"a","type":"basic"}"#,
+        )];
+        let log_for = |url: &str| ExtensionLog {
+            timestamp: 0.0,
+            domain: "youtube.com".to_string(),
+            activity: "watching_videos".to_string(),
+            url: url.to_string(),
+            title: "YouTube".to_string(),
+            elements: None,
+            title_sentiment: None,
+            url_category: None,
+        };
+        let mut cached = RuleEngine::new();
+        cached.set_feature_flags(HashMap::from([(NEW_REGEX_EVALUATOR_FLAG.to_string(), true)]));
+
+        for engine in [RuleEngine::new(), cached] {
+            let matches = |url: &str| engine.evaluate(&log_for(url), &rules, &[], &[]).len() == 1;
+            assert!(matches("https://www.youtube.com/shorts/abc123"));
+            assert!(matches("https://m.YouTube.com/Shorts/xyz"));
+            assert!(!matches("https://www.youtube.com/shorts/"));
+            assert!(!matches("https://www.youtube.com/watch?v=abc123"));
+            assert!(!matches("https://youtubeXcom/shorts/abc"));
+        }
+    }
+
+    fn browsing(domain: &str, title: &str) -> ExtensionLog {
+        ExtensionLog {
+            timestamp: 0.0,
+            domain: domain.to_string(),
+            activity: "browsing".to_string(),
+            url: format!("https://{}/", domain),
+            title: title.to_string(),
+            elements: None,
+            title_sentiment: None,
+            url_category: None,
+        }
+    }
+
+    #[test]
+    fn domain_time_conditions_use_todays_time_on_the_domain() {
+        let template = crate::templates::RULE_TEMPLATES
+            .iter()
+            .find(|template| template.id == "youtube_time_limit")
+            .unwrap();
+        let rules = vec![rule(1, template.rule_json)];
+        let spent = |domain: &str, total_seconds: f64| DomainTimeStat {
+            domain: domain.to_string(),
+            total_seconds,
+            visit_count: 1,
+            last_visit_ms: 0.0,
+        };
+        let engine = RuleEngine::new();
+        let matches = |log: &ExtensionLog, today: &[DomainTimeStat]| {
+            engine.evaluate(log, &rules, &[], today).len() == 1
+        };
+
+        let watching = browsing("www.youtube.com", "YouTube");
+        assert!(!matches(&watching, &[spent("youtube.com", 1200.0)]));
+        assert!(matches(&watching, &[spent("youtube.com", 1200.0), spent("m.youtube.com", 600.0)]));
+        assert!(!matches(&browsing("example.com", ""), &[spent("youtube.com", 3600.0)]));
+        assert!(!matches(&watching, &[spent("notyoutube.com", 3600.0)]));
+    }
+
+    #[tokio::test]
+    async fn stored_rules_match_in_priority_order() {
+        let db = crate::database::Database::in_memory().await.unwrap();
+        for (name, priority) in [("late", 5), ("first", -1), ("middle", 2)] {
+            db.create_rule(crate::database::NewRule {
+                name: name.to_string(),
+                natural_language: String::new(),
+                rule_json: r#"{"actions":[],"conditions":[{"field":"activity","operator":"equals","value":"browsing"}],"name":"a","type":"basic"}"#
+                    .to_string(),
+                priority,
+                tags: Vec::new(),
+                expires_at: None,
+            })
+            .await
+            .unwrap();
+        }
+        // The evaluators get every rule, newest first
+        let rules = db.get_all_rules().await.unwrap();
+        let engine = RuleEngine::new();
+
+        let names = |matches: Vec<RuleMatch>| -> Vec<String> {
+            matches.into_iter().map(|rule_match| rule_match.rule_name).collect()
+        };
+        let expected = ["first", "middle", "late"];
+        let matches = engine.evaluate(&browsing("example.com", ""), &rules, &[], &[]);
+        assert_eq!(names(matches), expected);
+        let mut activity = activity(0.0, "Safari", "com.apple.Safari");
+        activity.activity = "browsing".to_string();
+        assert_eq!(
+            names(engine.evaluate_activity(&activity, &rules, &HashMap::new(), &[])),
+            expected
+        );
+    }
+
+    fn matched_ids(rules: &[Rule], log: &ExtensionLog) -> Vec<i64> {
+        RuleEngine::new()
+            .evaluate(log, rules, &[], &[])
+            .iter()
+            .map(|rule_match| rule_match.rule_id)
+            .collect()
+    }
+
+    #[test]
+    fn or_rules_match_when_any_condition_holds() {
+        let rules = vec![rule(
+            1,
+            r#"{"actions":[],"conditions":[{"field":"domain","operator":"contains","value":"instagram.com"},{"field":"domain","operator":"contains","value":"youtube.com"}],"logic":"or","name":"a","type":"basic"}"#,
+        )];
+
+        assert_eq!(matched_ids(&rules, &browsing("www.instagram.com", "Instagram")), vec![1]);
+        assert_eq!(matched_ids(&rules, &browsing("youtube.com", "YouTube")), vec![1]);
+        assert!(matched_ids(&rules, &browsing("github.com", "GitHub")).is_empty());
+    }
+
+    #[test]
+    fn nested_groups_combine_and_and_or() {
+        // reddit.com AND (title mentions memes OR title mentions funny)
+        let rules = vec![rule(
+            1,
+            r#"{"actions":[],"conditions":[{"field":"domain","operator":"equals","value":"reddit.com"},{"conditions":[{"field":"title","operator":"contains","value":"memes"},{"field":"title","operator":"contains","value":"funny"}],"logic":"or","type":"group"}],"name":"a","type":"basic"}"#,
+        )];
+
+        assert_eq!(matched_ids(&rules, &browsing("reddit.com", "r/memes")), vec![1]);
+        assert_eq!(matched_ids(&rules, &browsing("reddit.com", "r/funny")), vec![1]);
+        assert!(matched_ids(&rules, &browsing("reddit.com", "r/rust")).is_empty());
+        assert!(matched_ids(&rules, &browsing("imgur.com", "funny memes")).is_empty());
+    }
+
+    #[test]
+    fn not_inverts_conditions_and_groups() {
+        let rules = vec![
+            // Any YouTube page that isn't a tutorial
+            rule(
+                1,
+                r#"{"actions":[],"conditions":[{"field":"domain","operator":"equals","value":"youtube.com"},{"field":"title","not":true,"operator":"contains","value":"tutorial"}],"name":"a","type":"basic"}"#,
+            ),
+            // Anything outside the work sites
+            rule(
+                2,
+                r#"{"actions":[],"conditions":[{"conditions":[{"field":"domain","operator":"equals","value":"github.com"},{"field":"domain","operator":"equals","value":"docs.rs"}],"logic":"or","not":true,"type":"group"}],"name":"b","type":"basic"}"#,
+            ),
+        ];
+
+        assert_eq!(matched_ids(&rules, &browsing("youtube.com", "Cat videos")), vec![1, 2]);
+        assert_eq!(matched_ids(&rules, &browsing("youtube.com", "Rust tutorial")), vec![2]);
+        assert!(matched_ids(&rules, &browsing("docs.rs", "serde")).is_empty());
+    }
+
+    #[test]
+    fn scheduled_rules_only_match_inside_their_window() {
+        use chrono::TimeZone;
+
+        let mut scheduled = rule(
+            1,
+            r#"{"actions":[],"conditions":[{"field":"domain","operator":"equals","value":"reddit.com"}],"name":"r","type":"basic"}"#,
+        );
+        scheduled.schedule = Some(RuleSchedule {
+            days_of_week: vec![1, 2, 3, 4, 5],
+            start_time_minutes: 9 * 60,
+            end_time_minutes: 17 * 60,
+        });
+        let rules = vec![scheduled];
+        // 2024-01-08 was a Monday
+        let log_at = |day: u32, hour: u32| {
+            let time = chrono::Local.with_ymd_and_hms(2024, 1, day, hour, 30, 0).unwrap();
+            ExtensionLog {
+                timestamp: time.timestamp_millis() as f64,
+                domain: "reddit.com".to_string(),
+                activity: "browsing".to_string(),
+                url: "https://reddit.com/".to_string(),
+                title: "reddit".to_string(),
+                elements: None,
+                title_sentiment: None,
+                url_category: None,
+            }
+        };
+        let engine = RuleEngine::new();
+
+        assert_eq!(engine.evaluate(&log_at(8, 10), &rules, &[], &[]).len(), 1);
+        assert!(engine.evaluate(&log_at(8, 20), &rules, &[], &[]).is_empty());
+        assert!(engine.evaluate(&log_at(13, 10), &rules, &[], &[]).is_empty());
+    }
+
+    #[test]
+    fn dependency_graph_reports_cycles() {
+        let depends_on = |id: i64, rule_id_ref: i64| {
+            rule(
+                id,
+                &format!(
+                    r#"{{"actions":[],"conditions":[{{"condition_type":"rule_trigger_rate","rule_id_ref":{},"max_triggers_per_hour":1}}],"name":"r","type":"basic"}}"#,
+                    rule_id_ref
+                ),
+            )
+        };
+        let rules = vec![depends_on(1, 3), depends_on(2, 1), rule(3, "{}")];
+
+        let graph = rule_dependency_graph(&rules);
+
+        assert_eq!(graph.edges, vec![(1, 2), (3, 1)]);
+        assert!(graph.cycles.is_empty());
+        assert!(check_rule_dependencies(&rules, depends_on(4, 2)).is_ok());
+        assert_eq!(
+            check_rule_dependencies(&rules, depends_on(3, 2)).unwrap_err(),
+            "Rule would create a circular dependency: 1 -> 2 -> 3 -> 1"
+        );
+    }
+
+    #[test]
+    fn similar_rules_uses_word_set_overlap() {
+        let mut rules = vec![rule(1, "{}"), rule(2, "{}"), rule(3, "{}")];
+        rules[0].natural_language = "Block YouTube after 10pm".to_string();
+        rules[1].natural_language = "block youtube".to_string();
+        rules[2].natural_language = "Remind me to stretch".to_string();
+
+        let results = similar_rules(rules, "block youtube", 0.3, 5);
+
+        let ids: Vec<i64> = results.iter().map(|result| result.rule.id).collect();
+        assert_eq!(ids, vec![2, 1]);
+        assert_eq!(results[0].similarity, 1.0);
+        assert_eq!(results[1].similarity, 0.5);
+    }
+
+    #[test]
+    fn trigger_rate_counts_matches_in_the_past_hour() {
+        let rules = vec![
+            rule(1, r#"{"actions":[],"conditions":[{"condition_type":"rule_trigger_rate","rule_id_ref":9,"max_triggers_per_hour":1}],"name":"a","type":"basic"}"#),
+            rule(2, r#"{"actions":[],"conditions":[{"condition_type":"global_trigger_rate","max_total_triggers_per_hour":1}],"name":"b","type":"basic"}"#),
+        ];
+        let record = |rule_id: i64, matched_at: i64| RuleMatchRecord {
+            id: 0,
+            rule_id,
+            matched_at,
+            domain: String::new(),
+        };
+        let now = activity(7_200_000.0, "Safari", "com.apple.Safari");
+        let engine = RuleEngine::new();
+
+        let recent = vec![record(9, 3_000), record(9, 7_000), record(8, 7_100)];
+        let ids: Vec<i64> = engine
+            .evaluate_activity(&now, &rules, &HashMap::new(), &recent)
+            .iter()
+            .map(|rule_match| rule_match.rule_id)
+            .collect();
+        assert_eq!(ids, vec![2]);
+
+        let recent = vec![record(9, 6_000), record(9, 7_000), record(8, 7_100)];
+        assert_eq!(engine.evaluate_activity(&now, &rules, &HashMap::new(), &recent).len(), 2);
+    }
+
+    #[test]
+    fn suggest_fix_reports_common_misconfigurations() {
+        let broken = rule(
+            1,
+            r#"{"actions":[],"conditions":[{"field":"domain","operator":"contains","value":""},{"condition_type":"url_pattern","host":"https://reddit.com","path_prefix":"/r"},{"condition_type":"time_since_last_rule_trigger","rule_id_ref":42,"min_minutes":5}],"name":"a","type":"basic"}"#,
+        );
+        let healthy = rule(
+            2,
+            r#"{"actions":[],"conditions":[{"field":"domain","operator":"equals","value":"reddit.com"},{"field":"title","operator":"regex","value":"shorts"},{"field":"url","operator":"starts_with","value":"https://"}],"name":"b","type":"basic"}"#,
+        );
+        let rules = vec![broken.clone(), healthy.clone()];
+
+        let suggestions: Vec<String> =
+            suggest_fix(&broken, &rules).lines().map(str::to_string).collect();
+        assert_eq!(suggestions.len(), 3);
+        assert!(suggestions[0].contains("empty"));
+        assert!(suggestions[1].contains("https://reddit.com"));
+        assert!(suggestions[2].contains("42"));
+
+        assert_eq!(suggest_fix(&healthy, &rules), "No common misconfigurations found.");
+    }
+
+    #[test]
+    fn regex_conditions_match_and_raise_complexity() {
+        let rules = vec![rule(
+            1,
+            r#"{"actions":[],"conditions":[{"field":"app","operator":"regex","value":"^(steam|epic)"},{"field":"bundle_id","operator":"regex","value":"valve"},{"condition_type":"foreground_duration","app_name":"Steam","min_seconds":0}],"name":"a","type":"basic"}"#,
+        )];
+        let steam = activity(0.0, "Steam", "com.valve.steam");
+        let mut foreground = HashMap::new();
+        track_foreground(&mut foreground, &steam);
+
+        assert_eq!(RuleEngine::new().evaluate_activity(&steam, &rules, &foreground, &[]).len(), 1);
+        let mut engine = RuleEngine::new();
+        engine.set_feature_flags(HashMap::from([(NEW_REGEX_EVALUATOR_FLAG.to_string(), true)]));
+        assert_eq!(engine.evaluate_activity(&steam, &rules, &foreground, &[]).len(), 1);
+        assert_eq!(engine.regex_cache.lock().unwrap().len(), 2);
+
+        let warmed = RuleEngine::new();
+        let mut invalid = rule(
+            2,
+            r#"{"actions":[],"conditions":[{"field":"app","operator":"regex","value":"("}],"name":"b","type":"basic"}"#,
+        );
+        assert!(warmed.warm_up(&rules).is_ok());
+        assert_eq!(warmed.regex_cache.lock().unwrap().len(), 2);
+        assert!(warmed.warm_up(std::slice::from_ref(&invalid)).is_err());
+        invalid.is_active = false;
+        assert!(warmed.warm_up(&[invalid]).is_ok());
+
+        let definition = RuleDefinition::from_json(&rules[0].rule_json).unwrap();
+        let score = get_rule_complexity_score(&definition);
+        assert_eq!(score.total_conditions, 3);
+        assert_eq!(score.regex_condition_count, 2);
+        assert_eq!(score.estimated_eval_cost, 6);
+    }
+
+    #[test]
+    fn rules_cover_domains_they_name_and_their_subdomains() {
+        let bbc = rule(
+            1,
+            r#"{"actions":[],"conditions":[{"conditions":[{"field":"domain","operator":"equals","value":"www.bbc.co.uk"}],"type":"group"}],"name":"a","type":"basic"}"#,
+        );
+        let urls = rule(
+            2,
+            r#"{"actions":[],"conditions":[{"field":"url","operator":"starts_with","value":"https://t.co/"},{"condition_type":"url_pattern","host":"x.com","path_prefix":"/home"}],"name":"b","type":"basic"}"#,
+        );
+        let unrelated = rule(
+            3,
+            r#"{"actions":[],"conditions":[{"field":"title","operator":"contains","value":"bbc.co.uk"}],"name":"c","type":"basic"}"#,
+        );
+
+        assert!(rule_covers_domain(&bbc, "bbc.co.uk"));
+        assert!(rule_covers_domain(&bbc, "news.bbc.co.uk"));
+        assert!(!rule_covers_domain(&bbc, "example.co.uk"));
+        assert!(!rule_covers_domain(&bbc, "co.uk"));
+        assert!(rule_covers_domain(&urls, "t.co"));
+        assert!(rule_covers_domain(&urls, "mobile.x.com"));
+        assert!(!rule_covers_domain(&urls, "box.com"));
+        assert!(!rule_covers_domain(&unrelated, "bbc.co.uk"));
+    }
+}
@@ -0,0 +1,207 @@
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use sha2::Sha256;
+
+use crate::database::{NewRule, Rule};
+use crate::rule_definition;
+use serde::Deserialize;
+
+const PBKDF2_ITERATIONS: u32 = 100_000;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+fn derive_key(password: &str, salt: &[u8]) -> Key<Aes256Gcm> {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, PBKDF2_ITERATIONS, &mut key);
+    key.into()
+}
+
+// Output layout: salt (16 bytes) | nonce (12 bytes) | AES-256-GCM ciphertext
+pub fn encrypt_export(plaintext: &[u8], password: &str) -> Result<Vec<u8>, String> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+
+    let cipher = Aes256Gcm::new(&derive_key(password, &salt));
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .map_err(|e| format!("Failed to encrypt export: {}", e))?;
+
+    let mut output = Vec::with_capacity(SALT_LEN + NONCE_LEN + ciphertext.len());
+    output.extend_from_slice(&salt);
+    output.extend_from_slice(&nonce);
+    output.extend_from_slice(&ciphertext);
+    Ok(output)
+}
+
+pub fn decrypt_export(data: &[u8], password: &str) -> Result<Vec<u8>, String> {
+    if data.len() < SALT_LEN + NONCE_LEN {
+        return Err("Encrypted export is too short".to_string());
+    }
+    let (salt, rest) = data.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+    let cipher = Aes256Gcm::new(&derive_key(password, salt));
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| "Failed to decrypt export: wrong password or corrupted file".to_string())
+}
+
+// The `export_rules` format. Ids and timestamps in the file are ignored.
+#[derive(Debug, Clone, Deserialize)]
+struct ExportedRule {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    natural_language: String,
+    #[serde(default)]
+    rule_json: Option<String>,
+    #[serde(default = "default_active")]
+    is_active: bool,
+    #[serde(default)]
+    priority: i32,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    expires_at: Option<i64>,
+}
+
+fn default_active() -> bool {
+    true
+}
+
+// Validates every rule before returning any, so a bad entry rejects the whole
+// import. Pairs each rule with its `is_active` flag.
+pub fn parse_rule_import(json: &str) -> Result<Vec<(NewRule, bool)>, String> {
+    let entries: Vec<serde_json::Value> =
+        serde_json::from_str(json).map_err(|e| format!("Import is not a JSON array: {}", e))?;
+
+    let mut rules = Vec::with_capacity(entries.len());
+    for (index, entry) in entries.into_iter().enumerate() {
+        let label = entry
+            .get("name")
+            .and_then(|name| name.as_str())
+            .map_or_else(|| format!("#{}", index + 1), |name| format!("'{}'", name));
+        let exported: ExportedRule =
+            serde_json::from_value(entry).map_err(|e| format!("Rule {}: {}", label, e))?;
+
+        let name = exported.name.filter(|name| !name.trim().is_empty());
+        let name = name.ok_or_else(|| format!("Rule {}: missing name", label))?;
+        let rule_json = exported
+            .rule_json
+            .ok_or_else(|| format!("Rule {}: missing rule_json", label))?;
+        rule_definition::validate_rule_json(&rule_json)
+            .map_err(|e| format!("Rule {}: {}", label, e))?;
+
+        rules.push((
+            NewRule {
+                name,
+                natural_language: exported.natural_language,
+                rule_json,
+                priority: exported.priority,
+                tags: exported.tags,
+                expires_at: exported.expires_at,
+            },
+            exported.is_active,
+        ));
+    }
+    Ok(rules)
+}
+
+// Accepts a copied `Rule` or a bare rule definition
+pub fn parse_shared_rule(text: &str) -> Result<NewRule, String> {
+    let text = text.trim();
+    if let Ok(rule) = serde_json::from_str::<Rule>(text) {
+        rule_definition::validate_rule_json(&rule.rule_json)?;
+        return Ok(NewRule {
+            name: rule.name,
+            natural_language: rule.natural_language,
+            rule_json: rule.rule_json,
+            priority: rule.priority,
+            tags: rule.tags,
+            expires_at: rule.expires_at,
+        });
+    }
+
+    serde_json::from_str::<serde_json::Value>(text)
+        .map_err(|e| format!("Clipboard does not contain JSON: {}", e))?;
+    let definition = rule_definition::validate_rule_json(text)
+        .map_err(|e| format!("Clipboard JSON is not a rule: {}", e))?;
+    Ok(NewRule {
+        name: definition.name.clone(),
+        natural_language: String::new(),
+        rule_json: definition.to_json()?,
+        priority: 0,
+        tags: Vec::new(),
+        expires_at: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypted_export_round_trips_and_rejects_wrong_password() {
+        let plaintext = br#"[{"name":"Block reddit"}]"#;
+
+        let encrypted = encrypt_export(plaintext, "hunter2").unwrap();
+
+        assert_ne!(&encrypted[SALT_LEN + NONCE_LEN..], &plaintext[..]);
+        assert_eq!(decrypt_export(&encrypted, "hunter2").unwrap(), plaintext);
+        assert!(decrypt_export(&encrypted, "wrong").is_err());
+    }
+
+    #[test]
+    fn rule_import_rejects_the_whole_file_on_a_bad_rule() {
+        let rule_json = r#"{"actions":[],"conditions":[],"name":"Block reddit","type":"basic"}"#;
+        let valid = serde_json::json!({
+            "id": 3,
+            "name": "Block reddit",
+            "rule_json": rule_json,
+            "is_active": false,
+            "priority": 2,
+            "tags": ["social"],
+        });
+
+        let rules = parse_rule_import(&serde_json::json!([valid]).to_string()).unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].0.priority, 2);
+        assert!(!rules[0].1);
+
+        let unnamed = serde_json::json!({ "rule_json": rule_json });
+        let error = parse_rule_import(&serde_json::json!([valid, unnamed]).to_string());
+        assert_eq!(error.unwrap_err(), "Rule #2: missing name");
+
+        let broken = serde_json::json!({ "name": "Broken", "rule_json": "{}" });
+        let error = parse_rule_import(&serde_json::json!([valid, broken]).to_string());
+        assert!(error.unwrap_err().starts_with("Rule 'Broken': "));
+    }
+
+    #[test]
+    fn shared_rules_accept_copied_rules_and_bare_definitions() {
+        let rule_json = r#"{"actions":[],"conditions":[],"name":"Block reddit","type":"basic"}"#;
+        let copied = serde_json::json!({
+            "id": 7,
+            "name": "Copied",
+            "natural_language": "no reddit",
+            "rule_json": rule_json,
+            "is_active": false,
+            "created_at": 0,
+            "tags": ["social"],
+        });
+
+        let from_rule = parse_shared_rule(&copied.to_string()).unwrap();
+        assert_eq!(from_rule.name, "Copied");
+        assert_eq!(from_rule.rule_json, rule_json);
+        assert_eq!(from_rule.tags, ["social"]);
+
+        let from_definition = parse_shared_rule(&format!("  {}\n", rule_json)).unwrap();
+        assert_eq!(from_definition.name, "Block reddit");
+        assert_eq!(from_definition.rule_json, rule_json);
+
+        assert!(parse_shared_rule("hello").unwrap_err().contains("does not contain JSON"));
+        assert!(parse_shared_rule(r#"{"name":1}"#).unwrap_err().contains("is not a rule"));
+    }
+}
@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+// Word -> sentiment weight in [-1.0, 1.0]
+static SENTIMENT_KEYWORDS: LazyLock<HashMap<&'static str, f32>> = LazyLock::new(|| {
+    HashMap::from([
+        ("amazing", 0.8),
+        ("awesome", 0.8),
+        ("best", 0.6),
+        ("calm", 0.5),
+        ("celebrate", 0.7),
+        ("fun", 0.6),
+        ("funny", 0.6),
+        ("good", 0.5),
+        ("great", 0.7),
+        ("happy", 0.8),
+        ("helpful", 0.5),
+        ("hope", 0.5),
+        ("inspiring", 0.7),
+        ("love", 0.8),
+        ("success", 0.7),
+        ("win", 0.6),
+        ("wins", 0.6),
+        ("wonderful", 0.8),
+        ("angry", -0.7),
+        ("attack", -0.7),
+        ("awful", -0.8),
+        ("bad", -0.5),
+        ("crash", -0.6),
+        ("crisis", -0.7),
+        ("dead", -0.8),
+        ("death", -0.8),
+        ("disaster", -0.8),
+        ("fail", -0.6),
+        ("fails", -0.6),
+        ("fear", -0.6),
+        ("hate", -0.8),
+        ("horrible", -0.8),
+        ("killed", -0.9),
+        ("outrage", -0.7),
+        ("sad", -0.6),
+        ("scandal", -0.6),
+        ("terrible", -0.8),
+        ("war", -0.7),
+        ("worst", -0.8),
+    ])
+});
+
+// Average weight of the sentiment words in a page title, or None when the
+// title contains none
+pub fn score_title(title: &str) -> Option<f32> {
+    let weights: Vec<f32> = title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter_map(|word| SENTIMENT_KEYWORDS.get(word.to_lowercase().as_str()).copied())
+        .collect();
+    if weights.is_empty() {
+        return None;
+    }
+    let average = weights.iter().sum::<f32>() / weights.len() as f32;
+    Some(average.clamp(-1.0, 1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn title_score_averages_keyword_weights() {
+        assert_eq!(score_title("Great win for the home team!"), Some(0.65));
+        assert_eq!(score_title("WAR: a terrible crisis"), Some(-0.73333335));
+        assert_eq!(score_title("Quarterly report - Docs"), None);
+    }
+}
@@ -0,0 +1,99 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const STREAKS_FILE: &str = "streaks.json";
+
+// A day is productive once its productivity score reaches this
+pub const DEFAULT_STREAK_THRESHOLD: f64 = 0.6;
+
+// Days are UTC calendar days, like the rest of the daily analytics
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Streaks {
+    pub current_streak_days: u32,
+    pub longest_streak_days: u32,
+    pub last_productive_date: Option<NaiveDate>,
+}
+
+impl Streaks {
+    // Marks `date` as productive and returns whether the current streak
+    // changed. Recording the same day again is a no-op.
+    pub fn record_productive_day(&mut self, date: NaiveDate) -> bool {
+        if self.last_productive_date.is_some_and(|last| last >= date) {
+            return false;
+        }
+
+        let continues = self.last_productive_date == date.pred_opt();
+        self.current_streak_days = if continues {
+            self.current_streak_days + 1
+        } else {
+            1
+        };
+        self.longest_streak_days = self.longest_streak_days.max(self.current_streak_days);
+        self.last_productive_date = Some(date);
+        true
+    }
+
+    // The streak as seen on `today`. It survives until the end of the day
+    // after the last productive one, so today still has time to continue it.
+    pub fn as_of(&self, today: NaiveDate) -> Streaks {
+        let alive = self
+            .last_productive_date
+            .is_some_and(|last| last >= today || Some(last) == today.pred_opt());
+        Streaks {
+            current_streak_days: if alive { self.current_streak_days } else { 0 },
+            ..self.clone()
+        }
+    }
+}
+
+// A missing file means no streak yet
+pub fn load_streaks(data_dir: &Path) -> Result<Streaks, String> {
+    match std::fs::read_to_string(data_dir.join(STREAKS_FILE)) {
+        Ok(json) => {
+            serde_json::from_str(&json).map_err(|e| format!("Failed to parse streaks: {}", e))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Streaks::default()),
+        Err(e) => Err(format!("Failed to read streaks: {}", e)),
+    }
+}
+
+pub fn save_streaks(data_dir: &Path, streaks: &Streaks) -> Result<(), String> {
+    std::fs::create_dir_all(data_dir)
+        .map_err(|e| format!("Failed to create app data directory: {}", e))?;
+    let json = serde_json::to_string_pretty(streaks)
+        .map_err(|e| format!("Failed to serialize streaks: {}", e))?;
+    std::fs::write(data_dir.join(STREAKS_FILE), json)
+        .map_err(|e| format!("Failed to save streaks: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap()
+    }
+
+    #[test]
+    fn streak_survives_today_until_it_is_missed() {
+        let mut streaks = Streaks::default();
+        assert!(streaks.record_productive_day(day(1)));
+        assert!(streaks.record_productive_day(day(2)));
+        assert!(!streaks.record_productive_day(day(2)));
+        assert_eq!(streaks.current_streak_days, 2);
+
+        // Yesterday was productive and today hasn't reached the threshold yet
+        assert_eq!(streaks.as_of(day(3)).current_streak_days, 2);
+        assert_eq!(streaks.as_of(day(4)).current_streak_days, 0);
+        assert_eq!(streaks.as_of(day(4)).longest_streak_days, 2);
+
+        assert!(streaks.record_productive_day(day(3)));
+        assert_eq!(streaks.current_streak_days, 3);
+
+        assert!(streaks.record_productive_day(day(5)));
+        assert_eq!(streaks.current_streak_days, 1);
+        assert_eq!(streaks.longest_streak_days, 3);
+        assert_eq!(streaks.last_productive_date, Some(day(5)));
+    }
+}
@@ -0,0 +1,113 @@
+use serde::Serialize;
+
+// A ready-made rule for users who haven't set up an LLM. `rule_json` uses the
+// same schema as generated rules.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct RuleTemplate {
+    pub id: &'static str,
+    pub display_name: &'static str,
+    pub description: &'static str,
+    pub rule_json: &'static str,
+}
+
+pub static RULE_TEMPLATES: [RuleTemplate; 11] = [
+    RuleTemplate {
+        id: "instagram_scroll_limit",
+        display_name: "Instagram scroll limit",
+        description: "Nudges you when you start scrolling Instagram",
+        rule_json: r#"{"actions":[{"parameters":{"message":"You're scrolling Instagram again. Time to put it down?"},"type":"popup"}],"conditions":[{"field":"domain","operator":"contains","value":"instagram.com"},{"field":"activity","operator":"contains","value":"scroll"}],"name":"Instagram scroll limit","schema_version":2,"type":"basic"}"#,
+    },
+    RuleTemplate {
+        id: "youtube_time_limit",
+        display_name: "YouTube time limit",
+        description: "Reminds you to stop after 30 minutes on YouTube in a day",
+        rule_json: r#"{"actions":[{"parameters":{"message":"You've spent 30 minutes on YouTube today."},"type":"popup"}],"conditions":[{"condition_type":"domain_time_today","domain":"youtube.com","min_seconds":1800}],"name":"YouTube time limit","schema_version":2,"type":"basic"}"#,
+    },
+    RuleTemplate {
+        id: "youtube_shorts_block",
+        display_name: "Block YouTube Shorts",
+        description: "Sends YouTube Shorts to your subscriptions feed instead",
+        rule_json: r#"{"actions":[{"parameters":{"target_url":"https://www.youtube.com/feed/subscriptions"},"type":"redirect"}],"conditions":[{"condition_type":"url_pattern","host":"youtube.com","path_prefix":"/shorts"}],"name":"Block YouTube Shorts","schema_version":2,"type":"basic"}"#,
+    },
+    RuleTemplate {
+        id: "twitter_block",
+        display_name: "Block Twitter",
+        description: "Redirects Twitter and X to Google",
+        rule_json: r#"{"actions":[{"parameters":{"target_url":"https://www.google.com"},"type":"redirect"}],"conditions":[{"field":"domain","operator":"regex","value":"(^|\\.)(twitter|x)\\.com$"}],"name":"Block Twitter","schema_version":2,"type":"basic"}"#,
+    },
+    RuleTemplate {
+        id: "reddit_focus_alert",
+        display_name: "Reddit focus alert",
+        description: "Asks whether Reddit can wait whenever you open it",
+        rule_json: r#"{"actions":[{"parameters":{"message":"Reddit can wait. Back to what you were doing?"},"type":"popup"}],"conditions":[{"field":"domain","operator":"contains","value":"reddit.com"}],"name":"Reddit focus alert","schema_version":2,"type":"basic"}"#,
+    },
+    RuleTemplate {
+        id: "tiktok_block",
+        display_name: "Block TikTok",
+        description: "Redirects TikTok to Google",
+        rule_json: r#"{"actions":[{"parameters":{"target_url":"https://www.google.com"},"type":"redirect"}],"conditions":[{"field":"domain","operator":"contains","value":"tiktok.com"}],"name":"Block TikTok","schema_version":2,"type":"basic"}"#,
+    },
+    RuleTemplate {
+        id: "facebook_reminder",
+        display_name: "Facebook reminder",
+        description: "Reminds you what you opened Facebook for",
+        rule_json: r#"{"actions":[{"parameters":{"message":"What did you open Facebook for?"},"type":"popup"}],"conditions":[{"field":"domain","operator":"contains","value":"facebook.com"}],"name":"Facebook reminder","schema_version":2,"type":"basic"}"#,
+    },
+    RuleTemplate {
+        id: "linkedin_feed_alert",
+        display_name: "LinkedIn feed alert",
+        description: "Flags the LinkedIn feed but leaves messages and jobs alone",
+        rule_json: r#"{"actions":[{"parameters":{"message":"The LinkedIn feed is endless. Did you mean to check messages?"},"type":"popup"}],"conditions":[{"condition_type":"url_pattern","host":"linkedin.com","path_prefix":"/feed"}],"name":"LinkedIn feed alert","schema_version":2,"type":"basic"}"#,
+    },
+    RuleTemplate {
+        id: "news_break",
+        display_name: "News break",
+        description: "Reminds you that the news will still be there later",
+        rule_json: r#"{"actions":[{"parameters":{"message":"The news will still be there later. Back to work?"},"type":"popup"}],"conditions":[{"field":"domain","operator":"regex","value":"(^|\\.)(cnn\\.com|nytimes\\.com|bbc\\.co\\.uk|news\\.ycombinator\\.com)$"}],"name":"News break","schema_version":2,"type":"basic"}"#,
+    },
+    RuleTemplate {
+        id: "shopping_pause",
+        display_name: "Shopping pause",
+        description: "Suggests sleeping on it before buying on Amazon",
+        rule_json: r#"{"actions":[{"parameters":{"message":"Do you need it? Try sleeping on it before buying."},"type":"popup"}],"conditions":[{"field":"domain","operator":"contains","value":"amazon."}],"name":"Shopping pause","schema_version":2,"type":"basic"}"#,
+    },
+    RuleTemplate {
+        id: "gaming_session_limit",
+        display_name: "Gaming session limit",
+        description: "Suggests a break after an hour with Steam in the foreground",
+        rule_json: r#"{"actions":[{"parameters":{"message":"You've been gaming for an hour. Time for a break?"},"type":"popup"}],"conditions":[{"condition_type":"foreground_duration","app_name":"Steam","min_seconds":3600}],"name":"Gaming session limit","schema_version":2,"type":"basic"}"#,
+    },
+];
+
+pub fn find_template(id: &str) -> Option<&'static RuleTemplate> {
+    RULE_TEMPLATES.iter().find(|template| template.id == id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rule_definition::validate_rule_schema;
+    use std::collections::HashSet;
+
+    #[test]
+    fn templates_are_valid_rules_with_unique_ids() {
+        let mut ids = HashSet::new();
+        for template in &RULE_TEMPLATES {
+            assert!(
+                ids.insert(template.id),
+                "duplicate template {}",
+                template.id
+            );
+            let definition = validate_rule_schema(template.rule_json)
+                .unwrap_or_else(|e| panic!("{}: {}", template.id, e));
+            assert_eq!(definition.name, template.display_name);
+            // Stored byte-for-byte as the rule engine would write it
+            assert_eq!(definition.to_json().unwrap(), template.rule_json);
+        }
+        assert_eq!(
+            find_template("reddit_focus_alert").unwrap().display_name,
+            "Reddit focus alert"
+        );
+        assert!(find_template("missing").is_none());
+    }
+}
@@ -0,0 +1,124 @@
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::path::Path;
+
+const TLS_CERT_FILE: &str = "tls_cert.pem";
+const TLS_KEY_FILE: &str = "tls_key.pem";
+
+// Self-signed certificate for serving the extension bridge over HTTPS. The
+// browser has to be told to trust it; the fingerprint lets the user check
+// they are trusting this one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsCertificate {
+    pub cert_pem: String,
+    pub key_pem: String,
+}
+
+impl TlsCertificate {
+    // SHA-256 of the DER certificate as colon-separated uppercase hex, the
+    // format browsers show
+    pub fn fingerprint(&self) -> Result<String, String> {
+        let der = pem::parse(&self.cert_pem)
+            .map_err(|e| format!("Failed to parse TLS certificate: {}", e))?;
+        let digest = Sha256::digest(der.contents());
+        Ok(digest
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<Vec<_>>()
+            .join(":"))
+    }
+}
+
+// Valid for the addresses the server can listen on locally
+pub fn generate_tls_cert() -> Result<TlsCertificate, String> {
+    let names = vec!["127.0.0.1".to_string(), "localhost".to_string()];
+    let certified = rcgen::generate_simple_self_signed(names)
+        .map_err(|e| format!("Failed to generate TLS certificate: {}", e))?;
+    Ok(TlsCertificate {
+        cert_pem: certified.cert.pem(),
+        key_pem: certified.key_pair.serialize_pem(),
+    })
+}
+
+pub fn load_or_create_tls_cert(data_dir: &Path) -> Result<TlsCertificate, String> {
+    let cert_pem = std::fs::read_to_string(data_dir.join(TLS_CERT_FILE));
+    let key_pem = std::fs::read_to_string(data_dir.join(TLS_KEY_FILE));
+    match (cert_pem, key_pem) {
+        (Ok(cert_pem), Ok(key_pem))
+            if !cert_pem.trim().is_empty() && !key_pem.trim().is_empty() =>
+        {
+            Ok(TlsCertificate { cert_pem, key_pem })
+        }
+        _ => {
+            let cert = generate_tls_cert()?;
+            save_tls_cert(data_dir, &cert)?;
+            Ok(cert)
+        }
+    }
+}
+
+pub fn save_tls_cert(data_dir: &Path, cert: &TlsCertificate) -> Result<(), String> {
+    std::fs::create_dir_all(data_dir)
+        .map_err(|e| format!("Failed to create app data directory: {}", e))?;
+    write_private_file(&data_dir.join(TLS_KEY_FILE), &cert.key_pem)
+        .map_err(|e| format!("Failed to save TLS key: {}", e))?;
+    std::fs::write(data_dir.join(TLS_CERT_FILE), &cert.cert_pem)
+        .map_err(|e| format!("Failed to save TLS certificate: {}", e))
+}
+
+// Readable and writable by the owner only, on platforms that have modes
+fn write_private_file(path: &Path, contents: &str) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // The mode only applies to new files, so tighten an existing one too
+        if path.exists() {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    options.open(path)?.write_all(contents.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn certificate_is_persisted_once_and_fingerprinted() {
+        let dir = std::env::temp_dir().join(format!(
+            "cortex-tls-{}",
+            crate::websocket_server::generate_api_key()
+        ));
+
+        let cert = load_or_create_tls_cert(&dir).unwrap();
+        assert!(cert.cert_pem.starts_with("-----BEGIN CERTIFICATE-----"));
+        assert_eq!(load_or_create_tls_cert(&dir).unwrap(), cert);
+
+        let fingerprint = cert.fingerprint().unwrap();
+        assert_eq!(fingerprint.len(), 32 * 3 - 1);
+        assert!(fingerprint.split(':').all(|byte| {
+            byte.len() == 2
+                && byte
+                    .chars()
+                    .all(|c| c.is_ascii_hexdigit() && !c.is_lowercase())
+        }));
+        let regenerated = generate_tls_cert().unwrap();
+        assert_ne!(regenerated.fingerprint().unwrap(), fingerprint);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            save_tls_cert(&dir, &regenerated).unwrap();
+            let key_mode = std::fs::metadata(dir.join(TLS_KEY_FILE))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(key_mode & 0o777, 0o600);
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,127 @@
+use crate::config::DomainCategoryMap;
+use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
+
+static DOMAIN_CATEGORIES: LazyLock<DomainCategoryMap> = LazyLock::new(DomainCategoryMap::default);
+
+const EDUCATIONAL_KEYWORDS: [&str; 9] = [
+    "course",
+    "explained",
+    "how to",
+    "learn",
+    "lecture",
+    "lesson",
+    "tutorial",
+    "university",
+    "walkthrough",
+];
+
+const SEARCH_HOSTS: [&str; 3] = ["google.com", "bing.com", "duckduckgo.com"];
+
+// Finer-grained than the domain category: the same site can serve several of
+// these depending on the page
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UrlCategory {
+    EducationalVideo,
+    EntertainmentVideo,
+    ShortVideo,
+    SocialFeed,
+    SocialMessaging,
+    NewsArticle,
+    Shopping,
+    Documentation,
+    CodeHosting,
+    Search,
+    Other,
+}
+
+pub fn classify_url(url: &str, title: &str, elements: Option<&serde_json::Value>) -> UrlCategory {
+    let Ok(parsed) = url::Url::parse(url) else {
+        return UrlCategory::Other;
+    };
+    let host = parsed.host_str().unwrap_or_default().trim_start_matches("www.").to_lowercase();
+    let path = parsed.path().to_lowercase();
+
+    if path.starts_with("/shorts") || host.ends_with("tiktok.com") {
+        return UrlCategory::ShortVideo;
+    }
+    if host.starts_with("docs.") || host == "developer.mozilla.org" || path.starts_with("/docs") {
+        return UrlCategory::Documentation;
+    }
+    if SEARCH_HOSTS.iter().any(|search| host.ends_with(search)) && path.starts_with("/search") {
+        return UrlCategory::Search;
+    }
+
+    match DOMAIN_CATEGORIES.category_for(&host) {
+        Some("video") => {
+            let mut text = title.to_lowercase();
+            if let Some(elements) = elements {
+                text.push(' ');
+                text.push_str(&elements.to_string().to_lowercase());
+            }
+            if EDUCATIONAL_KEYWORDS.iter().any(|keyword| text.contains(keyword)) {
+                UrlCategory::EducationalVideo
+            } else {
+                UrlCategory::EntertainmentVideo
+            }
+        }
+        Some("social") => {
+            let messaging = ["/messages", "/direct", "/inbox", "/messaging"]
+                .iter()
+                .any(|prefix| path.starts_with(prefix));
+            if messaging {
+                UrlCategory::SocialMessaging
+            } else {
+                UrlCategory::SocialFeed
+            }
+        }
+        Some("news") => UrlCategory::NewsArticle,
+        Some("shopping") => UrlCategory::Shopping,
+        Some("development") => UrlCategory::CodeHosting,
+        _ => UrlCategory::Other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn video_pages_split_on_educational_keywords() {
+        let elements = serde_json::json!({ "video_title": "Rust lifetimes explained" });
+        assert_eq!(
+            classify_url("https://www.youtube.com/watch?v=abc", "YouTube", Some(&elements)),
+            UrlCategory::EducationalVideo
+        );
+        assert_eq!(
+            classify_url("https://youtube.com/watch?v=xyz", "Funny Cat Video - YouTube", None),
+            UrlCategory::EntertainmentVideo
+        );
+        assert_eq!(
+            classify_url("https://youtube.com/shorts/123", "Learn Rust", None),
+            UrlCategory::ShortVideo
+        );
+    }
+
+    #[test]
+    fn social_and_other_pages() {
+        assert_eq!(
+            classify_url("https://instagram.com/direct/inbox", "Inbox", None),
+            UrlCategory::SocialMessaging
+        );
+        assert_eq!(
+            classify_url("https://reddit.com/r/rust", "r/rust", None),
+            UrlCategory::SocialFeed
+        );
+        assert_eq!(
+            classify_url("https://docs.rs/serde", "serde", None),
+            UrlCategory::Documentation
+        );
+        assert_eq!(
+            classify_url("https://www.google.com/search?q=rust", "rust - Google", None),
+            UrlCategory::Search
+        );
+        assert_eq!(classify_url("not a url", "", None), UrlCategory::Other);
+    }
+}
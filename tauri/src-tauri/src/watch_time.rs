@@ -0,0 +1,178 @@
+use crate::ExtensionLog;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::LazyLock;
+
+// Fallback lengths, in minutes, for titles that carry no duration
+const DEFAULT_SHORT_MINUTES: u32 = 1;
+const DEFAULT_VIDEO_MINUTES: u32 = 10;
+const DEFAULT_EPISODE_MINUTES: u32 = 45;
+const DEFAULT_MOVIE_MINUTES: u32 = 100;
+
+// "1:02:03", "12:34" or "(5:07)"
+static DURATION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b(?:(\d{1,2}):)?(\d{1,2}):(\d{2})\b").unwrap());
+
+// "S2:E5", "Season 1", "Episode 3", "Ep. 4"
+static EPISODE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\bS\d+\s*:?\s*E\d+\b|\bseason \d+|\bepisode \d+|\bep\.? ?\d+").unwrap()
+});
+
+type Extractor = fn(&ExtensionLog) -> TitleEstimate;
+
+// Platforms with a known title format, keyed by domain
+static EXTRACTORS: LazyLock<HashMap<&'static str, Extractor>> = LazyLock::new(|| {
+    HashMap::from([
+        ("youtube.com", youtube_estimate as Extractor),
+        ("netflix.com", netflix_estimate as Extractor),
+    ])
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ContentType {
+    Movie,
+    TVShow,
+    Short,
+    Unknown,
+}
+
+struct TitleEstimate {
+    minutes: u32,
+    content_type: ContentType,
+}
+
+// Every figure is a rough guess from page titles, not measured playback time
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WatchTimeEstimate {
+    pub total_estimated_minutes: u32,
+    pub by_platform: HashMap<String, u32>,
+    pub by_content_type: HashMap<String, u32>,
+    pub is_approximate: bool,
+}
+
+fn title_duration_minutes(title: &str) -> Option<u32> {
+    let captures = DURATION.captures(title)?;
+    let part = |index: usize| {
+        captures
+            .get(index)
+            .map_or(0, |part| part.as_str().parse::<u32>().unwrap_or(0))
+    };
+    let seconds = part(1) * 3600 + part(2) * 60 + part(3);
+    Some(seconds.div_ceil(60))
+}
+
+fn youtube_estimate(log: &ExtensionLog) -> TitleEstimate {
+    if log.url.contains("/shorts/") {
+        return TitleEstimate {
+            minutes: DEFAULT_SHORT_MINUTES,
+            content_type: ContentType::Short,
+        };
+    }
+    TitleEstimate {
+        minutes: title_duration_minutes(&log.title).unwrap_or(DEFAULT_VIDEO_MINUTES),
+        content_type: ContentType::Unknown,
+    }
+}
+
+fn netflix_estimate(log: &ExtensionLog) -> TitleEstimate {
+    let (content_type, default_minutes) = if EPISODE.is_match(&log.title) {
+        (ContentType::TVShow, DEFAULT_EPISODE_MINUTES)
+    } else {
+        (ContentType::Movie, DEFAULT_MOVIE_MINUTES)
+    };
+    TitleEstimate {
+        minutes: title_duration_minutes(&log.title).unwrap_or(default_minutes),
+        content_type,
+    }
+}
+
+fn platform_for(domain: &str) -> Option<(&'static str, Extractor)> {
+    let domain = domain.trim_start_matches("www.");
+    EXTRACTORS
+        .iter()
+        .find(|(platform, _)| domain == **platform || domain.ends_with(&format!(".{}", platform)))
+        .map(|(platform, extractor)| (*platform, *extractor))
+}
+
+// Each distinct URL is counted once, however many logs it produced
+pub fn estimate_watch_time(logs: &[ExtensionLog]) -> WatchTimeEstimate {
+    let mut estimate = WatchTimeEstimate {
+        is_approximate: true,
+        ..WatchTimeEstimate::default()
+    };
+    let mut seen_urls = HashSet::new();
+    for log in logs {
+        let Some((platform, extractor)) = platform_for(&log.domain) else {
+            continue;
+        };
+        if !seen_urls.insert(log.url.as_str()) {
+            continue;
+        }
+
+        let title_estimate = extractor(log);
+        estimate.total_estimated_minutes += title_estimate.minutes;
+        *estimate
+            .by_platform
+            .entry(platform.to_string())
+            .or_insert(0) += title_estimate.minutes;
+        *estimate
+            .by_content_type
+            .entry(format!("{:?}", title_estimate.content_type))
+            .or_insert(0) += title_estimate.minutes;
+    }
+    estimate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(domain: &str, url: &str, title: &str) -> ExtensionLog {
+        ExtensionLog {
+            timestamp: 0.0,
+            domain: domain.to_string(),
+            activity: "watching_videos".to_string(),
+            url: url.to_string(),
+            title: title.to_string(),
+            elements: None,
+            title_sentiment: None,
+            url_category: None,
+        }
+    }
+
+    #[test]
+    fn estimates_use_title_durations_and_platform_defaults() {
+        let logs = vec![
+            log(
+                "www.youtube.com",
+                "https://youtube.com/watch?v=a",
+                "Rust in 100s (12:01)",
+            ),
+            log(
+                "www.youtube.com",
+                "https://youtube.com/watch?v=a",
+                "Rust in 100s (12:01)",
+            ),
+            log("youtube.com", "https://youtube.com/shorts/b", "Cat"),
+            log(
+                "netflix.com",
+                "https://netflix.com/watch/1",
+                "Dark: Season 1: Episode 3",
+            ),
+            log("netflix.com", "https://netflix.com/watch/2", "Inception"),
+            log("reddit.com", "https://reddit.com/", "Reddit"),
+        ];
+
+        let estimate = estimate_watch_time(&logs);
+
+        assert!(estimate.is_approximate);
+        assert_eq!(estimate.total_estimated_minutes, 13 + 1 + 45 + 100);
+        assert_eq!(estimate.by_platform["youtube.com"], 14);
+        assert_eq!(estimate.by_platform["netflix.com"], 145);
+        assert_eq!(estimate.by_content_type["TVShow"], 45);
+        assert_eq!(estimate.by_content_type["Movie"], 100);
+        assert_eq!(estimate.by_content_type["Unknown"], 13);
+        assert_eq!(estimate.by_content_type["Short"], 1);
+    }
+}
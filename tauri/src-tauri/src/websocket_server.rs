@@ -1,17 +1,255 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{Read, Write};
 use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use futures_util::future::BoxFuture;
+use futures_util::{FutureExt, Stream, StreamExt};
 use tokio::sync::{broadcast, Mutex};
-use warp::Filter;
+use tokio_util::sync::CancellationToken;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use warp::{Filter, Reply};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use crate::rate_limiter::RateLimiter;
+use crate::rule_definition::TabAction;
+use crate::sentiment;
+use crate::tls::TlsCertificate;
+use crate::url_classifier;
+use crate::config::DEFAULT_MAX_BODY_BYTES;
 use crate::ExtensionLog;
 
+// How long an idempotency key is remembered after its message was processed
+const IDEMPOTENCY_WINDOW: Duration = Duration::from_secs(60);
+
+// Number of requests kept in the access log
+const ACCESS_LOG_CAPACITY: usize = 200;
+
+// Commands left unfetched beyond this are dropped, oldest first
+const PENDING_COMMANDS_CAPACITY: usize = 100;
+
+// Comment lines sent to idle /stream clients so proxies keep the connection
+const SSE_KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+// POSTs to /extension-data and requests to /command, /pending-commands and
+// /stream must carry the app's API key in this header. The browser extension
+// sends it with every request, e.g.
+// `fetch(url, { headers: { "X-Cortex-Key": key, ... } })`, using the key shown
+// by the app (`get_api_key`). EventSource can't set headers, so /stream also
+// accepts the key as `?key=`. /health and /status stay open.
+pub const API_KEY_HEADER: &str = "x-cortex-key";
+const API_KEY_FILE: &str = "api_key";
+
+#[derive(Debug)]
+struct Unauthorized;
+
+impl warp::reject::Reject for Unauthorized {}
+
+// Why an extension data body couldn't be read
+#[derive(Debug)]
+enum InvalidBody {
+    TooLarge,
+    // The connection failed while the body was being read
+    Unreadable,
+    UnsupportedEncoding,
+    InvalidGzip,
+    InvalidJson,
+}
+
+impl warp::reject::Reject for InvalidBody {}
+
+#[derive(Debug)]
+struct RateLimited {
+    retry_after_secs: u64,
+}
+
+impl warp::reject::Reject for RateLimited {}
+
+// 32 random bytes, hex encoded
+pub fn generate_api_key() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn load_or_create_api_key(data_dir: &Path) -> Result<String, String> {
+    let path = data_dir.join(API_KEY_FILE);
+    match std::fs::read_to_string(&path) {
+        Ok(key) if !key.trim().is_empty() => Ok(key.trim().to_string()),
+        _ => {
+            let key = generate_api_key();
+            save_api_key(data_dir, &key)?;
+            Ok(key)
+        }
+    }
+}
+
+pub fn save_api_key(data_dir: &Path, key: &str) -> Result<(), String> {
+    std::fs::create_dir_all(data_dir)
+        .map_err(|e| format!("Failed to create app data directory: {}", e))?;
+    std::fs::write(data_dir.join(API_KEY_FILE), key)
+        .map_err(|e| format!("Failed to save API key: {}", e))
+}
+
+// Compares every byte so the time taken doesn't reveal how much matched
+fn keys_match(expected: &str, provided: &str) -> bool {
+    expected.len() == provided.len()
+        && expected
+            .bytes()
+            .zip(provided.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn check_api_key(expected: &str, provided: Option<String>) -> Result<(), warp::Rejection> {
+    match provided {
+        Some(provided) if keys_match(expected, &provided) => Ok(()),
+        _ => Err(warp::reject::custom(Unauthorized)),
+    }
+}
+
+// Reads the raw body, giving up as soon as it passes the limit instead of
+// buffering whatever the client sends. Compressed bodies are held to the
+// same limit as decompressed ones.
+async fn read_body(
+    body: impl Stream<Item = Result<impl warp::Buf, warp::Error>>,
+    max_body_bytes: usize,
+) -> Result<Vec<u8>, InvalidBody> {
+    let mut body = std::pin::pin!(body);
+    let mut read = Vec::new();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(|_| InvalidBody::Unreadable)?;
+        if read.len() + chunk.remaining() > max_body_bytes {
+            return Err(InvalidBody::TooLarge);
+        }
+        read.extend_from_slice(chunk.chunk());
+    }
+    Ok(read)
+}
+
+// Gunzips the body if it is gzip-encoded. The limit applies to the
+// decompressed size, so a small compressed body can't expand without bound.
+fn decode_body(
+    content_encoding: Option<&str>,
+    body: &[u8],
+    max_body_bytes: usize,
+) -> Result<Vec<u8>, InvalidBody> {
+    let decoded = match content_encoding.map(|encoding| encoding.trim().to_ascii_lowercase()) {
+        None => body.to_vec(),
+        Some(encoding) if encoding == "identity" => body.to_vec(),
+        Some(encoding) if encoding == "gzip" => {
+            let mut decoded = Vec::new();
+            flate2::read::GzDecoder::new(body)
+                .take(max_body_bytes as u64 + 1)
+                .read_to_end(&mut decoded)
+                .map_err(|_| InvalidBody::InvalidGzip)?;
+            decoded
+        }
+        Some(_) => return Err(InvalidBody::UnsupportedEncoding),
+    };
+    if decoded.len() > max_body_bytes {
+        return Err(InvalidBody::TooLarge);
+    }
+    Ok(decoded)
+}
+
+// Compresses the reply when the client accepts gzip. Every reply also
+// advertises that request bodies may be gzipped.
+async fn encode_reply(
+    reply: impl warp::Reply,
+    accept_encoding: Option<String>,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let mut response = reply.into_response();
+    response.headers_mut().insert(
+        warp::http::header::ACCEPT_ENCODING,
+        warp::http::HeaderValue::from_static("gzip"),
+    );
+    let accepts_gzip = accept_encoding.is_some_and(|accepted| {
+        accepted
+            .split(',')
+            .any(|encoding| encoding.split(';').next().unwrap_or_default().trim() == "gzip")
+    });
+    if !accepts_gzip {
+        return Ok(response);
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let body = warp::hyper::body::to_bytes(body)
+        .await
+        .map_err(|_| warp::reject::reject())?;
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    let compressed = encoder
+        .write_all(&body)
+        .and_then(|_| encoder.finish())
+        .map_err(|_| warp::reject::reject())?;
+    parts.headers.insert(
+        warp::http::header::CONTENT_ENCODING,
+        warp::http::HeaderValue::from_static("gzip"),
+    );
+    parts.headers.remove(warp::http::header::CONTENT_LENGTH);
+    Ok(warp::reply::Response::from_parts(parts, compressed.into()))
+}
+
+// Counts a /stream client for as long as its event stream is alive; warp
+// drops the stream when the client disconnects
+struct SseClient(Arc<AtomicUsize>);
+
+impl SseClient {
+    fn connect(sse_clients: Arc<AtomicUsize>) -> Self {
+        sse_clients.fetch_add(1, Ordering::SeqCst);
+        SseClient(sse_clients)
+    }
+}
+
+impl Drop for SseClient {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// One event per log, as JSON. A client that falls behind skips the logs it
+// missed rather than being disconnected.
+fn log_events(
+    receiver: broadcast::Receiver<ExtensionLog>,
+    client: SseClient,
+) -> impl Stream<Item = Result<warp::sse::Event, serde_json::Error>> + Send + 'static {
+    futures_util::stream::unfold((receiver, client), |(mut receiver, client)| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(log) => {
+                    let event = warp::sse::Event::default().json_data(&log);
+                    return Some((event, (receiver, client)));
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccessLogEntry {
+    pub timestamp: f64,
+    pub method: String,
+    pub path: String,
+    pub status_code: u16,
+    pub latency_us: u64,
+    // Taken from the request's Content-Length header
+    pub content_length: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ExtensionMessage {
     pub event_type: String,
     pub data: ExtensionMessageData,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ExtensionMessageData {
     pub domain: String,
     pub activity: String,
@@ -20,31 +258,168 @@ pub struct ExtensionMessageData {
     pub elements: Option<serde_json::Value>,
 }
 
+// A tab action for the extension to carry out, serialized as e.g.
+// `{"command":"redirect","url":"...","tab_url":"..."}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExtensionCommand {
+    #[serde(flatten)]
+    pub action: TabAction,
+    // The page the rule matched, so the extension can find its tab
+    pub tab_url: String,
+}
+
+pub fn queue_command(queue: &mut VecDeque<ExtensionCommand>, command: ExtensionCommand) {
+    if queue.len() >= PENDING_COMMANDS_CAPACITY {
+        queue.pop_front();
+    }
+    queue.push_back(command);
+}
+
 pub struct WebSocketServer {
     pub sender: broadcast::Sender<ExtensionLog>,
     pub connection_count: Arc<Mutex<u32>>,
+    pub recent_keys: Arc<Mutex<HashMap<String, Instant>>>,
+    // Written from warp's synchronous log hook, hence the std mutex
+    pub access_log: Arc<std::sync::Mutex<VecDeque<AccessLogEntry>>>,
+    // Starts as a throwaway key until the persisted one is loaded
+    api_key: Arc<std::sync::RwLock<String>>,
+    // Shared with `AppState`, which adjusts the limit and reads the stats
+    rate_limiter: Arc<std::sync::Mutex<RateLimiter>>,
+    // Shared with `AppState`, where matched rules queue their tab actions
+    pending_commands: Arc<Mutex<VecDeque<ExtensionCommand>>>,
+    sse_clients: Arc<AtomicUsize>,
+    // Used when `ServerConfig::tls_enabled` is set; loaded at startup
+    tls_cert: Arc<std::sync::RwLock<Option<TlsCertificate>>>,
+    // Mirrors `ServerConfig::max_body_bytes`
+    max_body_bytes: Arc<AtomicUsize>,
 }
 
 impl WebSocketServer {
-    pub fn new() -> Self {
+    pub fn new(
+        rate_limiter: Arc<std::sync::Mutex<RateLimiter>>,
+        pending_commands: Arc<Mutex<VecDeque<ExtensionCommand>>>,
+    ) -> Self {
         let (sender, _) = broadcast::channel(100);
         
         WebSocketServer {
             sender,
             connection_count: Arc::new(Mutex::new(0)),
+            recent_keys: Arc::new(Mutex::new(HashMap::new())),
+            access_log: Arc::new(std::sync::Mutex::new(VecDeque::new())),
+            api_key: Arc::new(std::sync::RwLock::new(generate_api_key())),
+            rate_limiter,
+            pending_commands,
+            sse_clients: Arc::new(AtomicUsize::new(0)),
+            tls_cert: Arc::new(std::sync::RwLock::new(None)),
+            max_body_bytes: Arc::new(AtomicUsize::new(DEFAULT_MAX_BODY_BYTES)),
         }
     }
 
-    pub async fn start(&self, port: u16) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    pub fn api_key(&self) -> String {
+        self.api_key.read().unwrap().clone()
+    }
+
+    pub fn set_api_key(&self, key: String) {
+        *self.api_key.write().unwrap() = key;
+    }
+
+    // Applies to requests from then on, without a restart
+    pub fn set_max_body_bytes(&self, max_body_bytes: usize) {
+        self.max_body_bytes.store(max_body_bytes, Ordering::SeqCst);
+    }
+
+    pub fn tls_cert(&self) -> Option<TlsCertificate> {
+        self.tls_cert.read().unwrap().clone()
+    }
+
+    pub fn set_tls_cert(&self, cert: TlsCertificate) {
+        *self.tls_cert.write().unwrap() = Some(cert);
+    }
+
+    // Binds right away so a taken port is reported to the caller. The returned
+    // future serves requests until `shutdown` is cancelled. With `tls`, the
+    // server only speaks HTTPS.
+    pub fn bind(
+        &self,
+        addr: SocketAddr,
+        tls: Option<&TlsCertificate>,
+        shutdown: CancellationToken,
+    ) -> Result<(SocketAddr, BoxFuture<'static, ()>), warp::Error> {
+        let (addr, server) = match tls {
+            Some(cert) => {
+                let (addr, server) = warp::serve(self.routes())
+                    .tls()
+                    .cert(&cert.cert_pem)
+                    .key(&cert.key_pem)
+                    .try_bind_with_graceful_shutdown(addr, shutdown.cancelled_owned())?;
+                (addr, server.boxed())
+            }
+            None => {
+                let (addr, server) = warp::serve(self.routes())
+                    .try_bind_with_graceful_shutdown(addr, shutdown.cancelled_owned())?;
+                (addr, server.boxed())
+            }
+        };
+        let scheme = if tls.is_some() { "https" } else { "http" };
+        println!("🌐 Extension bridge server starting on {}://{}", scheme, addr);
+        Ok((addr, server))
+    }
+
+    fn routes(
+        &self,
+    ) -> impl Filter<Extract = (impl warp::Reply,), Error = std::convert::Infallible> + Clone {
         let sender = self.sender.clone();
         let connection_count = self.connection_count.clone();
+        let recent_keys = self.recent_keys.clone();
+        let access_log = self.access_log.clone();
+        let api_key = self.api_key.clone();
+        let rate_limiter = self.rate_limiter.clone();
+        let next_commands = self.pending_commands.clone();
+        let pending_commands = self.pending_commands.clone();
+        let stream_sender = self.sender.clone();
+        let stream_api_key = self.api_key.clone();
+        let sse_clients = self.sse_clients.clone();
+        let status_sse_clients = self.sse_clients.clone();
+        let max_body_bytes = self.max_body_bytes.clone();
 
         // CORS headers for all routes
         let cors = warp::cors()
             .allow_any_origin()
-            .allow_headers(vec!["content-type"])
+            .allow_headers(vec!["content-type", "content-encoding", API_KEY_HEADER])
             .allow_methods(vec!["GET", "POST", "OPTIONS"]);
 
+        // Rejects requests once the limiter runs out of tokens
+        let within_rate_limit = warp::any()
+            .and_then(move || {
+                let checked = rate_limiter.lock().unwrap().check(Instant::now());
+                async move {
+                    checked.map_err(|retry_after| {
+                        warp::reject::custom(RateLimited {
+                            retry_after_secs: retry_after.as_secs_f64().ceil().max(1.0) as u64,
+                        })
+                    })
+                }
+            })
+            .untuple_one();
+
+        // Rejects requests without the current API key
+        let authorized = warp::header::optional::<String>(API_KEY_HEADER)
+            .and_then(move |provided: Option<String>| {
+                let checked = check_api_key(&api_key.read().unwrap(), provided);
+                async move { checked }
+            })
+            .untuple_one();
+
+        // As above, but also taking the key from the query string
+        let stream_authorized = warp::header::optional::<String>(API_KEY_HEADER)
+            .and(warp::query::<HashMap<String, String>>())
+            .and_then(move |provided: Option<String>, mut query: HashMap<String, String>| {
+                let provided = provided.or_else(|| query.remove("key"));
+                let checked = check_api_key(&stream_api_key.read().unwrap(), provided);
+                async move { checked }
+            })
+            .untuple_one();
+
         // Health check endpoint
         let health = warp::path("health")
             .and(warp::get())
@@ -55,49 +430,168 @@ impl WebSocketServer {
                 }))
             });
 
+        // A JSON body, optionally gzip-encoded
+        let message_body = warp::header::optional::<String>("content-encoding")
+            .and(warp::body::stream())
+            .and_then(move |content_encoding: Option<String>, body| {
+                let limit = max_body_bytes.load(Ordering::SeqCst);
+                async move {
+                    let message = read_body(body, limit)
+                        .await
+                        .and_then(|body| decode_body(content_encoding.as_deref(), &body, limit))
+                        .and_then(|decoded| {
+                            serde_json::from_slice::<ExtensionMessage>(&decoded)
+                                .map_err(|_| InvalidBody::InvalidJson)
+                        });
+                    message.map_err(warp::reject::custom)
+                }
+            });
+
         // Extension data endpoint (HTTP POST)
         let extension_data = warp::path("extension-data")
             .and(warp::post())
-            .and(warp::body::json())
+            // Authenticate first so unauthenticated callers can't spend the
+            // extension's tokens
+            .and(authorized.clone())
+            .and(within_rate_limit)
+            .and(warp::header::optional::<String>("accept-encoding"))
+            .and(message_body)
             .and(warp::any().map(move || sender.clone()))
-            .and_then(handle_extension_data);
+            .and(warp::any().map(move || recent_keys.clone()))
+            .and_then(
+                |accept_encoding: Option<String>,
+                 message: ExtensionMessage,
+                 sender: broadcast::Sender<ExtensionLog>,
+                 recent_keys: Arc<Mutex<HashMap<String, Instant>>>| async move {
+                    let reply = handle_extension_data(message, sender, recent_keys).await?;
+                    encode_reply(reply, accept_encoding).await
+                },
+            );
 
         // Extension connection status
         let connection_status = warp::path("status")
             .and(warp::get())
             .and(warp::any().map(move || connection_count.clone()))
+            .and(warp::any().map(move || status_sse_clients.clone()))
             .and_then(handle_connection_status);
 
-        let routes = health
+        // Server-sent events with every new log, for dashboards outside the app
+        let stream = warp::path("stream")
+            .and(warp::get())
+            .and(stream_authorized)
+            .map(move || {
+                let client = SseClient::connect(sse_clients.clone());
+                let events = log_events(stream_sender.subscribe(), client);
+                warp::sse::reply(
+                    warp::sse::keep_alive().interval(SSE_KEEP_ALIVE_INTERVAL).stream(events),
+                )
+            });
+
+        // Oldest queued command, for an extension that handles one at a time
+        let next_command = warp::path("command")
+            .and(warp::get())
+            .and(authorized.clone())
+            .and(warp::any().map(move || next_commands.clone()))
+            .and_then(handle_next_command);
+
+        // Every queued command, clearing the queue
+        let drain_commands = warp::path("pending-commands")
+            .and(warp::get())
+            .and(authorized)
+            .and(warp::any().map(move || pending_commands.clone()))
+            .and_then(handle_pending_commands);
+
+        let access_log = warp::log::custom(move |info| {
+            let content_length = info
+                .request_headers()
+                .get(warp::http::header::CONTENT_LENGTH)
+                .and_then(|length| length.to_str().ok())
+                .and_then(|length| length.parse().ok());
+            let mut log = access_log.lock().unwrap();
+            if log.len() >= ACCESS_LOG_CAPACITY {
+                log.pop_front();
+            }
+            log.push_back(AccessLogEntry {
+                timestamp: chrono::Utc::now().timestamp_millis() as f64,
+                method: info.method().to_string(),
+                path: info.path().to_string(),
+                status_code: info.status().as_u16(),
+                latency_us: info.elapsed().as_micros() as u64,
+                content_length,
+            });
+        });
+
+        health
             .or(extension_data)
             .or(connection_status)
+            .or(next_command)
+            .or(drain_commands)
+            .or(stream)
             .with(cors)
-            .recover(handle_rejection);
+            .recover(handle_rejection)
+            .with(access_log)
+    }
 
-        let addr: SocketAddr = ([127, 0, 0, 1], port).into();
-        println!("🌐 Extension bridge server starting on http://127.0.0.1:{}", port);
+    pub async fn get_connection_count(&self) -> u32 {
+        *self.connection_count.lock().await
+    }
 
-        warp::serve(routes)
-            .run(addr)
-            .await;
+    // Most recent first
+    pub fn get_access_log(&self, limit: usize) -> Vec<AccessLogEntry> {
+        self.access_log.lock().unwrap().iter().rev().take(limit).cloned().collect()
+    }
 
-        Ok(())
+    pub fn clear_access_log(&self) {
+        self.access_log.lock().unwrap().clear();
     }
+}
 
-    pub async fn get_connection_count(&self) -> u32 {
-        *self.connection_count.lock().await
+async fn handle_next_command(
+    pending_commands: Arc<Mutex<VecDeque<ExtensionCommand>>>,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    match pending_commands.lock().await.pop_front() {
+        Some(command) => Ok(warp::reply::json(&command).into_response()),
+        None => Ok(warp::http::StatusCode::NO_CONTENT.into_response()),
     }
 }
 
+async fn handle_pending_commands(
+    pending_commands: Arc<Mutex<VecDeque<ExtensionCommand>>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let commands: Vec<ExtensionCommand> = pending_commands.lock().await.drain(..).collect();
+    Ok(warp::reply::json(&commands))
+}
+
 async fn handle_extension_data(
     message: ExtensionMessage,
     sender: broadcast::Sender<ExtensionLog>,
+    recent_keys: Arc<Mutex<HashMap<String, Instant>>>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
+    if let Some(key) = &message.idempotency_key {
+        let mut keys = recent_keys.lock().await;
+        keys.retain(|_, processed_at| processed_at.elapsed() < IDEMPOTENCY_WINDOW);
+        
+        if keys.contains_key(key) {
+            println!("🔁 Skipping duplicate extension data: {}", key);
+            return Ok(warp::reply::json(&serde_json::json!({
+                "status": "already_processed"
+            })));
+        }
+        keys.insert(key.clone(), Instant::now());
+    }
+
+    let url_category = url_classifier::classify_url(
+        &message.data.url,
+        &message.data.title,
+        message.data.elements.as_ref(),
+    );
     let log = ExtensionLog {
         timestamp: chrono::Utc::now().timestamp_millis() as f64,
         domain: message.data.domain,
         activity: message.data.activity,
         url: message.data.url,
+        title_sentiment: sentiment::score_title(&message.data.title),
+        url_category: Some(url_category),
         title: message.data.title,
         elements: message.data.elements,
     };
@@ -117,10 +611,12 @@ async fn handle_extension_data(
 
 async fn handle_connection_status(
     connection_count: Arc<Mutex<u32>>,
+    sse_clients: Arc<AtomicUsize>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     let count = *connection_count.lock().await;
     Ok(warp::reply::json(&serde_json::json!({
         "connected_extensions": count,
+        "sse_clients": sse_clients.load(Ordering::SeqCst),
         "server_status": "running"
     })))
 }
@@ -132,9 +628,23 @@ async fn handle_rejection(err: warp::Rejection) -> Result<impl warp::Reply, std:
     if err.is_not_found() {
         code = warp::http::StatusCode::NOT_FOUND;
         message = "Not Found";
-    } else if let Some(_) = err.find::<warp::filters::body::BodyDeserializeError>() {
-        code = warp::http::StatusCode::BAD_REQUEST;
-        message = "Invalid JSON";
+    } else if err.find::<Unauthorized>().is_some() {
+        code = warp::http::StatusCode::UNAUTHORIZED;
+        message = "Missing or invalid API key";
+    } else if err.find::<RateLimited>().is_some() {
+        code = warp::http::StatusCode::TOO_MANY_REQUESTS;
+        message = "Too many requests";
+    } else if let Some(invalid) = err.find::<InvalidBody>() {
+        (code, message) = match invalid {
+            InvalidBody::TooLarge => (warp::http::StatusCode::PAYLOAD_TOO_LARGE, "Body too large"),
+            InvalidBody::Unreadable => (warp::http::StatusCode::BAD_REQUEST, "Unreadable body"),
+            InvalidBody::UnsupportedEncoding => (
+                warp::http::StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "Unsupported content encoding",
+            ),
+            InvalidBody::InvalidGzip => (warp::http::StatusCode::BAD_REQUEST, "Invalid gzip body"),
+            InvalidBody::InvalidJson => (warp::http::StatusCode::BAD_REQUEST, "Invalid JSON"),
+        };
     } else {
         code = warp::http::StatusCode::INTERNAL_SERVER_ERROR;
         message = "Internal Server Error";
@@ -144,5 +654,258 @@ async fn handle_rejection(err: warp::Rejection) -> Result<impl warp::Reply, std:
         "error": message
     }));
 
-    Ok(warp::reply::with_status(json, code))
-}
\ No newline at end of file
+    let mut response = warp::reply::with_status(json, code).into_response();
+    if let Some(limited) = err.find::<RateLimited>() {
+        response.headers_mut().insert(
+            warp::http::header::RETRY_AFTER,
+            warp::http::HeaderValue::from(limited.retry_after_secs),
+        );
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(idempotency_key: Option<&str>) -> ExtensionMessage {
+        ExtensionMessage {
+            event_type: "activity".to_string(),
+            data: ExtensionMessageData {
+                domain: "reddit.com".to_string(),
+                activity: "browsing".to_string(),
+                url: "https://reddit.com/".to_string(),
+                title: "Reddit".to_string(),
+                elements: None,
+            },
+            idempotency_key: idempotency_key.map(str::to_string),
+        }
+    }
+
+    #[tokio::test]
+    async fn duplicate_idempotency_key_is_not_broadcast_twice() {
+        let server = WebSocketServer::new(Default::default(), Default::default());
+        let mut receiver = server.sender.subscribe();
+
+        for msg in [message(Some("retry-1")), message(Some("retry-1")), message(None)] {
+            handle_extension_data(msg, server.sender.clone(), server.recent_keys.clone())
+                .await
+                .unwrap();
+        }
+
+        assert!(receiver.try_recv().is_ok());
+        assert!(receiver.try_recv().is_ok());
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn extension_data_requires_the_api_key() {
+        let server = WebSocketServer::new(Default::default(), Default::default());
+        server.set_api_key("secret".to_string());
+        let routes = server.routes();
+        let post = |key: Option<&str>| {
+            let request = warp::test::request()
+                .method("POST")
+                .path("/extension-data")
+                .json(&message(None));
+            match key {
+                Some(key) => request.header(API_KEY_HEADER, key),
+                None => request,
+            }
+        };
+
+        assert_eq!(post(None).reply(&routes).await.status(), 401);
+        assert_eq!(post(Some("wrong")).reply(&routes).await.status(), 401);
+        assert_eq!(post(Some("secret")).reply(&routes).await.status(), 200);
+
+        let health = warp::test::request().path("/health").reply(&routes).await;
+        assert_eq!(health.status(), 200);
+        let status = warp::test::request().path("/status").reply(&routes).await;
+        assert_eq!(status.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn extension_data_is_rate_limited() {
+        let server = WebSocketServer::new(
+            Arc::new(std::sync::Mutex::new(RateLimiter::new(2))),
+            Default::default(),
+        );
+        let routes = server.routes();
+        let key = server.api_key();
+        let post_with = |key: &str| {
+            warp::test::request()
+                .method("POST")
+                .path("/extension-data")
+                .header(API_KEY_HEADER, key)
+                .json(&message(None))
+        };
+        let post = || post_with(&key);
+
+        for _ in 0..3 {
+            assert_eq!(post_with("wrong").reply(&routes).await.status(), 401);
+        }
+        assert_eq!(post().reply(&routes).await.status(), 200);
+        assert_eq!(post().reply(&routes).await.status(), 200);
+        let limited = post().reply(&routes).await;
+        assert_eq!(limited.status(), 429);
+        assert_eq!(limited.headers()["retry-after"], "1");
+    }
+
+    #[tokio::test]
+    async fn queued_commands_are_handed_out_once() {
+        let server = WebSocketServer::new(Default::default(), Default::default());
+        let routes = server.routes();
+        let key = server.api_key();
+        let get = |path: &str| warp::test::request().path(path).header(API_KEY_HEADER, &key);
+        {
+            let mut queue = server.pending_commands.lock().await;
+            for action in [
+                TabAction::CloseTab,
+                TabAction::Redirect { url: "https://todoist.com".to_string() },
+                TabAction::CloseTab,
+            ] {
+                let tab_url = "https://reddit.com/".to_string();
+                queue_command(&mut queue, ExtensionCommand { action, tab_url });
+            }
+        }
+
+        let next = get("/command").reply(&routes).await;
+        assert_eq!(next.status(), 200);
+        let next: serde_json::Value = serde_json::from_slice(next.body()).unwrap();
+        assert_eq!(
+            next,
+            serde_json::json!({ "command": "close_tab", "tab_url": "https://reddit.com/" })
+        );
+
+        let pending = get("/pending-commands").reply(&routes).await;
+        let pending: Vec<ExtensionCommand> = serde_json::from_slice(pending.body()).unwrap();
+        assert_eq!(pending.len(), 2);
+        assert_eq!(
+            pending[0].action,
+            TabAction::Redirect { url: "https://todoist.com".to_string() }
+        );
+
+        assert_eq!(get("/command").reply(&routes).await.status(), 204);
+        assert_eq!(get("/pending-commands").reply(&routes).await.body(), "[]");
+        let unauthorized = warp::test::request().path("/pending-commands").reply(&routes).await;
+        assert_eq!(unauthorized.status(), 401);
+    }
+
+    #[tokio::test]
+    async fn stream_sends_each_new_log_as_an_event() {
+        use warp::hyper::body::HttpBody;
+
+        let server = WebSocketServer::new(Default::default(), Default::default());
+        let routes = server.routes();
+        let sse_clients = || async {
+            let status = warp::test::request().path("/status").reply(&routes).await;
+            let status: serde_json::Value = serde_json::from_slice(status.body()).unwrap();
+            status["sse_clients"].as_u64().unwrap()
+        };
+        let unauthorized = warp::test::request().path("/stream?key=wrong").reply(&routes).await;
+        assert_eq!(unauthorized.status(), 401);
+
+        let path = format!("/stream?key={}", server.api_key());
+        let reply = warp::test::request().path(&path).filter(&routes).await.unwrap();
+        let response = reply.into_response();
+        assert_eq!(response.headers()["content-type"], "text/event-stream");
+        assert_eq!(sse_clients().await, 1);
+
+        handle_extension_data(message(None), server.sender.clone(), server.recent_keys.clone())
+            .await
+            .unwrap();
+        let mut body = response.into_body();
+        let chunk = body.data().await.unwrap().unwrap();
+        let event = std::str::from_utf8(&chunk).unwrap();
+        let json = event.strip_prefix("data:").unwrap().trim_end();
+        let log: ExtensionLog = serde_json::from_str(json).unwrap();
+        assert_eq!(log.domain, "reddit.com");
+        assert!(event.ends_with("\n\n"));
+
+        drop(body);
+        assert_eq!(sse_clients().await, 0);
+    }
+
+    #[tokio::test]
+    async fn extension_data_accepts_gzip_bodies_up_to_the_limit() {
+        let server = WebSocketServer::new(Default::default(), Default::default());
+        let mut receiver = server.sender.subscribe();
+        let routes = server.routes();
+        let key = server.api_key();
+        let gzip = |body: &[u8]| {
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(body).unwrap();
+            encoder.finish().unwrap()
+        };
+        let post = |body: Vec<u8>| {
+            warp::test::request()
+                .method("POST")
+                .path("/extension-data")
+                .header(API_KEY_HEADER, &key)
+                .header("content-encoding", "gzip")
+                .header("accept-encoding", "gzip, deflate")
+                .body(body)
+        };
+
+        let json = serde_json::to_vec(&message(None)).unwrap();
+        let accepted = post(gzip(&json)).reply(&routes).await;
+        assert_eq!(accepted.status(), 200);
+        assert_eq!(accepted.headers()["content-encoding"], "gzip");
+        assert_eq!(accepted.headers()["accept-encoding"], "gzip");
+        let mut reply = String::new();
+        flate2::read::GzDecoder::new(&accepted.body()[..])
+            .read_to_string(&mut reply)
+            .unwrap();
+        assert!(reply.contains("received"));
+        assert_eq!(receiver.try_recv().unwrap().domain, "reddit.com");
+
+        // Compresses to a few kilobytes but expands past the limit
+        let bomb = gzip(&vec![b' '; DEFAULT_MAX_BODY_BYTES + 1]);
+        assert!(bomb.len() < 10_000);
+        assert_eq!(post(bomb).reply(&routes).await.status(), 413);
+        server.set_max_body_bytes(json.len() - 1);
+        assert_eq!(post(gzip(&json)).reply(&routes).await.status(), 413);
+        assert_eq!(post(b"not gzip".to_vec()).reply(&routes).await.status(), 400);
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn body_reading_stops_at_the_limit() {
+        let endless = || {
+            futures_util::stream::repeat_with(|| {
+                Ok::<_, warp::Error>(warp::hyper::body::Bytes::from_static(b"0123456789"))
+            })
+        };
+
+        assert_eq!(read_body(endless().take(3), 30).await.unwrap().len(), 30);
+        assert!(matches!(read_body(endless().take(4), 30).await, Err(InvalidBody::TooLarge)));
+        // Never read to the end, or this would not return
+        assert!(matches!(read_body(endless(), 1024).await, Err(InvalidBody::TooLarge)));
+    }
+
+    #[tokio::test]
+    async fn server_binds_with_a_self_signed_certificate() {
+        let server = WebSocketServer::new(Default::default(), Default::default());
+        let cert = crate::tls::generate_tls_cert().unwrap();
+        let shutdown = CancellationToken::new();
+
+        let (addr, serving) =
+            server.bind(([127, 0, 0, 1], 0).into(), Some(&cert), shutdown.clone()).unwrap();
+        assert_ne!(addr.port(), 0);
+        shutdown.cancel();
+        serving.await;
+    }
+
+    #[test]
+    fn api_key_is_persisted_once() {
+        let dir = std::env::temp_dir().join(format!("cortex-api-key-{}", generate_api_key()));
+
+        let key = load_or_create_api_key(&dir).unwrap();
+        assert_eq!(key.len(), 64);
+        assert!(key.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(load_or_create_api_key(&dir).unwrap(), key);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
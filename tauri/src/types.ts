@@ -5,6 +5,17 @@ export interface Rule {
   rule_json: string;
   is_active: boolean;
   created_at: number;
+  last_triggered_at?: number;
+  priority?: number;
+  tags?: string[];
+  schedule?: RuleSchedule | null;
+  expires_at?: number | null;
+}
+
+export interface RuleSchedule {
+  days_of_week: number[];
+  start_time_minutes: number;
+  end_time_minutes: number;
 }
 
 export interface ActivityRecord {
@@ -15,6 +26,8 @@ export interface ActivityRecord {
   app: string;
   bundle_id?: string;
   domain?: string;
+  notes?: string;
+  duration_ms?: number;
 }
 
 export interface ExtensionLog {
@@ -24,4 +37,10 @@ export interface ExtensionLog {
   url: string;
   title: string;
   elements?: any;
-}
\ No newline at end of file
+  title_sentiment?: number;
+  url_category?: string;
+}
+export interface CortexError {
+  type: "NotFound" | "DatabaseError" | "ValidationError" | "IoError" | "LockError";
+  message: string;
+}